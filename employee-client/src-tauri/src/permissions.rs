@@ -1,16 +1,40 @@
 // TaskFleet Desktop Client - 权限检查模块
-// 与后端权限保持一致,前端仅用于UI控制
+// 权限清单和内置角色的权限组合与服务器共用(flow-farm-rbac),仅用于UI控制
 
-use crate::taskfleet_models::UserRole;
+use crate::taskfleet_models::{Permission, PermissionSet, User, UserRole};
 
 /// 权限检查辅助结构
 pub struct Permissions {
     role: UserRole,
+    granted: PermissionSet,
 }
 
 impl Permissions {
+    /// 按内置角色的权限组合构造
     pub fn new(role: UserRole) -> Self {
-        Self { role }
+        let granted = role.builtin().permissions();
+        Self { role, granted }
+    }
+
+    /// 按服务器下发的有效权限构造,旧版本服务器未下发权限时按内置角色计算
+    pub fn for_user(user: &User) -> Self {
+        if user.permissions.is_empty() {
+            return Self::new(user.role.clone());
+        }
+        Self {
+            role: user.role.clone(),
+            granted: user.permissions.clone(),
+        }
+    }
+
+    /// 全部有效权限
+    pub fn granted(&self) -> &PermissionSet {
+        &self.granted
+    }
+
+    /// 是否拥有指定权限
+    pub fn has(&self, permission: Permission) -> bool {
+        self.granted.contains(permission)
     }
 
     // ==================== 角色检查 ====================
@@ -39,58 +63,54 @@ impl Permissions {
 
     /// 是否可以管理公司
     pub fn can_manage_companies(&self) -> bool {
-        self.is_platform_admin()
+        self.has(Permission::PlatformManage)
     }
 
     /// 是否可以管理用户
     pub fn can_manage_users(&self) -> bool {
-        self.has_admin_role()
+        self.has(Permission::UserCreate) || self.has(Permission::UserUpdate) || self.has(Permission::UserDelete)
     }
 
     /// 是否可以创建任务
     pub fn can_create_task(&self) -> bool {
-        self.has_admin_role()
+        self.has(Permission::TaskCreate)
     }
 
     /// 是否可以创建项目
     pub fn can_create_project(&self) -> bool {
-        self.has_admin_role()
+        self.has(Permission::ProjectCreate)
     }
 
     /// 是否可以分配任务
     pub fn can_assign_tasks(&self) -> bool {
-        self.has_admin_role()
+        self.has(Permission::TaskAssign)
     }
 
     /// 是否可以查看数据分析
     pub fn can_view_analytics(&self) -> bool {
-        self.has_admin_role()
+        self.has(Permission::StatisticsView)
     }
 
     /// 是否可以删除任务/项目
     pub fn can_delete(&self) -> bool {
-        self.has_admin_role()
+        self.has(Permission::TaskDelete) || self.has(Permission::ProjectDelete)
     }
 
     /// 是否可以查看所有任务
     pub fn can_view_all_tasks(&self) -> bool {
-        self.has_admin_role()
+        self.has(Permission::TaskViewAll)
     }
 
     /// 是否只能查看自己的任务
     pub fn can_only_view_own_tasks(&self) -> bool {
-        self.is_task_executor()
+        !self.can_view_all_tasks()
     }
 
     // ==================== UI显示控制 ====================
 
     /// 获取角色的中文显示名称
     pub fn get_role_display_name(&self) -> &'static str {
-        match self.role {
-            UserRole::PlatformAdmin => "平台管理员",
-            UserRole::ProjectManager => "项目经理",
-            UserRole::TaskExecutor => "任务执行者",
-        }
+        self.role.builtin().display_name()
     }
 
     /// 获取角色对应的颜色(用于UI标识)
@@ -102,7 +122,7 @@ impl Permissions {
         }
     }
 
-    /// 获取桌面端应该显示的功能列表
+    /// 获取桌面端应该显示的功能列表(由权限推导,与上面的 `can_*` 判断一致)
     pub fn get_desktop_features(&self) -> Vec<DesktopFeature> {
         let mut features = vec![
             DesktopFeature::ViewTasks,
@@ -111,21 +131,15 @@ impl Permissions {
             DesktopFeature::WorkLogs,
         ];
 
-        if self.has_admin_role() {
-            features.extend_from_slice(&[
-                DesktopFeature::CreateTask,
-                DesktopFeature::AssignTask,
-                DesktopFeature::ViewAnalytics,
-                DesktopFeature::ManageProjects,
-            ]);
-        }
-
-        if self.is_platform_admin() {
-            features.extend_from_slice(&[
-                DesktopFeature::ManageCompanies,
-                DesktopFeature::ManageUsers,
-            ]);
-        }
+        let optional = [
+            (DesktopFeature::CreateTask, self.can_create_task()),
+            (DesktopFeature::AssignTask, self.can_assign_tasks()),
+            (DesktopFeature::ViewAnalytics, self.can_view_analytics()),
+            (DesktopFeature::ManageProjects, self.can_create_project()),
+            (DesktopFeature::ManageCompanies, self.can_manage_companies()),
+            (DesktopFeature::ManageUsers, self.can_manage_users()),
+        ];
+        features.extend(optional.into_iter().filter(|(_, allowed)| *allowed).map(|(feature, _)| feature));
 
         features
     }
@@ -140,13 +154,11 @@ pub enum DesktopFeature {
     ViewProfile,
     WorkLogs,
 
-    // 管理功能(管理员)
+    // 管理功能(按权限显示)
     CreateTask,
     AssignTask,
     ViewAnalytics,
     ManageProjects,
-
-    // 高级功能(平台管理员)
     ManageCompanies,
     ManageUsers,
 }
@@ -232,5 +244,33 @@ mod tests {
         assert!(features.contains(&DesktopFeature::ViewTasks));
         assert!(features.contains(&DesktopFeature::ManageCompanies));
         assert!(features.len() > 4); // 应该有更多功能
+
+        // 项目经理可以管理用户,功能列表与权限判断一致
+        let manager = Permissions::new(UserRole::ProjectManager);
+        assert!(manager.get_desktop_features().contains(&DesktopFeature::ManageUsers));
+        assert!(!manager.get_desktop_features().contains(&DesktopFeature::ManageCompanies));
+    }
+
+    #[test]
+    fn test_server_permissions_override_role() {
+        // 分配了"组长"自定义角色的任务执行者
+        let user = User {
+            id: 1,
+            username: "lead".to_string(),
+            full_name: "组长".to_string(),
+            email: "lead@example.com".to_string(),
+            role: UserRole::TaskExecutor,
+            company_id: Some(1),
+            permissions: [Permission::CompanyView, Permission::TaskAssign].into_iter().collect(),
+        };
+        let perms = Permissions::for_user(&user);
+
+        assert!(perms.can_assign_tasks());
+        assert!(!perms.can_create_task());
+        assert!(perms.get_desktop_features().contains(&DesktopFeature::AssignTask));
+
+        // 服务器未下发权限时按内置角色计算
+        let legacy = User { permissions: PermissionSet::new(), ..user };
+        assert!(!Permissions::for_user(&legacy).can_assign_tasks());
     }
 }
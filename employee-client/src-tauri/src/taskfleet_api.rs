@@ -6,6 +6,9 @@ use anyhow::{Context, Result};
 use reqwest::Client;
 use std::time::Duration;
 
+/// 桌面客户端的 User-Agent,服务器据此在会话列表中区分桌面端和网页端
+const USER_AGENT: &str = concat!("FlowFarm-Desktop/", env!("CARGO_PKG_VERSION"));
+
 pub struct TaskFleetApiClient {
     client: Client,
     base_url: String,
@@ -16,6 +19,7 @@ impl TaskFleetApiClient {
     pub fn new(base_url: String) -> Self {
         let client = Client::builder()
             .timeout(Duration::from_secs(30))
+            .user_agent(USER_AGENT)
             .build()
             .expect("Failed to create HTTP client");
 
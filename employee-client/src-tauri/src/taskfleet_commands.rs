@@ -83,7 +83,7 @@ pub async fn get_user_permissions(state: State<'_, AppState>) -> Result<UserPerm
 
     match current_user.as_ref() {
         Some(user) => {
-            let permissions = Permissions::new(user.role.clone());
+            let permissions = Permissions::for_user(user);
             Ok(UserPermissionsInfo {
                 role: user.role.clone(),
                 role_display: permissions.get_role_display_name().to_string(),
@@ -95,6 +95,7 @@ pub async fn get_user_permissions(state: State<'_, AppState>) -> Result<UserPerm
                 can_assign_tasks: permissions.can_assign_tasks(),
                 can_view_analytics: permissions.can_view_analytics(),
                 can_delete: permissions.can_delete(),
+                permissions: permissions.granted().clone(),
                 available_features: permissions.get_desktop_features()
                     .iter()
                     .map(|f| FeatureInfo {
@@ -121,6 +122,8 @@ pub struct UserPermissionsInfo {
     pub can_assign_tasks: bool,
     pub can_view_analytics: bool,
     pub can_delete: bool,
+    /// 全部权限名称,界面按名称控制具体按钮
+    pub permissions: PermissionSet,
     pub available_features: Vec<FeatureInfo>,
 }
 
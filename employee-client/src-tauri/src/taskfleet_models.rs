@@ -4,6 +4,8 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
+pub use flow_farm_rbac::{BuiltinRole, Permission, PermissionSet};
+
 // ==================== 用户角色 ====================
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -29,6 +31,15 @@ impl UserRole {
             _ => None,
         }
     }
+
+    /// 对应的内置角色(权限组合与服务器共用)
+    pub fn builtin(&self) -> BuiltinRole {
+        match self {
+            UserRole::PlatformAdmin => BuiltinRole::PlatformAdmin,
+            UserRole::ProjectManager => BuiltinRole::ProjectManager,
+            UserRole::TaskExecutor => BuiltinRole::TaskExecutor,
+        }
+    }
 }
 
 // ==================== 认证相关 ====================
@@ -53,6 +64,9 @@ pub struct User {
     pub email: String,
     pub role: UserRole,
     pub company_id: Option<i64>,
+    /// 服务器计算的有效权限(包含公司自定义角色)
+    #[serde(default)]
+    pub permissions: PermissionSet,
 }
 
 // ==================== 任务相关 ====================
@@ -1,22 +1,107 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
+/// 默认的 JWT_SECRET,未设置 ALLOW_INSECURE_DEFAULTS 时不允许使用
+pub const DEFAULT_JWT_SECRET: &str = "your-secret-key-change-this-in-production";
+/// JWT_SECRET 的最短长度
+const MIN_JWT_SECRET_LENGTH: usize = 32;
+/// 前端单页应用的默认内容安全策略,Ant Design 的样式需要 'unsafe-inline'
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; font-src 'self' data:; connect-src 'self'; object-src 'none'; base-uri 'self'; form-action 'self'";
+/// /docs 页面的默认内容安全策略: 只有内联样式,不允许脚本
+pub const DEFAULT_DOCS_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'unsafe-inline'; img-src 'self' data:; base-uri 'none'; form-action 'none'";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub app_name: String,
     pub version: String,
     pub debug: bool,
+    /// 允许使用默认或过短的 JWT_SECRET 启动(仅限本地开发),与 DEBUG 无关
+    pub allow_insecure_defaults: bool,
     pub host: String,
     pub port: u16,
     pub database_url: String,
     pub jwt_secret: String,
     pub jwt_expires_in: i64,
+    /// 访问令牌签名算法: HS256(使用 jwt_secret) / RS256 / EdDSA
+    pub jwt_algorithm: String,
+    /// RS256/EdDSA 密钥目录,存放 `<kid>.key.pem` 私钥和 `<kid>.pub.pem` 公钥
+    pub jwt_keys_dir: Option<String>,
+    /// 用于签名的密钥ID,未设置时使用目录中文件名排序最后的私钥
+    pub jwt_signing_kid: Option<String>,
+    /// 刷新令牌有效期(秒),也是会话无操作后的最长保持时间
+    pub refresh_token_expires_in: i64,
+    /// 同一账号连续登录失败多少次后临时锁定
+    pub login_max_attempts: u32,
+    /// 同一IP连续登录失败多少次后临时锁定
+    pub login_ip_max_attempts: u32,
+    /// 临时锁定时长(秒)
+    pub login_lockout_seconds: i64,
+    /// 登录失败后的退避基数(秒),第n次失败后需等待 基数×2^(n-1) 秒,0表示不退避
+    pub login_backoff_seconds: i64,
+    /// 允许跨域访问的来源,`*` 表示任意来源,支持 `https://*.example.com` 通配子域名
     pub allowed_origins: Vec<String>,
+    /// 前端页面的内容安全策略(Content-Security-Policy),为空表示不发送
+    pub content_security_policy: String,
+    /// /docs 页面的内容安全策略,覆盖默认策略,为空时沿用前端页面的策略
+    pub docs_content_security_policy: String,
+    /// 允许嵌入本站页面的来源(CSP frame-ancestors),默认禁止被嵌入
+    pub frame_ancestors: String,
+    /// Referrer-Policy 响应头,为空表示不发送
+    pub referrer_policy: String,
+    /// 启用TLS时 Strict-Transport-Security 的 max-age(秒),0表示不发送
+    pub hsts_max_age: u64,
+    /// 登录、注册和申请找回密码接口按IP限流,格式 `次数/秒数`,为空表示不限制
+    pub rate_limit_auth: String,
+    /// 需要认证的接口按用户或访问令牌限流
+    pub rate_limit_user: String,
+    /// 需要认证的接口按公司限流(同一公司的所有用户和API密钥共用)
+    pub rate_limit_company: String,
+    /// 个人信息字段加密主密钥(`版本:base64密钥`),为空表示不加密
+    pub pii_keys: Vec<String>,
+    /// 加密新数据使用的主密钥版本,默认最新版本
+    pub pii_active_key: Option<u32>,
+    /// 邮箱、手机号盲索引的 HMAC 密钥(base64),轮换主密钥时保持不变
+    pub pii_index_key: Option<String>,
+    /// 新密码使用的哈希算法: argon2id / bcrypt,已有哈希在登录成功后自动迁移
+    pub password_hash_algorithm: String,
+    /// Argon2id 内存开销(KiB)
+    pub argon2_memory_kib: u32,
+    /// Argon2id 迭代次数
+    pub argon2_iterations: u32,
+    /// Argon2id 并行度
+    pub argon2_parallelism: u32,
+    /// bcrypt 成本因子(仅 password_hash_algorithm=bcrypt 时用于新哈希)
     pub bcrypt_rounds: u32,
+    /// 密码最短长度
+    pub password_min_length: usize,
+    /// 密码必须包含的字符类别: lower / upper / digit / symbol
+    pub password_required_classes: Vec<String>,
+    /// 泄露密码列表文件(每行一个,不区分大小写),命中的密码不能使用
+    pub password_blocklist_file: Option<String>,
+    /// 不能与最近几次用过的密码(含当前密码)相同,0表示不限制
+    pub password_history_size: usize,
     pub static_dir: String,
     pub enable_tls: bool,
     pub tls_cert_path: Option<String>,
     pub tls_key_path: Option<String>,
+    /// 访问 /metrics 所需的 Bearer 令牌
+    pub metrics_token: Option<String>,
+    /// /metrics 单独监听的地址(如 127.0.0.1:9100),设置后主端口不再提供 /metrics
+    pub metrics_bind: Option<String>,
+    /// 邮件发送方式: smtp / file(写入 mail_dir) / log(只输出到日志)
+    pub mailer: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    /// SMTP加密方式: starttls / tls / none
+    pub smtp_tls: String,
+    /// 发件人,如 "Flow Farm <noreply@example.com>"
+    pub mail_from: String,
+    /// file 方式下邮件(.eml)的保存目录
+    pub mail_dir: String,
+    /// 邮件中链接指向的站点地址
+    pub public_url: String,
 }
 
 impl Config {
@@ -29,6 +114,9 @@ impl Config {
                 .unwrap_or_else(|_| "true".to_string())
                 .parse()
                 .unwrap_or(true),
+            allow_insecure_defaults: std::env::var("ALLOW_INSECURE_DEFAULTS")
+                .map(|value| value.parse().unwrap_or(false))
+                .unwrap_or(false),
             host: std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
             port: std::env::var("PORT")
                 .unwrap_or_else(|_| "8000".to_string())
@@ -37,20 +125,94 @@ impl Config {
             database_url: std::env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "sqlite:data/flow_farm.db".to_string()),
             jwt_secret: std::env::var("JWT_SECRET")
-                .unwrap_or_else(|_| "your-secret-key-change-this-in-production".to_string()),
+                .unwrap_or_else(|_| DEFAULT_JWT_SECRET.to_string()),
             jwt_expires_in: std::env::var("JWT_EXPIRES_IN")
-                .unwrap_or_else(|_| "86400".to_string()) // 24小时
+                .unwrap_or_else(|_| "900".to_string()) // 访问令牌15分钟,过期后用刷新令牌续期
+                .parse()
+                .unwrap_or(900),
+            jwt_algorithm: std::env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string()),
+            jwt_keys_dir: std::env::var("JWT_KEYS_DIR").ok().filter(|dir| !dir.is_empty()),
+            jwt_signing_kid: std::env::var("JWT_SIGNING_KID").ok().filter(|kid| !kid.is_empty()),
+            refresh_token_expires_in: std::env::var("REFRESH_TOKEN_EXPIRES_IN")
+                .unwrap_or_else(|_| "2592000".to_string()) // 30天
+                .parse()
+                .unwrap_or(2592000),
+            login_max_attempts: std::env::var("LOGIN_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            login_ip_max_attempts: std::env::var("LOGIN_IP_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "50".to_string())
+                .parse()
+                .unwrap_or(50),
+            login_lockout_seconds: std::env::var("LOGIN_LOCKOUT_SECONDS")
+                .unwrap_or_else(|_| "900".to_string()) // 15分钟
                 .parse()
-                .unwrap_or(86400),
+                .unwrap_or(900),
+            login_backoff_seconds: std::env::var("LOGIN_BACKOFF_SECONDS")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .unwrap_or(1),
             allowed_origins: std::env::var("ALLOWED_ORIGINS")
                 .unwrap_or_else(|_| "*".to_string())
                 .split(',')
                 .map(|s| s.trim().to_string())
                 .collect(),
+            content_security_policy: std::env::var("CONTENT_SECURITY_POLICY")
+                .unwrap_or_else(|_| DEFAULT_CONTENT_SECURITY_POLICY.to_string()),
+            docs_content_security_policy: std::env::var("DOCS_CONTENT_SECURITY_POLICY")
+                .unwrap_or_else(|_| DEFAULT_DOCS_CONTENT_SECURITY_POLICY.to_string()),
+            frame_ancestors: std::env::var("FRAME_ANCESTORS").unwrap_or_else(|_| "'none'".to_string()),
+            referrer_policy: std::env::var("REFERRER_POLICY")
+                .unwrap_or_else(|_| "strict-origin-when-cross-origin".to_string()),
+            hsts_max_age: std::env::var("HSTS_MAX_AGE")
+                .unwrap_or_else(|_| "31536000".to_string())
+                .parse()
+                .unwrap_or(31536000),
+            rate_limit_auth: std::env::var("RATE_LIMIT_AUTH").unwrap_or_else(|_| "20/60".to_string()),
+            rate_limit_user: std::env::var("RATE_LIMIT_USER").unwrap_or_else(|_| "600/60".to_string()),
+            rate_limit_company: std::env::var("RATE_LIMIT_COMPANY").unwrap_or_else(|_| "3000/60".to_string()),
+            pii_keys: std::env::var("PII_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            pii_active_key: std::env::var("PII_ACTIVE_KEY").ok().and_then(|version| version.parse().ok()),
+            pii_index_key: std::env::var("PII_INDEX_KEY").ok().filter(|key| !key.is_empty()),
+            password_hash_algorithm: std::env::var("PASSWORD_HASH_ALGORITHM")
+                .unwrap_or_else(|_| "argon2id".to_string()),
+            argon2_memory_kib: std::env::var("ARGON2_MEMORY_KIB")
+                .unwrap_or_else(|_| "19456".to_string())
+                .parse()
+                .unwrap_or(19456),
+            argon2_iterations: std::env::var("ARGON2_ITERATIONS")
+                .unwrap_or_else(|_| "2".to_string())
+                .parse()
+                .unwrap_or(2),
+            argon2_parallelism: std::env::var("ARGON2_PARALLELISM")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .unwrap_or(1),
             bcrypt_rounds: std::env::var("BCRYPT_ROUNDS")
                 .unwrap_or_else(|_| "12".to_string())
                 .parse()
                 .unwrap_or(12),
+            password_min_length: std::env::var("PASSWORD_MIN_LENGTH")
+                .unwrap_or_else(|_| "8".to_string())
+                .parse()
+                .unwrap_or(8),
+            password_required_classes: std::env::var("PASSWORD_REQUIRED_CLASSES")
+                .unwrap_or_else(|_| "lower,upper,digit".to_string())
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            password_blocklist_file: std::env::var("PASSWORD_BLOCKLIST_FILE").ok().filter(|path| !path.is_empty()),
+            password_history_size: std::env::var("PASSWORD_HISTORY_SIZE")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
             static_dir: std::env::var("STATIC_DIR")
                 .unwrap_or_else(|_| "../server-frontend/dist".to_string()),
             enable_tls: std::env::var("ENABLE_TLS")
@@ -59,8 +221,43 @@ impl Config {
                 .unwrap_or(false),
             tls_cert_path: std::env::var("TLS_CERT_PATH").ok(),
             tls_key_path: std::env::var("TLS_KEY_PATH").ok(),
+            metrics_token: std::env::var("METRICS_TOKEN").ok().filter(|token| !token.is_empty()),
+            metrics_bind: std::env::var("METRICS_BIND").ok().filter(|addr| !addr.is_empty()),
+            mailer: std::env::var("MAILER").unwrap_or_else(|_| "log".to_string()),
+            smtp_host: std::env::var("SMTP_HOST").unwrap_or_else(|_| "localhost".to_string()),
+            smtp_port: std::env::var("SMTP_PORT")
+                .unwrap_or_else(|_| "587".to_string())
+                .parse()
+                .unwrap_or(587),
+            smtp_username: std::env::var("SMTP_USERNAME").ok().filter(|value| !value.is_empty()),
+            smtp_password: std::env::var("SMTP_PASSWORD").ok().filter(|value| !value.is_empty()),
+            smtp_tls: std::env::var("SMTP_TLS").unwrap_or_else(|_| "starttls".to_string()),
+            mail_from: std::env::var("MAIL_FROM")
+                .unwrap_or_else(|_| "Flow Farm <noreply@localhost>".to_string()),
+            mail_dir: std::env::var("MAIL_DIR").unwrap_or_else(|_| "data/mail".to_string()),
+            public_url: std::env::var("PUBLIC_URL")
+                .unwrap_or_else(|_| "http://localhost:8000".to_string()),
         };
 
         Ok(config)
     }
+
+    /// 拒绝使用默认或过短的 JWT_SECRET,除非显式设置了 ALLOW_INSECURE_DEFAULTS
+    ///
+    /// 访问令牌改用 RS256/EdDSA 签名后,邮件链接令牌仍使用该密钥计算签名。
+    pub fn ensure_secure(&self) -> Result<()> {
+        let problem = if self.jwt_secret.starts_with(DEFAULT_JWT_SECRET) {
+            "使用默认的 JWT_SECRET".to_string()
+        } else if self.jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
+            format!("JWT_SECRET 长度少于 {} 个字符", MIN_JWT_SECRET_LENGTH)
+        } else {
+            return Ok(());
+        };
+
+        if !self.allow_insecure_defaults {
+            bail!("{},请设置随机生成的密钥(仅本地开发可设置 ALLOW_INSECURE_DEFAULTS=true 跳过检查)", problem);
+        }
+        tracing::warn!("⚠️ {}: 邮件链接令牌可被伪造,已因 ALLOW_INSECURE_DEFAULTS=true 继续启动,切勿用于生产环境", problem);
+        Ok(())
+    }
 }
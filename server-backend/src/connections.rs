@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+use tokio::sync::oneshot;
+
+/// 会话被撤销时关闭连接的关闭码
+pub const CLOSE_SESSION_REVOKED: u16 = 4001;
+/// 公司停用时关闭连接的关闭码(对应错误码 1011)
+pub const CLOSE_COMPANY_INACTIVE: u16 = 4011;
+
+/// 服务端主动关闭连接的原因
+#[derive(Debug, Clone, Copy)]
+pub struct CloseReason {
+    pub code: u16,
+    pub reason: &'static str,
+}
+
+/// 在线的 WebSocket 连接,服务端需要断开时通过 `close` 通知连接任务
+struct Connection {
+    user_id: i64,
+    company_id: Option<i64>,
+    /// 登录会话ID,访问令牌建立的连接为空
+    session_id: Option<String>,
+    close: oneshot::Sender<CloseReason>,
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+static CONNECTIONS: LazyLock<Mutex<HashMap<u64, Connection>>> = LazyLock::new(Default::default);
+
+/// 在线连接登记,连接结束时自动移除
+pub struct ConnectionRegistration(u64);
+
+impl ConnectionRegistration {
+    /// 登记连接,返回的接收端在服务端要求断开时收到关闭原因
+    pub fn new(
+        user_id: i64,
+        company_id: Option<i64>,
+        session_id: Option<String>,
+    ) -> (Self, oneshot::Receiver<CloseReason>) {
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        let (close, closed) = oneshot::channel();
+        let connection = Connection {
+            user_id,
+            company_id,
+            session_id,
+            close,
+        };
+        CONNECTIONS.lock().unwrap_or_else(|e| e.into_inner()).insert(id, connection);
+        (Self(id), closed)
+    }
+}
+
+impl Drop for ConnectionRegistration {
+    fn drop(&mut self) {
+        CONNECTIONS.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.0);
+    }
+}
+
+/// 关闭满足条件的连接,返回关闭的连接数
+fn disconnect(matches: impl Fn(&Connection) -> bool, reason: CloseReason) -> usize {
+    let mut connections = CONNECTIONS.lock().unwrap_or_else(|e| e.into_inner());
+    let ids: Vec<u64> = connections
+        .iter()
+        .filter(|(_, connection)| matches(connection))
+        .map(|(id, _)| *id)
+        .collect();
+    for id in &ids {
+        if let Some(connection) = connections.remove(id) {
+            let _ = connection.close.send(reason);
+        }
+    }
+    ids.len()
+}
+
+/// 关闭指定公司用户的全部连接
+pub fn disconnect_company(company_id: i64) -> usize {
+    disconnect(
+        |connection| connection.company_id == Some(company_id),
+        CloseReason {
+            code: CLOSE_COMPANY_INACTIVE,
+            reason: "所属公司已停用",
+        },
+    )
+}
+
+/// 关闭通过指定登录会话建立的连接
+pub fn disconnect_session(session_id: &str) -> usize {
+    disconnect(
+        |connection| connection.session_id.as_deref() == Some(session_id),
+        CloseReason {
+            code: CLOSE_SESSION_REVOKED,
+            reason: "登录会话已撤销",
+        },
+    )
+}
+
+/// 关闭用户除 `except_session` 外所有登录会话建立的连接
+pub fn disconnect_user_sessions(user_id: i64, except_session: Option<&str>) -> usize {
+    disconnect(
+        |connection| {
+            connection.user_id == user_id
+                && connection.session_id.is_some()
+                && connection.session_id.as_deref() != except_session
+        },
+        CloseReason {
+            code: CLOSE_SESSION_REVOKED,
+            reason: "登录会话已撤销",
+        },
+    )
+}
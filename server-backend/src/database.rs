@@ -1,6 +1,8 @@
 use anyhow::Result;
 use sqlx::{Row, SqlitePool};
 
+use crate::utils::{hash_password, join_code::generate_join_code};
+
 #[derive(Debug, Clone)]
 pub struct Database {
     pub pool: SqlitePool,
@@ -202,6 +204,147 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // 创建项目表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS projects (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                description TEXT,
+                status TEXT NOT NULL DEFAULT 'planning',
+                owner_id TEXT,
+                manager_id TEXT,
+                company_id INTEGER,
+                start_date TEXT,
+                end_date TEXT,
+                budget REAL,
+                actual_cost REAL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // 创建任务表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tasks (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL DEFAULT '',
+                status TEXT NOT NULL DEFAULT 'pending',
+                priority TEXT NOT NULL DEFAULT 'medium',
+                company_id INTEGER,
+                project_id TEXT,
+                assigned_to TEXT,
+                created_by TEXT NOT NULL,
+                due_date TEXT,
+                estimated_hours REAL,
+                actual_hours REAL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                completed_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // 创建工作日志表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS work_logs (
+                id TEXT PRIMARY KEY,
+                task_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                hours REAL NOT NULL,
+                notes TEXT,
+                logged_at TEXT NOT NULL DEFAULT (date('now')),
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // 创建任务标签表(任务删除时一并清理)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS task_labels (
+                task_id TEXT NOT NULL,
+                label TEXT NOT NULL,
+                PRIMARY KEY (task_id, label)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_task_labels_label ON task_labels(label)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS task_labels_after_task_delete AFTER DELETE ON tasks
+            BEGIN
+                DELETE FROM task_labels WHERE task_id = OLD.id;
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // 旧库中的项目/任务表可能缺少多租户隔离字段
+        self.ensure_column("projects", "company_id", "INTEGER").await?;
+        self.ensure_column("tasks", "company_id", "INTEGER").await?;
+
+        // 公司级两步验证策略: 要求项目经理启用两步验证
+        self.ensure_column("companies", "require_2fa_for_managers", "BOOLEAN NOT NULL DEFAULT 0").await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_tasks_company_status ON tasks(company_id, status)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_tasks_assigned_to ON tasks(assigned_to)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_projects_company_id ON projects(company_id)")
+            .execute(&self.pool)
+            .await?;
+
+        // 创建统计汇总表(首次创建时需要根据现有数据重建)
+        let rollups_missing = !self.table_exists("stats_daily_company").await?;
+        self.create_statistics_rollups().await?;
+
+        // 创建任务状态流转记录表
+        self.create_task_status_history().await?;
+
+        // 创建登录会话与刷新令牌表
+        self.create_session_tables().await?;
+
+        // 创建登录失败计数表和审计日志表
+        self.create_login_throttles().await?;
+        self.create_audit_log().await?;
+
+        // 创建两步验证相关表
+        self.create_two_factor_tables().await?;
+        self.create_email_tokens().await?;
+        self.create_password_history().await?;
+
+        // 创建个人访问令牌与公司API密钥表
+        self.create_api_tokens().await?;
+
+        // 创建单点登录相关表
+        self.create_sso_tables().await?;
+        self.create_company_roles().await?;
+
+        // 创建成员邀请表
+        self.create_invitations().await?;
+
+        // 个人信息字段加密后按邮箱、手机号查找用的盲索引列
+        self.create_pii_indexes().await?;
+
         // 插入默认系统管理员(如果不存在)
         let admin_exists =
             sqlx::query("SELECT COUNT(*) as count FROM users WHERE role = 'platform_admin'")
@@ -211,7 +354,7 @@ impl Database {
                 > 0;
 
         if !admin_exists {
-            let password_hash = bcrypt::hash("admin123", 12)?;
+            let password_hash = hash_password("admin123")?;
 
             sqlx::query(
                 r#"
@@ -245,10 +388,778 @@ impl Database {
         // 创建测试项目和任务数据
         self.create_test_projects_and_tasks().await?;
 
+        // 回填缺失的company_id,保证统计等多租户查询能正确隔离
+        self.backfill_company_ids().await?;
+
+        // 自助注册用的公司加入码,与单点登录使用的公司代码分开保存
+        self.create_company_join_codes().await?;
+
+        if rollups_missing {
+            self.rebuild_statistics_rollups().await?;
+        }
+
+        // 为没有流转记录的旧任务补录状态流转
+        self.backfill_task_status_history().await?;
+
         tracing::info!("✅ 数据库迁移完成");
         Ok(())
     }
 
+    /// 创建按天汇总的统计表及维护它们的触发器
+    ///
+    /// 汇总表记录每天的增量: 任务状态变化时旧状态计数-1、新状态计数+1,
+    /// 因此对某个维度的所有日期求和即可得到当前的任务数和工时,
+    /// 仪表板查询不再需要扫描 tasks 表。
+    pub async fn create_statistics_rollups(&self) -> Result<()> {
+        for rollup in RollupTable::ALL {
+            let dimension_column = rollup
+                .dimension_column()
+                .map(|column| format!("{} TEXT NOT NULL,", column))
+                .unwrap_or_default();
+
+            sqlx::query(&format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {table} (
+                    company_id INTEGER NOT NULL DEFAULT 0,
+                    {dimension_column}
+                    stat_date TEXT NOT NULL,
+                    pending_delta INTEGER NOT NULL DEFAULT 0,
+                    in_progress_delta INTEGER NOT NULL DEFAULT 0,
+                    completed_delta INTEGER NOT NULL DEFAULT 0,
+                    cancelled_delta INTEGER NOT NULL DEFAULT 0,
+                    estimated_hours_delta REAL NOT NULL DEFAULT 0,
+                    actual_hours_delta REAL NOT NULL DEFAULT 0,
+                    logged_hours REAL NOT NULL DEFAULT 0,
+                    work_log_count INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY ({key})
+                )
+                "#,
+                table = rollup.table(),
+                dimension_column = dimension_column,
+                key = rollup.key_columns(),
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_stats_daily_project_company ON stats_daily_project(company_id)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_stats_daily_user_company ON stats_daily_user(company_id)")
+            .execute(&self.pool)
+            .await?;
+
+        let task_changed = "OLD.status IS NOT NEW.status OR OLD.company_id IS NOT NEW.company_id \
+            OR OLD.project_id IS NOT NEW.project_id OR OLD.assigned_to IS NOT NEW.assigned_to \
+            OR OLD.estimated_hours IS NOT NEW.estimated_hours OR OLD.actual_hours IS NOT NEW.actual_hours";
+        let work_log_changed = "OLD.task_id IS NOT NEW.task_id OR OLD.user_id IS NOT NEW.user_id \
+            OR OLD.hours IS NOT NEW.hours OR OLD.logged_at IS NOT NEW.logged_at";
+
+        let triggers = [
+            ("stats_tasks_after_insert", "AFTER INSERT ON tasks".to_string(), task_rollup_sql("NEW", 1)),
+            (
+                "stats_tasks_after_update",
+                format!("AFTER UPDATE ON tasks WHEN {}", task_changed),
+                format!("{}\n{}", task_rollup_sql("OLD", -1), task_rollup_sql("NEW", 1)),
+            ),
+            ("stats_tasks_after_delete", "AFTER DELETE ON tasks".to_string(), task_rollup_sql("OLD", -1)),
+            (
+                "stats_tasks_work_logs_after_move",
+                "AFTER UPDATE ON tasks WHEN OLD.company_id IS NOT NEW.company_id OR OLD.project_id IS NOT NEW.project_id"
+                    .to_string(),
+                format!("{}\n{}", task_work_logs_rollup_sql("OLD", -1), task_work_logs_rollup_sql("NEW", 1)),
+            ),
+            ("stats_work_logs_after_insert", "AFTER INSERT ON work_logs".to_string(), work_log_rollup_sql("NEW", 1)),
+            (
+                "stats_work_logs_after_update",
+                format!("AFTER UPDATE ON work_logs WHEN {}", work_log_changed),
+                format!("{}\n{}", work_log_rollup_sql("OLD", -1), work_log_rollup_sql("NEW", 1)),
+            ),
+            ("stats_work_logs_after_delete", "AFTER DELETE ON work_logs".to_string(), work_log_rollup_sql("OLD", -1)),
+        ];
+
+        for (name, event, body) in triggers {
+            sqlx::query(&format!("CREATE TRIGGER IF NOT EXISTS {} {} BEGIN\n{}\nEND", name, event, body))
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// 根据 tasks 和 work_logs 表全量重建统计汇总表
+    ///
+    /// 历史的状态变化无法还原,重建后每个任务的当前状态计入其创建日期,
+    /// 工作日志计入其工作日期。
+    pub async fn rebuild_statistics_rollups(&self) -> Result<()> {
+        tracing::info!("🔄 重建统计汇总表");
+
+        let mut tx = self.pool.begin().await?;
+
+        for rollup in RollupTable::ALL {
+            sqlx::query(&format!("DELETE FROM {}", rollup.table()))
+                .execute(&mut *tx)
+                .await?;
+
+            let (dimension_column, task_dimension, task_condition, log_dimension, log_condition, group_by) =
+                match rollup {
+                    RollupTable::Company => ("", "", "1 = 1", "", "1 = 1", "1, 2"),
+                    RollupTable::Project => (
+                        ", project_id",
+                        ", project_id",
+                        "project_id IS NOT NULL AND project_id <> ''",
+                        ", t.project_id",
+                        "t.project_id IS NOT NULL AND t.project_id <> ''",
+                        "1, 2, 3",
+                    ),
+                    RollupTable::User => (
+                        ", user_id",
+                        ", assigned_to",
+                        "assigned_to IS NOT NULL",
+                        ", w.user_id",
+                        "1 = 1",
+                        "1, 2, 3",
+                    ),
+                };
+
+            sqlx::query(&format!(
+                r#"
+                INSERT INTO {table} (company_id{dimension_column}, stat_date,
+                    pending_delta, in_progress_delta, completed_delta, cancelled_delta,
+                    estimated_hours_delta, actual_hours_delta)
+                SELECT COALESCE(company_id, 0){task_dimension}, COALESCE(date(created_at), date('now')),
+                    SUM(status = 'pending'), SUM(status = 'in_progress'),
+                    SUM(status = 'completed'), SUM(status = 'cancelled'),
+                    SUM(COALESCE(estimated_hours, 0)), SUM(COALESCE(actual_hours, 0))
+                FROM tasks
+                WHERE {task_condition}
+                GROUP BY {group_by}
+                "#,
+                table = rollup.table(),
+            ))
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query(&format!(
+                r#"
+                INSERT INTO {table} (company_id{dimension_column}, stat_date, logged_hours, work_log_count)
+                SELECT COALESCE(t.company_id, 0){log_dimension}, COALESCE(date(w.logged_at), date('now')),
+                    SUM(w.hours), COUNT(*)
+                FROM work_logs w
+                LEFT JOIN tasks t ON t.id = w.task_id
+                WHERE {log_condition}
+                GROUP BY {group_by}
+                ON CONFLICT({key}) DO UPDATE SET
+                    logged_hours = logged_hours + excluded.logged_hours,
+                    work_log_count = work_log_count + excluded.work_log_count
+                "#,
+                table = rollup.table(),
+                key = rollup.key_columns(),
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        tracing::info!("✅ 统计汇总表重建完成");
+        Ok(())
+    }
+
+    /// 创建任务状态流转记录表及维护它的触发器
+    ///
+    /// 任务一律视为从待处理(pending)开始: 新建任务记录一条创建流转,
+    /// 若创建时已处于其他状态,再补一条 pending -> 当前状态 的流转。
+    /// 之后每次状态变化都记录一条流转,交付周期等指标据此计算。
+    pub async fn create_task_status_history(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS task_status_transitions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                task_id TEXT NOT NULL,
+                from_status TEXT,
+                to_status TEXT NOT NULL,
+                changed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_task_status_transitions_task ON task_status_transitions(task_id, changed_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let triggers = [
+            r#"
+            CREATE TRIGGER IF NOT EXISTS task_status_after_insert AFTER INSERT ON tasks
+            BEGIN
+                INSERT INTO task_status_transitions (task_id, from_status, to_status, changed_at)
+                VALUES (NEW.id, NULL, 'pending', COALESCE(datetime(NEW.created_at), datetime('now')));
+                INSERT INTO task_status_transitions (task_id, from_status, to_status, changed_at)
+                SELECT NEW.id, 'pending', NEW.status,
+                    COALESCE(datetime(NEW.completed_at), datetime(NEW.updated_at), datetime(NEW.created_at), datetime('now'))
+                WHERE NEW.status <> 'pending';
+            END
+            "#,
+            r#"
+            CREATE TRIGGER IF NOT EXISTS task_status_after_update AFTER UPDATE OF status ON tasks
+            WHEN OLD.status IS NOT NEW.status
+            BEGIN
+                INSERT INTO task_status_transitions (task_id, from_status, to_status, changed_at)
+                VALUES (NEW.id, OLD.status, NEW.status, datetime('now'));
+            END
+            "#,
+            r#"
+            CREATE TRIGGER IF NOT EXISTS task_status_after_delete AFTER DELETE ON tasks
+            BEGIN
+                DELETE FROM task_status_transitions WHERE task_id = OLD.id;
+            END
+            "#,
+        ];
+
+        for trigger in triggers {
+            sqlx::query(trigger).execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 创建登录会话与刷新令牌表
+    ///
+    /// 每次登录创建一个会话,访问令牌通过 `sid` 关联会话;
+    /// 刷新令牌只保存 SHA-256 摘要,每次刷新后旧令牌作废并由新令牌替换,
+    /// 同一会话下的所有刷新令牌构成一个令牌族,会话撤销即整族失效。
+    pub async fn create_session_tables(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                last_used_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME NOT NULL,
+                revoked_at DATETIME,
+                revoked_reason TEXT,
+                user_agent TEXT,
+                ip_address TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        // 模拟登录会话记录代为操作的管理员
+        self.ensure_column("sessions", "impersonator_id", "INTEGER").await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS refresh_tokens (
+                token_hash TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME NOT NULL,
+                used_at DATETIME,
+                replaced_by TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_refresh_tokens_session ON refresh_tokens(session_id)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 创建登录失败计数表
+    ///
+    /// `key` 为 `user:<用户ID>`、`login:<登录名>`(账号不存在时) 或 `ip:<IP地址>`,
+    /// 登录成功后清除对应账号的计数。
+    pub async fn create_login_throttles(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS login_throttles (
+                key TEXT PRIMARY KEY,
+                failures INTEGER NOT NULL DEFAULT 0,
+                last_failed_at DATETIME,
+                locked_until DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// 创建审计日志表(只追加)
+    ///
+    /// 每条记录保存上一条记录的哈希(`prev_hash`)和本条内容的哈希(`hash`),组成防篡改的哈希链;
+    /// 触发器拒绝修改和删除已写入的记录。
+    pub async fn create_audit_log(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS audit_logs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                occurred_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                action TEXT NOT NULL,
+                actor_id INTEGER,
+                company_id INTEGER,
+                target_type TEXT,
+                target_id TEXT,
+                ip_address TEXT,
+                details TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        for (column, definition) in [
+            ("impersonator_id", "INTEGER"),
+            ("user_agent", "TEXT"),
+            ("request_id", "TEXT"),
+            ("prev_hash", "TEXT"),
+            ("hash", "TEXT"),
+        ] {
+            self.ensure_column("audit_logs", column, definition).await?;
+        }
+        for ddl in [
+            "CREATE INDEX IF NOT EXISTS idx_audit_logs_company ON audit_logs(company_id, occurred_at)",
+            "CREATE INDEX IF NOT EXISTS idx_audit_logs_target ON audit_logs(target_type, target_id)",
+            r#"
+            CREATE TRIGGER IF NOT EXISTS audit_logs_no_update BEFORE UPDATE ON audit_logs
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_logs is append-only');
+            END
+            "#,
+            r#"
+            CREATE TRIGGER IF NOT EXISTS audit_logs_no_delete BEFORE DELETE ON audit_logs
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_logs is append-only');
+            END
+            "#,
+        ] {
+            sqlx::query(ddl).execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 创建两步验证(TOTP)相关表
+    ///
+    /// - `user_two_factor`: 用户密钥,`enabled_at` 为空表示已生成密钥但尚未确认;
+    ///   `last_used_step` 记录最近一次通过的时间步,拒绝同一验证码重复使用
+    /// - `two_factor_recovery_codes`: 恢复码摘要,每个只能使用一次
+    /// - `login_challenges`: 密码校验通过、等待输入验证码的登录,只保存令牌摘要
+    pub async fn create_two_factor_tables(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS user_two_factor (
+                user_id INTEGER PRIMARY KEY,
+                secret TEXT NOT NULL,
+                enabled_at DATETIME,
+                last_used_step INTEGER,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS two_factor_recovery_codes (
+                user_id INTEGER NOT NULL,
+                code_hash TEXT NOT NULL,
+                used_at DATETIME,
+                PRIMARY KEY (user_id, code_hash)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS login_challenges (
+                token_hash TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// 邮箱验证、找回密码的一次性令牌(只保存哈希)
+    pub async fn create_email_tokens(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS email_tokens (
+                token_hash TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                purpose TEXT NOT NULL,
+                email TEXT NOT NULL,
+                created_at DATETIME NOT NULL,
+                expires_at DATETIME NOT NULL,
+                used_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_email_tokens_user ON email_tokens(user_id, purpose)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 个人访问令牌与公司API密钥(只保存 SHA-256 摘要)
+    ///
+    /// `scopes` 为空格分隔的授权范围;公司密钥以创建人的身份访问,
+    /// 创建人被禁用或不再是该公司管理者后密钥随即失效。
+    pub async fn create_api_tokens(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_tokens (
+                id TEXT PRIMARY KEY,
+                kind TEXT NOT NULL,
+                name TEXT NOT NULL,
+                token_hash TEXT NOT NULL UNIQUE,
+                token_prefix TEXT NOT NULL,
+                scopes TEXT NOT NULL,
+                user_id INTEGER NOT NULL,
+                company_id INTEGER,
+                created_at DATETIME NOT NULL,
+                expires_at DATETIME,
+                last_used_at DATETIME,
+                last_used_ip TEXT,
+                revoked_at DATETIME,
+                revoked_by INTEGER
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens(user_id)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_api_tokens_company ON api_tokens(company_id, kind)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 单点登录(OpenID Connect)相关表
+    ///
+    /// `company_sso_configs` 每个公司一条身份提供方配置,`role_mapping` 为声明值到角色的 JSON 对象;
+    /// `sso_login_states` 保存授权请求的 state(只存摘要)、PKCE code_verifier 和 nonce,一次性使用;
+    /// `user_identities` 记录外部身份(issuer + sub)与本地用户的关联。
+    pub async fn create_sso_tables(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS company_sso_configs (
+                company_id INTEGER PRIMARY KEY,
+                issuer TEXT NOT NULL,
+                client_id TEXT NOT NULL,
+                client_secret TEXT,
+                scopes TEXT NOT NULL,
+                role_claim TEXT,
+                role_mapping TEXT NOT NULL DEFAULT '{}',
+                default_role TEXT NOT NULL,
+                jit_provisioning BOOLEAN NOT NULL DEFAULT 1,
+                enabled BOOLEAN NOT NULL DEFAULT 1,
+                updated_at DATETIME NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sso_login_states (
+                state_hash TEXT PRIMARY KEY,
+                company_id INTEGER NOT NULL,
+                code_verifier TEXT NOT NULL,
+                nonce TEXT NOT NULL,
+                link_user_id INTEGER,
+                created_at DATETIME NOT NULL,
+                expires_at DATETIME NOT NULL,
+                used_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS user_identities (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                company_id INTEGER NOT NULL,
+                issuer TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                email TEXT,
+                created_at DATETIME NOT NULL,
+                last_login_at DATETIME,
+                UNIQUE (issuer, subject)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_user_identities_user ON user_identities(user_id)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 公司自定义角色
+    ///
+    /// `permissions` 为权限名称的 JSON 数组;用户的 `custom_role_id` 指向该表,
+    /// 设置后用户的权限以自定义角色为准,否则使用内置角色(`role` 列)的权限组合。
+    pub async fn create_company_roles(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS company_roles (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                company_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT,
+                permissions TEXT NOT NULL DEFAULT '[]',
+                created_at DATETIME NOT NULL,
+                updated_at DATETIME NOT NULL,
+                UNIQUE (company_id, name)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.ensure_column("users", "custom_role_id", "INTEGER").await?;
+
+        Ok(())
+    }
+
+    /// 成员邀请(只保存邀请令牌的 SHA-256 摘要)
+    ///
+    /// 未接受、未撤销且未过期的邀请占用公司名额;接受后 `accepted_user_id` 指向新建的用户。
+    pub async fn create_invitations(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS invitations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                company_id INTEGER NOT NULL,
+                email TEXT NOT NULL,
+                email_index TEXT,
+                role TEXT NOT NULL,
+                full_name TEXT,
+                token_hash TEXT NOT NULL UNIQUE,
+                invited_by INTEGER NOT NULL,
+                created_at DATETIME NOT NULL,
+                expires_at DATETIME NOT NULL,
+                accepted_at DATETIME,
+                accepted_user_id INTEGER,
+                revoked_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // 邀请邮箱加密存储,按盲索引查找同一邮箱的待处理邀请
+        self.ensure_column("invitations", "email_index", "TEXT").await?;
+        for ddl in [
+            "CREATE INDEX IF NOT EXISTS idx_invitations_company ON invitations(company_id, email)",
+            "CREATE INDEX IF NOT EXISTS idx_invitations_email_index ON invitations(company_id, email_index)",
+        ] {
+            sqlx::query(ddl).execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 加密字段的盲索引列(HMAC-SHA256,见 `utils::pii`)
+    ///
+    /// 邮箱和手机号加密后无法直接比较,登录和查重改按盲索引查找;
+    /// 尚未被重新加密任务处理的旧数据仍按明文列匹配。
+    pub async fn create_pii_indexes(&self) -> Result<()> {
+        self.ensure_column("users", "email_index", "TEXT").await?;
+        self.ensure_column("users", "phone_index", "TEXT").await?;
+        for ddl in [
+            "CREATE INDEX IF NOT EXISTS idx_users_email_index ON users(email_index)",
+            "CREATE INDEX IF NOT EXISTS idx_users_phone_index ON users(phone_index)",
+        ] {
+            sqlx::query(ddl).execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 用户用过的旧密码哈希,用于禁止重复使用最近的密码
+    pub async fn create_password_history(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS password_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                hashed_password TEXT NOT NULL,
+                created_at DATETIME NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_password_history_user ON password_history(user_id, id)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 为触发器创建之前已存在的任务补录状态流转(规则与新建任务相同)
+    async fn backfill_task_status_history(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let backfilled = sqlx::query(
+            r#"
+            INSERT INTO task_status_transitions (task_id, from_status, to_status, changed_at)
+            SELECT t.id, NULL, 'pending', COALESCE(datetime(t.created_at), datetime('now'))
+            FROM tasks t
+            WHERE NOT EXISTS (SELECT 1 FROM task_status_transitions h WHERE h.task_id = t.id)
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if backfilled > 0 {
+            sqlx::query(
+                r#"
+                INSERT INTO task_status_transitions (task_id, from_status, to_status, changed_at)
+                SELECT t.id, 'pending', t.status,
+                    COALESCE(datetime(t.completed_at), datetime(t.updated_at), datetime(t.created_at), datetime('now'))
+                FROM tasks t
+                WHERE t.status <> 'pending'
+                  AND NOT EXISTS (
+                      SELECT 1 FROM task_status_transitions h
+                      WHERE h.task_id = t.id AND h.from_status IS NOT NULL
+                  )
+                "#,
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            tracing::info!("✅ 已为 {} 个任务补录状态流转记录", backfilled);
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 检查表是否存在
+    async fn table_exists(&self, table: &str) -> Result<bool> {
+        let count = sqlx::query("SELECT COUNT(*) as count FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(table)
+            .fetch_one(&self.pool)
+            .await?
+            .get::<i64, _>("count");
+        Ok(count > 0)
+    }
+
+    /// 检查表中是否存在指定列
+    async fn column_exists(&self, table: &str, column: &str) -> Result<bool> {
+        let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(columns
+            .iter()
+            .any(|row| row.get::<String, _>("name") == column))
+    }
+
+    /// 为旧表补齐新增的列(SQLite不支持 ADD COLUMN IF NOT EXISTS)
+    async fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        if !self.column_exists(table, column).await? {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
+            tracing::info!("✅ 已为 {} 表添加 {} 列", table, column);
+        }
+        Ok(())
+    }
+
+    /// 公司加入码列(唯一索引),为还没有加入码的公司生成一个
+    ///
+    /// `code` 是单点登录入口使用的公司代码,重新生成加入码不能影响它。
+    pub async fn create_company_join_codes(&self) -> Result<()> {
+        self.ensure_column("companies", "join_code", "TEXT").await?;
+        sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_companies_join_code ON companies(join_code)")
+            .execute(&self.pool)
+            .await?;
+
+        let missing: Vec<i64> = sqlx::query_scalar("SELECT id FROM companies WHERE join_code IS NULL OR join_code = ''")
+            .fetch_all(&self.pool)
+            .await?;
+        for company_id in &missing {
+            sqlx::query("UPDATE companies SET join_code = ? WHERE id = ?")
+                .bind(generate_join_code())
+                .bind(company_id)
+                .execute(&self.pool)
+                .await?;
+        }
+        if !missing.is_empty() {
+            tracing::info!("✅ 已为 {} 个公司生成加入码", missing.len());
+        }
+
+        Ok(())
+    }
+
+    /// 按创建者/负责人所属公司回填任务和项目的company_id
+    async fn backfill_company_ids(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE tasks
+            SET company_id = (SELECT u.company_id FROM users u WHERE u.id = tasks.created_by)
+            WHERE company_id IS NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if self.column_exists("projects", "owner_id").await? {
+            sqlx::query(
+                r#"
+                UPDATE projects
+                SET company_id = (SELECT u.company_id FROM users u WHERE u.id = projects.owner_id)
+                WHERE company_id IS NULL
+                "#,
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
     async fn create_test_pricing_rules(&self) -> Result<()> {
         tracing::info!("🔄 创建测试价格规则数据");
 
@@ -294,7 +1205,7 @@ impl Database {
     async fn create_test_users(&self) -> Result<()> {
         tracing::info!("🔄 创建测试用户数据");
 
-        let password_hash = bcrypt::hash("admin123", 12)?;
+        let password_hash = hash_password("admin123")?;
 
         // 检查是否已存在company_admin_1用户
         let company_admin_exists =
@@ -852,3 +1763,153 @@ impl Database {
         Ok(())
     }
 }
+
+/// 按天汇总的统计表: 分别按公司、项目、员工维度汇总
+#[derive(Debug, Clone, Copy)]
+enum RollupTable {
+    Company,
+    Project,
+    User,
+}
+
+impl RollupTable {
+    const ALL: [RollupTable; 3] = [RollupTable::Company, RollupTable::Project, RollupTable::User];
+
+    fn table(&self) -> &'static str {
+        match self {
+            RollupTable::Company => "stats_daily_company",
+            RollupTable::Project => "stats_daily_project",
+            RollupTable::User => "stats_daily_user",
+        }
+    }
+
+    /// company_id 之外的维度列
+    fn dimension_column(&self) -> Option<&'static str> {
+        match self {
+            RollupTable::Company => None,
+            RollupTable::Project => Some("project_id"),
+            RollupTable::User => Some("user_id"),
+        }
+    }
+
+    /// 主键列(也是 UPSERT 的冲突目标)
+    fn key_columns(&self) -> String {
+        match self.dimension_column() {
+            Some(column) => format!("company_id, {}, stat_date", column),
+            None => "company_id, stat_date".to_string(),
+        }
+    }
+
+    /// 生成一条把 `metrics` 累加到当天汇总行的 UPSERT 语句
+    ///
+    /// `dimension` 为维度值表达式及其生效条件,公司维度汇总表忽略该参数。
+    fn upsert_sql(
+        &self,
+        date: &str,
+        company: &str,
+        dimension: (&str, &str),
+        metrics: &[(&str, String)],
+    ) -> String {
+        self.upsert_from_sql(date, company, dimension, metrics, ("", "1 = 1", ""))
+    }
+
+    /// 与 `upsert_sql` 相同,但从 `source` 的 (`FROM` 子句, 过滤条件, `GROUP BY` 子句) 中分组汇总后累加
+    fn upsert_from_sql(
+        &self,
+        date: &str,
+        company: &str,
+        dimension: (&str, &str),
+        metrics: &[(&str, String)],
+        source: (&str, &str, &str),
+    ) -> String {
+        let (source, filter, group_by) = source;
+        let (dimension_column, dimension_value, condition) = match self.dimension_column() {
+            Some(column) => (format!(", {}", column), format!(", {}", dimension.0), dimension.1),
+            None => (String::new(), String::new(), "1 = 1"),
+        };
+        let columns: Vec<&str> = metrics.iter().map(|(column, _)| *column).collect();
+        let values: Vec<&str> = metrics.iter().map(|(_, value)| value.as_str()).collect();
+        let updates: Vec<String> = columns
+            .iter()
+            .map(|column| format!("{0} = {0} + excluded.{0}", column))
+            .collect();
+
+        format!(
+            "INSERT INTO {table} (company_id{dimension_column}, stat_date, {columns}) \
+             SELECT {company}{dimension_value}, {date}, {values} {source} WHERE {filter} AND {condition} {group_by} \
+             ON CONFLICT({key}) DO UPDATE SET {updates};",
+            table = self.table(),
+            columns = columns.join(", "),
+            values = values.join(", "),
+            key = self.key_columns(),
+            updates = updates.join(", "),
+        )
+    }
+}
+
+/// 任务行(NEW/OLD)计入或移出汇总表的语句, `sign` 为 1 或 -1
+fn task_rollup_sql(row: &str, sign: i32) -> String {
+    let metrics = [
+        ("pending_delta", format!("{} * ({}.status = 'pending')", sign, row)),
+        ("in_progress_delta", format!("{} * ({}.status = 'in_progress')", sign, row)),
+        ("completed_delta", format!("{} * ({}.status = 'completed')", sign, row)),
+        ("cancelled_delta", format!("{} * ({}.status = 'cancelled')", sign, row)),
+        ("estimated_hours_delta", format!("{} * COALESCE({}.estimated_hours, 0)", sign, row)),
+        ("actual_hours_delta", format!("{} * COALESCE({}.actual_hours, 0)", sign, row)),
+    ];
+    let company = format!("COALESCE({}.company_id, 0)", row);
+    let project = format!("{}.project_id", row);
+    let project_condition = format!("{0} IS NOT NULL AND {0} <> ''", project);
+    let assignee = format!("{}.assigned_to", row);
+    let assignee_condition = format!("{} IS NOT NULL", assignee);
+
+    [
+        RollupTable::Company.upsert_sql("date('now')", &company, ("", ""), &metrics),
+        RollupTable::Project.upsert_sql("date('now')", &company, (&project, &project_condition), &metrics),
+        RollupTable::User.upsert_sql("date('now')", &company, (&assignee, &assignee_condition), &metrics),
+    ]
+    .join("\n")
+}
+
+/// 任务(NEW/OLD)名下全部工作日志计入或移出汇总表的语句, `sign` 为 1 或 -1
+///
+/// 任务改变所属项目或公司时,已记录的工时要从原项目/公司移到新的项目/公司。
+fn task_work_logs_rollup_sql(row: &str, sign: i32) -> String {
+    let metrics = [
+        ("logged_hours", format!("{} * SUM(w.hours)", sign)),
+        ("work_log_count", format!("{} * COUNT(*)", sign)),
+    ];
+    let date = "COALESCE(date(w.logged_at), date('now'))";
+    let company = format!("COALESCE({}.company_id, 0)", row);
+    let project = format!("{}.project_id", row);
+    let project_condition = format!("{0} IS NOT NULL AND {0} <> ''", project);
+    let filter = format!("w.task_id = {}.id", row);
+    let source = |group_by| ("FROM work_logs w", filter.as_str(), group_by);
+
+    [
+        RollupTable::Company.upsert_from_sql(date, &company, ("", ""), &metrics, source("GROUP BY 2")),
+        RollupTable::Project.upsert_from_sql(date, &company, (&project, &project_condition), &metrics, source("GROUP BY 3")),
+        RollupTable::User.upsert_from_sql(date, &company, ("w.user_id", "1 = 1"), &metrics, source("GROUP BY 2, 3")),
+    ]
+    .join("\n")
+}
+
+/// 工作日志行(NEW/OLD)计入或移出汇总表的语句, `sign` 为 1 或 -1
+fn work_log_rollup_sql(row: &str, sign: i32) -> String {
+    let metrics = [
+        ("logged_hours", format!("{} * {}.hours", sign, row)),
+        ("work_log_count", sign.to_string()),
+    ];
+    let date = format!("COALESCE(date({}.logged_at), date('now'))", row);
+    let company = format!("COALESCE((SELECT company_id FROM tasks WHERE id = {}.task_id), 0)", row);
+    let project = format!("(SELECT project_id FROM tasks WHERE id = {}.task_id)", row);
+    let project_condition = format!("COALESCE({}, '') <> ''", project);
+    let user = format!("{}.user_id", row);
+
+    [
+        RollupTable::Company.upsert_sql(&date, &company, ("", ""), &metrics),
+        RollupTable::Project.upsert_sql(&date, &company, (&project, &project_condition), &metrics),
+        RollupTable::User.upsert_sql(&date, &company, (&user, "1 = 1"), &metrics),
+    ]
+    .join("\n")
+}
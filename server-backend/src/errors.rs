@@ -6,6 +6,8 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::models::Permission;
+
 /// 统一的错误响应格式
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
@@ -52,6 +54,10 @@ pub mod error_codes {
     pub const AUTH_FORBIDDEN: u32 = 1005;
     pub const AUTH_USER_NOT_FOUND: u32 = 1006;
     pub const AUTH_DUPLICATE_USERNAME: u32 = 1007;
+    pub const AUTH_TOO_MANY_ATTEMPTS: u32 = 1008;
+    pub const AUTH_INVALID_TWO_FACTOR_CODE: u32 = 1009;
+    pub const AUTH_INSUFFICIENT_SCOPE: u32 = 1010;
+    pub const AUTH_COMPANY_INACTIVE: u32 = 1011;
 
     // 数据验证错误 (2000-2999)
     pub const VALIDATION_INVALID_INPUT: u32 = 2001;
@@ -73,6 +79,8 @@ pub mod error_codes {
     pub const BUSINESS_DEVICE_NOT_FOUND: u32 = 4004;
     pub const BUSINESS_INVALID_STATE: u32 = 4005;
     pub const BUSINESS_OPERATION_NOT_ALLOWED: u32 = 4006;
+    pub const BUSINESS_SEAT_LIMIT_EXCEEDED: u32 = 4007;
+    pub const BUSINESS_RATE_LIMITED: u32 = 4008;
 
     // 外部服务错误 (5000-5999)
     pub const EXTERNAL_SERVICE_UNAVAILABLE: u32 = 5001;
@@ -102,12 +110,27 @@ pub enum AppError {
     #[error("权限不足")]
     Forbidden,
 
+    #[error("权限不足: 需要 {0} 权限")]
+    MissingPermission(Permission),
+
     #[error("用户不存在: {0}")]
     UserNotFound(String),
 
     #[error("用户名已存在: {0}")]
     DuplicateUsername(String),
 
+    #[error("登录尝试过于频繁,请在{0}秒后重试")]
+    TooManyAttempts(i64),
+
+    #[error("验证码错误")]
+    InvalidTwoFactorCode,
+
+    #[error("访问令牌无权访问该接口: {0}")]
+    InsufficientScope(String),
+
+    #[error("所属公司已停用,请联系平台管理员")]
+    CompanyInactive,
+
     // 数据验证错误
     #[error("输入数据无效: {0}")]
     InvalidInput(String),
@@ -162,6 +185,12 @@ pub enum AppError {
     #[error("操作不允许: {0}")]
     OperationNotAllowed(String),
 
+    #[error("公司人数已达上限({0}人)")]
+    SeatLimitExceeded(i64),
+
+    #[error("请求过于频繁,请在{0}秒后重试")]
+    RateLimited(i64),
+
     // 外部服务错误
     #[error("外部服务不可用: {0}")]
     ServiceUnavailable(String),
@@ -187,9 +216,13 @@ impl AppError {
             AppError::TokenExpired => AUTH_TOKEN_EXPIRED,
             AppError::TokenInvalid => AUTH_TOKEN_INVALID,
             AppError::Unauthorized => AUTH_UNAUTHORIZED,
-            AppError::Forbidden => AUTH_FORBIDDEN,
+            AppError::Forbidden | AppError::MissingPermission(_) => AUTH_FORBIDDEN,
             AppError::UserNotFound(_) => AUTH_USER_NOT_FOUND,
             AppError::DuplicateUsername(_) => AUTH_DUPLICATE_USERNAME,
+            AppError::TooManyAttempts(_) => AUTH_TOO_MANY_ATTEMPTS,
+            AppError::InvalidTwoFactorCode => AUTH_INVALID_TWO_FACTOR_CODE,
+            AppError::InsufficientScope(_) => AUTH_INSUFFICIENT_SCOPE,
+            AppError::CompanyInactive => AUTH_COMPANY_INACTIVE,
 
             // 数据验证
             AppError::InvalidInput(_) => VALIDATION_INVALID_INPUT,
@@ -213,6 +246,8 @@ impl AppError {
             AppError::DeviceNotFound(_) => BUSINESS_DEVICE_NOT_FOUND,
             AppError::InvalidState(_) => BUSINESS_INVALID_STATE,
             AppError::OperationNotAllowed(_) => BUSINESS_OPERATION_NOT_ALLOWED,
+            AppError::SeatLimitExceeded(_) => BUSINESS_SEAT_LIMIT_EXCEEDED,
+            AppError::RateLimited(_) => BUSINESS_RATE_LIMITED,
 
             // 外部服务
             AppError::ServiceUnavailable(_) => EXTERNAL_SERVICE_UNAVAILABLE,
@@ -231,9 +266,16 @@ impl AppError {
             AppError::InvalidCredentials
             | AppError::TokenExpired
             | AppError::TokenInvalid
-            | AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            | AppError::Unauthorized
+            | AppError::InvalidTwoFactorCode => StatusCode::UNAUTHORIZED,
+
+            AppError::Forbidden
+            | AppError::MissingPermission(_)
+            | AppError::InsufficientScope(_)
+            | AppError::CompanyInactive => StatusCode::FORBIDDEN,
 
-            AppError::Forbidden => StatusCode::FORBIDDEN,
+            // 登录尝试过多、请求超出频率限制 - 429
+            AppError::TooManyAttempts(_) | AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
 
             // 数据验证 - 400
             AppError::InvalidInput(_)
@@ -253,7 +295,8 @@ impl AppError {
             // 冲突 - 409
             AppError::Conflict(_)
             | AppError::DuplicateUsername(_)
-            | AppError::DeviceLimitExceeded => StatusCode::CONFLICT,
+            | AppError::DeviceLimitExceeded
+            | AppError::SeatLimitExceeded(_) => StatusCode::CONFLICT,
 
             // 业务逻辑错误 - 422
             AppError::InsufficientBalance | AppError::ConstraintViolation(_) => {
@@ -290,6 +333,15 @@ impl IntoResponse for AppError {
         let status_code = self.status_code();
         let error_response = self.to_error_response();
 
+        if let AppError::TooManyAttempts(retry_after) | AppError::RateLimited(retry_after) = self {
+            return (
+                status_code,
+                [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+                Json(error_response),
+            )
+                .into_response();
+        }
+
         (status_code, Json(error_response)).into_response()
     }
 }
@@ -316,6 +368,11 @@ impl From<sqlx::Error> for AppError {
 
 impl From<anyhow::Error> for AppError {
     fn from(err: anyhow::Error) -> Self {
+        // 经 anyhow 传递的业务错误保留原有的错误代码和状态码
+        let err = match err.downcast::<AppError>() {
+            Ok(app_error) => return app_error,
+            Err(err) => err,
+        };
         // 在开发环境记录详细错误
         tracing::error!("Anyhow error: {:?}", err);
         AppError::Internal(err.to_string())
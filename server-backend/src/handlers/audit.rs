@@ -0,0 +1,70 @@
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+
+use crate::{
+    errors::AppError,
+    middleware::{auth::AuthContext, client::ClientInfo},
+    models::{ApiResponse, AuditChainStatus, AuditLogEntry, AuditLogQuery},
+    services::audit::AuditService,
+    Config, Database,
+};
+
+type AppState = (Database, Config);
+
+/// 查询审计日志(平台管理员查看全部,其他有权限的用户只能查看本公司)
+/// GET /api/v1/audit-logs?action=&actor_id=&company_id=&target_type=&target_id=&from=&to=&before_id=&limit=
+pub async fn list_audit_logs(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<ApiResponse<Vec<AuditLogEntry>>>, AppError> {
+    let entries = AuditService::new(database)
+        .query(&query, &auth_context.user)
+        .await?;
+
+    Ok(Json(ApiResponse::success(entries)))
+}
+
+/// 导出审计日志,查询条件同上
+/// GET /api/v1/audit-logs/export?format=csv|jsonl
+pub async fn export_audit_logs(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let (content, content_type) = AuditService::new(database)
+        .export(&query, &auth_context.user, &client)
+        .await?;
+    let extension = if content_type.starts_with("text/csv") { "csv" } else { "jsonl" };
+    let filename = format!(
+        "audit-logs-{}.{}",
+        chrono::Utc::now().format("%Y%m%d%H%M%S"),
+        extension
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        content,
+    ))
+}
+
+/// 校验审计日志哈希链(仅平台管理员)
+/// GET /api/v1/audit-logs/verify
+pub async fn verify_audit_logs(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<Json<ApiResponse<AuditChainStatus>>, AppError> {
+    let status = AuditService::new(database)
+        .verify_chain(&auth_context.user)
+        .await?;
+
+    Ok(Json(ApiResponse::success(status)))
+}
@@ -1,11 +1,27 @@
-use axum::{extract::{Json, State}, response::Json as ResponseJson};
+use axum::{extract::{Json, Path, State}, http::header, response::{IntoResponse, Json as ResponseJson}};
 use validator::Validate;
 
 use crate::{
     errors::AppError,
-    middleware::auth::AuthContext,
-    models::{ApiResponse, CreateUserRequest, LoginRequest, LoginResponse, UserInfo},
-    services::auth::AuthService,
+    middleware::{auth::AuthContext, client::ClientInfo},
+    models::{
+        ApiResponse, ApiTokenInfo, ChangePasswordRequest, CreateApiTokenRequest, CreatedApiToken,
+        CurrentUserInfo, PublicRegistrationPolicy, RegisterRequest, RegistrationPolicy,
+        LoginRequest, LoginResponse, PasswordResetRequest, SsoAuthorization, SsoCallbackRequest, SsoStartRequest, UserIdentityInfo, RefreshTokenRequest, ResetPasswordRequest, TokenPair, TwoFactorChallengeRequest, TwoFactorCodeRequest, TwoFactorEnrollment,
+        TwoFactorPolicyRequest, TwoFactorStatus, UserInfo, VerifyEmailRequest, VerifyTwoFactorRequest,
+    },
+    services::{
+        account_email::AccountEmailService,
+        api_token::ApiTokenService,
+        auth::AuthService,
+        impersonation::ImpersonationService,
+        password::PasswordService,
+        registration::RegistrationService,
+        session::{SessionService, REVOKED_LOGOUT},
+        sso::SsoService,
+        two_factor::TwoFactorService,
+    },
+    utils::jwt::JwtKeys,
     Config, Database,
 };
 
@@ -13,6 +29,7 @@ type AppState = (Database, Config);
 
 pub async fn login(
     State((database, config)): State<AppState>,
+    client: ClientInfo,
     Json(request): Json<LoginRequest>,
 ) -> Result<ResponseJson<ApiResponse<LoginResponse>>, AppError> {
     // 验证输入
@@ -20,7 +37,7 @@ pub async fn login(
 
     let auth_service = AuthService::new(database, config);
     let response = auth_service
-        .login(&request.username, &request.password)
+        .login(&request.username, &request.password, &client)
         .await?;
 
     Ok(ResponseJson(ApiResponse::success(response)))
@@ -28,39 +45,455 @@ pub async fn login(
 
 pub async fn register(
     State((database, config)): State<AppState>,
-    Json(request): Json<CreateUserRequest>,
+    client: ClientInfo,
+    Json(request): Json<RegisterRequest>,
 ) -> Result<ResponseJson<ApiResponse<UserInfo>>, AppError> {
     // 验证输入
     request.validate()?;
+    PasswordService::new(database.clone(), config.clone())
+        .check_new_user_password(&request.password, &request.username)?;
 
-    let auth_service = AuthService::new(database, config);
-    let user = auth_service.register(request).await?;
+    let auth_service = AuthService::new(database.clone(), config.clone());
+    let user = auth_service.register(request, &client).await?;
+
+    // 验证邮件发送失败不影响注册,用户可稍后重新发送
+    let sent = match AccountEmailService::new(database, config) {
+        Ok(service) => service.send_verification(&user).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        tracing::warn!("⚠️ 发送邮箱验证邮件失败 (用户 {}): {}", user.id, e);
+    }
 
     Ok(ResponseJson(ApiResponse::success(user)))
 }
 
 pub async fn get_current_user(
+    State((database, config)): State<AppState>,
     auth_context: AuthContext,
-) -> Result<ResponseJson<ApiResponse<UserInfo>>, AppError> {
-    Ok(ResponseJson(ApiResponse::success(auth_context.user)))
+) -> Result<ResponseJson<ApiResponse<CurrentUserInfo>>, AppError> {
+    // 模拟登录时返回实际操作的管理员,前端据此显示模拟提示
+    let impersonator = match (auth_context.impersonator_id, auth_context.claims.sid.as_deref()) {
+        (Some(_), Some(session_id)) => {
+            ImpersonationService::new(database, config).impersonator(session_id).await?
+        }
+        _ => None,
+    };
+
+    Ok(ResponseJson(ApiResponse::success(CurrentUserInfo {
+        user: auth_context.user,
+        impersonator,
+    })))
 }
 
-pub async fn refresh_token(
+/// 结束模拟登录,撤销模拟会话
+/// POST /api/v1/auth/impersonation/end
+pub async fn end_impersonation(
     State((database, config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
 ) -> Result<ResponseJson<ApiResponse<String>>, AppError> {
-    let auth_service = AuthService::new(database, config);
-    let token = auth_service
-        .refresh_token(&auth_context.user.id.to_string())
+    let (Some(impersonator_id), Some(session_id)) = (auth_context.impersonator_id, auth_context.claims.sid.as_deref())
+    else {
+        return Err(AppError::InvalidState("当前不是模拟登录".to_string()));
+    };
+    ImpersonationService::new(database, config)
+        .end(session_id, &auth_context.user, impersonator_id, &client)
         .await?;
 
-    Ok(ResponseJson(ApiResponse::success(token)))
+    Ok(ResponseJson(ApiResponse::success("已结束模拟登录".to_string())))
+}
+
+/// 访问令牌的验证公钥(JWK Set),供其他服务验证令牌
+/// GET /.well-known/jwks.json
+pub async fn jwks(State((_database, config)): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let keys = JwtKeys::from_config(&config)
+        .map_err(|e| AppError::Internal(format!("加载 JWT 密钥失败: {}", e)))?;
+
+    Ok(([(header::CACHE_CONTROL, "public, max-age=300")], ResponseJson(keys.jwks())))
 }
 
+/// 用刷新令牌换取新的访问令牌和刷新令牌
+/// POST /api/v1/auth/refresh
+pub async fn refresh_token(
+    State((database, config)): State<AppState>,
+    Json(request): Json<RefreshTokenRequest>,
+) -> Result<ResponseJson<ApiResponse<TokenPair>>, AppError> {
+    request.validate()?;
+
+    let tokens = SessionService::new(database, config)
+        .refresh(&request.refresh_token)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(tokens)))
+}
+
+/// 注销: 撤销当前会话,会话下的访问令牌和刷新令牌立即失效
+/// POST /api/v1/auth/logout
 pub async fn logout(
-    State((_database, _config)): State<AppState>,
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
 ) -> Result<ResponseJson<ApiResponse<String>>, AppError> {
-    // 对于JWT token，logout通常在前端处理（删除token）
-    // 这里只是返回成功响应
+    if let Some(session_id) = auth_context.claims.sid.as_deref() {
+        SessionService::new(database, config)
+            .revoke_session(session_id, REVOKED_LOGOUT)
+            .await?;
+    }
+
     Ok(ResponseJson(ApiResponse::success("注销成功".to_string())))
 }
+
+/// 两步验证登录第二步: 提交验证码或恢复码
+/// POST /api/v1/auth/2fa/verify
+pub async fn verify_two_factor(
+    State((database, config)): State<AppState>,
+    client: ClientInfo,
+    Json(request): Json<VerifyTwoFactorRequest>,
+) -> Result<ResponseJson<ApiResponse<LoginResponse>>, AppError> {
+    request.validate()?;
+
+    let response = AuthService::new(database, config)
+        .verify_two_factor(&request.challenge_token, &request.code, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(response)))
+}
+
+/// 策略要求但尚未启用两步验证的用户在登录过程中注册验证器
+/// POST /api/v1/auth/2fa/challenge/enroll
+pub async fn enroll_two_factor_challenge(
+    State((database, config)): State<AppState>,
+    Json(request): Json<TwoFactorChallengeRequest>,
+) -> Result<ResponseJson<ApiResponse<TwoFactorEnrollment>>, AppError> {
+    request.validate()?;
+
+    let enrollment = AuthService::new(database, config)
+        .enroll_two_factor_for_challenge(&request.challenge_token)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(enrollment)))
+}
+
+/// 当前用户的两步验证状态
+/// GET /api/v1/auth/2fa
+pub async fn get_two_factor_status(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<ResponseJson<ApiResponse<TwoFactorStatus>>, AppError> {
+    let status = TwoFactorService::new(database).status(&auth_context.user).await?;
+
+    Ok(ResponseJson(ApiResponse::success(status)))
+}
+
+/// 生成两步验证密钥和二维码地址
+/// POST /api/v1/auth/2fa/setup
+pub async fn setup_two_factor(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<ResponseJson<ApiResponse<TwoFactorEnrollment>>, AppError> {
+    let enrollment = TwoFactorService::new(database)
+        .begin_enrollment(auth_context.user.id, &auth_context.user.email)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(enrollment)))
+}
+
+/// 用验证码确认并启用两步验证,返回恢复码(只显示这一次)
+/// POST /api/v1/auth/2fa/confirm
+pub async fn confirm_two_factor(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Json(request): Json<TwoFactorCodeRequest>,
+) -> Result<ResponseJson<ApiResponse<Vec<String>>>, AppError> {
+    request.validate()?;
+
+    let recovery_codes = TwoFactorService::new(database)
+        .confirm_enrollment(auth_context.user.id, &request.code)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(recovery_codes)))
+}
+
+/// 关闭两步验证
+/// POST /api/v1/auth/2fa/disable
+pub async fn disable_two_factor(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Json(request): Json<TwoFactorCodeRequest>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    request.validate()?;
+
+    TwoFactorService::new(database)
+        .disable(&auth_context.user, &request.code)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 重新生成恢复码
+/// POST /api/v1/auth/2fa/recovery-codes
+pub async fn regenerate_recovery_codes(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Json(request): Json<TwoFactorCodeRequest>,
+) -> Result<ResponseJson<ApiResponse<Vec<String>>>, AppError> {
+    request.validate()?;
+
+    let recovery_codes = TwoFactorService::new(database)
+        .regenerate_recovery_codes(auth_context.user.id, &request.code)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(recovery_codes)))
+}
+
+/// 平台级两步验证策略: 要求所有用户启用(仅平台管理员)
+/// PUT /api/v1/settings/2fa-policy
+pub async fn update_platform_two_factor_policy(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Json(request): Json<TwoFactorPolicyRequest>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    TwoFactorService::new(database)
+        .set_platform_policy(request.required, &auth_context.user)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 是否开放自助注册,供注册页判断(公开)
+/// GET /api/v1/auth/registration-policy
+pub async fn get_public_registration_policy(
+    State((database, _config)): State<AppState>,
+) -> Result<ResponseJson<ApiResponse<PublicRegistrationPolicy>>, AppError> {
+    let policy = RegistrationService::new(database).policy().await?;
+
+    Ok(ResponseJson(ApiResponse::success(PublicRegistrationPolicy { mode: policy.mode })))
+}
+
+/// 查看平台注册策略(仅平台管理员)
+/// GET /api/v1/settings/registration
+pub async fn get_registration_policy(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<ResponseJson<ApiResponse<RegistrationPolicy>>, AppError> {
+    let policy = RegistrationService::new(database)
+        .policy_settings(&auth_context.user)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(policy)))
+}
+
+/// 设置平台注册策略: 禁止注册、仅限邀请或凭加入码开放注册(仅平台管理员)
+/// PUT /api/v1/settings/registration
+pub async fn update_registration_policy(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Json(request): Json<RegistrationPolicy>,
+) -> Result<ResponseJson<ApiResponse<RegistrationPolicy>>, AppError> {
+    request.validate()?;
+
+    let policy = RegistrationService::new(database)
+        .set_policy(request, &auth_context.user, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(policy)))
+}
+
+/// 重新发送邮箱验证邮件
+/// POST /api/v1/auth/verify-email/send
+pub async fn send_verification_email(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    AccountEmailService::new(database, config)?
+        .send_verification(&auth_context.user)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 用邮件中的令牌完成邮箱验证
+/// POST /api/v1/auth/verify-email
+pub async fn verify_email(
+    State((database, config)): State<AppState>,
+    client: ClientInfo,
+    Json(request): Json<VerifyEmailRequest>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    request.validate()?;
+
+    AccountEmailService::new(database, config)?
+        .verify_email(&request.token, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 申请找回密码,邮箱是否存在都返回成功
+/// POST /api/v1/auth/password-reset/request
+pub async fn request_password_reset(
+    State((database, config)): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    request.validate()?;
+
+    AccountEmailService::new(database, config)?
+        .request_password_reset(&request.email)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 用邮件中的令牌设置新密码
+/// POST /api/v1/auth/password-reset/confirm
+pub async fn reset_password(
+    State((database, config)): State<AppState>,
+    client: ClientInfo,
+    Json(request): Json<ResetPasswordRequest>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    request.validate()?;
+
+    AccountEmailService::new(database, config)?
+        .reset_password(&request.token, &request.new_password, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 修改自己的密码(需验证当前密码),当前会话以外的会话全部失效
+/// POST /api/v1/auth/change-password
+pub async fn change_password(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    request.validate()?;
+
+    PasswordService::new(database, config)
+        .change_password(
+            &auth_context.user,
+            auth_context.claims.sid.as_deref(),
+            &request.old_password,
+            &request.new_password,
+            &client,
+        )
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 当前用户的个人访问令牌,管理者还能看到本公司的API密钥
+/// GET /api/v1/auth/tokens
+pub async fn list_api_tokens(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<ResponseJson<ApiResponse<Vec<ApiTokenInfo>>>, AppError> {
+    let tokens = ApiTokenService::new(database).list(&auth_context.user).await?;
+
+    Ok(ResponseJson(ApiResponse::success(tokens)))
+}
+
+/// 创建个人访问令牌或公司API密钥,令牌明文只返回这一次
+/// POST /api/v1/auth/tokens
+pub async fn create_api_token(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Json(request): Json<CreateApiTokenRequest>,
+) -> Result<ResponseJson<ApiResponse<CreatedApiToken>>, AppError> {
+    request.validate()?;
+
+    let token = ApiTokenService::new(database)
+        .create(&auth_context.user, &request, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(token)))
+}
+
+/// 撤销访问令牌
+/// DELETE /api/v1/auth/tokens/:id
+pub async fn revoke_api_token(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(token_id): Path<String>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    ApiTokenService::new(database)
+        .revoke(&auth_context.user, &token_id, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 按公司代码发起单点登录,前端跳转到返回的授权地址
+/// POST /api/v1/auth/sso/start
+pub async fn start_sso(
+    State((database, config)): State<AppState>,
+    Json(request): Json<SsoStartRequest>,
+) -> Result<ResponseJson<ApiResponse<SsoAuthorization>>, AppError> {
+    request.validate()?;
+
+    let authorization = SsoService::new(database, config)
+        .start_login(&request.company_code)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(authorization)))
+}
+
+/// 身份提供方回调后由前端提交授权码和 state,成功后返回登录令牌
+/// POST /api/v1/auth/sso/callback
+pub async fn sso_callback(
+    State((database, config)): State<AppState>,
+    client: ClientInfo,
+    Json(request): Json<SsoCallbackRequest>,
+) -> Result<ResponseJson<ApiResponse<LoginResponse>>, AppError> {
+    request.validate()?;
+
+    let response = SsoService::new(database, config)
+        .complete(&request, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(response)))
+}
+
+/// 已登录用户发起关联外部身份
+/// POST /api/v1/auth/sso/link/start
+pub async fn start_sso_link(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<ResponseJson<ApiResponse<SsoAuthorization>>, AppError> {
+    let authorization = SsoService::new(database, config)
+        .start_link(&auth_context.user)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(authorization)))
+}
+
+/// 当前用户关联的外部身份
+/// GET /api/v1/auth/sso/identities
+pub async fn list_sso_identities(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+) -> Result<ResponseJson<ApiResponse<Vec<UserIdentityInfo>>>, AppError> {
+    let identities = SsoService::new(database, config)
+        .list_identities(&auth_context.user)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(identities)))
+}
+
+/// 解除关联外部身份
+/// DELETE /api/v1/auth/sso/identities/:id
+pub async fn unlink_sso_identity(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(identity_id): Path<i64>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    SsoService::new(database, config)
+        .unlink_identity(&auth_context.user, identity_id, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
@@ -5,13 +5,18 @@ use axum::{
     Json,
 };
 use serde::Deserialize;
+use validator::Validate;
 
 use crate::{
     database::Database,
     errors::AppError,
-    models::UserRole,
-    middleware::auth::AuthContext,
+    connections,
+    models::{ApiResponse, CompanyJoinCode, CompanySsoConfig, CompanySsoConfigRequest, TwoFactorPolicyRequest},
+    middleware::{auth::AuthContext, client::ClientInfo},
     services::company::{CompanyService, CreateCompanyRequest, UpdateCompanyRequest},
+    services::registration::RegistrationService,
+    services::sso::SsoService,
+    services::two_factor::TwoFactorService,
     Config,
 };
 
@@ -29,14 +34,12 @@ pub struct ListCompaniesQuery {
 pub async fn create_company(
     State((database, _config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
     Json(request): Json<CreateCompanyRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     let service = CompanyService::new(database);
     
-    let user_role: UserRole = auth_context.claims.role.parse()
-        .map_err(|_| AppError::BadRequest("无效的用户角色".to_string()))?;
-    
-    let company = service.create_company(request, user_role).await?;
+    let company = service.create_company(request, &auth_context.user, &client).await?;
     
     Ok((StatusCode::CREATED, Json(company)))
 }
@@ -50,10 +53,7 @@ pub async fn list_companies(
 ) -> Result<impl IntoResponse, AppError> {
     let service = CompanyService::new(database);
     
-    let user_role: UserRole = auth_context.claims.role.parse()
-        .map_err(|_| AppError::BadRequest("无效的用户角色".to_string()))?;
-    
-    let companies = service.list_companies(user_role, query.active_only).await?;
+    let companies = service.list_companies(&auth_context.user, query.active_only).await?;
     
     Ok(Json(companies))
 }
@@ -67,13 +67,7 @@ pub async fn get_company(
 ) -> Result<impl IntoResponse, AppError> {
     let service = CompanyService::new(database);
     
-    let user_role: UserRole = auth_context.claims.role.parse()
-        .map_err(|_| AppError::BadRequest("无效的用户角色".to_string()))?;
-    
-    // 从用户信息获取company_id
-    let user_company_id = auth_context.user.company_id;
-    
-    let company = service.get_company(id, user_role, user_company_id).await?;
+    let company = service.get_company(id, &auth_context.user).await?;
     
     Ok(Json(company))
 }
@@ -83,15 +77,16 @@ pub async fn get_company(
 pub async fn update_company(
     State((database, _config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
     Path(id): Path<i64>,
     Json(request): Json<UpdateCompanyRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     let service = CompanyService::new(database);
     
-    let user_role: UserRole = auth_context.claims.role.parse()
-        .map_err(|_| AppError::BadRequest("无效的用户角色".to_string()))?;
-    
-    let company = service.update_company(id, request, user_role).await?;
+    let company = service.update_company(id, request, &auth_context.user, &client).await?;
+    if !company.is_active {
+        connections::disconnect_company(id);
+    }
     
     Ok(Json(company))
 }
@@ -101,14 +96,12 @@ pub async fn update_company(
 pub async fn delete_company(
     State((database, _config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
     Path(id): Path<i64>,
 ) -> Result<impl IntoResponse, AppError> {
     let service = CompanyService::new(database);
     
-    let user_role: UserRole = auth_context.claims.role.parse()
-        .map_err(|_| AppError::BadRequest("无效的用户角色".to_string()))?;
-    
-    service.delete_company(id, user_role).await?;
+    service.delete_company(id, &auth_context.user, &client).await?;
     
     Ok(StatusCode::NO_CONTENT)
 }
@@ -118,14 +111,107 @@ pub async fn delete_company(
 pub async fn toggle_company_status(
     State((database, _config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
     Path(id): Path<i64>,
 ) -> Result<impl IntoResponse, AppError> {
     let service = CompanyService::new(database);
     
-    let user_role: UserRole = auth_context.claims.role.parse()
-        .map_err(|_| AppError::BadRequest("无效的用户角色".to_string()))?;
-    
-    let company = service.toggle_company_status(id, user_role).await?;
+    let company = service.toggle_company_status(id, &auth_context.user, &client).await?;
+    // 停用后立即断开本公司用户的实时连接,HTTP 请求由认证中间件拒绝
+    if !company.is_active {
+        connections::disconnect_company(id);
+    }
     
     Ok(Json(company))
 }
+
+/// 设置公司两步验证策略: 是否要求本公司项目经理启用(SystemAdmin或本公司项目经理)
+/// PUT /api/companies/:id/2fa-policy
+pub async fn update_two_factor_policy(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Path(id): Path<i64>,
+    Json(request): Json<TwoFactorPolicyRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    TwoFactorService::new(database)
+        .set_company_policy(id, request.required, &auth_context.user)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 查看公司加入码,用户凭加入码自助注册为本公司任务执行者(本公司管理者或平台管理员)
+/// GET /api/v1/companies/:id/join-code
+pub async fn get_join_code(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Path(id): Path<i64>,
+) -> Result<Json<ApiResponse<CompanyJoinCode>>, AppError> {
+    let join_code = RegistrationService::new(database)
+        .join_code(id, &auth_context.user)
+        .await?;
+
+    Ok(Json(ApiResponse::success(join_code)))
+}
+
+/// 重新生成公司加入码,旧加入码立即失效
+/// POST /api/v1/companies/:id/join-code
+pub async fn regenerate_join_code(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(id): Path<i64>,
+) -> Result<Json<ApiResponse<CompanyJoinCode>>, AppError> {
+    let join_code = RegistrationService::new(database)
+        .regenerate_join_code(id, &auth_context.user, &client)
+        .await?;
+
+    Ok(Json(ApiResponse::success(join_code)))
+}
+
+/// 查看公司单点登录配置,未配置时返回 null(本公司项目经理或平台管理员)
+/// GET /api/v1/companies/:id/sso
+pub async fn get_sso_config(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    Path(id): Path<i64>,
+) -> Result<Json<ApiResponse<Option<CompanySsoConfig>>>, AppError> {
+    let sso_config = SsoService::new(database, config)
+        .get_config(id, &auth_context.user)
+        .await?;
+
+    Ok(Json(ApiResponse::success(sso_config)))
+}
+
+/// 设置公司单点登录配置
+/// PUT /api/v1/companies/:id/sso
+pub async fn update_sso_config(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(id): Path<i64>,
+    Json(request): Json<CompanySsoConfigRequest>,
+) -> Result<Json<ApiResponse<CompanySsoConfig>>, AppError> {
+    request.validate()?;
+
+    let sso_config = SsoService::new(database, config)
+        .set_config(id, &request, &auth_context.user, &client)
+        .await?;
+
+    Ok(Json(ApiResponse::success(sso_config)))
+}
+
+/// 删除公司单点登录配置
+/// DELETE /api/v1/companies/:id/sso
+pub async fn delete_sso_config(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    SsoService::new(database, config)
+        .delete_config(id, &auth_context.user, &client)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -33,7 +33,7 @@ pub async fn api_docs() -> Result<Html<String>, StatusCode> {
     <div class="endpoint">
         <span class="method post">POST</span>
         <code>/api/v1/auth/register</code>
-        <p>用户注册</p>
+        <p>凭公司加入码自助注册为任务执行者(需开放注册)</p>
     </div>
 
     <div class="endpoint">
@@ -0,0 +1,93 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use validator::Validate;
+
+use crate::{
+    errors::AppError,
+    middleware::{auth::AuthContext, client::ClientInfo},
+    models::{
+        AcceptInvitationRequest, ApiResponse, CreateInvitationRequest, InvitationInfo, InvitationPreview,
+        InvitationQuery, InvitationTokenRequest, UserInfo,
+    },
+    services::invitation::InvitationService,
+    Config, Database,
+};
+
+type AppState = (Database, Config);
+
+/// 邀请新成员,邀请链接通过邮件发送给受邀人
+/// POST /api/v1/invitations
+pub async fn create_invitation(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Json(request): Json<CreateInvitationRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    let invitation = InvitationService::new(database, config)?
+        .invite(&request, &auth_context.user, &client)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(ApiResponse::success(invitation))))
+}
+
+/// 公司的邀请记录
+/// GET /api/v1/invitations?company_id=
+pub async fn list_invitations(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<InvitationQuery>,
+) -> Result<Json<ApiResponse<Vec<InvitationInfo>>>, AppError> {
+    let invitations = InvitationService::new(database, config)?
+        .list(query.company_id, &auth_context.user)
+        .await?;
+
+    Ok(Json(ApiResponse::success(invitations)))
+}
+
+/// 撤销待接受的邀请
+/// DELETE /api/v1/invitations/:id
+pub async fn revoke_invitation(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(invitation_id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    InvitationService::new(database, config)?
+        .revoke(invitation_id, &auth_context.user, &client)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 受邀人查看邀请内容(公司、角色、邮箱)
+/// POST /api/v1/auth/invitation
+pub async fn preview_invitation(
+    State((database, config)): State<AppState>,
+    Json(request): Json<InvitationTokenRequest>,
+) -> Result<Json<ApiResponse<InvitationPreview>>, AppError> {
+    request.validate()?;
+
+    let preview = InvitationService::new(database, config)?.preview(&request.token).await?;
+
+    Ok(Json(ApiResponse::success(preview)))
+}
+
+/// 受邀人设置用户名、密码和资料,创建账号
+/// POST /api/v1/auth/invitation/accept
+pub async fn accept_invitation(
+    State((database, config)): State<AppState>,
+    client: ClientInfo,
+    Json(request): Json<AcceptInvitationRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    let user = InvitationService::new(database, config)?.accept(&request, &client).await?;
+
+    Ok((StatusCode::CREATED, Json(ApiResponse::<UserInfo>::success(user))))
+}
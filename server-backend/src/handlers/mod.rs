@@ -1,8 +1,12 @@
+pub mod audit;
 pub mod auth;
 pub mod docs;
 pub mod health;
+pub mod invitations;
 pub mod users;
 pub mod company;
+pub mod roles;
+pub mod sessions;
 // TODO: 暂时注释,等待类型迁移完成
 // pub mod tasks;
 pub mod tasks_temp;  // 临时任务端点(返回空数组,避免404)
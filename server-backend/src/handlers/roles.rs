@@ -0,0 +1,104 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use validator::Validate;
+
+use crate::{
+    errors::AppError,
+    middleware::{auth::AuthContext, client::ClientInfo},
+    models::{ApiResponse, AssignRoleRequest, CompanyRoleInfo, CompanyRoleRequest, CompanyRoles, PermissionInfo, UserInfo},
+    services::role::RoleService,
+    Config, Database,
+};
+
+type AppState = (Database, Config);
+
+/// 权限清单
+/// GET /api/v1/permissions
+pub async fn list_permissions() -> Json<ApiResponse<Vec<PermissionInfo>>> {
+    Json(ApiResponse::success(RoleService::permission_catalog()))
+}
+
+/// 公司可用的角色(内置角色和自定义角色)
+/// GET /api/v1/companies/:id/roles
+pub async fn list_company_roles(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Path(company_id): Path<i64>,
+) -> Result<Json<ApiResponse<CompanyRoles>>, AppError> {
+    let roles = RoleService::new(database)
+        .list_roles(company_id, &auth_context.user)
+        .await?;
+
+    Ok(Json(ApiResponse::success(roles)))
+}
+
+/// 创建公司自定义角色
+/// POST /api/v1/companies/:id/roles
+pub async fn create_company_role(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(company_id): Path<i64>,
+    Json(request): Json<CompanyRoleRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    let role = RoleService::new(database)
+        .create_role(company_id, &request, &auth_context.user, &client)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(ApiResponse::success(role))))
+}
+
+/// 修改公司自定义角色
+/// PUT /api/v1/companies/:id/roles/:role_id
+pub async fn update_company_role(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path((company_id, role_id)): Path<(i64, i64)>,
+    Json(request): Json<CompanyRoleRequest>,
+) -> Result<Json<ApiResponse<CompanyRoleInfo>>, AppError> {
+    request.validate()?;
+
+    let role = RoleService::new(database)
+        .update_role(company_id, role_id, &request, &auth_context.user, &client)
+        .await?;
+
+    Ok(Json(ApiResponse::success(role)))
+}
+
+/// 删除公司自定义角色
+/// DELETE /api/v1/companies/:id/roles/:role_id
+pub async fn delete_company_role(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path((company_id, role_id)): Path<(i64, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    RoleService::new(database)
+        .delete_role(company_id, role_id, &auth_context.user, &client)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 为用户分配或取消自定义角色
+/// PUT /api/v1/users/:id/custom-role
+pub async fn assign_user_role(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(user_id): Path<i64>,
+    Json(request): Json<AssignRoleRequest>,
+) -> Result<Json<ApiResponse<UserInfo>>, AppError> {
+    let user = RoleService::new(database)
+        .assign_role(user_id, &request, &auth_context.user, &client)
+        .await?;
+
+    Ok(Json(ApiResponse::success(user)))
+}
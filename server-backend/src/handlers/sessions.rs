@@ -0,0 +1,46 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+
+use crate::{
+    errors::AppError,
+    middleware::{auth::AuthContext, client::ClientInfo},
+    models::{ApiResponse, SessionInfo, SessionQuery},
+    services::session::SessionService,
+    Config, Database,
+};
+
+type AppState = (Database, Config);
+
+/// 有效的登录会话(浏览器与桌面客户端),默认查看自己的
+/// GET /api/v1/sessions?user_id=
+pub async fn list_sessions(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<SessionQuery>,
+) -> Result<Json<ApiResponse<Vec<SessionInfo>>>, AppError> {
+    let user_id = query.user_id.unwrap_or(auth_context.user.id);
+    let sessions = SessionService::new(database, config)
+        .list_sessions(user_id, &auth_context.user, auth_context.claims.sid.as_deref())
+        .await?;
+
+    Ok(Json(ApiResponse::success(sessions)))
+}
+
+/// 撤销登录会话,该会话的访问令牌、刷新令牌和实时连接立即失效
+/// DELETE /api/v1/sessions/:id
+pub async fn revoke_session(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(session_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    SessionService::new(database, config)
+        .revoke_by_id(&session_id, &auth_context.user, &client)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,111 +1,166 @@
 use axum::{
-    extract::State,
+    extract::{Path, Query, State},
     response::Json,
 };
+use serde::Deserialize;
+use uuid::Uuid;
+
 use crate::database::Database;
 use crate::errors::AppError;
-use crate::services::statistics::{ProjectStatistics, TaskStatistics};
+use crate::middleware::auth::AuthContext;
+use crate::services::estimation::{
+    EstimationReport, EstimationService, SimilarTaskQuery, SuggestedEstimate, DEFAULT_ESTIMATION_MONTHS,
+};
+use crate::services::flow_metrics::{FlowMetrics, FlowMetricsService, DEFAULT_FLOW_WEEKS};
+use crate::services::statistics::{
+    ProjectProgressStatistics, ProjectStatistics, StatisticsScope, StatisticsService,
+    TaskStatistics, UserWorkloadStatistics,
+};
 use crate::Config;
 
 type AppState = (Database, Config);
 
-/// 获取任务统计 (轻量实现：如果 tasks 表不存在则返回 0 值统计)
-/// GET /api/v1/statistics/tasks
+/// 统计查询参数
+#[derive(Debug, Deserialize)]
+pub struct StatisticsQuery {
+    /// 公司ID(仅PlatformAdmin可指定其他公司,不指定则查看全平台)
+    pub company_id: Option<i64>,
+}
+
+/// 交付周期统计查询参数
+#[derive(Debug, Deserialize)]
+pub struct FlowMetricsQuery {
+    /// 公司ID(仅PlatformAdmin可指定其他公司,不指定则查看全平台)
+    pub company_id: Option<i64>,
+    /// 统计最近几周完成的任务(默认12周)
+    pub weeks: Option<u32>,
+}
+
+/// 估算准确度查询参数
+#[derive(Debug, Deserialize)]
+pub struct EstimationQuery {
+    /// 公司ID(仅PlatformAdmin可指定其他公司,不指定则查看全平台)
+    pub company_id: Option<i64>,
+    /// 分析最近几个月完成的任务(默认6个月)
+    pub months: Option<u32>,
+}
+
+/// 建议工时查询参数
+#[derive(Debug, Deserialize)]
+pub struct SuggestEstimateQuery {
+    /// 公司ID(仅PlatformAdmin可指定其他公司,不指定则查看全平台)
+    pub company_id: Option<i64>,
+    pub project_id: Option<Uuid>,
+    pub priority: Option<String>,
+    /// 逗号分隔的标签
+    pub labels: Option<String>,
+}
+
+/// 获取任务统计(按当前用户的数据范围隔离)
+/// GET /api/v1/statistics/tasks?company_id=1
 pub async fn get_task_statistics(
     State((db, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<StatisticsQuery>,
 ) -> Result<Json<TaskStatistics>, AppError> {
-    // 尝试在数据库中执行计数查询；如果表不存在或查询失败，返回默认值以避免 404/500
-    let total = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM tasks")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let pending = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM tasks WHERE status = 'pending'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let in_progress = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM tasks WHERE status = 'in_progress'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let completed = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM tasks WHERE status = 'completed'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let cancelled = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM tasks WHERE status = 'cancelled'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let completion_rate = if total > 0 { (completed as f64 / total as f64) * 100.0 } else { 0.0 };
-
-    Ok(Json(TaskStatistics {
-        total_tasks: total,
-        pending_tasks: pending,
-        in_progress_tasks: in_progress,
-        completed_tasks: completed,
-        cancelled_tasks: cancelled,
-        completion_rate,
-    }))
+    let scope = StatisticsScope::for_user(&auth_context.user, query.company_id)?;
+    let statistics = StatisticsService::new(db).get_task_statistics(scope).await?;
+
+    Ok(Json(statistics))
 }
 
-/// 获取项目统计 (轻量实现：如果 projects 表不存在则返回 0 值统计)
-/// GET /api/v1/statistics/projects
+/// 获取项目统计(按当前用户的数据范围隔离)
+/// GET /api/v1/statistics/projects?company_id=1
 pub async fn get_project_statistics(
     State((db, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<StatisticsQuery>,
 ) -> Result<Json<ProjectStatistics>, AppError> {
-    let total = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM projects")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let planning = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM projects WHERE status = 'planning'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let active = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM projects WHERE status = 'active'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let on_hold = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM projects WHERE status = 'on_hold'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let completed = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM projects WHERE status = 'completed'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    let cancelled = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM projects WHERE status = 'cancelled'")
-        .fetch_one(&db.pool)
-        .await
-        .map(|r| r.0)
-        .unwrap_or(0);
-
-    Ok(Json(ProjectStatistics {
-        total_projects: total,
-        planning_projects: planning,
-        active_projects: active,
-        on_hold_projects: on_hold,
-        completed_projects: completed,
-        cancelled_projects: cancelled,
-    }))
+    let scope = StatisticsScope::for_user(&auth_context.user, query.company_id)?;
+    let statistics = StatisticsService::new(db).get_project_statistics(scope).await?;
+
+    Ok(Json(statistics))
+}
+
+/// 获取所有员工工作量统计(按当前用户的数据范围隔离)
+/// GET /api/v1/statistics/users/workload?company_id=1
+pub async fn get_all_users_workload(
+    State((db, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<StatisticsQuery>,
+) -> Result<Json<Vec<UserWorkloadStatistics>>, AppError> {
+    let scope = StatisticsScope::for_user(&auth_context.user, query.company_id)?;
+    let workloads = StatisticsService::new(db).get_all_users_workload(scope).await?;
+
+    Ok(Json(workloads))
+}
+
+/// 获取项目进度统计(项目不在当前用户数据范围内时返回404)
+/// GET /api/v1/statistics/projects/:project_id/progress
+pub async fn get_project_progress(
+    State((db, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Path(project_id): Path<Uuid>,
+    Query(query): Query<StatisticsQuery>,
+) -> Result<Json<ProjectProgressStatistics>, AppError> {
+    let scope = StatisticsScope::for_user(&auth_context.user, query.company_id)?;
+    let progress = StatisticsService::new(db)
+        .get_project_progress(project_id, scope)
+        .await?;
+
+    Ok(Json(progress))
+}
+
+/// 获取交付周期统计: 前置时间、周期时间、各状态停留时长的分位数及每周吞吐量
+/// GET /api/v1/statistics/flow?company_id=1&weeks=12
+pub async fn get_flow_metrics(
+    State((db, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<FlowMetricsQuery>,
+) -> Result<Json<FlowMetrics>, AppError> {
+    let scope = StatisticsScope::for_user(&auth_context.user, query.company_id)?;
+    let metrics = FlowMetricsService::new(db)
+        .get_flow_metrics(scope, query.weeks.unwrap_or(DEFAULT_FLOW_WEEKS))
+        .await?;
+
+    Ok(Json(metrics))
 }
 
-// 其余统计端点（按员工/按项目进度）仍在迁移中，若需要可在后续迭代中实现。
+/// 获取工时估算准确度报告: 按员工、项目、标签统计误差、偏差、分布及月度趋势
+/// GET /api/v1/statistics/estimation?company_id=1&months=6
+pub async fn get_estimation_report(
+    State((db, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<EstimationQuery>,
+) -> Result<Json<EstimationReport>, AppError> {
+    let scope = StatisticsScope::for_user(&auth_context.user, query.company_id)?;
+    let report = EstimationService::new(db)
+        .get_estimation_report(scope, query.months.unwrap_or(DEFAULT_ESTIMATION_MONTHS))
+        .await?;
+
+    Ok(Json(report))
+}
+
+/// 根据相似历史任务为新任务建议工时
+/// GET /api/v1/statistics/estimation/suggest?project_id=...&priority=high&labels=design,copywriting
+pub async fn suggest_estimate(
+    State((db, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Query(query): Query<SuggestEstimateQuery>,
+) -> Result<Json<SuggestedEstimate>, AppError> {
+    let scope = StatisticsScope::for_user(&auth_context.user, query.company_id)?;
+    let task = SimilarTaskQuery {
+        project_id: query.project_id.map(|id| id.to_string()),
+        priority: query.priority,
+        labels: query
+            .labels
+            .unwrap_or_default()
+            .split(',')
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty())
+            .collect(),
+    };
+    let suggestion = EstimationService::new(db).suggest_estimate(scope, &task).await?;
+
+    Ok(Json(suggestion))
+}
@@ -1,15 +1,22 @@
 use axum::{
     extract::{Path, Query, State},
-    response::Json as ResponseJson,
+    http::header,
+    response::{IntoResponse, Json as ResponseJson},
     Json,
 };
 use serde::Deserialize;
+use validator::Validate;
 
 use crate::{
     errors::AppError,
-    middleware::auth::AuthContext,
-    models::{ApiResponse, CreateUserRequest, UpdateUserRequest, UserInfo},
-    services::user::UserService,
+    middleware::{auth::AuthContext, client::ClientInfo},
+    models::{
+        ApiResponse, CreateUserRequest, ImpersonationResponse, StartImpersonationRequest, UpdateUserRequest, UserInfo,
+    },
+    services::{
+        impersonation::ImpersonationService, login_throttle::LoginThrottleService, password::PasswordService,
+        personal_data::PersonalDataService, two_factor::TwoFactorService, user::UserService,
+    },
     Config, Database,
 };
 
@@ -37,15 +44,18 @@ pub async fn list_users(
 }
 
 pub async fn create_user(
-    State((database, _config)): State<AppState>,
+    State((database, config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
     Json(request): Json<CreateUserRequest>,
 ) -> Result<ResponseJson<ApiResponse<UserInfo>>, AppError> {
-    tracing::info!("创建用户请求: {:?}", request);
+    tracing::info!("创建用户请求: {} ({:?})", request.username, request.role);
     tracing::info!("请求用户: {:?}", auth_context.user);
 
+    PasswordService::new(database.clone(), config).check_new_user_password(&request.password, &request.username)?;
+
     let user_service = UserService::new(database);
-    let user = user_service.create_user(request, &auth_context.user).await?;
+    let user = user_service.create_user(request, &auth_context.user, &client).await?;
 
     tracing::info!("用户创建成功: {:?}", user);
     Ok(ResponseJson(ApiResponse::success(user)))
@@ -63,26 +73,109 @@ pub async fn get_user(
 }
 
 pub async fn update_user(
-    State((database, _config)): State<AppState>,
+    State((database, config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
     Path(user_id): Path<i64>,
-    Json(request): Json<UpdateUserRequest>,
+    Json(mut request): Json<UpdateUserRequest>,
 ) -> Result<ResponseJson<ApiResponse<UserInfo>>, AppError> {
+    // 先设置密码: 不满足密码策略时其他字段也不修改
+    if let Some(password) = request.password.take() {
+        if auth_context.impersonator_id.is_some() {
+            return Err(AppError::InsufficientScope("模拟登录期间不能修改密码".to_string()));
+        }
+        PasswordService::new(database.clone(), config)
+            .set_password_for_user(user_id, &password, &auth_context.user, &client)
+            .await?;
+    }
+
     let user_service = UserService::new(database);
     let user = user_service
-        .update_user(user_id, request, &auth_context.user)
+        .update_user(user_id, request, &auth_context.user, &client)
         .await?;
 
     Ok(ResponseJson(ApiResponse::success(user)))
 }
 
+/// 删除用户: 匿名化账号并清除个人数据,任务和统计数据保留
+/// DELETE /api/v1/users/:id
 pub async fn delete_user(
     State((database, _config)): State<AppState>,
     auth_context: AuthContext,
+    client: ClientInfo,
     Path(user_id): Path<i64>,
 ) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
-    let user_service = UserService::new(database);
-    user_service.delete_user(user_id, &auth_context.user).await?;
+    PersonalDataService::new(database)
+        .erase(user_id, &auth_context.user, &client)
+        .await?;
 
     Ok(ResponseJson(ApiResponse::success(())))
 }
+
+/// 导出用户的个人数据(ZIP,内含 JSON 文件)
+/// GET /api/v1/users/:id/export
+pub async fn export_personal_data(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(user_id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let archive = PersonalDataService::new(database)
+        .export(user_id, &auth_context.user, &client)
+        .await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", archive.filename)),
+        ],
+        archive.content,
+    ))
+}
+
+/// 解除因登录失败导致的账号锁定
+/// POST /api/v1/users/:id/unlock
+pub async fn unlock_user(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(user_id): Path<i64>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    LoginThrottleService::new(database, config)
+        .unlock_user(user_id, &auth_context.user, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 为丢失验证器的用户重置两步验证
+/// DELETE /api/v1/users/:id/2fa
+pub async fn reset_two_factor(
+    State((database, _config)): State<AppState>,
+    auth_context: AuthContext,
+    Path(user_id): Path<i64>,
+) -> Result<ResponseJson<ApiResponse<()>>, AppError> {
+    TwoFactorService::new(database)
+        .reset_for_user(user_id, &auth_context.user)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(())))
+}
+
+/// 平台管理员模拟登录为指定用户,返回有时限的模拟令牌
+/// POST /api/v1/users/:id/impersonate
+pub async fn impersonate_user(
+    State((database, config)): State<AppState>,
+    auth_context: AuthContext,
+    client: ClientInfo,
+    Path(user_id): Path<i64>,
+    Json(request): Json<StartImpersonationRequest>,
+) -> Result<ResponseJson<ApiResponse<ImpersonationResponse>>, AppError> {
+    request.validate()?;
+
+    let response = ImpersonationService::new(database, config)
+        .start(user_id, request, &auth_context.user, &client)
+        .await?;
+
+    Ok(ResponseJson(ApiResponse::success(response)))
+}
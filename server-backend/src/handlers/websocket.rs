@@ -1,6 +1,6 @@
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
         State,
     },
     response::Response,
@@ -9,11 +9,14 @@ use axum::{
 use futures::{stream::StreamExt, SinkExt};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot};
 use uuid::Uuid;
 
+use crate::connections::{CloseReason, ConnectionRegistration};
 use crate::database::Database;
-use crate::models::{Task, User};
+use crate::metrics::{WebSocketConnectionGuard, METRICS};
+use crate::middleware::auth::AuthContext;
+use crate::models::{Task, UserInfo};
 use crate::Config;
 
 type AppState = (Database, Config);
@@ -66,22 +69,28 @@ pub fn create_event_broadcaster() -> EventBroadcaster {
 /// GET /ws/task-updates
 pub async fn task_updates_websocket(
     ws: WebSocketUpgrade,
-    Extension(user): Extension<User>,
+    auth_context: AuthContext,
     State((db, _config)): State<AppState>,
     Extension(broadcaster): Extension<EventBroadcaster>,
 ) -> Response {
+    let user = auth_context.user;
     tracing::info!("User {} connecting to WebSocket", user.username);
+    // 登记连接,会话撤销或公司停用时由服务端主动断开
+    let (registration, closed) = ConnectionRegistration::new(user.id, user.company_id, auth_context.claims.sid);
     
-    ws.on_upgrade(move |socket| handle_socket(socket, user, db, broadcaster))
+    ws.on_upgrade(move |socket| handle_socket(socket, user, registration, closed, db, broadcaster))
 }
 
 /// 处理WebSocket连接
 async fn handle_socket(
     socket: WebSocket,
-    user: User,
+    user: UserInfo,
+    _registration: ConnectionRegistration,
+    mut closed: oneshot::Receiver<CloseReason>,
     _db: Database,
     broadcaster: EventBroadcaster,
 ) {
+    let _connection = WebSocketConnectionGuard::new();
     let (mut sender, mut receiver) = socket.split();
     
     // 订阅广播频道
@@ -95,7 +104,35 @@ async fn handle_socket(
     
     // 接收任务:监听广播事件并发送给客户端
     let mut send_task = tokio::spawn(async move {
-        while let Ok(event) = rx.recv().await {
+        loop {
+            let received = tokio::select! {
+                reason = &mut closed => {
+                    // 服务端要求断开(如公司停用): 发送关闭帧告知客户端原因
+                    if let Ok(reason) = reason {
+                        let frame = CloseFrame {
+                            code: reason.code,
+                            reason: reason.reason.into(),
+                        };
+                        let _ = sender.send(Message::Close(Some(frame))).await;
+                    }
+                    break;
+                }
+                received = rx.recv() => received,
+            };
+            let event = match received {
+                Ok(event) => event,
+                // 处理太慢,部分事件已被覆盖,记录后继续接收最新事件
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    METRICS
+                        .broadcast_dropped_events
+                        .with_label_values(&["lagged"])
+                        .inc_by(skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            METRICS.broadcast_queue_depth.set(broadcaster.len() as i64);
+
             // 序列化事件并发送
             if let Ok(json) = serde_json::to_string(&event) {
                 if sender.send(Message::Text(json)).await.is_err() {
@@ -154,8 +191,13 @@ async fn handle_socket(
 /// 广播任务事件
 pub async fn broadcast_event(broadcaster: &EventBroadcaster, event: TaskEvent) {
     if let Err(e) = broadcaster.send(event.clone()) {
+        METRICS
+            .broadcast_dropped_events
+            .with_label_values(&["no_subscribers"])
+            .inc();
         tracing::warn!("Failed to broadcast event: {:?}, error: {}", event, e);
     } else {
         tracing::debug!("Broadcasted event: {:?}", event);
     }
+    METRICS.broadcast_queue_depth.set(broadcaster.len() as i64);
 }
@@ -1,7 +1,9 @@
 pub mod config;
+pub mod connections;
 pub mod database;
 pub mod errors;
 pub mod handlers;
+pub mod metrics;
 pub mod middleware;
 pub mod models;
 pub mod repositories;  // 新增 Repository 层
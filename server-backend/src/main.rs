@@ -1,21 +1,33 @@
 use anyhow::Result;
-use flow_farm_backend::{config::Config, server::create_app, database::Database};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use flow_farm_backend::{config::Config, server::create_app, database::Database, metrics, services::pii::{PiiService, REENCRYPT_BATCH_SIZE}, utils::{jwt::JwtKeys, pii::{self, Keyring}, PasswordHasher}};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // 初始化日志
+    // 初始化日志(SQL耗时指标单独订阅 sqlx 的语句日志,不受日志级别影响)
     tracing_subscriber::registry()
         .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "flow_farm_backend=debug,tower_http=debug".into()),
+            tracing_subscriber::fmt::layer().with_filter(
+                tracing_subscriber::EnvFilter::try_from_default_env()
+                    .unwrap_or_else(|_| "flow_farm_backend=debug,tower_http=debug".into()),
+            ),
+        )
+        .with(
+            metrics::QueryMetricsLayer.with_filter(
+                tracing_subscriber::filter::Targets::new()
+                    .with_target("sqlx::query", tracing::Level::DEBUG),
+            ),
         )
-        .with(tracing_subscriber::fmt::layer())
         .init();
 
     // 加载配置
     dotenvy::dotenv().ok();
     let config = Config::new()?;
+    // 密码哈希算法或参数、JWT 密钥、个人信息加密密钥配置错误时直接拒绝启动
+    config.ensure_secure()?;
+    PasswordHasher::from_config(&config)?;
+    JwtKeys::from_config(&config)?;
+    Keyring::from_config(&config)?;
 
     tracing::info!("🚀 启动 Flow Farm 服务器后端");
     tracing::info!("📊 配置: {}", config.app_name);
@@ -33,8 +45,46 @@ async fn main() -> Result<()> {
     database.migrate().await?;
     tracing::info!("✅ 数据库连接成功");
 
+    // 维护命令: 根据任务与工作日志重建统计汇总表后退出
+    if std::env::args().nth(1).as_deref() == Some("rebuild-statistics") {
+        database.rebuild_statistics_rollups().await?;
+        return Ok(());
+    }
+
+    // 维护命令: 按当前密钥重新加密全部个人信息字段后退出(轮换密钥后移除旧密钥前执行)
+    if std::env::args().nth(1).as_deref() == Some("reencrypt-pii") {
+        pii::install(&config)?;
+        let report = PiiService::new(database.clone()).reencrypt(REENCRYPT_BATCH_SIZE).await?;
+        tracing::info!("✅ 个人信息重新加密: 检查{}项,更新{}项", report.scanned, report.updated);
+        return Ok(());
+    }
+
+    // 监控指标单独监听
+    if let Some(metrics_bind) = config.metrics_bind.clone() {
+        let metrics_listener = tokio::net::TcpListener::bind(&metrics_bind).await?;
+        let metrics_app = metrics::metrics_router(database.clone(), config.clone());
+        tracing::info!("📈 监控指标: http://{}/metrics", metrics_bind);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(metrics_listener, metrics_app).await {
+                tracing::error!("监控指标服务异常退出: {}", e);
+            }
+        });
+    } else if config.metrics_token.is_none() {
+        tracing::info!("💡 未配置 METRICS_TOKEN 或 METRICS_BIND, /metrics 未启用");
+    }
+
     // 创建应用
-    let app = create_app(database, config.clone()).await;
+    let app = create_app(database.clone(), config.clone()).await;
+
+    // 后台把明文旧数据和旧版本密钥加密的数据改用当前密钥加密
+    if pii::keyring().is_some() {
+        let pii_service = PiiService::new(database);
+        tokio::spawn(async move {
+            if let Err(e) = pii_service.reencrypt(REENCRYPT_BATCH_SIZE).await {
+                tracing::error!("个人信息重新加密失败: {}", e);
+            }
+        });
+    }
 
     // 启动服务器
     let listener = tokio::net::TcpListener::bind(format!("{}:{}", config.host, config.port)).await?;
@@ -53,7 +103,8 @@ async fn main() -> Result<()> {
         tracing::warn!("⚠️  TLS支持尚未实现，将使用HTTP启动");
     }
 
-    axum::serve(listener, app).await?;
+    // 记录对端地址,登录会话据此保存客户端IP
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
 
     Ok(())
 }
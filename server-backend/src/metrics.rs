@@ -0,0 +1,322 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use subtle::ConstantTimeEq;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::Context;
+
+use crate::{Config, Database};
+
+type AppState = (Database, Config);
+
+/// 全局监控指标
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// 服务端 Prometheus 监控指标
+pub struct Metrics {
+    registry: Registry,
+    /// HTTP请求数(按方法、路由、状态码)
+    pub http_requests: IntCounterVec,
+    /// HTTP请求耗时(按方法、路由)
+    pub http_request_duration: HistogramVec,
+    /// SQL语句耗时(按语句类型)
+    pub db_query_duration: HistogramVec,
+    /// 连接池连接数(按 active / idle / max)
+    pub db_pool_connections: IntGaugeVec,
+    /// 当前 /ws/task-updates 连接数
+    pub websocket_connections: IntGauge,
+    /// 广播通道中尚未被最慢的订阅者取走的事件数
+    pub broadcast_queue_depth: IntGauge,
+    /// 丢弃的广播事件数(lagged: 订阅者处理太慢被跳过; no_subscribers: 发送时没有订阅者)
+    pub broadcast_dropped_events: IntCounterVec,
+    /// 各公司未完成(待处理/进行中)的任务数
+    pub open_tasks: IntGaugeVec,
+    /// 各公司启用中的用户数
+    pub active_users: IntGaugeVec,
+    /// 超出频率限制被拒绝的请求数(按路由组、计数类型 ip / user / api_key / company)
+    pub rate_limited_requests: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("flow_farm".to_string()), None)
+            .expect("指标命名空间无效");
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP请求数"),
+            &["method", "route", "status"],
+        )
+        .expect("指标定义无效");
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP请求耗时(秒)"),
+            &["method", "route"],
+        )
+        .expect("指标定义无效");
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new("db_query_duration_seconds", "SQL语句耗时(秒)")
+                .buckets(vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]),
+            &["statement"],
+        )
+        .expect("指标定义无效");
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "数据库连接池连接数"),
+            &["state"],
+        )
+        .expect("指标定义无效");
+        let websocket_connections = IntGauge::new("websocket_connections", "当前WebSocket连接数")
+            .expect("指标定义无效");
+        let broadcast_queue_depth = IntGauge::new("broadcast_queue_depth", "广播通道积压的事件数")
+            .expect("指标定义无效");
+        let broadcast_dropped_events = IntCounterVec::new(
+            Opts::new("broadcast_dropped_events_total", "丢弃的广播事件数"),
+            &["reason"],
+        )
+        .expect("指标定义无效");
+        let open_tasks = IntGaugeVec::new(
+            Opts::new("open_tasks", "未完成的任务数"),
+            &["company_id"],
+        )
+        .expect("指标定义无效");
+        let active_users = IntGaugeVec::new(
+            Opts::new("active_users", "启用中的用户数"),
+            &["company_id"],
+        )
+        .expect("指标定义无效");
+
+        let rate_limited_requests = IntCounterVec::new(
+            Opts::new("rate_limited_requests_total", "超出频率限制被拒绝的请求数"),
+            &["group", "limit"],
+        )
+        .expect("指标定义无效");
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 10] = [
+            Box::new(http_requests.clone()),
+            Box::new(http_request_duration.clone()),
+            Box::new(db_query_duration.clone()),
+            Box::new(db_pool_connections.clone()),
+            Box::new(websocket_connections.clone()),
+            Box::new(broadcast_queue_depth.clone()),
+            Box::new(broadcast_dropped_events.clone()),
+            Box::new(open_tasks.clone()),
+            Box::new(active_users.clone()),
+            Box::new(rate_limited_requests.clone()),
+        ];
+        for collector in collectors {
+            registry.register(collector).expect("指标重复注册");
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_query_duration,
+            db_pool_connections,
+            websocket_connections,
+            broadcast_queue_depth,
+            broadcast_dropped_events,
+            open_tasks,
+            active_users,
+            rate_limited_requests,
+        }
+    }
+
+    /// 采集时刷新连接池和业务指标
+    async fn refresh(&self, db: &Database) -> Result<(), sqlx::Error> {
+        let size = db.pool.size() as i64;
+        let idle = db.pool.num_idle() as i64;
+        self.db_pool_connections.with_label_values(&["active"]).set(size - idle);
+        self.db_pool_connections.with_label_values(&["idle"]).set(idle);
+        self.db_pool_connections
+            .with_label_values(&["max"])
+            .set(db.pool.options().get_max_connections() as i64);
+
+        // 未完成任务数直接读取统计汇总表
+        let open_tasks: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT company_id, SUM(pending_delta + in_progress_delta) FROM stats_daily_company GROUP BY company_id",
+        )
+        .fetch_all(&db.pool)
+        .await?;
+        self.open_tasks.reset();
+        for (company_id, count) in open_tasks {
+            self.open_tasks.with_label_values(&[&company_id.to_string()]).set(count);
+        }
+
+        let active_users: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT COALESCE(company_id, 0), COUNT(*) FROM users WHERE is_active = 1 GROUP BY 1",
+        )
+        .fetch_all(&db.pool)
+        .await?;
+        self.active_users.reset();
+        for (company_id, count) in active_users {
+            self.active_users.with_label_values(&[&company_id.to_string()]).set(count);
+        }
+
+        Ok(())
+    }
+
+    /// 以 Prometheus 文本格式输出所有指标
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("指标编码失败: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// WebSocket 连接计数守卫,连接结束(drop)时自动减一
+pub struct WebSocketConnectionGuard;
+
+impl WebSocketConnectionGuard {
+    pub fn new() -> Self {
+        METRICS.websocket_connections.inc();
+        Self
+    }
+}
+
+impl Default for WebSocketConnectionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for WebSocketConnectionGuard {
+    fn drop(&mut self) {
+        METRICS.websocket_connections.dec();
+    }
+}
+
+/// 记录HTTP请求数和耗时的中间件(未匹配路由的请求统一记为 unmatched,避免标签基数失控)
+pub async fn track_http_metrics(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    METRICS
+        .http_requests
+        .with_label_values(&[&method, &route, &status])
+        .inc();
+    METRICS
+        .http_request_duration
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+
+    response
+}
+
+/// 从 sqlx 的语句日志中采集SQL耗时
+///
+/// sqlx 每执行完一条语句会在 `sqlx::query` 目标下记录一条带 `elapsed_secs` 的事件,
+/// 该层只需订阅这一目标(DEBUG 级别)即可,不影响其他日志的输出级别。
+pub struct QueryMetricsLayer;
+
+#[derive(Default)]
+struct QueryEventVisitor {
+    statement: Option<String>,
+    elapsed_secs: Option<f64>,
+}
+
+impl QueryEventVisitor {
+    fn set_statement(&mut self, summary: &str) {
+        let keyword = summary.split_whitespace().next().unwrap_or_default().to_uppercase();
+        let statement = match keyword.as_str() {
+            "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "WITH" | "PRAGMA" => keyword,
+            _ => "OTHER".to_string(),
+        };
+        self.statement = Some(statement);
+    }
+}
+
+impl Visit for QueryEventVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.elapsed_secs = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "summary" {
+            self.set_statement(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "summary" {
+            self.set_statement(format!("{:?}", value).trim_start_matches('"'));
+        }
+    }
+}
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for QueryMetricsLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != "sqlx::query" {
+            return;
+        }
+
+        let mut visitor = QueryEventVisitor::default();
+        event.record(&mut visitor);
+        if let Some(elapsed) = visitor.elapsed_secs {
+            let statement = visitor.statement.unwrap_or_else(|| "OTHER".to_string());
+            METRICS
+                .db_query_duration
+                .with_label_values(&[&statement])
+                .observe(elapsed);
+        }
+    }
+}
+
+/// 检查采集令牌(未配置令牌时不校验,由 metrics_bind 限制访问),按常量时间比较
+fn authorize_scrape(config: &Config, headers: &HeaderMap) -> bool {
+    let Some(expected) = config.metrics_token.as_deref() else {
+        return true;
+    };
+
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(expected.as_bytes())))
+}
+
+/// 监控指标
+/// GET /metrics
+pub async fn metrics_handler(State((db, config)): State<AppState>, headers: HeaderMap) -> Response {
+    if !authorize_scrape(&config, &headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    if let Err(e) = METRICS.refresh(&db).await {
+        tracing::warn!("刷新业务指标失败: {}", e);
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        METRICS.encode(),
+    )
+        .into_response()
+}
+
+/// 只包含 /metrics 的路由,用于挂到主服务或单独的监听地址
+pub fn metrics_router(database: Database, config: Config) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state((database, config))
+}
@@ -9,35 +9,74 @@ use axum_extra::{
     TypedHeader,
 };
 
+use chrono::{NaiveDateTime, Utc};
+
 use crate::{
     Database, Config,
+    errors::AppError,
+    middleware::client::ClientInfo,
     models::UserInfo,
-    utils::jwt::{decode_jwt_token, Claims},
+    services::{
+        api_token::{is_api_token, ApiTokenGrant, ApiTokenService},
+        company::ensure_company_active,
+        impersonation::ImpersonationService,
+        role::RoleService,
+        session::SessionService,
+    },
+    utils::jwt::{Claims, JwtKeys},
 };
 
 type AppState = (Database, Config);
 
 #[derive(Clone)]
-pub struct AuthLayer {
-    jwt_secret: String,
-}
+pub struct AuthLayer;
 
 impl AuthLayer {
-    pub fn new(jwt_secret: String) -> Self {
-        Self { jwt_secret }
-    }
-
     pub async fn middleware(
         State((database, config)): State<AppState>,
         TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+        client: ClientInfo,
         mut request: Request,
         next: Next,
-    ) -> Result<Response, StatusCode> {
+    ) -> Result<Response, AppError> {
         let token = authorization.token();
 
+        // 个人访问令牌和公司API密钥与 JWT 通过前缀区分
+        let auth_context = if is_api_token(token) {
+            Self::authenticate_api_token(&database, token, &client).await?
+        } else {
+            Self::authenticate_session(&database, &config, token).await?
+        };
+
+        // 公司停用期间本公司用户的会话和访问令牌一律拒绝,恢复启用后无需重新登录
+        ensure_company_active(&database, auth_context.user.id).await?;
+
+        // 模拟登录期间的每个请求都记入审计日志
+        if let Some(impersonator_id) = auth_context.impersonator_id {
+            ImpersonationService::new(database.clone(), config.clone())
+                .record_request(
+                    &auth_context.user,
+                    impersonator_id,
+                    request.method().as_str(),
+                    request.uri().path(),
+                    &client,
+                )
+                .await?;
+        }
+
+        // 将认证上下文添加到请求扩展中
+        request.extensions_mut().insert(auth_context);
+
+        Ok(next.run(request).await)
+    }
+
+    async fn authenticate_session(database: &Database, config: &Config, token: &str) -> Result<AuthContext, AppError> {
         // 解码JWT token
-        let claims = decode_jwt_token(token, &config.jwt_secret)
-            .map_err(|_| StatusCode::UNAUTHORIZED)?;
+        let keys = JwtKeys::from_config(config).map_err(|e| {
+            tracing::error!("加载 JWT 密钥失败: {:#}", e);
+            AppError::Internal("加载 JWT 密钥失败".to_string())
+        })?;
+        let claims = keys.decode(token).map_err(|_| AppError::Unauthorized)?;
 
         // 从数据库获取用户信息
         let user = sqlx::query_as::<_, crate::models::User>(
@@ -45,27 +84,104 @@ impl AuthLayer {
         )
         .bind(&claims.sub)
         .fetch_optional(&database.pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+        // 令牌必须关联未撤销、未过期的登录会话(注销或刷新令牌被盗用后立即失效)
+        let session_id = claims.sid.as_deref().ok_or(AppError::Unauthorized)?;
+        let sessions = SessionService::new(database.clone(), config.clone());
+        if !sessions.is_session_active(session_id, user.id).await? {
+            return Err(AppError::Unauthorized);
+        }
+        sessions.touch(session_id).await?;
+
+        let user = RoleService::new(database.clone()).user_info(user).await?;
 
-        // 创建认证上下文
-        let auth_context = AuthContext {
-            user: user.into(),
+        Ok(AuthContext {
+            user,
+            impersonator_id: claims.impersonator_id(),
             claims,
+            api_token: None,
+        })
+    }
+
+    async fn authenticate_api_token(database: &Database, token: &str, client: &ClientInfo) -> Result<AuthContext, AppError> {
+        let (user, grant) = ApiTokenService::new(database.clone())
+            .authenticate(token, client)
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+
+        // 访问令牌没有登录会话,按令牌信息构造 claims 供处理器读取用户和角色
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: user.id.to_string(),
+            role: user.role.as_str().to_string(),
+            exp: grant
+                .expires_at
+                .as_deref()
+                .and_then(|expires_at| NaiveDateTime::parse_from_str(expires_at, "%Y-%m-%d %H:%M:%S").ok())
+                .map_or(i64::MAX, |expires_at| expires_at.and_utc().timestamp()),
+            iat: now,
+            sid: None,
+            act: None,
         };
 
-        // 将认证上下文添加到请求扩展中
-        request.extensions_mut().insert(auth_context);
+        Ok(AuthContext {
+            user,
+            claims,
+            api_token: Some(grant),
+            impersonator_id: None,
+        })
+    }
+}
 
-        Ok(next.run(request).await)
+/// 访问令牌必须带有指定的授权范围,登录会话不受限制
+///
+/// 用 `route_layer` 挂到需要该范围的路由上。
+pub async fn require_scope(
+    State(scope): State<&'static str>,
+    auth_context: AuthContext,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    match &auth_context.api_token {
+        Some(grant) if !grant.allows(scope) => Err(AppError::InsufficientScope(format!("需要 {} 权限", scope))),
+        _ => Ok(next.run(request).await),
+    }
+}
+
+/// 仅限登录会话访问的路由(账号安全、令牌管理等),访问令牌一律拒绝
+pub async fn require_session(
+    auth_context: AuthContext,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if auth_context.api_token.is_some() {
+        return Err(AppError::InsufficientScope("仅限登录会话访问".to_string()));
+    }
+    Ok(next.run(request).await)
+}
+
+/// 模拟登录期间不能访问的路由(修改密码、两步验证设置等)
+pub async fn forbid_impersonation(
+    auth_context: AuthContext,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if auth_context.impersonator_id.is_some() {
+        return Err(AppError::InsufficientScope("模拟登录期间不能进行该操作".to_string()));
     }
+    Ok(next.run(request).await)
 }
 
 #[derive(Clone, Debug)]
 pub struct AuthContext {
     pub user: UserInfo,
     pub claims: Claims,
+    /// 通过访问令牌认证时的授权,登录会话为空
+    pub api_token: Option<ApiTokenGrant>,
+    /// 模拟登录时实际操作的管理员
+    pub impersonator_id: Option<i64>,
 }
 
 // 实现从请求扩展中提取认证上下文
@@ -0,0 +1,72 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+
+/// 请求ID的请求头,由全局中间件为没有携带的请求生成并回写到响应
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+use super::auth::AuthContext;
+
+/// 请求来源信息(客户端IP、User-Agent和请求ID),用于记录登录会话和审计日志
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    /// 模拟登录时实际操作的管理员,由认证中间件之后的处理器读取
+    pub impersonator_id: Option<i64>,
+}
+
+impl ClientInfo {
+    fn from_parts(parts: &Parts) -> Self {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        // 只有来自本机反向代理(如 nginx)的请求才信任 X-Real-IP,避免客户端伪造来源
+        let forwarded = parts
+            .headers
+            .get("x-real-ip")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok());
+        let ip = match (peer, forwarded) {
+            (Some(peer), Some(forwarded)) if peer.is_loopback() => Some(forwarded),
+            (Some(peer), _) => Some(peer),
+            (None, forwarded) => forwarded,
+        };
+
+        Self {
+            ip_address: ip.map(|ip| ip.to_string()),
+            user_agent: parts
+                .headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(255).collect()),
+            request_id: parts
+                .headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(64).collect()),
+            impersonator_id: parts
+                .extensions
+                .get::<AuthContext>()
+                .and_then(|auth_context| auth_context.impersonator_id),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts))
+    }
+}
@@ -1 +1,4 @@
 pub mod auth;
+pub mod client;
+pub mod rate_limit;
+pub mod security;
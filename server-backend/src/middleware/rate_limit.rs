@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+
+use crate::{
+    errors::AppError,
+    metrics::METRICS,
+    middleware::{auth::AuthContext, client::ClientInfo},
+};
+
+/// 内存中最多保留的令牌桶数,超过后清理已经补满的桶
+const MAX_IDLE_BUCKETS: usize = 10_000;
+/// 两次清理之间的最短间隔,避免桶数较多时每个新键都遍历一遍
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 令牌桶额度: 最多连续 `capacity` 个请求,`period_seconds` 秒内补满
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub capacity: u32,
+    pub period_seconds: u64,
+}
+
+impl Quota {
+    /// 解析 `次数/秒数` 形式的配置,如 `600/60`;为空或为0表示不限制
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        let parsed = value.split_once('/').and_then(|(capacity, period)| {
+            Some(Self {
+                capacity: capacity.trim().parse().ok()?,
+                period_seconds: period.trim().parse().ok()?,
+            })
+        });
+        match parsed {
+            Some(quota) if quota.capacity > 0 && quota.period_seconds > 0 => Some(quota),
+            Some(_) => None,
+            None => {
+                tracing::warn!("⚠️  忽略无效的限流配置: {}(格式: 次数/秒数)", value);
+                None
+            }
+        }
+    }
+
+    /// 每秒补充的令牌数
+    fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period_seconds as f64
+    }
+}
+
+/// 一个令牌桶的检查结果
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    /// 该桶是否还有令牌
+    pub allowed: bool,
+    pub quota: Quota,
+    /// 本次检查后桶内剩余的令牌数
+    pub remaining: u32,
+    /// 桶补满还需的秒数
+    pub reset_seconds: u64,
+    /// 被拒绝时至少需要等待的秒数
+    pub retry_after: u64,
+}
+
+/// 令牌桶存储
+///
+/// 目前只有进程内的 [`MemoryStore`];多实例部署时可以实现基于共享存储(如 Redis)的版本。
+pub trait RateLimitStore: Send + Sync {
+    /// 原子地检查一组令牌桶: 每个桶都有令牌时各取一个,否则一个都不取
+    ///
+    /// 被某个桶拒绝的请求不消耗其他桶的额度,已被限流的用户或API密钥不会继续占用公司额度。
+    fn acquire<'a>(&'a self, buckets: &'a [(String, Quota)]) -> BoxFuture<'a, Result<Vec<Decision>, AppError>>;
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    /// 每秒补充的令牌数
+    rate: f64,
+    updated: Instant,
+}
+
+/// 进程内的令牌桶存储,重启后清空
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    buckets: HashMap<String, Bucket>,
+    last_sweep: Option<Instant>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn take(&self, requested: &[(String, Quota)], now: Instant) -> Vec<Decision> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.buckets.len() >= MAX_IDLE_BUCKETS
+            && state.last_sweep.is_none_or(|last| now.duration_since(last) >= SWEEP_INTERVAL)
+        {
+            // 已补满的桶与新建的桶等价,可以直接丢弃
+            state.buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * bucket.rate < bucket.capacity
+            });
+            state.last_sweep = Some(now);
+        }
+
+        // 先补充令牌并检查所有桶,全部有令牌时才扣减
+        for (key, quota) in requested {
+            let bucket = state.buckets.entry(key.clone()).or_insert(Bucket {
+                tokens: quota.capacity as f64,
+                capacity: quota.capacity as f64,
+                rate: quota.refill_rate(),
+                updated: now,
+            });
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.capacity);
+            bucket.updated = now;
+        }
+        let admitted = requested.iter().all(|(key, _)| state.buckets[key].tokens >= 1.0);
+
+        requested
+            .iter()
+            .map(|(key, quota)| {
+                let bucket = state.buckets.get_mut(key).expect("令牌桶已创建");
+                let allowed = bucket.tokens >= 1.0;
+                if admitted {
+                    bucket.tokens -= 1.0;
+                }
+                Decision {
+                    allowed,
+                    quota: *quota,
+                    remaining: bucket.tokens.floor() as u32,
+                    reset_seconds: ((bucket.capacity - bucket.tokens) / bucket.rate).ceil() as u64,
+                    retry_after: if allowed { 0 } else { ((1.0 - bucket.tokens) / bucket.rate).ceil().max(1.0) as u64 },
+                }
+            })
+            .collect()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn acquire<'a>(&'a self, buckets: &'a [(String, Quota)]) -> BoxFuture<'a, Result<Vec<Decision>, AppError>> {
+        Box::pin(async move { Ok(self.take(buckets, Instant::now())) })
+    }
+}
+
+/// 一组路由的限流规则
+///
+/// 未登录的请求按客户端IP计数;已登录的请求分别按用户(或访问令牌)和所属公司计数,
+/// 任一额度用完即拒绝。
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    /// 路由组名称,不同路由组的计数互不影响
+    group: &'static str,
+    per_ip: Option<Quota>,
+    per_principal: Option<Quota>,
+    per_company: Option<Quota>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, group: &'static str) -> Self {
+        Self {
+            store,
+            group,
+            per_ip: None,
+            per_principal: None,
+            per_company: None,
+        }
+    }
+
+    /// 按客户端IP限流
+    pub fn per_ip(mut self, quota: Option<Quota>) -> Self {
+        self.per_ip = quota;
+        self
+    }
+
+    /// 按登录用户或访问令牌限流
+    pub fn per_principal(mut self, quota: Option<Quota>) -> Self {
+        self.per_principal = quota;
+        self
+    }
+
+    /// 按所属公司限流(同一公司的所有用户和API密钥共用)
+    pub fn per_company(mut self, quota: Option<Quota>) -> Self {
+        self.per_company = quota;
+        self
+    }
+
+    /// 本次请求需要检查的令牌桶: (计数类型, 键, 额度)
+    fn buckets(&self, request: &Request, client: &ClientInfo) -> Vec<(&'static str, String, Quota)> {
+        let mut buckets = Vec::new();
+        if let Some(quota) = self.per_ip {
+            let ip = client.ip_address.as_deref().unwrap_or("unknown");
+            buckets.push(("ip", format!("{}:ip:{}", self.group, ip), quota));
+        }
+        if let Some(auth_context) = request.extensions().get::<AuthContext>() {
+            if let Some(quota) = self.per_principal {
+                let key = match &auth_context.api_token {
+                    Some(grant) => format!("{}:key:{}", self.group, grant.id),
+                    None => format!("{}:user:{}", self.group, auth_context.user.id),
+                };
+                let kind = if auth_context.api_token.is_some() { "api_key" } else { "user" };
+                buckets.push((kind, key, quota));
+            }
+            if let (Some(quota), Some(company_id)) = (self.per_company, auth_context.user.company_id) {
+                buckets.push(("company", format!("{}:company:{}", self.group, company_id), quota));
+            }
+        }
+        buckets
+    }
+}
+
+/// 在响应中附加 `RateLimit-*` 响应头
+fn set_rate_limit_headers(response: &mut Response, decision: &Decision) {
+    let headers = response.headers_mut();
+    for (name, value) in [
+        ("ratelimit-limit", decision.quota.capacity.to_string()),
+        ("ratelimit-remaining", decision.remaining.to_string()),
+        ("ratelimit-reset", decision.reset_seconds.to_string()),
+        (
+            "ratelimit-policy",
+            format!("{};w={}", decision.quota.capacity, decision.quota.period_seconds),
+        ),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+/// 令牌桶限流中间件
+///
+/// 挂在认证中间件内层,才能按用户和公司计数;存储出错时放行请求,避免限流拖垮服务。
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    client: ClientInfo,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (kinds, buckets): (Vec<&'static str>, Vec<(String, Quota)>) = limiter
+        .buckets(&request, &client)
+        .into_iter()
+        .map(|(kind, key, quota)| (kind, (key, quota)))
+        .unzip();
+    let decisions = if buckets.is_empty() {
+        Vec::new()
+    } else {
+        limiter.store.acquire(&buckets).await.unwrap_or_else(|e| {
+            tracing::warn!("限流存储不可用,放行请求: {}", e);
+            Vec::new()
+        })
+    };
+
+    // 响应头报告剩余额度最少的桶;被拒绝时报告需要等待最久的桶
+    let mut reported: Option<Decision> = None;
+    let mut rejected: Option<(&'static str, Decision)> = None;
+    for (kind, decision) in kinds.into_iter().zip(decisions) {
+        if !decision.allowed {
+            if rejected.is_none_or(|(_, current)| decision.retry_after > current.retry_after) {
+                rejected = Some((kind, decision));
+            }
+        } else if reported.is_none_or(|current| decision.remaining < current.remaining) {
+            reported = Some(decision);
+        }
+    }
+
+    if let Some((kind, decision)) = rejected {
+        METRICS
+            .rate_limited_requests
+            .with_label_values(&[limiter.group, kind])
+            .inc();
+        let mut response = AppError::RateLimited(decision.retry_after as i64).into_response();
+        set_rate_limit_headers(&mut response, &decision);
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    if let Some(decision) = reported {
+        set_rate_limit_headers(&mut response, &decision);
+    }
+    response
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::Config;
+
+/// 允许跨域访问的来源,支持 `https://*.example.com` 形式的通配子域名
+#[derive(Debug, Clone)]
+enum OriginPattern {
+    Exact(String),
+    /// (协议前缀 `https://`, 域名后缀 `.example.com[:端口]`)
+    Subdomain(String, String),
+}
+
+impl OriginPattern {
+    fn parse(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim().trim_end_matches('/').to_ascii_lowercase();
+        if pattern.is_empty() {
+            return None;
+        }
+        match pattern.split_once("://*.") {
+            Some((scheme, domain)) if !domain.is_empty() => {
+                Some(Self::Subdomain(format!("{}://", scheme), format!(".{}", domain)))
+            }
+            _ => Some(Self::Exact(pattern)),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Exact(allowed) => allowed == origin,
+            // 通配符只匹配子域名,不匹配主域名本身
+            Self::Subdomain(scheme, suffix) => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|subdomain| {
+                    !subdomain.is_empty()
+                        && subdomain
+                            .split('.')
+                            .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+                }),
+        }
+    }
+}
+
+/// 按 `ALLOWED_ORIGINS` 构建CORS中间件,配置为 `*` 时允许任意来源
+pub fn cors_layer(config: &Config) -> CorsLayer {
+    let allow_origin = if config.allowed_origins.iter().any(|origin| origin.trim() == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        let patterns: Vec<OriginPattern> = config
+            .allowed_origins
+            .iter()
+            .filter_map(|origin| OriginPattern::parse(origin))
+            .collect();
+        AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin
+                .to_str()
+                .map(|origin| {
+                    let origin = origin.to_ascii_lowercase();
+                    patterns.iter().any(|pattern| pattern.matches(&origin))
+                })
+                .unwrap_or(false)
+        })
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::PATCH,
+            Method::OPTIONS,
+        ])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static("x-requested-with"),
+            HeaderName::from_static("x-request-id"),
+        ])
+        .expose_headers([
+            HeaderName::from_static("x-request-id"),
+            HeaderName::from_static("ratelimit-limit"),
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderName::from_static("ratelimit-reset"),
+            header::RETRY_AFTER,
+        ])
+        .allow_credentials(false)
+}
+
+/// 内容安全策略,并按 `frame_ancestors` 补充 `frame-ancestors` 指令
+///
+/// 策略为空时不发送该响应头。
+pub fn content_security_policy(policy: &str, frame_ancestors: &str) -> Option<HeaderValue> {
+    let policy = policy.trim().trim_end_matches(';');
+    if policy.is_empty() {
+        return None;
+    }
+    let policy = if frame_ancestors.is_empty() || policy.contains("frame-ancestors") {
+        policy.to_string()
+    } else {
+        format!("{}; frame-ancestors {}", policy, frame_ancestors)
+    };
+    header_value(header::CONTENT_SECURITY_POLICY, &policy)
+}
+
+fn header_value(name: HeaderName, value: &str) -> Option<HeaderValue> {
+    match HeaderValue::from_str(value) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            tracing::warn!("⚠️  忽略无效的 {} 配置: {}", name, value);
+            None
+        }
+    }
+}
+
+/// 所有响应统一附加的安全响应头
+#[derive(Debug, Clone, Default)]
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &Config) -> Arc<Self> {
+        let mut headers = vec![(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"))];
+
+        if let Some(policy) = content_security_policy(&config.content_security_policy, &config.frame_ancestors) {
+            headers.push((header::CONTENT_SECURITY_POLICY, policy));
+        }
+        // 旧浏览器不支持 frame-ancestors,同时发送等价的 X-Frame-Options
+        match config.frame_ancestors.as_str() {
+            "'none'" => headers.push((header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"))),
+            "'self'" => headers.push((header::X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"))),
+            _ => {}
+        }
+        if !config.referrer_policy.is_empty() {
+            if let Some(value) = header_value(header::REFERRER_POLICY, &config.referrer_policy) {
+                headers.push((header::REFERRER_POLICY, value));
+            }
+        }
+        // HSTS 只在本服务直接提供 HTTPS 时发送,明文响应中的 HSTS 会被浏览器忽略
+        if config.enable_tls && config.hsts_max_age > 0 {
+            let value = format!("max-age={}; includeSubDomains", config.hsts_max_age);
+            if let Some(value) = header_value(header::STRICT_TRANSPORT_SECURITY, &value) {
+                headers.push((header::STRICT_TRANSPORT_SECURITY, value));
+            }
+        }
+
+        Arc::new(Self { headers })
+    }
+}
+
+/// 为响应附加安全响应头
+///
+/// 已由路由设置的同名响应头保留不变,路由可以用 `route_layer` 覆盖默认值(如 /docs 的内容安全策略)。
+pub async fn security_headers(
+    State(security): State<Arc<SecurityHeaders>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    for (name, value) in &security.headers {
+        if !headers.contains_key(name) {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+    response
+}
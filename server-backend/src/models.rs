@@ -2,7 +2,11 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
+use crate::utils::pii::{OptionalSealedText, SealedText};
 use std::str::FromStr;
+use std::collections::HashMap;
+
+pub use flow_farm_rbac::{BuiltinRole, Permission, PermissionSet};
 
 // ==================== 数据模型说明 ====================
 // 
@@ -43,7 +47,12 @@ use std::str::FromStr;
 pub struct Company {
     pub id: i64,
     pub name: String,
+    /// 公司代码,单点登录时按它找到公司(与自助注册的加入码无关)
+    pub code: String,
+    /// 联系方式加密保存,读取时自动解密
+    #[sqlx(try_from = "OptionalSealedText")]
     pub contact_email: Option<String>,
+    #[sqlx(try_from = "OptionalSealedText")]
     pub contact_phone: Option<String>,
     pub max_employees: i32,
     pub is_active: bool,
@@ -76,6 +85,7 @@ pub struct UpdateCompanyRequest {
 pub struct CompanyInfo {
     pub id: i64,
     pub name: String,
+    pub code: String,
     pub contact_email: Option<String>,
     pub contact_phone: Option<String>,
     pub max_employees: i32,
@@ -90,6 +100,7 @@ impl From<Company> for CompanyInfo {
         Self {
             id: company.id,
             name: company.name,
+            code: company.code,
             contact_email: company.contact_email,
             contact_phone: company.contact_phone,
             max_employees: company.max_employees,
@@ -107,6 +118,8 @@ impl From<Company> for CompanyInfo {
 pub struct User {
     pub id: i64,  // SQLite使用INTEGER类型
     pub username: String,
+    /// 邮箱加密保存,读取时自动解密
+    #[sqlx(try_from = "SealedText")]
     pub email: String,
     pub hashed_password: String,
     pub role: UserRole,
@@ -117,6 +130,10 @@ pub struct User {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub last_login: Option<DateTime<Utc>>,
+    /// 公司自定义角色,设置后用户的权限以该角色为准
+    #[sqlx(default)]
+    #[serde(default)]
+    pub custom_role_id: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -170,6 +187,19 @@ impl UserRole {
             _ => None,
         }
     }
+
+    pub fn builtin(&self) -> BuiltinRole {
+        match self {
+            UserRole::PlatformAdmin => BuiltinRole::PlatformAdmin,
+            UserRole::ProjectManager => BuiltinRole::ProjectManager,
+            UserRole::TaskExecutor => BuiltinRole::TaskExecutor,
+        }
+    }
+
+    /// 内置角色的权限组合
+    pub fn permissions(&self) -> PermissionSet {
+        self.builtin().permissions()
+    }
 }
 
 // 实现FromStr trait用于handlers中解析
@@ -222,12 +252,29 @@ pub struct CreateUserRequest {
     pub parent_id: Option<i64>,  // 上级用户ID,用于层级隔离(临时方案)
 }
 
+/// 自助注册请求: 只能以任务执行者身份加入加入码对应的公司
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RegisterRequest {
+    #[validate(length(min = 3, max = 50))]
+    pub username: String,
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(min = 6))]
+    pub password: String,
+    #[serde(default)]
+    pub full_name: String,
+    /// 公司加入码
+    #[validate(length(min = 1, max = 64))]
+    pub join_code: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct UpdateUserRequest {
     #[validate(length(min = 3, max = 50))]
     pub username: Option<String>,
     #[validate(email)]
     pub email: Option<String>,
+    /// 管理员为其他用户设置密码;修改自己的密码需使用修改密码接口
     pub password: Option<String>,
     pub full_name: Option<String>,
     pub is_active: Option<bool>,
@@ -241,16 +288,604 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+/// 登录结果: 需要两步验证时只返回 `two_factor` 挑战,验证通过后才签发令牌
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// 刷新令牌,访问令牌过期后用于换取新令牌(只能使用一次)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// 访问令牌有效期(秒)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<UserInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<TwoFactorChallenge>,
+    /// 登录时完成两步验证注册后生成的恢复码(只显示这一次)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+impl LoginResponse {
+    pub fn authenticated(tokens: TokenPair, user: UserInfo) -> Self {
+        Self {
+            token: Some(tokens.token),
+            refresh_token: Some(tokens.refresh_token),
+            expires_in: Some(tokens.expires_in),
+            user: Some(user),
+            two_factor: None,
+            recovery_codes: None,
+        }
+    }
+
+    pub fn challenge(challenge: TwoFactorChallenge) -> Self {
+        Self {
+            token: None,
+            refresh_token: None,
+            expires_in: None,
+            user: None,
+            two_factor: Some(challenge),
+            recovery_codes: None,
+        }
+    }
+}
+
+/// 待完成的两步验证登录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorChallenge {
+    /// 提交验证码时携带的挑战令牌
+    pub challenge_token: String,
+    /// 挑战有效期(秒)
+    pub expires_in: i64,
+    /// 策略要求两步验证但用户尚未启用,需先通过 /auth/2fa/challenge/enroll 注册
+    pub enrollment_required: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct VerifyTwoFactorRequest {
+    #[validate(length(min = 1))]
+    pub challenge_token: String,
+    /// 验证器应用生成的6位验证码或恢复码
+    #[validate(length(min = 1))]
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct TwoFactorChallengeRequest {
+    #[validate(length(min = 1))]
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct TwoFactorCodeRequest {
+    #[validate(length(min = 1))]
+    pub code: String,
+}
+
+/// 两步验证注册信息,用户确认验证码前不会启用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorEnrollment {
+    /// Base32密钥,无法扫码时手动输入
+    pub secret: String,
+    /// 生成二维码用的 otpauth:// 地址
+    pub otpauth_uri: String,
+}
+
+/// 当前用户的两步验证状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    /// 公司或平台策略是否要求该用户启用
+    pub required: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorPolicyRequest {
+    pub required: bool,
+}
+
+/// 平台注册策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    /// 只能由管理员创建账号
+    Disabled,
+    /// 只能通过邀请邮件创建账号
+    #[default]
+    InviteOnly,
+    /// 可以凭公司加入码自助注册,邀请邮件同样可用
+    Open,
+}
+
+impl RegistrationMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegistrationMode::Disabled => "disabled",
+            RegistrationMode::InviteOnly => "invite_only",
+            RegistrationMode::Open => "open",
+        }
+    }
+}
+
+impl FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disabled" => Ok(RegistrationMode::Disabled),
+            "invite_only" => Ok(RegistrationMode::InviteOnly),
+            "open" => Ok(RegistrationMode::Open),
+            _ => Err(format!("未知的注册策略: {}", s)),
+        }
+    }
+}
+
+/// 注册策略设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct RegistrationPolicy {
+    pub mode: RegistrationMode,
+    /// 开放注册时允许的邮箱域名,为空表示不限制
+    #[serde(default)]
+    #[validate(length(max = 100))]
+    pub allowed_domains: Vec<String>,
+}
+
+/// 公开的注册策略,只说明是否开放自助注册
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicRegistrationPolicy {
+    pub mode: RegistrationMode,
+}
+
+/// 公司加入码
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompanyJoinCode {
+    pub company_id: i64,
+    pub join_code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RefreshTokenRequest {
+    #[validate(length(min = 1))]
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1))]
+    pub old_password: String,
+    #[validate(length(min = 1))]
+    pub new_password: String,
+}
+
+/// 访问令牌类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenKind {
+    /// 个人访问令牌,以所有者身份访问
+    #[default]
+    Personal,
+    /// 公司API密钥,由公司管理者创建并以创建人的身份访问
+    Company,
+}
+
+impl ApiTokenKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTokenKind::Personal => "personal",
+            ApiTokenKind::Company => "company",
+        }
+    }
+
+}
+
+impl FromStr for ApiTokenKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "personal" => Ok(ApiTokenKind::Personal),
+            "company" => Ok(ApiTokenKind::Company),
+            _ => Err(format!("未知的令牌类型: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateApiTokenRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[serde(default)]
+    pub kind: ApiTokenKind,
+    /// 授权范围,如 `tasks:read`、`tasks:write`
+    #[validate(length(min = 1))]
+    pub scopes: Vec<String>,
+    /// 有效天数,为空表示不过期
+    #[validate(range(min = 1, max = 365))]
+    pub expires_in_days: Option<i64>,
+}
+
+/// 访问令牌信息(不含令牌本身)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiTokenInfo {
+    pub id: String,
+    pub kind: ApiTokenKind,
+    pub name: String,
+    /// 令牌开头几位,便于用户辨认
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+    /// 个人令牌的所有者或公司密钥的创建人
+    pub user_id: i64,
+    pub company_id: Option<i64>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub last_used_ip: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// 新创建的访问令牌,`token` 只在创建时返回一次
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedApiToken {
     pub token: String,
+    #[serde(flatten)]
+    pub info: ApiTokenInfo,
+}
+
+/// 邀请新成员加入公司
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateInvitationRequest {
+    #[validate(email)]
+    pub email: String,
+    pub role: UserRole,
+    /// 平台管理员需指定公司,其他用户只能邀请到本公司
+    pub company_id: Option<i64>,
+    #[validate(length(max = 100))]
+    pub full_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InvitationQuery {
+    /// 平台管理员查看指定公司的邀请,其他用户只能查看本公司
+    pub company_id: Option<i64>,
+}
+
+/// 邀请记录(不含邀请令牌)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitationInfo {
+    pub id: i64,
+    pub company_id: i64,
+    pub email: String,
+    pub role: UserRole,
+    pub full_name: Option<String>,
+    pub invited_by: i64,
+    /// pending / accepted / revoked / expired
+    pub status: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub accepted_user_id: Option<i64>,
+}
+
+/// 受邀人打开邀请链接时看到的信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitationPreview {
+    pub email: String,
+    pub company_name: String,
+    pub role: UserRole,
+    pub full_name: Option<String>,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct InvitationTokenRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+}
+
+/// 受邀人接受邀请,设置自己的账号和资料
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct AcceptInvitationRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+    #[validate(length(min = 3, max = 50))]
+    pub username: String,
+    pub password: String,
+    #[validate(length(max = 100))]
+    pub full_name: Option<String>,
+    #[validate(length(max = 30))]
+    pub phone: Option<String>,
+}
+
+/// 平台管理员模拟登录为指定用户
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct StartImpersonationRequest {
+    /// 模拟原因,记入审计日志
+    #[validate(length(min = 1, max = 500))]
+    pub reason: String,
+    /// 有效时长(分钟),默认30分钟,最长60分钟
+    pub duration_minutes: Option<i64>,
+}
+
+/// 模拟登录令牌,到期后不能刷新
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpersonationResponse {
+    pub token: String,
+    pub expires_in: i64,
+    pub user: UserInfo,
+}
+
+/// 正在模拟登录的管理员
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpersonatorInfo {
+    pub id: i64,
+    pub username: String,
+    pub full_name: Option<String>,
+    /// 模拟会话的到期时间
+    pub expires_at: String,
+}
+
+/// 当前登录用户,模拟登录时附带实际操作的管理员
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentUserInfo {
+    #[serde(flatten)]
     pub user: UserInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impersonator: Option<ImpersonatorInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SsoStartRequest {
+    /// 公司代码,用于找到该公司的身份提供方配置
+    #[validate(length(min = 1))]
+    pub company_code: String,
+}
+
+/// 跳转到身份提供方的授权地址
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SsoAuthorization {
+    pub authorization_url: String,
+}
+
+/// 身份提供方回调时带回的参数
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SsoCallbackRequest {
+    #[validate(length(min = 1))]
+    pub code: String,
+    #[validate(length(min = 1))]
+    pub state: String,
+}
+
+/// 设置公司的单点登录配置
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CompanySsoConfigRequest {
+    #[validate(url)]
+    pub issuer: String,
+    #[validate(length(min = 1))]
+    pub client_id: String,
+    /// 为空表示保留原值,空字符串表示清除(公开客户端只使用 PKCE)
+    pub client_secret: Option<String>,
+    #[serde(default = "default_sso_scopes")]
+    pub scopes: String,
+    /// 用于映射角色的声明名称,如 `groups`
+    pub role_claim: Option<String>,
+    /// 声明值到角色的映射,多个值匹配时取权限最高的角色
+    #[serde(default)]
+    pub role_mapping: HashMap<String, UserRole>,
+    /// 没有匹配的映射时新建用户使用的角色
+    #[serde(default = "default_sso_role")]
+    pub default_role: UserRole,
+    /// 首次登录时自动创建本公司用户
+    #[serde(default = "default_true")]
+    pub jit_provisioning: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_sso_scopes() -> String {
+    "openid email profile".to_string()
+}
+
+fn default_sso_role() -> UserRole {
+    UserRole::TaskExecutor
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 公司的单点登录配置(不含客户端密钥)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompanySsoConfig {
+    pub company_id: i64,
+    pub issuer: String,
+    pub client_id: String,
+    pub has_client_secret: bool,
+    pub scopes: String,
+    pub role_claim: Option<String>,
+    pub role_mapping: HashMap<String, UserRole>,
+    pub default_role: UserRole,
+    pub jit_provisioning: bool,
+    pub enabled: bool,
+    /// 身份提供方需登记的回调地址
+    pub redirect_uri: String,
+    pub updated_at: String,
+}
+
+/// 用户关联的外部身份
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserIdentityInfo {
+    pub id: i64,
+    pub issuer: String,
+    pub subject: String,
+    #[sqlx(try_from = "OptionalSealedText")]
+    pub email: Option<String>,
+    pub created_at: String,
+    pub last_login_at: Option<String>,
+}
+
+/// 权限说明
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionInfo {
+    pub name: Permission,
+    pub description: String,
+    /// 平台级权限不能加入公司自定义角色
+    pub platform: bool,
+}
+
+/// 内置角色及其权限组合
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuiltinRoleInfo {
+    pub role: UserRole,
+    pub name: String,
+    pub permissions: PermissionSet,
+}
+
+/// 公司自定义角色
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompanyRoleInfo {
+    pub id: i64,
+    pub company_id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: PermissionSet,
+    /// 使用该角色的用户数
+    pub user_count: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// 公司可用的角色: 内置角色和本公司的自定义角色
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompanyRoles {
+    pub builtin: Vec<BuiltinRoleInfo>,
+    pub custom: Vec<CompanyRoleInfo>,
+}
+
+/// 创建或修改自定义角色
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CompanyRoleRequest {
+    #[validate(length(min = 1, max = 50))]
+    pub name: String,
+    #[validate(length(max = 200))]
+    pub description: Option<String>,
+    /// 权限名称,如 `task.assign`
+    pub permissions: Vec<String>,
+}
+
+/// 为用户分配自定义角色,`custom_role_id` 为空时恢复为内置角色的权限
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssignRoleRequest {
+    pub custom_role_id: Option<i64>,
+}
+
+/// 审计日志记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub occurred_at: String,
+    pub action: String,
+    pub actor_id: Option<i64>,
+    /// 代为操作的管理员(模拟登录时)
+    pub impersonator_id: Option<i64>,
+    pub company_id: Option<i64>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    /// 事件详情,修改类事件包含字段变化 `{"字段": {"from": 旧值, "to": 新值}}`
+    pub details: Option<serde_json::Value>,
+    pub hash: Option<String>,
+}
+
+/// 审计日志查询条件,时间格式为 `YYYY-MM-DD` 或 `YYYY-MM-DD HH:MM:SS`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditLogQuery {
+    pub action: Option<String>,
+    pub actor_id: Option<i64>,
+    pub company_id: Option<i64>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub request_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// 分页游标: 只返回 id 小于该值的记录
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+    /// 导出格式: csv(默认)或 jsonl
+    pub format: Option<String>,
+}
+
+/// 哈希链校验结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditChainStatus {
+    /// 已校验的记录数
+    pub checked: i64,
+    pub valid: bool,
+    /// 第一条哈希不匹配或与上一条断开的记录
+    pub broken_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct VerifyEmailRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct PasswordResetRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+    #[validate(length(min = 1))]
+    pub new_password: String,
+}
+
+/// 有效的登录会话
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub user_id: i64,
+    /// 登录时的 User-Agent
+    pub user_agent: Option<String>,
+    /// web / desktop
+    pub client_type: String,
+    pub ip_address: Option<String>,
+    pub created_at: String,
+    pub last_used_at: String,
+    pub expires_at: String,
+    /// 是否为发出请求的当前会话
+    pub current: bool,
+    /// 模拟登录会话对应的管理员
+    pub impersonator_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionQuery {
+    /// 查看指定用户的会话(需要该用户所在公司的账号安全权限),默认查看自己的
+    pub user_id: Option<i64>,
+}
+
+/// 刷新后签发的新令牌
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct UserInfo {
     pub id: i64,  // SQLite使用INTEGER类型
     pub username: String,
+    #[sqlx(try_from = "SealedText")]
     pub email: String,
     pub full_name: String,
     pub role: UserRole,
@@ -259,10 +894,32 @@ pub struct UserInfo {
     pub parent_id: Option<i64>,  // 上级用户ID
     pub created_at: String,
     pub last_login: Option<String>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub custom_role_id: Option<i64>,
+    /// 有效权限(内置角色或公司自定义角色),只在当前用户的信息中返回
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "PermissionSet::is_empty")]
+    pub permissions: PermissionSet,
+}
+
+impl UserInfo {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(permission)
+    }
+
+    /// 是否可以在指定公司内行使权限: `platform.manage` 跨公司生效,其余权限只在本公司生效
+    pub fn can(&self, permission: Permission, company_id: Option<i64>) -> bool {
+        self.has_permission(permission)
+            && (self.has_permission(Permission::PlatformManage)
+                || (company_id.is_some() && company_id == self.company_id))
+    }
 }
 
 impl From<User> for UserInfo {
     fn from(user: User) -> Self {
+        // 自定义角色的权限需查询数据库,由 RoleService::user_info 填充
+        let permissions = if user.custom_role_id.is_none() { user.role.permissions() } else { PermissionSet::new() };
         Self {
             id: user.id,
             username: user.username,
@@ -276,6 +933,8 @@ impl From<User> for UserInfo {
             last_login: user
                 .last_login
                 .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string()),
+            custom_role_id: user.custom_role_id,
+            permissions,
         }
     }
 }
@@ -412,6 +1071,8 @@ pub struct CreateTaskRequest {
     pub assigned_to: Option<Uuid>,
     pub due_date: Option<DateTime<Utc>>,
     pub estimated_hours: Option<f64>,
+    #[serde(default)]
+    pub labels: Vec<String>,              // 任务标签(用于工时估算分析)
 }
 
 /// 更新任务请求
@@ -428,6 +1089,7 @@ pub struct UpdateTaskRequest {
     pub assigned_to: Option<Uuid>,
     pub due_date: Option<DateTime<Utc>>,
     pub estimated_hours: Option<f64>,
+    pub labels: Option<Vec<String>>,      // 提供时整体替换任务标签
 }
 
 /// 任务信息响应（包含额外的计算字段）
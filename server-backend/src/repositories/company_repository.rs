@@ -1,4 +1,4 @@
-use crate::{models::Company, Database};
+use crate::{models::Company, utils::{join_code::generate_join_code, pii}, Database};
 use anyhow::Result;
 
 /// CompanyRepository: 负责所有公司相关的数据库操作
@@ -29,6 +29,15 @@ impl CompanyRepository {
         Ok(company)
     }
 
+    /// 根据公司代码查询
+    pub async fn find_by_code(&self, code: &str) -> Result<Option<Company>> {
+        let company = sqlx::query_as::<_, Company>("SELECT * FROM companies WHERE code = ?")
+            .bind(code)
+            .fetch_optional(&self.database.pool)
+            .await?;
+        Ok(company)
+    }
+
     /// 获取所有公司列表
     pub async fn list_all(&self) -> Result<Vec<Company>> {
         let companies = sqlx::query_as::<_, Company>(
@@ -49,17 +58,19 @@ impl CompanyRepository {
         Ok(companies)
     }
 
-    /// 创建新公司
+    /// 创建新公司,同时生成自助注册用的加入码
     pub async fn create(&self, company: Company) -> Result<Company> {
         let result = sqlx::query(
             r#"
-            INSERT INTO companies (name, contact_email, contact_phone, max_employees, is_active, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO companies (name, code, join_code, contact_email, contact_phone, max_employees, is_active, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(&company.name)
-        .bind(&company.contact_email)
-        .bind(&company.contact_phone)
+        .bind(&company.code)
+        .bind(generate_join_code())
+        .bind(pii::seal_optional(company.contact_email.as_deref())?)
+        .bind(pii::seal_optional(company.contact_phone.as_deref())?)
         .bind(company.max_employees)
         .bind(company.is_active)
         .bind(company.created_at)
@@ -78,14 +89,15 @@ impl CompanyRepository {
         sqlx::query(
             r#"
             UPDATE companies 
-            SET name = ?, contact_email = ?, contact_phone = ?, max_employees = ?, 
+            SET name = ?, code = ?, contact_email = ?, contact_phone = ?, max_employees = ?, 
                 is_active = ?, updated_at = ?
             WHERE id = ?
             "#
         )
         .bind(&company.name)
-        .bind(&company.contact_email)
-        .bind(&company.contact_phone)
+        .bind(&company.code)
+        .bind(pii::seal_optional(company.contact_email.as_deref())?)
+        .bind(pii::seal_optional(company.contact_phone.as_deref())?)
         .bind(company.max_employees)
         .bind(company.is_active)
         .bind(company.updated_at)
@@ -2,6 +2,7 @@ use crate::database::Database;
 use crate::errors::AppError;
 use crate::models::{Task, TaskStatus, CreateTaskRequest, UpdateTaskRequest};
 use chrono::Utc;
+use sqlx::SqliteConnection;
 use uuid::Uuid;
 
 /// 任务数据仓库
@@ -34,6 +35,10 @@ impl TaskRepository {
             company_id,  // 多租户隔离
         };
 
+        // 任务和标签一起提交,标签写入失败时不留下没有标签的任务
+        let mut tx = self.db.pool.begin().await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
         sqlx::query(
             r#"
             INSERT INTO tasks (
@@ -59,10 +64,15 @@ impl TaskRepository {
         .bind(&task.updated_at)
         .bind(&task.completed_at)
         .bind(&task.company_id)
-        .execute(&self.db.pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
+        Self::replace_labels(&mut tx, task.id, &request.labels).await?;
+
+        tx.commit().await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
         Ok(task)
     }
 
@@ -109,9 +119,11 @@ impl TaskRepository {
         if let Some(estimated_hours) = request.estimated_hours {
             task.estimated_hours = Some(estimated_hours);
         }
-
         task.updated_at = Utc::now();
 
+        let mut tx = self.db.pool.begin().await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
         sqlx::query(
             r#"
             UPDATE tasks 
@@ -132,10 +144,17 @@ impl TaskRepository {
         .bind(&task.updated_at)
         .bind(&task.completed_at)
         .bind(&task.id)
-        .execute(&self.db.pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
+        if let Some(labels) = &request.labels {
+            Self::replace_labels(&mut tx, task.id, labels).await?;
+        }
+
+        tx.commit().await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
         Ok(task)
     }
 
@@ -292,4 +311,24 @@ impl TaskRepository {
 
         Ok(task)
     }
+
+    /// 替换任务标签(去除首尾空白、忽略空标签和重复标签),在调用方的事务中执行
+    async fn replace_labels(conn: &mut SqliteConnection, task_id: Uuid, labels: &[String]) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM task_labels WHERE task_id = ?")
+            .bind(task_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        for label in labels.iter().map(|label| label.trim()).filter(|label| !label.is_empty()) {
+            sqlx::query("INSERT OR IGNORE INTO task_labels (task_id, label) VALUES (?, ?)")
+                .bind(task_id)
+                .bind(label)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
 }
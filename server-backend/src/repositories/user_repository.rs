@@ -1,4 +1,4 @@
-use crate::{models::User, Database};
+use crate::{models::User, utils::pii, Database};
 use anyhow::Result;
 
 /// UserRepository: 负责所有用户相关的数据库操作
@@ -29,9 +29,10 @@ impl UserRepository {
         Ok(user)
     }
 
-    /// 根据邮箱查询用户
+    /// 根据邮箱查询用户(邮箱加密保存,按盲索引查找)
     pub async fn find_by_email(&self, email: &str) -> Result<Option<User>> {
-        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = ?")
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email_index = ? OR email = ?")
+            .bind(pii::blind_index(email))
             .bind(email)
             .fetch_optional(&self.database.pool)
             .await?;
@@ -50,12 +51,13 @@ impl UserRepository {
     pub async fn create(&self, user: User) -> Result<User> {
         let result = sqlx::query(
             r#"
-            INSERT INTO users (username, email, hashed_password, role, full_name, is_active, company_id, parent_id, created_at, updated_at, last_login)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO users (username, email, email_index, hashed_password, role, full_name, is_active, company_id, parent_id, created_at, updated_at, last_login)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(&user.username)
-        .bind(&user.email)
+        .bind(pii::seal(&user.email)?)
+        .bind(pii::blind_index(&user.email))
         .bind(&user.hashed_password)
         .bind(user.role.as_str())
         .bind(&user.full_name)
@@ -79,13 +81,14 @@ impl UserRepository {
         sqlx::query(
             r#"
             UPDATE users 
-            SET username = ?, email = ?, hashed_password = ?, role = ?, full_name = ?, 
+            SET username = ?, email = ?, email_index = ?, hashed_password = ?, role = ?, full_name = ?, 
                 is_active = ?, company_id = ?, parent_id = ?, updated_at = ?, last_login = ?
             WHERE id = ?
             "#
         )
         .bind(&user.username)
-        .bind(&user.email)
+        .bind(pii::seal(&user.email)?)
+        .bind(pii::blind_index(&user.email))
         .bind(&user.hashed_password)
         .bind(user.role.as_str())
         .bind(&user.full_name)
@@ -1,38 +1,54 @@
 use axum::{
-    http::Method,
+    http::header,
     middleware,
     routing::{delete, get, post, put},
     Extension, Router,
 };
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 use tower_http::{
-    compression::CompressionLayer, cors::CorsLayer, services::ServeDir,
+    compression::CompressionLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    set_header::SetResponseHeaderLayer,
     trace::TraceLayer,
 };
 
-use crate::{handlers, Config, Database};
+use crate::{
+    handlers,
+    metrics,
+    middleware::{
+        auth,
+        rate_limit::{self, MemoryStore, Quota, RateLimitStore, RateLimiter},
+        security::{self, SecurityHeaders},
+    },
+    services::api_token::{
+        SCOPE_PROJECTS_READ, SCOPE_STATISTICS_READ, SCOPE_TASKS_READ, SCOPE_TASKS_WRITE, SCOPE_USERS_READ,
+    },
+    utils::pii,
+    Config, Database,
+};
+
+/// 路由组只接受带有 `scope` 授权范围的访问令牌
+fn scoped<S>(scope: &'static str, routes: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    routes.route_layer(middleware::from_fn_with_state(scope, auth::require_scope))
+}
 
 pub async fn create_app(database: Database, config: Config) -> Router {
     // 创建事件广播器
     let event_broadcaster = handlers::websocket::create_event_broadcaster();
+
+    // 启用个人信息字段加密(密钥配置错误时 main 已拒绝启动)
+    if let Err(e) = pii::install(&config) {
+        tracing::error!("加载个人信息加密密钥失败: {:#}", e);
+    }
     
-    // 创建CORS中间件
-    let cors = CorsLayer::new()
-        .allow_origin(tower_http::cors::Any)
-        .allow_methods([
-            Method::GET,
-            Method::POST,
-            Method::PUT,
-            Method::DELETE,
-            Method::PATCH,
-            Method::OPTIONS,
-        ])
-        .allow_headers([
-            axum::http::HeaderName::from_static("authorization"),
-            axum::http::HeaderName::from_static("content-type"),
-            axum::http::HeaderName::from_static("x-requested-with"),
-        ])
-        .allow_credentials(false);
+    // 创建CORS中间件(按 ALLOWED_ORIGINS)和安全响应头
+    let cors = security::cors_layer(&config);
+    let security_headers = SecurityHeaders::from_config(&config);
+    let docs_csp = security::content_security_policy(&config.docs_content_security_policy, &config.frame_ancestors);
 
     // 静态文件服务配置
     let static_files_service = {
@@ -53,26 +69,110 @@ pub async fn create_app(database: Database, config: Config) -> Router {
         }
     };
 
+    // 限流: 各路由组共用同一个令牌桶存储,计数按路由组区分
+    let rate_limit_store: Arc<dyn RateLimitStore> = Arc::new(MemoryStore::new());
+    let auth_rate_limit = RateLimiter::new(rate_limit_store.clone(), "auth")
+        .per_ip(Quota::parse(&config.rate_limit_auth));
+    let api_rate_limit = RateLimiter::new(rate_limit_store, "api")
+        .per_principal(Quota::parse(&config.rate_limit_user))
+        .per_company(Quota::parse(&config.rate_limit_company));
+
+    // 登录、注册和申请找回密码按客户端IP限流
+    let auth_entry_routes = Router::new()
+        .route("/api/v1/auth/login", post(handlers::auth::login))
+        .route("/api/v1/auth/register", post(handlers::auth::register))
+        .route("/api/v1/auth/password-reset/request", post(handlers::auth::request_password_reset))
+        .route_layer(middleware::from_fn_with_state(auth_rate_limit, rate_limit::rate_limit));
+
     // 公开路由（不需要认证）
     let public_routes = Router::new()
+        .merge(auth_entry_routes)
         .route("/health", get(handlers::health::health_check))
-        .route("/api/v1/auth/login", post(handlers::auth::login))
-        .route("/api/v1/auth/register", post(handlers::auth::register))
-        .route("/api/v1/auth/logout", post(handlers::auth::logout))
-        .route("/docs", get(handlers::docs::api_docs))
+        .route("/.well-known/jwks.json", get(handlers::auth::jwks))
+        .route("/api/v1/auth/refresh", post(handlers::auth::refresh_token))
+        .route("/api/v1/auth/2fa/verify", post(handlers::auth::verify_two_factor))
+        .route("/api/v1/auth/2fa/challenge/enroll", post(handlers::auth::enroll_two_factor_challenge))
+        .route("/api/v1/auth/sso/start", post(handlers::auth::start_sso))
+        .route("/api/v1/auth/sso/callback", post(handlers::auth::sso_callback))
+        .route("/api/v1/auth/verify-email", post(handlers::auth::verify_email))
+        .route("/api/v1/auth/password-reset/confirm", post(handlers::auth::reset_password))
+        .route("/api/v1/auth/invitation", post(handlers::invitations::preview_invitation))
+        .route("/api/v1/auth/invitation/accept", post(handlers::invitations::accept_invitation))
+        .route("/api/v1/auth/registration-policy", get(handlers::auth::get_public_registration_policy))
+        // API文档页面使用单独的内容安全策略
+        .route(
+            "/docs",
+            get(handlers::docs::api_docs).route_layer(SetResponseHeaderLayer::if_not_present(
+                header::CONTENT_SECURITY_POLICY,
+                docs_csp,
+            )),
+        )
         .with_state((database.clone(), config.clone()));
 
-    // 受保护路由（需要认证）
-    let protected_routes = Router::new()
+    // 监控指标: 配置了单独监听地址时由 main 另行启动,否则仅在配置了令牌时挂到主端口
+    let metrics_routes = if config.metrics_bind.is_none() && config.metrics_token.is_some() {
+        metrics::metrics_router(database.clone(), config.clone())
+    } else {
+        Router::new()
+    };
+
+    // 模拟登录期间不能访问: 密码、两步验证设置、再次模拟、个人数据导出,以及会超出模拟时限的令牌签发和身份关联
+    let account_security_routes = Router::new()
+        .route("/api/v1/auth/change-password", post(handlers::auth::change_password))
+        .route("/api/v1/auth/tokens", post(handlers::auth::create_api_token))
+        .route("/api/v1/auth/sso/link/start", post(handlers::auth::start_sso_link))
+        .route("/api/v1/auth/2fa/setup", post(handlers::auth::setup_two_factor))
+        .route("/api/v1/auth/2fa/confirm", post(handlers::auth::confirm_two_factor))
+        .route("/api/v1/auth/2fa/disable", post(handlers::auth::disable_two_factor))
+        .route("/api/v1/auth/2fa/recovery-codes", post(handlers::auth::regenerate_recovery_codes))
+        .route("/api/v1/settings/2fa-policy", put(handlers::auth::update_platform_two_factor_policy))
+        .route("/api/v1/settings/registration", put(handlers::auth::update_registration_policy))
+        .route("/api/v1/companies/:id/join-code", post(handlers::company::regenerate_join_code))
+        .route("/api/v1/users/:id/2fa", delete(handlers::users::reset_two_factor))
+        .route("/api/v1/users/:id/impersonate", post(handlers::users::impersonate_user))
+        .route("/api/v1/users/:id/export", get(handlers::users::export_personal_data))
+        .route("/api/v1/companies/:id/2fa-policy", put(handlers::company::update_two_factor_policy))
+        .route_layer(middleware::from_fn(auth::forbid_impersonation));
+
+    // 受保护路由（需要认证）: 仅限登录会话,访问令牌不能访问(账号安全、令牌管理、管理操作等)
+    let session_routes = Router::new()
         .route("/api/v1/auth/me", get(handlers::auth::get_current_user))
-        .route("/api/v1/auth/refresh", post(handlers::auth::refresh_token))
+        .route("/api/v1/auth/logout", post(handlers::auth::logout))
+        .route("/api/v1/auth/impersonation/end", post(handlers::auth::end_impersonation))
+        .route("/api/v1/auth/verify-email/send", post(handlers::auth::send_verification_email))
+        .route("/api/v1/auth/2fa", get(handlers::auth::get_two_factor_status))
+        .route("/api/v1/auth/tokens", get(handlers::auth::list_api_tokens))
+        .route("/api/v1/auth/tokens/:id", delete(handlers::auth::revoke_api_token))
+        .route("/api/v1/auth/sso/identities", get(handlers::auth::list_sso_identities))
+        .route("/api/v1/auth/sso/identities/:id", delete(handlers::auth::unlink_sso_identity))
+        
+        // 登录会话管理
+        .route("/api/v1/sessions", get(handlers::sessions::list_sessions))
+        .route("/api/v1/sessions/:id", delete(handlers::sessions::revoke_session))
         
         // 用户管理
-        .route("/api/v1/users", get(handlers::users::list_users))
         .route("/api/v1/users", post(handlers::users::create_user))
-        .route("/api/v1/users/:id", get(handlers::users::get_user))
         .route("/api/v1/users/:id", put(handlers::users::update_user))
         .route("/api/v1/users/:id", delete(handlers::users::delete_user))
+        .route("/api/v1/users/:id/unlock", post(handlers::users::unlock_user))
+        .route("/api/v1/users/:id/custom-role", put(handlers::roles::assign_user_role))
+        
+        // 成员邀请
+        .route("/api/v1/invitations", get(handlers::invitations::list_invitations))
+        .route("/api/v1/invitations", post(handlers::invitations::create_invitation))
+        .route("/api/v1/invitations/:id", delete(handlers::invitations::revoke_invitation))
+        
+        // 权限与公司自定义角色
+        .route("/api/v1/permissions", get(handlers::roles::list_permissions))
+        .route("/api/v1/companies/:id/roles", get(handlers::roles::list_company_roles))
+        .route("/api/v1/companies/:id/roles", post(handlers::roles::create_company_role))
+        .route("/api/v1/companies/:id/roles/:role_id", put(handlers::roles::update_company_role))
+        .route("/api/v1/companies/:id/roles/:role_id", delete(handlers::roles::delete_company_role))
+        
+        // 审计日志
+        .route("/api/v1/audit-logs", get(handlers::audit::list_audit_logs))
+        .route("/api/v1/audit-logs/export", get(handlers::audit::export_audit_logs))
+        .route("/api/v1/audit-logs/verify", get(handlers::audit::verify_audit_logs))
         
         // 公司管理(SystemAdmin专用)
         .route("/api/v1/companies", get(handlers::company::list_companies))
@@ -81,29 +181,68 @@ pub async fn create_app(database: Database, config: Config) -> Router {
         .route("/api/v1/companies/:id", put(handlers::company::update_company))
         .route("/api/v1/companies/:id", delete(handlers::company::delete_company))
         .route("/api/v1/companies/:id/toggle-status", post(handlers::company::toggle_company_status))
+        .route("/api/v1/companies/:id/join-code", get(handlers::company::get_join_code))
+        .route("/api/v1/settings/registration", get(handlers::auth::get_registration_policy))
+        .route("/api/v1/companies/:id/sso", get(handlers::company::get_sso_config))
+        .route("/api/v1/companies/:id/sso", put(handlers::company::update_sso_config))
+        .route("/api/v1/companies/:id/sso", delete(handlers::company::delete_sso_config))
+        
+        // WebSocket实时通信
+        .route("/ws/task-updates", get(handlers::websocket::task_updates_websocket))
+        .merge(account_security_routes)
+        .route_layer(middleware::from_fn(auth::require_session));
+
+    // 受保护路由（需要认证）: 访问令牌按授权范围访问,登录会话不受限制
+    let scoped_routes = Router::new()
+        // 用户列表
+        .merge(scoped(
+            SCOPE_USERS_READ,
+            Router::new()
+                .route("/api/v1/users", get(handlers::users::list_users))
+                .route("/api/v1/users/:id", get(handlers::users::get_user)),
+        ))
         
         // 任务管理 API (临时实现：返回空数组避免404)
-        .route("/api/v1/tasks", get(handlers::tasks_temp::list_tasks))
-        .route("/api/v1/tasks/:id", get(handlers::tasks_temp::get_task))
-        .route("/api/v1/tasks", post(handlers::tasks_temp::create_task))
-        .route("/api/v1/tasks/:id", put(handlers::tasks_temp::update_task))
-        .route("/api/v1/tasks/:id", delete(handlers::tasks_temp::delete_task))
+        .merge(scoped(
+            SCOPE_TASKS_READ,
+            Router::new()
+                .route("/api/v1/tasks", get(handlers::tasks_temp::list_tasks))
+                .route("/api/v1/tasks/:id", get(handlers::tasks_temp::get_task)),
+        ))
+        .merge(scoped(
+            SCOPE_TASKS_WRITE,
+            Router::new()
+                .route("/api/v1/tasks", post(handlers::tasks_temp::create_task))
+                .route("/api/v1/tasks/:id", put(handlers::tasks_temp::update_task))
+                .route("/api/v1/tasks/:id", delete(handlers::tasks_temp::delete_task)),
+        ))
         
         // 项目管理 API (临时实现：返回空数组避免404)
-        .route("/api/v1/projects", get(handlers::projects_temp::list_projects))
-        
-    // 数据统计 API
-    .route("/api/v1/statistics/tasks", get(handlers::statistics::get_task_statistics))
-    .route("/api/v1/statistics/projects", get(handlers::statistics::get_project_statistics))
-    .route("/api/v1/statistics/users/workload", get(handlers::projects_temp::get_all_users_workload))
-    // .route("/api/v1/statistics/projects/:project_id/progress", get(handlers::statistics::get_project_progress))
-        
-        // WebSocket实时通信
-        .route("/ws/task-updates", get(handlers::websocket::task_updates_websocket))
+        .merge(scoped(
+            SCOPE_PROJECTS_READ,
+            Router::new().route("/api/v1/projects", get(handlers::projects_temp::list_projects)),
+        ))
         
+        // 数据统计 API (按角色隔离数据范围)
+        .merge(scoped(
+            SCOPE_STATISTICS_READ,
+            Router::new()
+                .route("/api/v1/statistics/tasks", get(handlers::statistics::get_task_statistics))
+                .route("/api/v1/statistics/projects", get(handlers::statistics::get_project_statistics))
+                .route("/api/v1/statistics/users/workload", get(handlers::statistics::get_all_users_workload))
+                .route("/api/v1/statistics/projects/:project_id/progress", get(handlers::statistics::get_project_progress))
+                .route("/api/v1/statistics/flow", get(handlers::statistics::get_flow_metrics))
+                .route("/api/v1/statistics/estimation", get(handlers::statistics::get_estimation_report))
+                .route("/api/v1/statistics/estimation/suggest", get(handlers::statistics::suggest_estimate)),
+        ));
+
+    // 限流挂在认证中间件内层,按用户和公司计数
+    let protected_routes = session_routes
+        .merge(scoped_routes)
+        .layer(middleware::from_fn_with_state(api_rate_limit, rate_limit::rate_limit))
         .layer(middleware::from_fn_with_state(
             (database.clone(), config.clone()),
-            crate::middleware::auth::AuthLayer::middleware,
+            auth::AuthLayer::middleware,
         ))
         .layer(Extension(event_broadcaster))
         .with_state((database, config));
@@ -112,10 +251,16 @@ pub async fn create_app(database: Database, config: Config) -> Router {
     Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        .merge(metrics_routes)
         // 静态文件服务 (优先级最低，放在最后)
         .fallback_service(static_files_service)
         // 全局中间件
+        .layer(middleware::from_fn(metrics::track_http_metrics))
+        .layer(middleware::from_fn_with_state(security_headers, security::security_headers))
         .layer(CompressionLayer::new())
+        // 请求ID: 沿用客户端传入的 X-Request-Id,没有时生成,并回写到响应,用于关联日志和审计记录
+        .layer(PropagateRequestIdLayer::x_request_id())
         .layer(TraceLayer::new_for_http())
         .layer(cors)
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
 }
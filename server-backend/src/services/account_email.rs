@@ -0,0 +1,343 @@
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::UserInfo,
+    services::{
+        audit::{AuditEvent, AuditService, ACTION_EMAIL_VERIFIED, ACTION_PASSWORD_RESET},
+        login_throttle::{LoginSubject, LoginThrottleService},
+        password::PasswordService,
+        session::{SessionService, REVOKED_PASSWORD_RESET},
+    },
+    utils::{
+        mailer::{mailer_from_config, EmailMessage, Mailer},
+        pii::{self, OptionalSealedText, SealedText},
+    },
+    Config, Database,
+};
+
+/// 邮箱验证链接有效期(秒)
+const VERIFY_EMAIL_EXPIRES_IN: i64 = 24 * 3600;
+/// 找回密码链接有效期(秒)
+const PASSWORD_RESET_EXPIRES_IN: i64 = 3600;
+/// 同一用户两次发送同类邮件的最短间隔(秒)
+const RESEND_INTERVAL: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenPurpose {
+    VerifyEmail,
+    PasswordReset,
+}
+
+impl TokenPurpose {
+    fn as_str(self) -> &'static str {
+        match self {
+            TokenPurpose::VerifyEmail => "verify_email",
+            TokenPurpose::PasswordReset => "password_reset",
+        }
+    }
+
+    fn expires_in(self) -> i64 {
+        match self {
+            TokenPurpose::VerifyEmail => VERIFY_EMAIL_EXPIRES_IN,
+            TokenPurpose::PasswordReset => PASSWORD_RESET_EXPIRES_IN,
+        }
+    }
+}
+
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn sha256_hex(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+/// 有效令牌的所属用户
+struct TokenOwner {
+    token_hash: String,
+    user_id: i64,
+    username: String,
+    company_id: Option<i64>,
+}
+
+/// 邮箱验证与找回密码服务
+///
+/// 邮件中的令牌格式为 `随机值.签名`,签名为用途和随机值的 HMAC-SHA256(密钥为 `jwt_secret`),
+/// 数据库只保存令牌哈希。令牌绑定签发时的邮箱,使用一次即作废,过期后不可再用。
+pub struct AccountEmailService {
+    db: Database,
+    config: Config,
+    mailer: Box<dyn Mailer>,
+}
+
+impl AccountEmailService {
+    /// 使用配置中的邮件发送方式
+    pub fn new(db: Database, config: Config) -> Result<Self, AppError> {
+        let mailer = mailer_from_config(&config)?;
+        Ok(Self::with_mailer(db, config, mailer))
+    }
+
+    pub fn with_mailer(db: Database, config: Config, mailer: Box<dyn Mailer>) -> Self {
+        Self { db, config, mailer }
+    }
+
+    /// 给当前用户的邮箱发送验证链接
+    pub async fn send_verification(&self, user: &UserInfo) -> Result<(), AppError> {
+        let verified = sqlx::query_scalar::<_, bool>("SELECT COALESCE(is_verified, 0) FROM users WHERE id = ?")
+            .bind(user.id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user.id.to_string()))?;
+        if verified {
+            return Err(AppError::Conflict("邮箱已验证".to_string()));
+        }
+        if user.email.trim().is_empty() {
+            return Err(AppError::InvalidInput("账号未设置邮箱".to_string()));
+        }
+
+        let wait = self.resend_wait(user.id, TokenPurpose::VerifyEmail).await?;
+        if wait > 0 {
+            return Err(AppError::TooManyAttempts(wait));
+        }
+
+        let token = self.issue_token(user.id, &user.email, TokenPurpose::VerifyEmail).await?;
+        let message = EmailMessage {
+            to: user.email.clone(),
+            subject: "验证您的 Flow Farm 邮箱".to_string(),
+            body: format!(
+                "{}您好:\n\n请在{}小时内打开以下链接完成邮箱验证:\n{}\n\n如果这不是您本人的操作,请忽略此邮件。\n",
+                user.full_name,
+                VERIFY_EMAIL_EXPIRES_IN / 3600,
+                self.link("verify-email", &token)
+            ),
+        };
+        self.mailer.send(&message).await
+    }
+
+    /// 用邮件中的令牌完成邮箱验证
+    pub async fn verify_email(&self, token: &str, client: &ClientInfo) -> Result<(), AppError> {
+        let owner = self.find_token(token, TokenPurpose::VerifyEmail).await?;
+        self.consume_token(&owner).await?;
+
+        sqlx::query("UPDATE users SET is_verified = 1 WHERE id = ?")
+            .bind(owner.user_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_EMAIL_VERIFIED)
+                    .actor(owner.user_id)
+                    .company(owner.company_id)
+                    .target("user", owner.user_id)
+                    .client(client),
+            )
+            .await
+    }
+
+    /// 申请找回密码
+    ///
+    /// 无论邮箱是否存在都正常返回,避免通过该接口探测账号;发送失败只记录日志。
+    pub async fn request_password_reset(&self, email: &str) -> Result<(), AppError> {
+        let user = sqlx::query_as::<_, (i64, SealedText, Option<String>)>(
+            "SELECT id, email, full_name FROM users WHERE (email_index = ? OR lower(email) = lower(?)) AND is_active = 1",
+        )
+        .bind(pii::blind_index(email))
+        .bind(email.trim())
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let Some((user_id, SealedText(email), full_name)) = user else {
+            tracing::debug!("找回密码: 邮箱 {} 未对应启用中的账号", email);
+            return Ok(());
+        };
+
+        if self.resend_wait(user_id, TokenPurpose::PasswordReset).await? > 0 {
+            return Ok(());
+        }
+
+        let token = self.issue_token(user_id, &email, TokenPurpose::PasswordReset).await?;
+        let message = EmailMessage {
+            to: email,
+            subject: "重置您的 Flow Farm 密码".to_string(),
+            body: format!(
+                "{}您好:\n\n请在{}分钟内打开以下链接设置新密码:\n{}\n\n如果您没有申请找回密码,请忽略此邮件,您的密码不会改变。\n",
+                full_name.unwrap_or_default(),
+                PASSWORD_RESET_EXPIRES_IN / 60,
+                self.link("reset-password", &token)
+            ),
+        };
+        if let Err(e) = self.mailer.send(&message).await {
+            tracing::warn!("⚠️ 找回密码邮件发送失败 (用户 {}): {}", user_id, e);
+        }
+
+        Ok(())
+    }
+
+    /// 用邮件中的令牌设置新密码
+    ///
+    /// 新密码需满足密码策略,不满足时令牌不作废,用户可修改后重试。
+    /// 成功后用户原有的所有会话失效、账号登录锁定解除,其他未使用的找回密码链接作废。
+    pub async fn reset_password(&self, token: &str, new_password: &str, client: &ClientInfo) -> Result<(), AppError> {
+        let owner = self.find_token(token, TokenPurpose::PasswordReset).await?;
+        let passwords = PasswordService::new(self.db.clone(), self.config.clone());
+        passwords.check(owner.user_id, &owner.username, new_password).await?;
+        self.consume_token(&owner).await?;
+        passwords.store(owner.user_id, new_password).await?;
+
+        // 邮件能送达说明邮箱属于本人,同时视为完成邮箱验证
+        sqlx::query("UPDATE users SET is_verified = 1 WHERE id = ?")
+            .bind(owner.user_id)
+            .execute(&self.db.pool)
+            .await?;
+        sqlx::query("UPDATE email_tokens SET used_at = ? WHERE user_id = ? AND purpose = ? AND used_at IS NULL")
+            .bind(timestamp(Utc::now()))
+            .bind(owner.user_id)
+            .bind(TokenPurpose::PasswordReset.as_str())
+            .execute(&self.db.pool)
+            .await?;
+
+        let revoked = SessionService::new(self.db.clone(), self.config.clone())
+            .revoke_user_sessions(owner.user_id, None, REVOKED_PASSWORD_RESET)
+            .await?;
+        LoginThrottleService::new(self.db.clone(), self.config.clone())
+            .record_success(&LoginSubject::user(owner.user_id, owner.company_id))
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_PASSWORD_RESET)
+                    .actor(owner.user_id)
+                    .company(owner.company_id)
+                    .target("user", owner.user_id)
+                    .client(client)
+                    .details(json!({ "revoked_sessions": revoked })),
+            )
+            .await
+    }
+
+    /// 距离可以再次发送同类邮件还需等待的秒数
+    async fn resend_wait(&self, user_id: i64, purpose: TokenPurpose) -> Result<i64, AppError> {
+        let last_sent = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT CAST(strftime('%s', MAX(created_at)) AS INTEGER) FROM email_tokens WHERE user_id = ? AND purpose = ?",
+        )
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(last_sent
+            .map(|sent_at| sent_at + RESEND_INTERVAL - Utc::now().timestamp())
+            .unwrap_or(0)
+            .max(0))
+    }
+
+    async fn issue_token(&self, user_id: i64, email: &str, purpose: TokenPurpose) -> Result<String, AppError> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let value = hex::encode(bytes);
+        let token = format!("{}.{}", value, hex::encode(self.sign(purpose, &value)?));
+
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            INSERT INTO email_tokens (token_hash, user_id, purpose, email, created_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(sha256_hex(&token))
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .bind(email)
+        .bind(timestamp(now))
+        .bind(timestamp(now + Duration::seconds(purpose.expires_in())))
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    /// 校验签名和有效期,返回令牌所属用户(不作废令牌)
+    ///
+    /// 签发后用户改了邮箱或被禁用,令牌同样视为无效。
+    async fn find_token(&self, token: &str, purpose: TokenPurpose) -> Result<TokenOwner, AppError> {
+        let token = token.trim();
+        let (value, signature) = token.split_once('.').ok_or(AppError::TokenInvalid)?;
+        let signature = hex::decode(signature).map_err(|_| AppError::TokenInvalid)?;
+        self.mac(purpose, value)?
+            .verify_slice(&signature)
+            .map_err(|_| AppError::TokenInvalid)?;
+
+        let token_hash = sha256_hex(token);
+        let (user_id, email, expires_at, used_at) = sqlx::query_as::<_, (i64, String, String, Option<String>)>(
+            "SELECT user_id, email, expires_at, used_at FROM email_tokens WHERE token_hash = ? AND purpose = ?",
+        )
+        .bind(&token_hash)
+        .bind(purpose.as_str())
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or(AppError::TokenInvalid)?;
+
+        if used_at.is_some() {
+            return Err(AppError::TokenInvalid);
+        }
+        if expires_at <= timestamp(Utc::now()) {
+            return Err(AppError::TokenExpired);
+        }
+
+        let user = sqlx::query_as::<_, (String, OptionalSealedText, Option<i64>, bool)>(
+            "SELECT username, email, company_id, is_active FROM users WHERE id = ?",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        match user {
+            Some((username, OptionalSealedText(Some(current_email)), company_id, true)) if current_email.eq_ignore_ascii_case(&email) => {
+                Ok(TokenOwner {
+                    token_hash,
+                    user_id,
+                    username,
+                    company_id,
+                })
+            }
+            _ => Err(AppError::TokenInvalid),
+        }
+    }
+
+    /// 作废令牌,条件更新保证并发使用同一令牌时只有一个请求能成功
+    async fn consume_token(&self, owner: &TokenOwner) -> Result<(), AppError> {
+        let claimed = sqlx::query("UPDATE email_tokens SET used_at = ? WHERE token_hash = ? AND used_at IS NULL")
+            .bind(timestamp(Utc::now()))
+            .bind(&owner.token_hash)
+            .execute(&self.db.pool)
+            .await?
+            .rows_affected();
+        if claimed == 0 {
+            return Err(AppError::TokenInvalid);
+        }
+
+        Ok(())
+    }
+
+    fn mac(&self, purpose: TokenPurpose, value: &str) -> Result<Hmac<Sha256>, AppError> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.jwt_secret.as_bytes())
+            .map_err(|e| AppError::Internal(format!("签名密钥无效: {}", e)))?;
+        mac.update(purpose.as_str().as_bytes());
+        mac.update(b":");
+        mac.update(value.as_bytes());
+        Ok(mac)
+    }
+
+    fn sign(&self, purpose: TokenPurpose, value: &str) -> Result<Vec<u8>, AppError> {
+        Ok(self.mac(purpose, value)?.finalize().into_bytes().to_vec())
+    }
+
+    fn link(&self, path: &str, token: &str) -> String {
+        format!("{}/{}?token={}", self.config.public_url.trim_end_matches('/'), path, token)
+    }
+}
@@ -0,0 +1,356 @@
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{ApiTokenInfo, ApiTokenKind, CreateApiTokenRequest, CreatedApiToken, Permission, User, UserInfo},
+    services::{
+        audit::{AuditEvent, AuditService, ACTION_API_TOKEN_CREATED, ACTION_API_TOKEN_REVOKED},
+        role::RoleService,
+    },
+    Database,
+};
+
+/// 读取任务
+pub const SCOPE_TASKS_READ: &str = "tasks:read";
+/// 创建、修改、删除任务
+pub const SCOPE_TASKS_WRITE: &str = "tasks:write";
+/// 读取项目
+pub const SCOPE_PROJECTS_READ: &str = "projects:read";
+/// 读取工作记录
+pub const SCOPE_WORKLOGS_READ: &str = "worklogs:read";
+/// 提交工作记录
+pub const SCOPE_WORKLOGS_WRITE: &str = "worklogs:write";
+/// 读取统计数据
+pub const SCOPE_STATISTICS_READ: &str = "statistics:read";
+/// 读取用户列表
+pub const SCOPE_USERS_READ: &str = "users:read";
+
+/// 所有可授予访问令牌的范围
+pub const ALL_SCOPES: [&str; 7] = [
+    SCOPE_TASKS_READ,
+    SCOPE_TASKS_WRITE,
+    SCOPE_PROJECTS_READ,
+    SCOPE_WORKLOGS_READ,
+    SCOPE_WORKLOGS_WRITE,
+    SCOPE_STATISTICS_READ,
+    SCOPE_USERS_READ,
+];
+
+/// 个人访问令牌前缀
+const PERSONAL_TOKEN_PREFIX: &str = "ffp_";
+/// 公司API密钥前缀
+const COMPANY_TOKEN_PREFIX: &str = "ffk_";
+/// 列表中展示的令牌开头长度(含前缀)
+const DISPLAY_PREFIX_LEN: usize = 12;
+/// 两次记录最近使用时间的最小间隔(秒),避免每个请求都写库
+const LAST_USED_INTERVAL_SECONDS: i64 = 60;
+
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token(kind: ApiTokenKind) -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let prefix = match kind {
+        ApiTokenKind::Personal => PERSONAL_TOKEN_PREFIX,
+        ApiTokenKind::Company => COMPANY_TOKEN_PREFIX,
+    };
+    format!("{}{}", prefix, hex::encode(bytes))
+}
+
+/// Bearer 令牌是否为访问令牌(否则按 JWT 处理)
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(PERSONAL_TOKEN_PREFIX) || token.starts_with(COMPANY_TOKEN_PREFIX)
+}
+
+/// 通过访问令牌认证的请求所持有的授权
+#[derive(Debug, Clone)]
+pub struct ApiTokenGrant {
+    pub id: String,
+    pub kind: ApiTokenKind,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,
+}
+
+impl ApiTokenGrant {
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiTokenRow {
+    id: String,
+    kind: String,
+    name: String,
+    token_prefix: String,
+    scopes: String,
+    user_id: i64,
+    company_id: Option<i64>,
+    created_at: String,
+    expires_at: Option<String>,
+    last_used_at: Option<String>,
+    last_used_ip: Option<String>,
+    revoked_at: Option<String>,
+}
+
+impl ApiTokenRow {
+    fn kind(&self) -> ApiTokenKind {
+        self.kind.parse().unwrap_or_default()
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        self.scopes.split_whitespace().map(str::to_string).collect()
+    }
+}
+
+impl From<ApiTokenRow> for ApiTokenInfo {
+    fn from(row: ApiTokenRow) -> Self {
+        Self {
+            kind: row.kind(),
+            scopes: row.scopes(),
+            id: row.id,
+            name: row.name,
+            token_prefix: row.token_prefix,
+            user_id: row.user_id,
+            company_id: row.company_id,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+            last_used_ip: row.last_used_ip,
+            revoked_at: row.revoked_at,
+        }
+    }
+}
+
+/// 个人访问令牌与公司API密钥
+///
+/// 供脚本和桌面端自动化使用,代替账号密码登录。令牌只保存摘要,
+/// 创建时返回一次明文;每个令牌只能访问授权范围内的接口,
+/// 并且仍受所代表用户自身权限的限制。
+pub struct ApiTokenService {
+    db: Database,
+}
+
+impl ApiTokenService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// 创建访问令牌,公司密钥需要本公司的 `company.settings` 权限
+    pub async fn create(
+        &self,
+        user: &UserInfo,
+        request: &CreateApiTokenRequest,
+        client: &ClientInfo,
+    ) -> Result<CreatedApiToken, AppError> {
+        let mut scopes: Vec<String> = Vec::new();
+        for scope in &request.scopes {
+            if !ALL_SCOPES.contains(&scope.as_str()) {
+                return Err(AppError::InvalidInput(format!("未知的授权范围: {}", scope)));
+            }
+            if !scopes.contains(scope) {
+                scopes.push(scope.clone());
+            }
+        }
+
+        let company_id = match request.kind {
+            ApiTokenKind::Personal => user.company_id,
+            ApiTokenKind::Company => {
+                let company_id = user
+                    .company_id
+                    .ok_or_else(|| AppError::OperationNotAllowed("没有所属公司,无法创建公司API密钥".to_string()))?;
+                if !user.can(Permission::CompanySettings, Some(company_id)) {
+                    return Err(AppError::MissingPermission(Permission::CompanySettings));
+                }
+                Some(company_id)
+            }
+        };
+
+        let token = generate_token(request.kind);
+        let now = Utc::now();
+        let row = ApiTokenRow {
+            id: Uuid::new_v4().to_string(),
+            kind: request.kind.as_str().to_string(),
+            name: request.name.trim().to_string(),
+            token_prefix: token[..DISPLAY_PREFIX_LEN].to_string(),
+            scopes: scopes.join(" "),
+            user_id: user.id,
+            company_id,
+            created_at: timestamp(now),
+            expires_at: request.expires_in_days.map(|days| timestamp(now + Duration::days(days))),
+            last_used_at: None,
+            last_used_ip: None,
+            revoked_at: None,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO api_tokens (id, kind, name, token_hash, token_prefix, scopes, user_id, company_id, created_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&row.id)
+        .bind(&row.kind)
+        .bind(&row.name)
+        .bind(hash_token(&token))
+        .bind(&row.token_prefix)
+        .bind(&row.scopes)
+        .bind(row.user_id)
+        .bind(row.company_id)
+        .bind(&row.created_at)
+        .bind(&row.expires_at)
+        .execute(&self.db.pool)
+        .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_API_TOKEN_CREATED)
+                    .actor(user.id)
+                    .company(company_id)
+                    .target("api_token", &row.id)
+                    .client(client)
+                    .details(json!({ "kind": row.kind, "name": row.name, "scopes": scopes })),
+            )
+            .await?;
+
+        Ok(CreatedApiToken { token, info: row.into() })
+    }
+
+    /// 当前用户可见的令牌: 自己的个人令牌,管理者还能看到本公司的API密钥
+    pub async fn list(&self, user: &UserInfo) -> Result<Vec<ApiTokenInfo>, AppError> {
+        let company_id = user.company_id.filter(|&id| user.can(Permission::CompanySettings, Some(id)));
+        let rows = sqlx::query_as::<_, ApiTokenRow>(
+            r#"
+            SELECT id, kind, name, token_prefix, scopes, user_id, company_id, created_at,
+                   expires_at, last_used_at, last_used_ip, revoked_at
+            FROM api_tokens
+            WHERE (kind = 'personal' AND user_id = ?) OR (kind = 'company' AND company_id = ?)
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user.id)
+        .bind(company_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(ApiTokenInfo::from).collect())
+    }
+
+    /// 撤销令牌: 个人令牌只能由所有者撤销,公司密钥可由本公司管理者撤销
+    pub async fn revoke(&self, user: &UserInfo, token_id: &str, client: &ClientInfo) -> Result<(), AppError> {
+        let row = sqlx::query_as::<_, ApiTokenRow>(
+            r#"
+            SELECT id, kind, name, token_prefix, scopes, user_id, company_id, created_at,
+                   expires_at, last_used_at, last_used_ip, revoked_at
+            FROM api_tokens WHERE id = ?
+            "#,
+        )
+        .bind(token_id)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("访问令牌不存在".to_string()))?;
+
+        let allowed = match row.kind() {
+            ApiTokenKind::Personal => row.user_id == user.id,
+            ApiTokenKind::Company => user.can(Permission::CompanySettings, row.company_id),
+        };
+        if !allowed {
+            return Err(AppError::NotFound("访问令牌不存在".to_string()));
+        }
+        if row.revoked_at.is_some() {
+            return Ok(());
+        }
+
+        sqlx::query("UPDATE api_tokens SET revoked_at = ?, revoked_by = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(timestamp(Utc::now()))
+            .bind(user.id)
+            .bind(&row.id)
+            .execute(&self.db.pool)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_API_TOKEN_REVOKED)
+                    .actor(user.id)
+                    .company(row.company_id)
+                    .target("api_token", &row.id)
+                    .client(client)
+                    .details(json!({ "kind": row.kind, "name": row.name })),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// 校验访问令牌,返回其代表的用户和授权
+    ///
+    /// 令牌不存在、已撤销、已过期,或代表的用户已禁用时返回 `None`;
+    /// 公司密钥的创建人失去本公司 `company.settings` 权限时同样失效。
+    pub async fn authenticate(
+        &self,
+        token: &str,
+        client: &ClientInfo,
+    ) -> Result<Option<(UserInfo, ApiTokenGrant)>, AppError> {
+        let row = sqlx::query_as::<_, ApiTokenRow>(
+            r#"
+            SELECT id, kind, name, token_prefix, scopes, user_id, company_id, created_at,
+                   expires_at, last_used_at, last_used_ip, revoked_at
+            FROM api_tokens WHERE token_hash = ?
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let now = Utc::now();
+        let row = match row {
+            Some(row) if row.revoked_at.is_none() => row,
+            _ => return Ok(None),
+        };
+        if row.expires_at.as_deref().is_some_and(|expires_at| expires_at <= timestamp(now).as_str()) {
+            return Ok(None);
+        }
+
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ? AND is_active = true")
+            .bind(row.user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        let user = match user {
+            Some(user) => RoleService::new(self.db.clone()).user_info(user).await?,
+            None => return Ok(None),
+        };
+        if row.kind() == ApiTokenKind::Company && !user.can(Permission::CompanySettings, row.company_id) {
+            return Ok(None);
+        }
+
+        let last_used_before = timestamp(now - Duration::seconds(LAST_USED_INTERVAL_SECONDS));
+        if row.last_used_at.as_deref().is_none_or(|last_used| last_used <= last_used_before.as_str())
+            || row.last_used_ip != client.ip_address
+        {
+            sqlx::query("UPDATE api_tokens SET last_used_at = ?, last_used_ip = ? WHERE id = ?")
+                .bind(timestamp(now))
+                .bind(&client.ip_address)
+                .bind(&row.id)
+                .execute(&self.db.pool)
+                .await?;
+        }
+
+        let grant = ApiTokenGrant {
+            kind: row.kind(),
+            scopes: row.scopes(),
+            id: row.id,
+            expires_at: row.expires_at,
+        };
+        Ok(Some((user, grant)))
+    }
+}
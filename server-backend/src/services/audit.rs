@@ -0,0 +1,490 @@
+use std::sync::LazyLock;
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{QueryBuilder, Sqlite};
+use tokio::sync::Mutex;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{AuditChainStatus, AuditLogEntry, AuditLogQuery, Permission, UserInfo},
+    Database,
+};
+
+/// 账号因连续登录失败被锁定
+pub const ACTION_ACCOUNT_LOCKED: &str = "auth.account_locked";
+/// IP因连续登录失败被锁定
+pub const ACTION_IP_LOCKED: &str = "auth.ip_locked";
+/// 管理员解除账号锁定
+pub const ACTION_ACCOUNT_UNLOCKED: &str = "auth.account_unlocked";
+/// 用户启用两步验证
+pub const ACTION_TWO_FACTOR_ENABLED: &str = "auth.2fa_enabled";
+/// 用户关闭两步验证,或管理员重置用户的两步验证
+pub const ACTION_TWO_FACTOR_DISABLED: &str = "auth.2fa_disabled";
+/// 修改公司或平台的两步验证策略
+pub const ACTION_TWO_FACTOR_POLICY_CHANGED: &str = "auth.2fa_policy_changed";
+/// 用户完成邮箱验证
+pub const ACTION_EMAIL_VERIFIED: &str = "auth.email_verified";
+/// 用户通过邮件重置密码
+pub const ACTION_PASSWORD_RESET: &str = "auth.password_reset";
+/// 用户修改自己的密码
+pub const ACTION_PASSWORD_CHANGED: &str = "auth.password_changed";
+/// 管理员为其他用户设置密码
+pub const ACTION_PASSWORD_SET_BY_ADMIN: &str = "auth.password_set_by_admin";
+/// 创建个人访问令牌或公司API密钥
+pub const ACTION_API_TOKEN_CREATED: &str = "auth.api_token_created";
+/// 撤销个人访问令牌或公司API密钥
+pub const ACTION_API_TOKEN_REVOKED: &str = "auth.api_token_revoked";
+/// 通过单点登录登录
+pub const ACTION_SSO_LOGIN: &str = "auth.sso_login";
+/// 首次单点登录时自动创建用户
+pub const ACTION_SSO_USER_PROVISIONED: &str = "auth.sso_user_provisioned";
+/// 本地账号关联外部身份
+pub const ACTION_SSO_IDENTITY_LINKED: &str = "auth.sso_identity_linked";
+/// 本地账号解除关联外部身份
+pub const ACTION_SSO_IDENTITY_UNLINKED: &str = "auth.sso_identity_unlinked";
+/// 修改公司的单点登录配置
+pub const ACTION_SSO_CONFIG_CHANGED: &str = "auth.sso_config_changed";
+/// 撤销登录会话(本人或管理者)
+pub const ACTION_SESSION_REVOKED: &str = "auth.session_revoked";
+/// 平台管理员开始模拟登录
+pub const ACTION_IMPERSONATION_STARTED: &str = "auth.impersonation_started";
+/// 平台管理员结束模拟登录
+pub const ACTION_IMPERSONATION_ENDED: &str = "auth.impersonation_ended";
+/// 模拟登录期间的每个请求
+pub const ACTION_IMPERSONATED_REQUEST: &str = "auth.impersonated_request";
+/// 创建公司自定义角色
+pub const ACTION_ROLE_CREATED: &str = "role.created";
+/// 修改公司自定义角色的名称或权限
+pub const ACTION_ROLE_UPDATED: &str = "role.updated";
+/// 删除公司自定义角色
+pub const ACTION_ROLE_DELETED: &str = "role.deleted";
+/// 为用户分配或取消自定义角色
+pub const ACTION_ROLE_ASSIGNED: &str = "role.assigned";
+/// 创建用户(管理员创建或自助注册)
+pub const ACTION_USER_CREATED: &str = "user.created";
+/// 修改用户资料或状态
+pub const ACTION_USER_UPDATED: &str = "user.updated";
+/// 删除用户
+pub const ACTION_USER_DELETED: &str = "user.deleted";
+/// 导出用户的个人数据
+pub const ACTION_PERSONAL_DATA_EXPORTED: &str = "user.data_exported";
+/// 邀请新成员
+pub const ACTION_USER_INVITED: &str = "user.invited";
+/// 撤销邀请
+pub const ACTION_INVITATION_REVOKED: &str = "user.invitation_revoked";
+/// 受邀人接受邀请并创建账号
+pub const ACTION_INVITATION_ACCEPTED: &str = "user.invitation_accepted";
+/// 创建公司
+pub const ACTION_COMPANY_CREATED: &str = "company.created";
+/// 修改公司信息或启用状态
+pub const ACTION_COMPANY_UPDATED: &str = "company.updated";
+/// 删除公司
+pub const ACTION_COMPANY_DELETED: &str = "company.deleted";
+/// 重新生成公司加入码
+pub const ACTION_JOIN_CODE_REGENERATED: &str = "company.join_code_regenerated";
+/// 修改平台注册策略
+pub const ACTION_REGISTRATION_POLICY_CHANGED: &str = "settings.registration_policy_changed";
+/// 导出审计日志
+pub const ACTION_AUDIT_EXPORTED: &str = "audit.exported";
+
+/// 单次查询最多返回的记录数
+const MAX_PAGE_SIZE: i64 = 1000;
+/// 单次导出最多的记录数
+const MAX_EXPORT_ROWS: i64 = 100_000;
+
+/// 写入时串行化"读取上一条哈希 + 插入",保证哈希链不分叉
+static CHAIN_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// 审计日志不可改写,个人信息字段只记录发生了变化,不记录取值
+const PII_FIELDS: &[&str] = &["username", "email", "full_name", "phone", "contact_email", "contact_phone"];
+
+/// 两个版本之间变化的字段: `{"字段": {"from": 旧值, "to": 新值}}`
+///
+/// 创建时 `before` 为空,删除时 `after` 为空。只比较顶层字段,调用方应传入不含密码等敏感字段的结构;
+/// 个人信息字段(见 `PII_FIELDS`)记为 `{"redacted": true}`。
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Value {
+    let fields = |value: Option<&T>| match value.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => Map::new(),
+    };
+    let (before, after) = (fields(before), fields(after));
+
+    let mut changes = Map::new();
+    for key in before.keys().chain(after.keys().filter(|key| !before.contains_key(*key))) {
+        let from = before.get(key).cloned().unwrap_or(Value::Null);
+        let to = after.get(key).cloned().unwrap_or(Value::Null);
+        if from == to {
+            continue;
+        }
+        if PII_FIELDS.contains(&key.as_str()) {
+            changes.insert(key.clone(), json!({ "redacted": true }));
+        } else {
+            changes.insert(key.clone(), json!({ "from": from, "to": to }));
+        }
+    }
+    Value::Object(changes)
+}
+
+#[derive(sqlx::FromRow)]
+struct AuditLogRow {
+    id: i64,
+    occurred_at: String,
+    action: String,
+    actor_id: Option<i64>,
+    impersonator_id: Option<i64>,
+    company_id: Option<i64>,
+    target_type: Option<String>,
+    target_id: Option<String>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    details: Option<String>,
+    prev_hash: Option<String>,
+    hash: Option<String>,
+}
+
+impl AuditLogRow {
+    /// 本条记录的哈希: 上一条记录的哈希与本条全部字段一起计算
+    fn compute_hash(&self, prev_hash: &str) -> String {
+        let content = json!([
+            prev_hash,
+            self.occurred_at,
+            self.action,
+            self.actor_id,
+            self.impersonator_id,
+            self.company_id,
+            self.target_type,
+            self.target_id,
+            self.ip_address,
+            self.user_agent,
+            self.request_id,
+            self.details,
+        ]);
+        hex::encode(Sha256::digest(content.to_string().as_bytes()))
+    }
+}
+
+impl From<AuditLogRow> for AuditLogEntry {
+    fn from(row: AuditLogRow) -> Self {
+        Self {
+            details: row.details.as_deref().and_then(|details| serde_json::from_str(details).ok()),
+            id: row.id,
+            occurred_at: row.occurred_at,
+            action: row.action,
+            actor_id: row.actor_id,
+            impersonator_id: row.impersonator_id,
+            company_id: row.company_id,
+            target_type: row.target_type,
+            target_id: row.target_id,
+            ip_address: row.ip_address,
+            user_agent: row.user_agent,
+            request_id: row.request_id,
+            hash: row.hash,
+        }
+    }
+}
+
+
+/// 一条审计事件
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub action: &'static str,
+    /// 操作人,系统自动触发的事件为空
+    pub actor_id: Option<i64>,
+    /// 代为操作的管理员(模拟登录时)
+    pub impersonator_id: Option<i64>,
+    /// 事件所属公司,用于按租户查询
+    pub company_id: Option<i64>,
+    pub target_type: Option<&'static str>,
+    pub target_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub details: Value,
+}
+
+impl AuditEvent {
+    pub fn new(action: &'static str) -> Self {
+        Self {
+            action,
+            actor_id: None,
+            impersonator_id: None,
+            company_id: None,
+            target_type: None,
+            target_id: None,
+            ip_address: None,
+            user_agent: None,
+            request_id: None,
+            details: Value::Null,
+        }
+    }
+
+    pub fn actor(mut self, actor_id: i64) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    pub fn impersonator(mut self, impersonator_id: Option<i64>) -> Self {
+        self.impersonator_id = impersonator_id;
+        self
+    }
+
+    pub fn company(mut self, company_id: Option<i64>) -> Self {
+        self.company_id = company_id;
+        self
+    }
+
+    pub fn target(mut self, target_type: &'static str, target_id: impl ToString) -> Self {
+        self.target_type = Some(target_type);
+        self.target_id = Some(target_id.to_string());
+        self
+    }
+
+    pub fn ip_address(mut self, ip_address: Option<String>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+
+    /// 记录请求来源: IP、User-Agent、请求ID,以及模拟登录时实际操作的管理员
+    pub fn client(mut self, client: &ClientInfo) -> Self {
+        self.ip_address = client.ip_address.clone();
+        self.user_agent = client.user_agent.clone();
+        self.request_id = client.request_id.clone();
+        self.impersonator_id = client.impersonator_id.or(self.impersonator_id);
+        self
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// 审计日志服务
+///
+/// 日志只追加: 数据库触发器拒绝修改和删除,每条记录通过哈希链与上一条相连,
+/// 事后改动任何一条都会在 [`AuditService::verify_chain`] 中被发现。
+pub struct AuditService {
+    db: Database,
+}
+
+impl AuditService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// 追加一条审计事件
+    pub async fn record(&self, event: AuditEvent) -> Result<(), AppError> {
+        let _guard = CHAIN_LOCK.lock().await;
+
+        let prev_hash = sqlx::query_scalar::<_, String>("SELECT hash FROM audit_logs WHERE hash IS NOT NULL ORDER BY id DESC LIMIT 1")
+            .fetch_optional(&self.db.pool)
+            .await?
+            .unwrap_or_default();
+        let mut row = AuditLogRow {
+            id: 0,
+            occurred_at: timestamp(),
+            action: event.action.to_string(),
+            actor_id: event.actor_id,
+            impersonator_id: event.impersonator_id,
+            company_id: event.company_id,
+            target_type: event.target_type.map(str::to_string),
+            target_id: event.target_id,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            request_id: event.request_id,
+            details: (!event.details.is_null()).then(|| event.details.to_string()),
+            prev_hash: None,
+            hash: None,
+        };
+        row.hash = Some(row.compute_hash(&prev_hash));
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_logs
+                (occurred_at, action, actor_id, impersonator_id, company_id, target_type, target_id,
+                 ip_address, user_agent, request_id, details, prev_hash, hash)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&row.occurred_at)
+        .bind(&row.action)
+        .bind(row.actor_id)
+        .bind(row.impersonator_id)
+        .bind(row.company_id)
+        .bind(&row.target_type)
+        .bind(&row.target_id)
+        .bind(&row.ip_address)
+        .bind(&row.user_agent)
+        .bind(&row.request_id)
+        .bind(&row.details)
+        .bind(&prev_hash)
+        .bind(&row.hash)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// 当前用户可查看的公司: 平台管理员可查看全部(`None`)或指定公司,
+    /// 有审计查看权限的用户只能查看本公司
+    fn scope(query: &AuditLogQuery, current_user: &UserInfo) -> Result<Option<i64>, AppError> {
+        if current_user.has_permission(Permission::PlatformManage) {
+            return Ok(query.company_id);
+        }
+        let company_id = query.company_id.or(current_user.company_id);
+        if !current_user.can(Permission::AuditView, company_id) {
+            return Err(AppError::MissingPermission(Permission::AuditView));
+        }
+        Ok(company_id)
+    }
+
+    async fn fetch(&self, query: &AuditLogQuery, company_id: Option<i64>, limit: i64) -> Result<Vec<AuditLogEntry>, AppError> {
+        let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM audit_logs WHERE 1 = 1");
+        if let Some(company_id) = company_id {
+            builder.push(" AND company_id = ").push_bind(company_id);
+        }
+        if let Some(action) = &query.action {
+            builder.push(" AND action = ").push_bind(action);
+        }
+        if let Some(actor_id) = query.actor_id {
+            builder.push(" AND actor_id = ").push_bind(actor_id);
+        }
+        if let Some(target_type) = &query.target_type {
+            builder.push(" AND target_type = ").push_bind(target_type);
+        }
+        if let Some(target_id) = &query.target_id {
+            builder.push(" AND target_id = ").push_bind(target_id);
+        }
+        if let Some(request_id) = &query.request_id {
+            builder.push(" AND request_id = ").push_bind(request_id);
+        }
+        if let Some(from) = &query.from {
+            builder.push(" AND occurred_at >= ").push_bind(from);
+        }
+        if let Some(to) = &query.to {
+            // 只给日期时包含当天
+            let to = if to.len() == 10 { format!("{} 23:59:59", to) } else { to.clone() };
+            builder.push(" AND occurred_at <= ").push_bind(to);
+        }
+        if let Some(before_id) = query.before_id {
+            builder.push(" AND id < ").push_bind(before_id);
+        }
+        builder.push(" ORDER BY id DESC LIMIT ").push_bind(limit);
+
+        let rows = builder.build_query_as::<AuditLogRow>().fetch_all(&self.db.pool).await?;
+        Ok(rows.into_iter().map(AuditLogEntry::from).collect())
+    }
+
+    /// 按条件查询审计日志,按时间倒序分页
+    pub async fn query(&self, query: &AuditLogQuery, current_user: &UserInfo) -> Result<Vec<AuditLogEntry>, AppError> {
+        let company_id = Self::scope(query, current_user)?;
+        let limit = query.limit.unwrap_or(100).clamp(1, MAX_PAGE_SIZE);
+        self.fetch(query, company_id, limit).await
+    }
+
+    /// 导出审计日志,返回文件内容和 Content-Type;导出操作本身也会记录审计
+    pub async fn export(
+        &self,
+        query: &AuditLogQuery,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<(Vec<u8>, &'static str), AppError> {
+        let company_id = Self::scope(query, current_user)?;
+        let format = query.format.as_deref().unwrap_or("csv");
+        if !matches!(format, "csv" | "jsonl") {
+            return Err(AppError::InvalidInput(format!("不支持的导出格式: {}", format)));
+        }
+        let limit = query.limit.unwrap_or(MAX_EXPORT_ROWS).clamp(1, MAX_EXPORT_ROWS);
+        let entries = self.fetch(query, company_id, limit).await?;
+
+        let (content, content_type) = if format == "jsonl" {
+            let mut content = Vec::new();
+            for entry in &entries {
+                serde_json::to_writer(&mut content, entry)
+                    .map_err(|e| AppError::Internal(format!("导出审计日志失败: {}", e)))?;
+                content.push(b'\n');
+            }
+            (content, "application/x-ndjson")
+        } else {
+            (Self::to_csv(&entries)?, "text/csv; charset=utf-8")
+        };
+
+        self.record(
+            AuditEvent::new(ACTION_AUDIT_EXPORTED)
+                .actor(current_user.id)
+                .company(company_id.or(current_user.company_id))
+                .client(client)
+                .details(json!({ "format": format, "rows": entries.len(), "filters": query })),
+        )
+        .await?;
+
+        Ok((content, content_type))
+    }
+
+    fn to_csv(entries: &[AuditLogEntry]) -> Result<Vec<u8>, AppError> {
+        let error = |e: csv::Error| AppError::Internal(format!("导出审计日志失败: {}", e));
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .write_record([
+                "id", "occurred_at", "action", "actor_id", "impersonator_id", "company_id", "target_type",
+                "target_id", "ip_address", "user_agent", "request_id", "details", "hash",
+            ])
+            .map_err(error)?;
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        let number = |value: Option<i64>| value.map(|v| v.to_string()).unwrap_or_default();
+        for entry in entries {
+            writer
+                .write_record([
+                    entry.id.to_string(),
+                    entry.occurred_at.clone(),
+                    entry.action.clone(),
+                    number(entry.actor_id),
+                    number(entry.impersonator_id),
+                    number(entry.company_id),
+                    text(&entry.target_type),
+                    text(&entry.target_id),
+                    text(&entry.ip_address),
+                    text(&entry.user_agent),
+                    text(&entry.request_id),
+                    entry.details.as_ref().map(Value::to_string).unwrap_or_default(),
+                    text(&entry.hash),
+                ])
+                .map_err(error)?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| AppError::Internal(format!("导出审计日志失败: {}", e)))
+    }
+
+    /// 校验整条哈希链(仅平台管理员)
+    ///
+    /// 启用哈希链之前写入的旧记录没有哈希,不参与校验。
+    pub async fn verify_chain(&self, current_user: &UserInfo) -> Result<AuditChainStatus, AppError> {
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
+        }
+
+        let rows = sqlx::query_as::<_, AuditLogRow>("SELECT * FROM audit_logs WHERE hash IS NOT NULL ORDER BY id")
+            .fetch_all(&self.db.pool)
+            .await?;
+        let mut prev_hash = String::new();
+        for (checked, row) in rows.iter().enumerate() {
+            let linked = row.prev_hash.as_deref().unwrap_or_default() == prev_hash;
+            if !linked || row.hash.as_deref() != Some(row.compute_hash(&prev_hash).as_str()) {
+                return Ok(AuditChainStatus { checked: checked as i64, valid: false, broken_at: Some(row.id) });
+            }
+            prev_hash = row.hash.clone().unwrap_or_default();
+        }
+
+        Ok(AuditChainStatus { checked: rows.len() as i64, valid: true, broken_at: None })
+    }
+}
@@ -1,12 +1,30 @@
+use std::sync::LazyLock;
+
 use anyhow::{anyhow, Result};
 use chrono::Utc;
 
 use crate::{
-    models::{CreateUserRequest, LoginResponse, User, UserInfo},
-    utils::jwt::create_jwt_token,
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{LoginResponse, RegisterRequest, TwoFactorEnrollment, User, UserInfo, UserRole},
+    services::{
+        audit::{self, AuditEvent, AuditService, ACTION_USER_CREATED},
+        company::ensure_company_active,
+        invitation::ensure_seat_available,
+        login_throttle::{LoginSubject, LoginThrottleService},
+        registration::RegistrationService,
+        role::RoleService,
+        session::SessionService,
+        two_factor::TwoFactorService,
+    },
+    utils::{hash_password, pii, PasswordHasher},
     Config, Database,
 };
 
+/// 账号不存在时用于校验的占位哈希(默认 Argon2id 参数)
+static DUMMY_PASSWORD_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("flow-farm-dummy-password").unwrap_or_default());
+
 pub struct AuthService {
     database: Database,
     config: Config,
@@ -17,105 +35,239 @@ impl AuthService {
         Self { database, config }
     }
 
-    pub async fn login(&self, username: &str, password: &str) -> Result<LoginResponse> {
+    /// 登录: 账号不存在、已禁用和密码错误统一返回"用户名或密码错误"
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+        client: &ClientInfo,
+    ) -> Result<LoginResponse, AppError> {
         // 查找用户 - 支持通过用户名、邮箱或手机号登录
+        // 邮箱和手机号加密保存,按盲索引查找;尚未重新加密的旧数据按明文匹配
+        let index = pii::blind_index(username);
         let user = sqlx::query_as::<_, User>(
-            "SELECT * FROM users WHERE (username = ? OR email = ? OR phone = ?) AND (is_active IS NULL OR is_active = 1)",
+            r#"
+            SELECT * FROM users
+            WHERE (username = ? OR email_index = ? OR phone_index = ? OR email = ? OR phone = ?)
+              AND (is_active IS NULL OR is_active = 1)
+            "#,
         )
         .bind(username)
+        .bind(&index)
+        .bind(&index)
         .bind(username)
         .bind(username)
         .fetch_optional(&self.database.pool)
-        .await?
-        .ok_or_else(|| anyhow!("用户不存在或已被禁用"))?;
+        .await?;
+
+        let subject = match &user {
+            Some(user) => LoginSubject::user(user.id, user.company_id),
+            None => LoginSubject::unknown(username),
+        };
+        let throttle = LoginThrottleService::new(self.database.clone(), self.config.clone());
+        throttle.check(&subject, client).await?;
+
+        // 账号不存在时也校验一次密码,避免通过响应时间判断账号是否存在
+        let password_hash = user.as_ref().map_or(DUMMY_PASSWORD_HASH.as_str(), |user| &user.hashed_password);
+        let hasher = PasswordHasher::from_config(&self.config)?;
+        let password_valid = hasher.verify(password, password_hash).unwrap_or_else(|e| {
+            tracing::warn!("密码哈希无法校验: {}", e);
+            false
+        });
+
+        let user = match user {
+            Some(user) if password_valid => user,
+            _ => {
+                throttle.record_failure(&subject, client).await?;
+                return Err(AppError::InvalidCredentials);
+            }
+        };
+        throttle.record_success(&subject).await?;
+
+        // 密码正确后再提示公司已停用,避免泄露账号是否存在
+        ensure_company_active(&self.database, user.id).await?;
+
+        // 哈希算法或参数已过时(如旧的 bcrypt 哈希),趁有明文密码时按当前配置重新哈希
+        if hasher.needs_rehash(&user.hashed_password) {
+            self.rehash_password(&hasher, &user, password).await;
+        }
+
+        // 已启用两步验证或策略要求启用时,先返回挑战,验证码通过后再签发令牌
+        let two_factor = TwoFactorService::new(self.database.clone());
+        let enabled = two_factor.is_enabled(user.id).await?;
+        if enabled || two_factor.is_required(&user.role, user.company_id).await? {
+            let challenge = two_factor.create_challenge(user.id, !enabled).await?;
+            return Ok(LoginResponse::challenge(challenge));
+        }
+
+        self.issue_session(user, client).await
+    }
+
+    /// 两步验证登录第二步: 校验验证码或恢复码后签发令牌
+    ///
+    /// 挑战要求注册时,验证码用于确认注册,并在响应中返回恢复码。
+    /// 验证码错误与密码错误一样计入账号的失败次数。
+    pub async fn verify_two_factor(
+        &self,
+        challenge_token: &str,
+        code: &str,
+        client: &ClientInfo,
+    ) -> Result<LoginResponse, AppError> {
+        let two_factor = TwoFactorService::new(self.database.clone());
+        let user_id = two_factor.challenge_user(challenge_token).await?;
+        let user = self.find_active_user(user_id).await?.ok_or(AppError::TokenInvalid)?;
+
+        let subject = LoginSubject::user(user.id, user.company_id);
+        let throttle = LoginThrottleService::new(self.database.clone(), self.config.clone());
+        throttle.check(&subject, client).await?;
+
+        // 验证失败为 None;在登录过程中完成注册时附带新生成的恢复码
+        let verified = if two_factor.is_enabled(user.id).await? {
+            two_factor.verify(user.id, code).await?.then_some(None)
+        } else {
+            match two_factor.confirm_enrollment(user.id, code).await {
+                Ok(codes) => Some(Some(codes)),
+                Err(AppError::InvalidTwoFactorCode) => None,
+                Err(e) => return Err(e),
+            }
+        };
+        let Some(recovery_codes) = verified else {
+            two_factor.record_challenge_failure(challenge_token).await?;
+            throttle.record_failure(&subject, client).await?;
+            return Err(AppError::InvalidTwoFactorCode);
+        };
+
+        two_factor.consume_challenge(challenge_token).await?;
+        let mut response = self.issue_session(user, client).await?;
+        response.recovery_codes = recovery_codes;
+        Ok(response)
+    }
+
+    /// 策略要求启用两步验证的用户在登录过程中注册验证器
+    pub async fn enroll_two_factor_for_challenge(&self, challenge_token: &str) -> Result<TwoFactorEnrollment, AppError> {
+        let two_factor = TwoFactorService::new(self.database.clone());
+        let user_id = two_factor.challenge_user(challenge_token).await?;
+        let user = self.find_active_user(user_id).await?.ok_or(AppError::TokenInvalid)?;
+
+        two_factor.begin_enrollment(user.id, &user.email).await
+    }
 
-        // 验证密码
-        if !bcrypt::verify(password, &user.hashed_password)? {
-            return Err(anyhow!("密码错误"));
+    async fn find_active_user(&self, user_id: i64) -> Result<Option<User>, AppError> {
+        Ok(sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ? AND (is_active IS NULL OR is_active = 1)")
+            .bind(user_id)
+            .fetch_optional(&self.database.pool)
+            .await?)
+    }
+
+    /// 登录成功后升级密码哈希,失败只记录日志,不影响本次登录
+    async fn rehash_password(&self, hasher: &PasswordHasher, user: &User, password: &str) {
+        let hashed_password = match hasher.hash(password) {
+            Ok(hashed_password) => hashed_password,
+            Err(e) => {
+                tracing::warn!("用户 {} 的密码重新哈希失败: {}", user.id, e);
+                return;
+            }
+        };
+        // 只在哈希未被并发修改(如同时修改密码)时更新
+        let result = sqlx::query("UPDATE users SET hashed_password = ? WHERE id = ? AND hashed_password = ?")
+            .bind(&hashed_password)
+            .bind(user.id)
+            .bind(&user.hashed_password)
+            .execute(&self.database.pool)
+            .await;
+        match result {
+            Ok(done) if done.rows_affected() > 0 => tracing::info!("用户 {} 的密码哈希已升级", user.id),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("用户 {} 的密码哈希更新失败: {}", user.id, e),
         }
+    }
+
+    /// 创建登录会话并签发访问令牌和刷新令牌
+    async fn issue_session(&self, user: User, client: &ClientInfo) -> Result<LoginResponse, AppError> {
+        let tokens = SessionService::new(self.database.clone(), self.config.clone())
+            .create_session(user.id, &user.role, client)
+            .await?;
+        let user = RoleService::new(self.database.clone()).user_info(user).await?;
 
-        // 生成JWT token
-        let token = create_jwt_token(
-            &user.id.to_string(),
-            user.role.as_str(),
-            &self.config.jwt_secret,
-            self.config.jwt_expires_in,
-        )?;
-
-        Ok(LoginResponse {
-            token,
-            user: user.into(),
-        })
+        Ok(LoginResponse::authenticated(tokens, user))
     }
 
-    pub async fn register(&self, request: CreateUserRequest) -> Result<UserInfo> {
+    /// 凭公司加入码自助注册,新用户一律是该公司的任务执行者
+    pub async fn register(&self, request: RegisterRequest, client: &ClientInfo) -> Result<UserInfo> {
+        let company_id = RegistrationService::new(self.database.clone())
+            .resolve_join_code(&request.email, &request.join_code)
+            .await?;
+        let role = UserRole::TaskExecutor;
+
+        // 生成密码哈希
+        let hashed_password = PasswordHasher::from_config(&self.config)?.hash(&request.password)?;
+        let now = Utc::now();
+
+        // 名额检查与插入在同一事务中,避免并发注册超出公司人数上限
+        let mut tx = self.database.pool.begin().await?;
+
         // 检查用户名是否已存在
-        let existing_user = sqlx::query("SELECT id FROM users WHERE username = ? OR email = ?")
+        let email_index = pii::blind_index(&request.email);
+        let existing_user = sqlx::query("SELECT id FROM users WHERE username = ? OR email_index = ? OR email = ?")
             .bind(&request.username)
+            .bind(&email_index)
             .bind(&request.email)
-            .fetch_optional(&self.database.pool)
+            .fetch_optional(&mut *tx)
             .await?;
 
         if existing_user.is_some() {
             return Err(anyhow!("用户名或邮箱已存在"));
         }
-
-        // 生成密码哈希
-        let hashed_password = bcrypt::hash(&request.password, self.config.bcrypt_rounds)?;
-        let now = Utc::now();
+        ensure_seat_available(&mut tx, company_id, true).await?;
 
         // 插入新用户 - 使用简化的TaskFleet字段(id会自动生成)
         let result = sqlx::query(
             r#"
-            INSERT INTO users (username, email, hashed_password, role, full_name, is_active, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO users (username, email, email_index, hashed_password, role, full_name, is_active, company_id, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(&request.username)
-        .bind(&request.email)
+        .bind(pii::seal(&request.email)?)
+        .bind(&email_index)
         .bind(&hashed_password)
-        .bind(request.role.as_str())
+        .bind(role.as_str())
         .bind(if request.full_name.is_empty() { request.username.clone() } else { request.full_name.clone() })
         .bind(true)
+        .bind(company_id)
         .bind(now)
         .bind(now)
-        .execute(&self.database.pool)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
 
         let user_id = result.last_insert_rowid();
 
-        // 返回用户信息
-        Ok(UserInfo {
+        let user = UserInfo {
             id: user_id,
             username: request.username.clone(),
             email: request.email.clone(),
             full_name: if request.full_name.is_empty() { request.username.clone() } else { request.full_name.clone() },
-            role: request.role,
+            permissions: role.permissions(),
+            role,
             is_active: true,
-            company_id: None,  // 注册的新用户没有公司
+            company_id: Some(company_id),
             parent_id: None,  // 注册的新用户没有上级
             created_at: now.format("%Y-%m-%d %H:%M:%S").to_string(),
             last_login: None,
-        })
-    }
+            custom_role_id: None,
+        };
+        AuditService::new(self.database.clone())
+            .record(
+                AuditEvent::new(ACTION_USER_CREATED)
+                    .actor(user.id)
+                    .company(user.company_id)
+                    .target("user", user.id)
+                    .client(client)
+                    .details(audit::diff(None, Some(&user))),
+            )
+            .await?;
 
-    pub async fn refresh_token(&self, user_id: &str) -> Result<String> {
-        // 查找用户
-        let user_id: i64 = user_id.parse()?;
-        let user = sqlx::query_as::<_, User>(
-            "SELECT * FROM users WHERE id = ? AND is_active = 1",
-        )
-        .bind(user_id)
-        .fetch_optional(&self.database.pool)
-        .await?
-        .ok_or_else(|| anyhow!("用户不存在或已被禁用"))?;
-
-        // 生成新的JWT token
-        create_jwt_token(
-            &user.id.to_string(),
-            user.role.as_str(),
-            &self.config.jwt_secret,
-            self.config.jwt_expires_in,
-        )
+        Ok(user)
     }
 }
@@ -1,13 +1,18 @@
 use crate::database::Database;
 use crate::errors::AppError;
-use crate::models::{Company, CompanyInfo, UserRole};
+use crate::middleware::client::ClientInfo;
+use crate::models::{Company, CompanyInfo, Permission, UserInfo};
 use crate::repositories::CompanyRepository;
+use crate::services::audit::{
+    self, AuditEvent, AuditService, ACTION_COMPANY_CREATED, ACTION_COMPANY_DELETED, ACTION_COMPANY_UPDATED,
+};
 use validator::Validate;
 use serde::Deserialize;
 use chrono::Utc;
 
 /// 公司管理服务
 pub struct CompanyService {
+    db: Database,
     company_repo: CompanyRepository,
 }
 
@@ -16,6 +21,10 @@ pub struct CompanyService {
 pub struct CreateCompanyRequest {
     #[validate(length(min = 1, max = 100, message = "公司名称长度必须在1-100个字符之间"))]
     pub name: String,
+
+    /// 公司代码,员工单点登录时填写
+    #[validate(length(min = 2, max = 50, message = "公司代码长度必须在2-50个字符之间"))]
+    pub code: String,
     
     #[validate(email(message = "请提供有效的电子邮件地址"))]
     pub contact_email: String,
@@ -29,6 +38,9 @@ pub struct CreateCompanyRequest {
 pub struct UpdateCompanyRequest {
     #[validate(length(min = 1, max = 100, message = "公司名称长度必须在1-100个字符之间"))]
     pub name: Option<String>,
+
+    #[validate(length(min = 2, max = 50, message = "公司代码长度必须在2-50个字符之间"))]
+    pub code: Option<String>,
     
     #[validate(email(message = "请提供有效的电子邮件地址"))]
     pub contact_email: Option<String>,
@@ -39,22 +51,54 @@ pub struct UpdateCompanyRequest {
     pub is_active: Option<bool>,
 }
 
+/// 公司代码只能包含字母、数字、下划线和横线
+fn validate_code(code: &str) -> Result<(), AppError> {
+    if !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(AppError::BadRequest("公司代码只能包含字母、数字、下划线和横线".to_string()));
+    }
+    Ok(())
+}
+
 impl CompanyService {
     pub fn new(db: Database) -> Self {
         Self {
-            company_repo: CompanyRepository::new(db),
+            company_repo: CompanyRepository::new(db.clone()),
+            db,
         }
     }
 
+    /// 记录公司的创建、修改和删除
+    async fn audit(
+        &self,
+        action: &'static str,
+        company_id: i64,
+        before: Option<&CompanyInfo>,
+        after: Option<&CompanyInfo>,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(action)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("company", company_id)
+                    .client(client)
+                    .details(audit::diff(before, after)),
+            )
+            .await
+    }
+
     /// 创建新公司(仅SystemAdmin)
     pub async fn create_company(
         &self,
         request: CreateCompanyRequest,
-        user_role: UserRole,
+        current_user: &UserInfo,
+        client: &ClientInfo,
     ) -> Result<CompanyInfo, AppError> {
         // 权限检查:仅PlatformAdmin可创建公司
-        if user_role != UserRole::PlatformAdmin {
-            return Err(AppError::Forbidden);
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
         }
 
         // 验证请求参数
@@ -68,12 +112,21 @@ impl CompanyService {
         {
             return Err(AppError::BadRequest("公司名称已存在".to_string()));
         }
+        validate_code(&request.code)?;
+        if self.company_repo.find_by_code(&request.code)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .is_some()
+        {
+            return Err(AppError::BadRequest("公司代码已存在".to_string()));
+        }
 
         // 创建公司对象
         let now = Utc::now();
         let company = Company {
             id: 0,  // SQLite会自动生成
             name: request.name,
+            code: request.code,
             contact_email: Some(request.contact_email),
             contact_phone: None,
             max_employees: request.max_employees,
@@ -87,21 +140,21 @@ impl CompanyService {
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-        Ok(CompanyInfo::from(created_company))
+        let info = CompanyInfo::from(created_company);
+        self.audit(ACTION_COMPANY_CREATED, info.id, None, Some(&info), current_user, client).await?;
+
+        Ok(info)
     }
 
     /// 获取公司详情(包含员工数量)
     pub async fn get_company(
         &self,
         id: i64,
-        user_role: UserRole,
-        user_company_id: Option<i64>,
+        current_user: &UserInfo,
     ) -> Result<CompanyInfo, AppError> {
-        // 权限检查:ProjectManager只能查看自己的公司
-        if user_role == UserRole::ProjectManager {
-            if user_company_id != Some(id) {
-                return Err(AppError::Forbidden);
-            }
+        // 权限检查:只能查看自己的公司,平台管理员不受限制
+        if !current_user.can(Permission::CompanyView, Some(id)) {
+            return Err(AppError::MissingPermission(Permission::CompanyView));
         }
 
         let company = self.company_repo.find_by_id(id)
@@ -123,12 +176,12 @@ impl CompanyService {
     /// 获取所有公司列表(仅PlatformAdmin)
     pub async fn list_companies(
         &self,
-        user_role: UserRole,
+        current_user: &UserInfo,
         active_only: bool,
     ) -> Result<Vec<CompanyInfo>, AppError> {
         // 权限检查:仅PlatformAdmin可查看所有公司
-        if user_role != UserRole::PlatformAdmin {
-            return Err(AppError::Forbidden);
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
         }
 
         let companies = if active_only {
@@ -149,11 +202,12 @@ impl CompanyService {
         &self,
         id: i64,
         request: UpdateCompanyRequest,
-        user_role: UserRole,
+        current_user: &UserInfo,
+        client: &ClientInfo,
     ) -> Result<CompanyInfo, AppError> {
         // 权限检查:仅PlatformAdmin可更新公司
-        if user_role != UserRole::PlatformAdmin {
-            return Err(AppError::Forbidden);
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
         }
 
         // 验证请求参数
@@ -165,6 +219,7 @@ impl CompanyService {
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?
             .ok_or_else(|| AppError::NotFound("公司不存在".to_string()))?;
+        let before = CompanyInfo::from(company.clone());
 
         // 如果更新名称,检查是否与其他公司重复
         if let Some(ref new_name) = request.name {
@@ -178,10 +233,26 @@ impl CompanyService {
             }
         }
 
+        // 修改公司代码后,员工单点登录需改用新代码
+        if let Some(ref new_code) = request.code {
+            validate_code(new_code)?;
+            if new_code != &company.code
+                && self.company_repo.find_by_code(new_code)
+                    .await
+                    .map_err(|e| AppError::DatabaseError(e.to_string()))?
+                    .is_some()
+            {
+                return Err(AppError::BadRequest("公司代码已被使用".to_string()));
+            }
+        }
+
         // 应用更新
         if let Some(name) = request.name {
             company.name = name;
         }
+        if let Some(code) = request.code {
+            company.code = code;
+        }
         if let Some(email) = request.contact_email {
             company.contact_email = Some(email);
         }
@@ -198,22 +269,26 @@ impl CompanyService {
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-        Ok(CompanyInfo::from(company))
+        let after = CompanyInfo::from(company);
+        self.audit(ACTION_COMPANY_UPDATED, id, Some(&before), Some(&after), current_user, client).await?;
+
+        Ok(after)
     }
 
     /// 删除公司(仅PlatformAdmin)
     pub async fn delete_company(
         &self,
         id: i64,
-        user_role: UserRole,
+        current_user: &UserInfo,
+        client: &ClientInfo,
     ) -> Result<(), AppError> {
         // 权限检查:仅PlatformAdmin可删除公司
-        if user_role != UserRole::PlatformAdmin {
-            return Err(AppError::Forbidden);
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
         }
 
         // 检查公司是否存在
-        self.company_repo.find_by_id(id)
+        let company = self.company_repo.find_by_id(id)
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?
             .ok_or_else(|| AppError::NotFound("公司不存在".to_string()))?;
@@ -234,18 +309,20 @@ impl CompanyService {
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-        Ok(())
+        let before = CompanyInfo::from(company);
+        self.audit(ACTION_COMPANY_DELETED, id, Some(&before), None, current_user, client).await
     }
 
     /// 停用/激活公司(仅PlatformAdmin)
     pub async fn toggle_company_status(
         &self,
         id: i64,
-        user_role: UserRole,
+        current_user: &UserInfo,
+        client: &ClientInfo,
     ) -> Result<CompanyInfo, AppError> {
         // 权限检查:仅PlatformAdmin可修改公司状态
-        if user_role != UserRole::PlatformAdmin {
-            return Err(AppError::Forbidden);
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
         }
 
         // 获取公司
@@ -253,6 +330,7 @@ impl CompanyService {
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?
             .ok_or_else(|| AppError::NotFound("公司不存在".to_string()))?;
+        let before = CompanyInfo::from(company.clone());
 
         // 切换状态
         company.is_active = !company.is_active;
@@ -263,6 +341,24 @@ impl CompanyService {
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-        Ok(CompanyInfo::from(company))
+        let after = CompanyInfo::from(company);
+        self.audit(ACTION_COMPANY_UPDATED, id, Some(&before), Some(&after), current_user, client).await?;
+
+        Ok(after)
+    }
+}
+
+/// 用户所属公司已停用时拒绝登录和访问(平台用户不属于任何公司,不受影响)
+pub async fn ensure_company_active(db: &Database, user_id: i64) -> Result<(), AppError> {
+    let is_active = sqlx::query_scalar::<_, Option<bool>>(
+        "SELECT c.is_active FROM users u JOIN companies c ON c.id = u.company_id WHERE u.id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    match is_active {
+        Some(Some(false)) => Err(AppError::CompanyInactive),
+        _ => Ok(()),
     }
 }
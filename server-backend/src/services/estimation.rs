@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::database::Database;
+use crate::errors::AppError;
+use crate::services::flow_metrics::percentile;
+use crate::services::statistics::StatisticsScope;
+
+/// 默认分析最近的月数
+pub const DEFAULT_ESTIMATION_MONTHS: u32 = 6;
+/// 允许分析的最大月数
+pub const MAX_ESTIMATION_MONTHS: u32 = 36;
+/// 建议工时参考的历史月数
+const SUGGESTION_HISTORY_MONTHS: u32 = 12;
+/// 给出建议工时至少需要的相似任务数
+const MIN_SIMILAR_TASKS: usize = 3;
+/// 实际/预估比值落在此区间内视为估算准确
+const ACCURATE_RATIO: (f64, f64) = (0.8, 1.2);
+/// 误差分布的区间边界(实际/预估)
+const RATIO_BUCKET_EDGES: [f64; 5] = [0.5, 0.8, 1.2, 1.5, 2.0];
+
+/// 误差分布区间
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RatioBucket {
+    /// 实际/预估比值区间,如 "0.8-1.2"
+    pub range: String,
+    pub tasks: i64,
+}
+
+/// 某个分组(员工/项目/标签)的估算准确度
+#[derive(Debug, Serialize)]
+pub struct EstimationAccuracy {
+    /// 分组键(员工ID、项目ID或标签),未分配员工/项目时为空
+    pub key: Option<String>,
+    /// 分组名称(员工姓名或项目名称)
+    pub name: Option<String>,
+    /// 有预估工时和实际工时的已完成任务数
+    pub samples: i64,
+    /// 平均相对误差: |实际 - 预估| / 预估
+    pub mean_absolute_error: Option<f64>,
+    /// 平均偏差: (实际 - 预估) / 预估, 正数表示普遍低估, 负数表示普遍高估
+    pub bias: Option<f64>,
+    /// 实际/预估比值的中位数
+    pub median_ratio: Option<f64>,
+    /// 低估(实际超出预估20%以上)的任务数
+    pub underestimated: i64,
+    /// 高估(实际不足预估80%)的任务数
+    pub overestimated: i64,
+    /// 估算准确的任务数
+    pub accurate: i64,
+    pub distribution: Vec<RatioBucket>,
+}
+
+/// 按完成月份统计的估算误差
+#[derive(Debug, Serialize)]
+pub struct EstimationTrendPoint {
+    /// 月份, 格式 YYYY-MM
+    pub month: String,
+    pub samples: i64,
+    pub mean_absolute_error: Option<f64>,
+    pub bias: Option<f64>,
+}
+
+/// 工时估算准确度报告
+#[derive(Debug, Serialize)]
+pub struct EstimationReport {
+    /// 分析的月数(含本月)
+    pub months: u32,
+    pub overall: EstimationAccuracy,
+    pub by_user: Vec<EstimationAccuracy>,
+    pub by_project: Vec<EstimationAccuracy>,
+    pub by_label: Vec<EstimationAccuracy>,
+    pub trend: Vec<EstimationTrendPoint>,
+}
+
+/// 新任务的特征,用于查找相似的历史任务
+#[derive(Debug, Default)]
+pub struct SimilarTaskQuery {
+    pub project_id: Option<String>,
+    pub priority: Option<String>,
+    pub labels: Vec<String>,
+}
+
+/// 建议工时
+#[derive(Debug, Serialize)]
+pub struct SuggestedEstimate {
+    /// 建议工时(相似任务实际工时的中位数),历史数据不足时为空
+    pub suggested_hours: Option<f64>,
+    /// 相似任务实际工时的25分位数
+    pub low_hours: Option<f64>,
+    /// 相似任务实际工时的75分位数
+    pub high_hours: Option<f64>,
+    /// 参考的相似任务数
+    pub similar_tasks: i64,
+    /// 相似任务的匹配依据: project_and_labels / labels / project / priority / all
+    pub basis: Option<String>,
+}
+
+/// 已完成任务查询行: (任务ID, 预估工时, 实际工时, 优先级, 项目ID, 项目名称, 执行者ID, 执行者姓名, 完成月份)
+type SampleRow = (
+    String,
+    Option<f64>,
+    f64,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// 判断历史任务是否与新任务相似
+type SimilarityRule<'a> = Box<dyn Fn(&EstimationSample) -> bool + 'a>;
+
+/// 一个有实际工时的已完成任务
+struct EstimationSample {
+    estimated: Option<f64>,
+    actual: f64,
+    priority: String,
+    project: (Option<String>, Option<String>),
+    assignee: (Option<String>, Option<String>),
+    month: Option<String>,
+    labels: Vec<String>,
+}
+
+impl EstimationSample {
+    /// 实际/预估比值,没有有效预估工时时为空
+    fn ratio(&self) -> Option<f64> {
+        self.estimated
+            .filter(|estimated| *estimated > 0.0)
+            .map(|estimated| self.actual / estimated)
+    }
+
+    fn shares_label(&self, labels: &[String]) -> bool {
+        self.labels.iter().any(|label| labels.contains(label))
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| round2(sum / count as f64))
+}
+
+fn bucket_label(index: usize) -> String {
+    match index {
+        0 => format!("<{}", RATIO_BUCKET_EDGES[0]),
+        i if i == RATIO_BUCKET_EDGES.len() => format!(">={}", RATIO_BUCKET_EDGES[i - 1]),
+        i => format!("{}-{}", RATIO_BUCKET_EDGES[i - 1], RATIO_BUCKET_EDGES[i]),
+    }
+}
+
+fn accuracy(key: Option<String>, name: Option<String>, samples: &[&EstimationSample]) -> EstimationAccuracy {
+    let mut ratios: Vec<f64> = samples.iter().filter_map(|sample| sample.ratio()).collect();
+    ratios.sort_by(|a, b| a.total_cmp(b));
+
+    let mut distribution: Vec<RatioBucket> = (0..=RATIO_BUCKET_EDGES.len())
+        .map(|index| RatioBucket { range: bucket_label(index), tasks: 0 })
+        .collect();
+    for ratio in &ratios {
+        let index = RATIO_BUCKET_EDGES.iter().take_while(|edge| *ratio >= **edge).count();
+        distribution[index].tasks += 1;
+    }
+
+    EstimationAccuracy {
+        key,
+        name,
+        samples: ratios.len() as i64,
+        mean_absolute_error: mean(ratios.iter().map(|ratio| (ratio - 1.0).abs())),
+        bias: mean(ratios.iter().map(|ratio| ratio - 1.0)),
+        median_ratio: percentile(&ratios, 50.0),
+        underestimated: ratios.iter().filter(|ratio| **ratio > ACCURATE_RATIO.1).count() as i64,
+        overestimated: ratios.iter().filter(|ratio| **ratio < ACCURATE_RATIO.0).count() as i64,
+        accurate: ratios
+            .iter()
+            .filter(|ratio| (ACCURATE_RATIO.0..=ACCURATE_RATIO.1).contains(*ratio))
+            .count() as i64,
+        distribution,
+    }
+}
+
+/// 按分组键汇总,分组按样本数从多到少排列
+fn group_by<F>(samples: &[&EstimationSample], dimension: F) -> Vec<EstimationAccuracy>
+where
+    F: Fn(&EstimationSample) -> Vec<(Option<String>, Option<String>)>,
+{
+    let mut groups: BTreeMap<Option<String>, (Option<String>, Vec<&EstimationSample>)> = BTreeMap::new();
+    for sample in samples {
+        for (key, name) in dimension(sample) {
+            groups.entry(key).or_insert_with(|| (name, Vec::new())).1.push(sample);
+        }
+    }
+
+    let mut report: Vec<EstimationAccuracy> = groups
+        .into_iter()
+        .map(|(key, (name, samples))| accuracy(key, name, &samples))
+        .collect();
+    report.sort_by_key(|group| std::cmp::Reverse(group.samples));
+    report
+}
+
+/// 从 `now` 所在月往前 `months` 个月(含本月)的第一天
+fn first_month(now: DateTime<Utc>, months: u32) -> NaiveDate {
+    let today = now.date_naive();
+    let this_month = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today);
+    this_month
+        .checked_sub_months(Months::new(months.saturating_sub(1)))
+        .unwrap_or(this_month)
+}
+
+/// 工时估算分析服务
+///
+/// 比较已完成任务的预估工时与实际工时,并根据相似的历史任务为新任务建议工时。
+pub struct EstimationService {
+    db: Database,
+}
+
+impl EstimationService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// 加载统计范围内自 `since` 起完成、且有实际工时的任务
+    async fn load_samples(&self, scope: StatisticsScope, since: NaiveDate) -> Result<Vec<EstimationSample>, AppError> {
+        let (filter, param) = scope.task_filter();
+        let completed = "t.status = 'completed' AND t.actual_hours > 0 \
+            AND COALESCE(datetime(t.completed_at), datetime(t.updated_at)) >= ?";
+        let since = since.format("%Y-%m-%d 00:00:00").to_string();
+
+        let sql = format!(
+            r#"
+            SELECT t.id, t.estimated_hours, t.actual_hours, t.priority,
+                   t.project_id, p.name, t.assigned_to, u.full_name,
+                   strftime('%Y-%m', COALESCE(t.completed_at, t.updated_at))
+            FROM tasks t
+            LEFT JOIN projects p ON p.id = t.project_id
+            LEFT JOIN users u ON u.id = t.assigned_to
+            WHERE {} AND {}
+            "#,
+            completed, filter
+        );
+        let mut query = sqlx::query_as::<_, SampleRow>(&sql).bind(&since);
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
+        let rows = query
+            .fetch_all(&self.db.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let sql = format!(
+            "SELECT l.task_id, l.label FROM task_labels l JOIN tasks t ON t.id = l.task_id WHERE {} AND {}",
+            completed, filter
+        );
+        let mut query = sqlx::query_as::<_, (String, String)>(&sql).bind(&since);
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
+        let mut labels: HashMap<String, Vec<String>> = HashMap::new();
+        for (task_id, label) in query
+            .fetch_all(&self.db.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        {
+            labels.entry(task_id).or_default().push(label);
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| EstimationSample {
+                labels: labels.remove(&row.0).unwrap_or_default(),
+                estimated: row.1,
+                actual: row.2,
+                priority: row.3,
+                project: (row.4, row.5),
+                assignee: (row.6, row.7),
+                month: row.8,
+            })
+            .collect())
+    }
+
+    /// 获取估算准确度报告
+    pub async fn get_estimation_report(&self, scope: StatisticsScope, months: u32) -> Result<EstimationReport, AppError> {
+        self.get_estimation_report_at(scope, months, Utc::now()).await
+    }
+
+    /// 以指定时间为"现在"生成估算准确度报告
+    pub async fn get_estimation_report_at(
+        &self,
+        scope: StatisticsScope,
+        months: u32,
+        now: DateTime<Utc>,
+    ) -> Result<EstimationReport, AppError> {
+        if !(1..=MAX_ESTIMATION_MONTHS).contains(&months) {
+            return Err(AppError::OutOfRange(format!("统计月数必须在1到{}之间", MAX_ESTIMATION_MONTHS)));
+        }
+
+        let since = first_month(now, months);
+        let samples = self.load_samples(scope, since).await?;
+        let estimated: Vec<&EstimationSample> = samples.iter().filter(|sample| sample.ratio().is_some()).collect();
+
+        let trend = (0..months)
+            .filter_map(|offset| since.checked_add_months(Months::new(offset)))
+            .map(|month| {
+                let month = month.format("%Y-%m").to_string();
+                let ratios: Vec<f64> = estimated
+                    .iter()
+                    .filter(|sample| sample.month.as_deref() == Some(month.as_str()))
+                    .filter_map(|sample| sample.ratio())
+                    .collect();
+                EstimationTrendPoint {
+                    samples: ratios.len() as i64,
+                    mean_absolute_error: mean(ratios.iter().map(|ratio| (ratio - 1.0).abs())),
+                    bias: mean(ratios.iter().map(|ratio| ratio - 1.0)),
+                    month,
+                }
+            })
+            .collect();
+
+        Ok(EstimationReport {
+            months,
+            overall: accuracy(None, None, &estimated),
+            by_user: group_by(&estimated, |sample| vec![sample.assignee.clone()]),
+            by_project: group_by(&estimated, |sample| vec![sample.project.clone()]),
+            by_label: group_by(&estimated, |sample| {
+                sample.labels.iter().map(|label| (Some(label.clone()), None)).collect()
+            }),
+            trend,
+        })
+    }
+
+    /// 根据相似历史任务的实际工时建议新任务的工时
+    ///
+    /// 依次尝试 同项目且有相同标签 / 有相同标签 / 同项目 / 同优先级 / 全部任务,
+    /// 采用第一个相似任务数足够的匹配方式。
+    pub async fn suggest_estimate(&self, scope: StatisticsScope, task: &SimilarTaskQuery) -> Result<SuggestedEstimate, AppError> {
+        let since = first_month(Utc::now(), SUGGESTION_HISTORY_MONTHS);
+        let samples = self.load_samples(scope, since).await?;
+
+        let same_project = |sample: &EstimationSample| {
+            task.project_id.is_some() && sample.project.0 == task.project_id
+        };
+        let same_labels = |sample: &EstimationSample| sample.shares_label(&task.labels);
+        let same_priority = |sample: &EstimationSample| task.priority.as_deref() == Some(sample.priority.as_str());
+
+        let levels: [(&str, SimilarityRule); 5] = [
+            ("project_and_labels", Box::new(|sample| same_project(sample) && same_labels(sample))),
+            ("labels", Box::new(same_labels)),
+            ("project", Box::new(same_project)),
+            ("priority", Box::new(same_priority)),
+            ("all", Box::new(|_| true)),
+        ];
+
+        for (basis, matches) in levels {
+            let mut hours: Vec<f64> = samples
+                .iter()
+                .filter(|sample| matches(sample))
+                .map(|sample| sample.actual)
+                .collect();
+            if hours.len() < MIN_SIMILAR_TASKS {
+                continue;
+            }
+
+            hours.sort_by(|a, b| a.total_cmp(b));
+            return Ok(SuggestedEstimate {
+                suggested_hours: percentile(&hours, 50.0),
+                low_hours: percentile(&hours, 25.0),
+                high_hours: percentile(&hours, 75.0),
+                similar_tasks: hours.len() as i64,
+                basis: Some(basis.to_string()),
+            });
+        }
+
+        Ok(SuggestedEstimate {
+            suggested_hours: None,
+            low_hours: None,
+            high_hours: None,
+            similar_tasks: samples.len() as i64,
+            basis: None,
+        })
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::database::Database;
+use crate::errors::AppError;
+use crate::services::statistics::StatisticsScope;
+
+/// 默认统计最近的周数
+pub const DEFAULT_FLOW_WEEKS: u32 = 12;
+/// 允许统计的最大周数
+pub const MAX_FLOW_WEEKS: u32 = 104;
+
+/// 耗时分布(小时),没有样本时各分位数为空
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DurationPercentiles {
+    /// 样本数
+    pub samples: i64,
+    pub p50: Option<f64>,
+    pub p85: Option<f64>,
+    pub p95: Option<f64>,
+}
+
+/// 某个分组(项目/执行者/优先级)的交付周期指标
+#[derive(Debug, Serialize)]
+pub struct FlowGroupMetrics {
+    /// 分组键(项目ID、员工ID或优先级),未分配项目/执行者时为空
+    pub key: Option<String>,
+    /// 分组名称(项目名称或员工姓名)
+    pub name: Option<String>,
+    /// 统计周期内完成的任务数
+    pub completed_tasks: i64,
+    /// 前置时间: 创建到完成
+    pub lead_time_hours: DurationPercentiles,
+    /// 周期时间: 首次开始处理到完成,未经过进行中状态的任务不计入
+    pub cycle_time_hours: DurationPercentiles,
+}
+
+/// 任务在某个状态停留的时长
+#[derive(Debug, Serialize)]
+pub struct StateDurationMetrics {
+    pub status: String,
+    pub hours: DurationPercentiles,
+}
+
+/// 每周完成的任务数
+#[derive(Debug, PartialEq, Serialize)]
+pub struct WeeklyThroughput {
+    /// 周一日期
+    pub week_start: NaiveDate,
+    pub completed_tasks: i64,
+}
+
+/// 交付周期与吞吐量统计
+#[derive(Debug, Serialize)]
+pub struct FlowMetrics {
+    /// 统计的周数(从本周往前)
+    pub weeks: u32,
+    pub overall: FlowGroupMetrics,
+    pub by_project: Vec<FlowGroupMetrics>,
+    pub by_assignee: Vec<FlowGroupMetrics>,
+    pub by_priority: Vec<FlowGroupMetrics>,
+    /// 完成前在各状态停留的时长
+    pub time_in_state: Vec<StateDurationMetrics>,
+    pub weekly_throughput: Vec<WeeklyThroughput>,
+}
+
+/// 状态流转查询行: (任务ID, 流转后状态, 流转时间戳, 优先级, 项目ID, 项目名称, 执行者ID, 执行者姓名)
+type TransitionRow = (
+    String,
+    String,
+    i64,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// 单个已完成任务的流转耗时
+struct CompletedTask {
+    priority: String,
+    project: (Option<String>, Option<String>),
+    assignee: (Option<String>, Option<String>),
+    completed_at: i64,
+    lead_hours: f64,
+    cycle_hours: Option<f64>,
+    state_hours: BTreeMap<String, f64>,
+}
+
+impl CompletedTask {
+    /// 根据按时间排序的流转记录计算耗时,任务最终未处于已完成状态时返回空
+    fn from_transitions(row: &TransitionRow, transitions: &[(String, i64)]) -> Option<Self> {
+        let completed_index = transitions.iter().rposition(|(status, _)| status == "completed")?;
+        let created_at = transitions.first()?.1;
+        let completed_at = transitions[completed_index].1;
+        let started_at = transitions[..completed_index]
+            .iter()
+            .find(|(status, _)| status == "in_progress")
+            .map(|(_, at)| *at);
+
+        let mut state_hours = BTreeMap::new();
+        for pair in transitions[..=completed_index].windows(2) {
+            *state_hours.entry(pair[0].0.clone()).or_insert(0.0) += hours_between(pair[0].1, pair[1].1);
+        }
+
+        Some(Self {
+            priority: row.3.clone(),
+            project: (row.4.clone(), row.5.clone()),
+            assignee: (row.6.clone(), row.7.clone()),
+            completed_at,
+            lead_hours: hours_between(created_at, completed_at),
+            cycle_hours: started_at.map(|started_at| hours_between(started_at, completed_at)),
+            state_hours,
+        })
+    }
+}
+
+fn hours_between(from: i64, to: i64) -> f64 {
+    (to - from).max(0) as f64 / 3600.0
+}
+
+/// 最近邻排名法计算分位数
+pub fn percentile(sorted: &[f64], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    let value = sorted[rank.clamp(1, sorted.len()) - 1];
+    Some((value * 100.0).round() / 100.0)
+}
+
+fn distribution(mut values: Vec<f64>) -> DurationPercentiles {
+    values.sort_by(|a, b| a.total_cmp(b));
+    DurationPercentiles {
+        samples: values.len() as i64,
+        p50: percentile(&values, 50.0),
+        p85: percentile(&values, 85.0),
+        p95: percentile(&values, 95.0),
+    }
+}
+
+fn group_metrics(key: Option<String>, name: Option<String>, tasks: &[&CompletedTask]) -> FlowGroupMetrics {
+    FlowGroupMetrics {
+        key,
+        name,
+        completed_tasks: tasks.len() as i64,
+        lead_time_hours: distribution(tasks.iter().map(|task| task.lead_hours).collect()),
+        cycle_time_hours: distribution(tasks.iter().filter_map(|task| task.cycle_hours).collect()),
+    }
+}
+
+/// 按分组键汇总,分组按完成任务数从多到少排列
+fn group_by<F>(tasks: &[CompletedTask], dimension: F) -> Vec<FlowGroupMetrics>
+where
+    F: Fn(&CompletedTask) -> (Option<String>, Option<String>),
+{
+    let mut groups: BTreeMap<Option<String>, (Option<String>, Vec<&CompletedTask>)> = BTreeMap::new();
+    for task in tasks {
+        let (key, name) = dimension(task);
+        groups.entry(key).or_insert_with(|| (name, Vec::new())).1.push(task);
+    }
+
+    let mut metrics: Vec<FlowGroupMetrics> = groups
+        .into_iter()
+        .map(|(key, (name, tasks))| group_metrics(key, name, &tasks))
+        .collect();
+    metrics.sort_by_key(|group| std::cmp::Reverse(group.completed_tasks));
+    metrics
+}
+
+/// 某天所在周的周一
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// 交付周期统计服务
+///
+/// 基于 `task_status_transitions` 中的状态流转记录计算,
+/// 只统计最近 `weeks` 周内完成的任务。
+pub struct FlowMetricsService {
+    db: Database,
+}
+
+impl FlowMetricsService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// 获取交付周期与吞吐量统计
+    pub async fn get_flow_metrics(&self, scope: StatisticsScope, weeks: u32) -> Result<FlowMetrics, AppError> {
+        self.get_flow_metrics_at(scope, weeks, Utc::now()).await
+    }
+
+    /// 以指定时间为"现在"计算统计
+    pub async fn get_flow_metrics_at(
+        &self,
+        scope: StatisticsScope,
+        weeks: u32,
+        now: DateTime<Utc>,
+    ) -> Result<FlowMetrics, AppError> {
+        if !(1..=MAX_FLOW_WEEKS).contains(&weeks) {
+            return Err(AppError::OutOfRange(format!("统计周数必须在1到{}之间", MAX_FLOW_WEEKS)));
+        }
+
+        let first_week = week_start(now.date_naive()) - Duration::weeks(weeks as i64 - 1);
+        let since = first_week.and_hms_opt(0, 0, 0).unwrap_or_default();
+
+        let (filter, param) = scope.task_filter();
+        let sql = format!(
+            r#"
+            SELECT h.task_id, h.to_status, CAST(strftime('%s', h.changed_at) AS INTEGER),
+                   t.priority, t.project_id, p.name, t.assigned_to, u.full_name
+            FROM task_status_transitions h
+            JOIN tasks t ON t.id = h.task_id
+            LEFT JOIN projects p ON p.id = t.project_id
+            LEFT JOIN users u ON u.id = t.assigned_to
+            WHERE t.status = 'completed' AND {}
+              AND EXISTS (
+                  SELECT 1 FROM task_status_transitions c
+                  WHERE c.task_id = t.id AND c.to_status = 'completed' AND c.changed_at >= ?
+              )
+            ORDER BY h.task_id, h.changed_at, h.from_status IS NOT NULL, h.id
+            "#,
+            filter
+        );
+
+        let mut query = sqlx::query_as::<_, TransitionRow>(&sql);
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
+        let rows = query
+            .bind(since.format("%Y-%m-%d %H:%M:%S").to_string())
+            .fetch_all(&self.db.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut tasks = Vec::new();
+        for chunk in rows.chunk_by(|a, b| a.0 == b.0) {
+            let transitions: Vec<(String, i64)> = chunk.iter().map(|row| (row.1.clone(), row.2)).collect();
+            tasks.extend(CompletedTask::from_transitions(&chunk[0], &transitions));
+        }
+
+        Ok(Self::summarize(&tasks, weeks, first_week))
+    }
+
+    fn summarize(tasks: &[CompletedTask], weeks: u32, first_week: NaiveDate) -> FlowMetrics {
+        let all: Vec<&CompletedTask> = tasks.iter().collect();
+
+        let mut state_hours: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+        for task in tasks {
+            for (status, hours) in &task.state_hours {
+                state_hours.entry(status).or_default().push(*hours);
+            }
+        }
+
+        let mut weekly: Vec<WeeklyThroughput> = (0..weeks)
+            .map(|week| WeeklyThroughput {
+                week_start: first_week + Duration::weeks(week as i64),
+                completed_tasks: 0,
+            })
+            .collect();
+        for task in tasks {
+            let Some(completed_at) = DateTime::from_timestamp(task.completed_at, 0) else {
+                continue;
+            };
+            let offset = (week_start(completed_at.date_naive()) - first_week).num_weeks();
+            if let Some(bucket) = usize::try_from(offset).ok().and_then(|index| weekly.get_mut(index)) {
+                bucket.completed_tasks += 1;
+            }
+        }
+
+        FlowMetrics {
+            weeks,
+            overall: group_metrics(None, None, &all),
+            by_project: group_by(tasks, |task| task.project.clone()),
+            by_assignee: group_by(tasks, |task| task.assignee.clone()),
+            by_priority: group_by(tasks, |task| (Some(task.priority.clone()), None)),
+            time_in_state: state_hours
+                .into_iter()
+                .map(|(status, hours)| StateDurationMetrics {
+                    status: status.to_string(),
+                    hours: distribution(hours),
+                })
+                .collect(),
+            weekly_throughput: weekly,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use serde_json::json;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{ImpersonationResponse, ImpersonatorInfo, Permission, StartImpersonationRequest, User, UserInfo},
+    services::{
+        audit::{
+            AuditEvent, AuditService, ACTION_IMPERSONATED_REQUEST, ACTION_IMPERSONATION_ENDED,
+            ACTION_IMPERSONATION_STARTED,
+        },
+        role::RoleService,
+        session::{SessionService, REVOKED_IMPERSONATION_ENDED},
+    },
+    Config, Database,
+};
+
+/// 模拟登录默认有效时长(分钟)
+const DEFAULT_DURATION_MINUTES: i64 = 30;
+/// 模拟登录最长有效时长(分钟)
+const MAX_DURATION_MINUTES: i64 = 60;
+
+/// 平台管理员模拟登录("以该用户身份操作")
+///
+/// 模拟令牌同时携带被模拟的用户(`sub`)和实际操作的管理员(`act`),
+/// 期间每个请求都记入审计日志,且不能修改密码和两步验证设置。
+pub struct ImpersonationService {
+    db: Database,
+    config: Config,
+}
+
+impl ImpersonationService {
+    pub fn new(db: Database, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    /// 开始模拟登录,不能模拟其他平台管理员
+    pub async fn start(
+        &self,
+        user_id: i64,
+        request: StartImpersonationRequest,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<ImpersonationResponse, AppError> {
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
+        }
+        if user_id == current_user.id {
+            return Err(AppError::OperationNotAllowed("不能模拟自己".to_string()));
+        }
+        let minutes = request.duration_minutes.unwrap_or(DEFAULT_DURATION_MINUTES);
+        if !(1..=MAX_DURATION_MINUTES).contains(&minutes) {
+            return Err(AppError::OutOfRange(format!("模拟时长需在1-{}分钟之间", MAX_DURATION_MINUTES)));
+        }
+
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ? AND is_active = 1")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+        let role = user.role.clone();
+        let user = RoleService::new(self.db.clone()).user_info(user).await?;
+        if user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::OperationNotAllowed("不能模拟其他平台管理员".to_string()));
+        }
+
+        let expires_in = minutes * 60;
+        let (session_id, token) = SessionService::new(self.db.clone(), self.config.clone())
+            .create_impersonation_session(user.id, &role, current_user.id, expires_in, client)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_IMPERSONATION_STARTED)
+                    .actor(current_user.id)
+                    .company(user.company_id)
+                    .target("user", user.id)
+                    .client(client)
+                    .details(json!({
+                        "reason": request.reason,
+                        "session_id": session_id,
+                        "expires_in": expires_in,
+                    })),
+            )
+            .await?;
+
+        Ok(ImpersonationResponse { token, expires_in, user })
+    }
+
+    /// 结束模拟登录并撤销模拟会话
+    pub async fn end(
+        &self,
+        session_id: &str,
+        user: &UserInfo,
+        impersonator_id: i64,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        SessionService::new(self.db.clone(), self.config.clone())
+            .revoke_session(session_id, REVOKED_IMPERSONATION_ENDED)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_IMPERSONATION_ENDED)
+                    .actor(impersonator_id)
+                    .company(user.company_id)
+                    .target("user", user.id)
+                    .client(client)
+                    .details(json!({ "session_id": session_id })),
+            )
+            .await
+    }
+
+    /// 模拟会话对应的管理员,普通会话为空
+    pub async fn impersonator(&self, session_id: &str) -> Result<Option<ImpersonatorInfo>, AppError> {
+        let row = sqlx::query_as::<_, (i64, String, Option<String>, String)>(
+            r#"
+            SELECT u.id, u.username, u.full_name, s.expires_at
+            FROM sessions s JOIN users u ON u.id = s.impersonator_id
+            WHERE s.id = ?
+            "#,
+        )
+        .bind(session_id)
+        .fetch_optional(&self.db.pool)
+        .await?;
+
+        Ok(row.map(|(id, username, full_name, expires_at)| ImpersonatorInfo {
+            id,
+            username,
+            full_name,
+            expires_at,
+        }))
+    }
+
+    /// 记录模拟登录期间的请求: 操作人为被模拟的用户,同时记录实际操作的管理员
+    pub async fn record_request(
+        &self,
+        user: &UserInfo,
+        impersonator_id: i64,
+        method: &str,
+        path: &str,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_IMPERSONATED_REQUEST)
+                    .actor(user.id)
+                    .impersonator(Some(impersonator_id))
+                    .company(user.company_id)
+                    .client(client)
+                    .details(json!({ "method": method, "path": path })),
+            )
+            .await
+    }
+}
@@ -0,0 +1,459 @@
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::SqliteConnection;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{
+        AcceptInvitationRequest, CreateInvitationRequest, InvitationInfo, InvitationPreview, Permission, User,
+        UserInfo, UserRole,
+    },
+    services::{
+        audit::{self, AuditEvent, AuditService, ACTION_INVITATION_ACCEPTED, ACTION_INVITATION_REVOKED, ACTION_USER_INVITED},
+        password::PasswordService,
+        registration::RegistrationService,
+        role::RoleService,
+    },
+    utils::{
+        mailer::{mailer_from_config, EmailMessage, Mailer},
+        pii::{self, SealedText},
+        PasswordHasher,
+    },
+    Config, Database,
+};
+
+/// 邀请链接有效期(秒)
+const INVITATION_EXPIRES_IN: i64 = 7 * 24 * 3600;
+
+/// 未接受、未撤销且未过期的邀请
+const PENDING: &str = "accepted_at IS NULL AND revoked_at IS NULL AND expires_at > ?";
+
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn sha256_hex(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+/// 检查公司是否还有空余名额(`max_employees`,为空表示不限)
+///
+/// 在职人数为启用中的用户数;`count_pending` 为真时待接受的邀请也占用名额,
+/// 接受邀请时该邀请本身已占用名额,只需与在职人数比较。
+pub async fn ensure_seat_available(
+    conn: &mut SqliteConnection,
+    company_id: i64,
+    count_pending: bool,
+) -> Result<(), AppError> {
+    let max_employees = sqlx::query_scalar::<_, Option<i64>>("SELECT max_employees FROM companies WHERE id = ?")
+        .bind(company_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("公司不存在".to_string()))?;
+    let Some(max_employees) = max_employees else {
+        return Ok(());
+    };
+
+    let mut occupied = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM users WHERE company_id = ? AND (is_active IS NULL OR is_active = 1)",
+    )
+    .bind(company_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    if count_pending {
+        occupied += sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*) FROM invitations WHERE company_id = ? AND {}",
+            PENDING
+        ))
+        .bind(company_id)
+        .bind(timestamp(Utc::now()))
+        .fetch_one(&mut *conn)
+        .await?;
+    }
+
+    if occupied >= max_employees {
+        return Err(AppError::SeatLimitExceeded(max_employees));
+    }
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct InvitationRow {
+    id: i64,
+    company_id: i64,
+    #[sqlx(try_from = "SealedText")]
+    email: String,
+    role: UserRole,
+    full_name: Option<String>,
+    invited_by: i64,
+    created_at: String,
+    expires_at: String,
+    accepted_at: Option<String>,
+    accepted_user_id: Option<i64>,
+    revoked_at: Option<String>,
+}
+
+impl InvitationRow {
+    fn status(&self) -> &'static str {
+        if self.accepted_at.is_some() {
+            "accepted"
+        } else if self.revoked_at.is_some() {
+            "revoked"
+        } else if self.expires_at <= timestamp(Utc::now()) {
+            "expired"
+        } else {
+            "pending"
+        }
+    }
+
+    fn into_info(self) -> InvitationInfo {
+        InvitationInfo {
+            status: self.status().to_string(),
+            id: self.id,
+            company_id: self.company_id,
+            email: self.email,
+            role: self.role,
+            full_name: self.full_name,
+            invited_by: self.invited_by,
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            accepted_user_id: self.accepted_user_id,
+        }
+    }
+}
+
+/// 成员邀请服务
+///
+/// 管理者按邮箱和角色邀请新成员,受邀人通过邮件中的链接自行设置用户名、密码和资料。
+/// 邀请令牌只保存哈希,一次性使用;发出邀请和接受邀请时都检查公司名额。
+pub struct InvitationService {
+    db: Database,
+    config: Config,
+    mailer: Box<dyn Mailer>,
+}
+
+impl InvitationService {
+    /// 使用配置中的邮件发送方式
+    pub fn new(db: Database, config: Config) -> Result<Self, AppError> {
+        let mailer = mailer_from_config(&config)?;
+        Ok(Self::with_mailer(db, config, mailer))
+    }
+
+    pub fn with_mailer(db: Database, config: Config, mailer: Box<dyn Mailer>) -> Self {
+        Self { db, config, mailer }
+    }
+
+    /// 发出邀请,需要目标公司的 `user.create` 权限,且只能邀请权限比自己少的角色
+    ///
+    /// 同一邮箱已有待接受的邀请时,原邀请作废并重新发送。
+    pub async fn invite(
+        &self,
+        request: &CreateInvitationRequest,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<InvitationInfo, AppError> {
+        let company_id = if current_user.has_permission(Permission::PlatformManage) {
+            request
+                .company_id
+                .ok_or_else(|| AppError::MissingField("company_id".to_string()))?
+        } else {
+            current_user
+                .company_id
+                .ok_or_else(|| AppError::OperationNotAllowed("没有所属公司,无法邀请成员".to_string()))?
+        };
+        if !current_user.can(Permission::UserCreate, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::UserCreate));
+        }
+        if request.role == UserRole::PlatformAdmin {
+            return Err(AppError::InvalidInput("不能邀请平台管理员".to_string()));
+        }
+        if !current_user.permissions.can_grant(&request.role.permissions()) {
+            return Err(AppError::Forbidden);
+        }
+
+        let email = request.email.trim().to_lowercase();
+        let email_index = pii::blind_index(&email);
+        let registered = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE email_index = ? OR lower(email) = ?")
+            .bind(&email_index)
+            .bind(&email)
+            .fetch_one(&self.db.pool)
+            .await?;
+        if registered > 0 {
+            return Err(AppError::Conflict("该邮箱已有账号".to_string()));
+        }
+
+        let company_name = self.active_company_name(company_id).await?;
+
+        let now = Utc::now();
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+
+        let mut tx = self.db.pool.begin().await?;
+        // 重新邀请同一邮箱时先作废原邀请,原邀请占用的名额随之释放
+        sqlx::query(&format!(
+            "UPDATE invitations SET revoked_at = ? WHERE company_id = ? AND (email_index = ? OR email = ?) AND {}",
+            PENDING
+        ))
+        .bind(timestamp(now))
+        .bind(company_id)
+        .bind(&email_index)
+        .bind(&email)
+        .bind(timestamp(now))
+        .execute(&mut *tx)
+        .await?;
+        ensure_seat_available(&mut tx, company_id, true).await?;
+        let invitation_id = sqlx::query(
+            r#"
+            INSERT INTO invitations (company_id, email, email_index, role, full_name, token_hash, invited_by, created_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(company_id)
+        .bind(pii::seal(&email)?)
+        .bind(&email_index)
+        .bind(request.role.as_str())
+        .bind(request.full_name.as_deref().map(str::trim).filter(|name| !name.is_empty()))
+        .bind(sha256_hex(&token))
+        .bind(current_user.id)
+        .bind(timestamp(now))
+        .bind(timestamp(now + Duration::seconds(INVITATION_EXPIRES_IN)))
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        tx.commit().await?;
+
+        let message = EmailMessage {
+            to: email.clone(),
+            subject: format!("{}邀请您加入 Flow Farm", company_name),
+            body: format!(
+                "您好:\n\n{}邀请您加入{}。请在{}天内打开以下链接设置账号和密码:\n{}\n\n如果您不认识邀请人,请忽略此邮件。\n",
+                current_user.full_name,
+                company_name,
+                INVITATION_EXPIRES_IN / (24 * 3600),
+                self.link(&token)
+            ),
+        };
+        if let Err(e) = self.mailer.send(&message).await {
+            // 邮件未送达的邀请无法接受,作废以免占用名额
+            sqlx::query("UPDATE invitations SET revoked_at = ? WHERE id = ?")
+                .bind(timestamp(Utc::now()))
+                .bind(invitation_id)
+                .execute(&self.db.pool)
+                .await?;
+            return Err(e);
+        }
+
+        let invitation = self.find(invitation_id).await?.into_info();
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_USER_INVITED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("invitation", invitation_id)
+                    .client(client)
+                    .details(audit::diff(None, Some(&invitation))),
+            )
+            .await?;
+
+        Ok(invitation)
+    }
+
+    /// 公司的邀请记录(最近的在前),需要 `user.view` 权限
+    pub async fn list(&self, company_id: Option<i64>, current_user: &UserInfo) -> Result<Vec<InvitationInfo>, AppError> {
+        let company_id = company_id
+            .or(current_user.company_id)
+            .ok_or_else(|| AppError::MissingField("company_id".to_string()))?;
+        if !current_user.can(Permission::UserView, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::UserView));
+        }
+
+        let rows = sqlx::query_as::<_, InvitationRow>("SELECT * FROM invitations WHERE company_id = ? ORDER BY id DESC")
+            .bind(company_id)
+            .fetch_all(&self.db.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(InvitationRow::into_info).collect())
+    }
+
+    /// 撤销待接受的邀请,需要该公司的 `user.create` 权限
+    pub async fn revoke(&self, invitation_id: i64, current_user: &UserInfo, client: &ClientInfo) -> Result<(), AppError> {
+        let invitation = self.find(invitation_id).await?;
+        if !current_user.can(Permission::UserCreate, Some(invitation.company_id)) {
+            return Err(AppError::NotFound("邀请不存在".to_string()));
+        }
+        if invitation.status() != "pending" {
+            return Err(AppError::InvalidState("邀请已接受、撤销或过期".to_string()));
+        }
+
+        sqlx::query("UPDATE invitations SET revoked_at = ? WHERE id = ? AND accepted_at IS NULL AND revoked_at IS NULL")
+            .bind(timestamp(Utc::now()))
+            .bind(invitation_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_INVITATION_REVOKED)
+                    .actor(current_user.id)
+                    .company(Some(invitation.company_id))
+                    .target("invitation", invitation_id)
+                    .client(client),
+            )
+            .await
+    }
+
+    /// 受邀人打开链接时查看邀请内容
+    pub async fn preview(&self, token: &str) -> Result<InvitationPreview, AppError> {
+        let invitation = self.find_by_token(token).await?;
+        let company_name = self.active_company_name(invitation.company_id).await?;
+
+        Ok(InvitationPreview {
+            email: invitation.email,
+            company_name,
+            role: invitation.role,
+            full_name: invitation.full_name,
+            expires_at: invitation.expires_at,
+        })
+    }
+
+    /// 接受邀请: 以受邀邮箱和邀请的角色创建账号,邮箱视为已验证,邀请人作为上级
+    ///
+    /// 邀请作废与创建用户在同一事务中完成,并在事务内再次检查公司名额,
+    /// 保证同一邀请只能接受一次,并发接受也不会超出名额。
+    pub async fn accept(&self, request: &AcceptInvitationRequest, client: &ClientInfo) -> Result<UserInfo, AppError> {
+        RegistrationService::new(self.db.clone()).ensure_invitations_allowed().await?;
+        let invitation = self.find_by_token(&request.token).await?;
+        self.active_company_name(invitation.company_id).await?;
+
+        let username = request.username.trim();
+        PasswordService::new(self.db.clone(), self.config.clone()).check_new_user_password(&request.password, username)?;
+        let hashed_password = PasswordHasher::from_config(&self.config)?.hash(&request.password)?;
+        let full_name = request
+            .full_name
+            .as_deref()
+            .or(invitation.full_name.as_deref())
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .unwrap_or(username)
+            .to_string();
+        let phone = request.phone.as_deref().map(str::trim).filter(|phone| !phone.is_empty());
+
+        let now = timestamp(Utc::now());
+        let mut tx = self.db.pool.begin().await?;
+        let claimed = sqlx::query("UPDATE invitations SET accepted_at = ? WHERE id = ? AND accepted_at IS NULL AND revoked_at IS NULL")
+            .bind(&now)
+            .bind(invitation.id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if claimed == 0 {
+            return Err(AppError::TokenInvalid);
+        }
+        ensure_seat_available(&mut tx, invitation.company_id, false).await?;
+
+        let taken = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_one(&mut *tx)
+            .await?;
+        if taken > 0 {
+            return Err(AppError::DuplicateUsername(username.to_string()));
+        }
+        let email_index = pii::blind_index(&invitation.email);
+        let registered = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE email_index = ? OR lower(email) = ?")
+            .bind(&email_index)
+            .bind(&invitation.email)
+            .fetch_one(&mut *tx)
+            .await?;
+        if registered > 0 {
+            return Err(AppError::Conflict("该邮箱已有账号".to_string()));
+        }
+
+        let user_id = sqlx::query(
+            r#"
+            INSERT INTO users (username, email, email_index, hashed_password, role, full_name, phone, phone_index, is_active, is_verified, company_id, parent_id, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, 1, 1, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(username)
+        .bind(pii::seal(&invitation.email)?)
+        .bind(&email_index)
+        .bind(&hashed_password)
+        .bind(invitation.role.as_str())
+        .bind(&full_name)
+        .bind(pii::seal_optional(phone)?)
+        .bind(pii::blind_index_optional(phone))
+        .bind(invitation.company_id)
+        .bind(invitation.invited_by)
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        sqlx::query("UPDATE invitations SET accepted_user_id = ? WHERE id = ?")
+            .bind(user_id)
+            .bind(invitation.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_one(&self.db.pool)
+            .await?;
+        let user = RoleService::new(self.db.clone()).user_info(user).await?;
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_INVITATION_ACCEPTED)
+                    .actor(user_id)
+                    .company(Some(invitation.company_id))
+                    .target("user", user_id)
+                    .client(client)
+                    .details(audit::diff(None, Some(&user))),
+            )
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn find(&self, invitation_id: i64) -> Result<InvitationRow, AppError> {
+        sqlx::query_as::<_, InvitationRow>("SELECT * FROM invitations WHERE id = ?")
+            .bind(invitation_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("邀请不存在".to_string()))
+    }
+
+    /// 按令牌查找待接受的邀请,过期返回 `TokenExpired`,其他情况返回 `TokenInvalid`
+    async fn find_by_token(&self, token: &str) -> Result<InvitationRow, AppError> {
+        let invitation = sqlx::query_as::<_, InvitationRow>("SELECT * FROM invitations WHERE token_hash = ?")
+            .bind(sha256_hex(token.trim()))
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or(AppError::TokenInvalid)?;
+
+        match invitation.status() {
+            "pending" => Ok(invitation),
+            "expired" => Err(AppError::TokenExpired),
+            _ => Err(AppError::TokenInvalid),
+        }
+    }
+
+    async fn active_company_name(&self, company_id: i64) -> Result<String, AppError> {
+        let company = sqlx::query_as::<_, (String, bool)>("SELECT name, COALESCE(is_active, 1) FROM companies WHERE id = ?")
+            .bind(company_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("公司不存在".to_string()))?;
+        match company {
+            (name, true) => Ok(name),
+            _ => Err(AppError::OperationNotAllowed("公司已停用".to_string())),
+        }
+    }
+
+    fn link(&self, token: &str) -> String {
+        format!("{}/accept-invitation?token={}", self.config.public_url.trim_end_matches('/'), token)
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde_json::json;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{Permission, UserInfo},
+    services::audit::{AuditEvent, AuditService, ACTION_ACCOUNT_LOCKED, ACTION_ACCOUNT_UNLOCKED, ACTION_IP_LOCKED},
+    Config, Database,
+};
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// 登录失败计数: (失败次数, 最近失败时间, 锁定截止时间)
+type ThrottleRow = (i64, Option<String>, Option<String>);
+
+/// 本次登录尝试对应的账号
+///
+/// 账号不存在时按登录名计数,使不存在的账号和存在的账号表现一致,
+/// 无法通过是否被锁定来探测账号是否存在。
+#[derive(Debug, Clone)]
+pub struct LoginSubject {
+    key: String,
+    user_id: Option<i64>,
+    company_id: Option<i64>,
+}
+
+impl LoginSubject {
+    pub fn user(user_id: i64, company_id: Option<i64>) -> Self {
+        Self {
+            key: format!("user:{}", user_id),
+            user_id: Some(user_id),
+            company_id,
+        }
+    }
+
+    pub fn unknown(identifier: &str) -> Self {
+        Self {
+            key: format!("login:{}", identifier.trim().to_lowercase()),
+            user_id: None,
+            company_id: None,
+        }
+    }
+}
+
+fn parse_timestamp(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT).ok()
+}
+
+/// 登录防爆破服务
+///
+/// 按账号和按IP分别计数连续失败次数:
+/// - 账号每次失败后需等待 `login_backoff_seconds × 2^(n-1)` 秒才能再次尝试;
+/// - 账号或IP失败次数达到阈值后锁定 `login_lockout_seconds` 秒,锁定到期前不再校验密码,
+///   到期后再失败一次即重新锁定,直到登录成功或被管理员解锁。
+pub struct LoginThrottleService {
+    db: Database,
+    config: Config,
+}
+
+impl LoginThrottleService {
+    pub fn new(db: Database, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    /// 校验本次登录是否允许尝试,需要等待时返回剩余秒数
+    pub async fn check(&self, subject: &LoginSubject, client: &ClientInfo) -> Result<(), AppError> {
+        let now = Utc::now().naive_utc();
+        let mut wait_until = self.allowed_at(&subject.key, true).await?;
+        if let Some(ip) = client.ip_address.as_deref() {
+            wait_until = wait_until.max(self.allowed_at(&ip_key(ip), false).await?);
+        }
+
+        match wait_until {
+            Some(until) if until > now => Err(AppError::TooManyAttempts((until - now).num_seconds().max(1))),
+            _ => Ok(()),
+        }
+    }
+
+    /// 记录一次失败的登录,达到阈值时锁定并写入审计日志
+    pub async fn record_failure(&self, subject: &LoginSubject, client: &ClientInfo) -> Result<(), AppError> {
+        let failures = self.increment(&subject.key).await?;
+        if failures >= i64::from(self.config.login_max_attempts) {
+            self.lock(&subject.key).await?;
+            tracing::warn!("🔒 登录失败 {} 次,已临时锁定 {}", failures, subject.key);
+
+            let mut event = AuditEvent::new(ACTION_ACCOUNT_LOCKED)
+                .company(subject.company_id)
+                .client(client)
+                .details(json!({ "failures": failures, "lockout_seconds": self.config.login_lockout_seconds }));
+            if let Some(user_id) = subject.user_id {
+                event = event.target("user", user_id);
+            }
+            AuditService::new(self.db.clone()).record(event).await?;
+        }
+
+        if let Some(ip) = client.ip_address.as_deref() {
+            let key = ip_key(ip);
+            let failures = self.increment(&key).await?;
+            if failures >= i64::from(self.config.login_ip_max_attempts) {
+                self.lock(&key).await?;
+                tracing::warn!("🔒 IP {} 登录失败 {} 次,已临时锁定", ip, failures);
+
+                let event = AuditEvent::new(ACTION_IP_LOCKED)
+                    .target("ip", ip)
+                    .ip_address(Some(ip.to_string()))
+                    .details(json!({ "failures": failures, "lockout_seconds": self.config.login_lockout_seconds }));
+                AuditService::new(self.db.clone()).record(event).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 登录成功后清除账号的失败计数(IP计数保留,避免用一个有效账号为爆破重置计数)
+    pub async fn record_success(&self, subject: &LoginSubject) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM login_throttles WHERE key = ?")
+            .bind(&subject.key)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 解除账号锁定: 项目经理只能解锁本公司用户,平台管理员可解锁任何用户
+    pub async fn unlock_user(
+        &self,
+        user_id: i64,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        let company_id = sqlx::query_scalar::<_, Option<i64>>("SELECT company_id FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+
+        if !current_user.can(Permission::UserSecurity, company_id) {
+            return Err(AppError::MissingPermission(Permission::UserSecurity));
+        }
+
+        let cleared = sqlx::query("DELETE FROM login_throttles WHERE key = ?")
+            .bind(LoginSubject::user(user_id, company_id).key)
+            .execute(&self.db.pool)
+            .await?
+            .rows_affected();
+
+        if cleared > 0 {
+            let event = AuditEvent::new(ACTION_ACCOUNT_UNLOCKED)
+                .actor(current_user.id)
+                .company(company_id)
+                .target("user", user_id)
+                .client(client);
+            AuditService::new(self.db.clone()).record(event).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 计算某个键下次允许尝试的时间,没有限制时返回空
+    async fn allowed_at(&self, key: &str, backoff: bool) -> Result<Option<NaiveDateTime>, AppError> {
+        let row = sqlx::query_as::<_, ThrottleRow>(
+            "SELECT failures, last_failed_at, locked_until FROM login_throttles WHERE key = ?",
+        )
+        .bind(key)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let Some((failures, last_failed_at, locked_until)) = row else {
+            return Ok(None);
+        };
+
+        let locked_until = locked_until.as_deref().and_then(parse_timestamp);
+        let backoff_until = last_failed_at
+            .as_deref()
+            .and_then(parse_timestamp)
+            .filter(|_| backoff && failures > 0 && self.config.login_backoff_seconds > 0)
+            .map(|last_failed_at| {
+                let exponent = (failures - 1).clamp(0, 30) as u32;
+                let wait = self
+                    .config
+                    .login_backoff_seconds
+                    .saturating_mul(1 << exponent)
+                    .min(self.config.login_lockout_seconds);
+                last_failed_at + Duration::seconds(wait)
+            });
+
+        Ok(locked_until.max(backoff_until))
+    }
+
+    async fn increment(&self, key: &str) -> Result<i64, AppError> {
+        let failures = sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO login_throttles (key, failures, last_failed_at) VALUES (?, 1, ?)
+            ON CONFLICT(key) DO UPDATE SET failures = failures + 1, last_failed_at = excluded.last_failed_at
+            RETURNING failures
+            "#,
+        )
+        .bind(key)
+        .bind(Utc::now().format(TIMESTAMP_FORMAT).to_string())
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(failures)
+    }
+
+    async fn lock(&self, key: &str) -> Result<(), AppError> {
+        let locked_until = Utc::now() + Duration::seconds(self.config.login_lockout_seconds);
+        sqlx::query("UPDATE login_throttles SET locked_until = ? WHERE key = ?")
+            .bind(locked_until.format(TIMESTAMP_FORMAT).to_string())
+            .bind(key)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
@@ -4,3 +4,19 @@ pub mod company;
 pub mod task;
 pub mod project;
 pub mod statistics;
+pub mod flow_metrics;
+pub mod estimation;
+pub mod session;
+pub mod audit;
+pub mod login_throttle;
+pub mod two_factor;
+pub mod account_email;
+pub mod password;
+pub mod api_token;
+pub mod sso;
+pub mod role;
+pub mod invitation;
+pub mod impersonation;
+pub mod pii;
+pub mod personal_data;
+pub mod registration;
@@ -0,0 +1,400 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{Permission, UserInfo},
+    services::{
+        audit::{AuditEvent, AuditService, ACTION_PASSWORD_CHANGED, ACTION_PASSWORD_SET_BY_ADMIN},
+        login_throttle::{LoginSubject, LoginThrottleService},
+        session::{SessionService, REVOKED_PASSWORD_CHANGED},
+    },
+    utils::PasswordHasher,
+    Config, Database,
+};
+
+/// bcrypt 只使用密码的前72个字节,更长的部分会被忽略;为了能随时切换哈希算法,统一按此限制
+const MAX_PASSWORD_BYTES: usize = 72;
+
+/// 已加载的泄露密码列表(按文件路径缓存,进程内只读取一次)
+static BLOCKLISTS: LazyLock<Mutex<HashMap<String, Arc<HashSet<String>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn load_blocklist(path: &str) -> Result<Arc<HashSet<String>>, AppError> {
+    let mut cache = BLOCKLISTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(blocklist) = cache.get(path) {
+        return Ok(blocklist.clone());
+    }
+
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| AppError::Internal(format!("读取泄露密码列表 {} 失败: {}", path, e)))?;
+    let blocklist: HashSet<String> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect();
+    tracing::info!("🔒 已加载泄露密码列表 {} ({} 条)", path, blocklist.len());
+
+    let blocklist = Arc::new(blocklist);
+    cache.insert(path.to_string(), blocklist.clone());
+    Ok(blocklist)
+}
+
+/// 密码字符类别
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharacterClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "lower" => Some(Self::Lower),
+            "upper" => Some(Self::Upper),
+            "digit" => Some(Self::Digit),
+            "symbol" => Some(Self::Symbol),
+            _ => None,
+        }
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            Self::Lower => c.is_lowercase(),
+            Self::Upper => c.is_uppercase(),
+            Self::Digit => c.is_ascii_digit(),
+            Self::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Lower => "小写字母",
+            Self::Upper => "大写字母",
+            Self::Digit => "数字",
+            Self::Symbol => "符号",
+        }
+    }
+}
+
+/// 密码强度策略(长度、字符类别、泄露密码列表)
+///
+/// 只在设置新密码时校验,不影响已有密码的登录。
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    required_classes: Vec<CharacterClass>,
+    blocklist_file: Option<String>,
+}
+
+impl PasswordPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        let required_classes = config
+            .password_required_classes
+            .iter()
+            .filter_map(|name| {
+                let class = CharacterClass::parse(name);
+                if class.is_none() {
+                    tracing::warn!("⚠️ 忽略未知的密码字符类别: {}", name);
+                }
+                class
+            })
+            .collect();
+
+        Self {
+            min_length: config.password_min_length,
+            required_classes,
+            blocklist_file: config.password_blocklist_file.clone(),
+        }
+    }
+
+    /// 校验密码强度,不满足时返回所有未满足的要求
+    pub fn check(&self, password: &str, username: &str) -> Result<(), AppError> {
+        let mut problems = Vec::new();
+
+        if password.chars().count() < self.min_length {
+            problems.push(format!("密码至少{}个字符", self.min_length));
+        }
+        if password.len() > MAX_PASSWORD_BYTES {
+            problems.push(format!("密码不能超过{}个字节", MAX_PASSWORD_BYTES));
+        }
+        for class in &self.required_classes {
+            if !password.chars().any(|c| class.matches(c)) {
+                problems.push(format!("密码必须包含{}", class.description()));
+            }
+        }
+        if !username.is_empty() && password.eq_ignore_ascii_case(username) {
+            problems.push("密码不能与用户名相同".to_string());
+        }
+        if let Some(path) = &self.blocklist_file {
+            if load_blocklist(path)?.contains(&password.to_lowercase()) {
+                problems.push("该密码已出现在泄露密码列表中,请换一个".to_string());
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::InvalidInput(problems.join("; ")))
+        }
+    }
+}
+
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// 密码设置服务
+///
+/// 所有设置新密码的入口(修改密码、找回密码、管理员重置)都经过这里,
+/// 统一校验密码策略和历史密码,并在 `password_history` 中保留旧密码哈希。
+pub struct PasswordService {
+    db: Database,
+    config: Config,
+    policy: PasswordPolicy,
+}
+
+impl PasswordService {
+    pub fn new(db: Database, config: Config) -> Self {
+        let policy = PasswordPolicy::from_config(&config);
+        Self { db, config, policy }
+    }
+
+    /// 校验新用户的初始密码(只校验强度)
+    pub fn check_new_user_password(&self, password: &str, username: &str) -> Result<(), AppError> {
+        self.policy.check(password, username)
+    }
+
+    /// 校验用户的新密码: 强度以及不能与最近 `password_history_size` 次的密码相同
+    pub async fn check(&self, user_id: i64, username: &str, password: &str) -> Result<(), AppError> {
+        self.policy.check(password, username)?;
+
+        let history_size = self.config.password_history_size;
+        if history_size == 0 {
+            return Ok(());
+        }
+
+        let mut hashes = sqlx::query_scalar::<_, String>("SELECT hashed_password FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))
+            .map(|current| vec![current])?;
+        hashes.extend(
+            sqlx::query_scalar::<_, String>(
+                "SELECT hashed_password FROM password_history WHERE user_id = ? ORDER BY id DESC LIMIT ?",
+            )
+            .bind(user_id)
+            .bind(history_size as i64 - 1)
+            .fetch_all(&self.db.pool)
+            .await?,
+        );
+
+        let hasher = PasswordHasher::from_config(&self.config)?;
+        if hashes.iter().any(|hash| hasher.verify(password, hash).unwrap_or(false)) {
+            return Err(AppError::InvalidInput(format!(
+                "不能使用最近{}次用过的密码",
+                history_size
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// 保存新密码(调用前应先通过 `check`),旧密码哈希进入历史
+    pub async fn store(&self, user_id: i64, password: &str) -> Result<(), AppError> {
+        let hashed_password = PasswordHasher::from_config(&self.config)?.hash(password)?;
+        let now = timestamp(Utc::now());
+
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO password_history (user_id, hashed_password, created_at) SELECT id, hashed_password, ? FROM users WHERE id = ?",
+        )
+        .bind(&now)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE users SET hashed_password = ?, updated_at = ? WHERE id = ?")
+            .bind(&hashed_password)
+            .bind(&now)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        // 当前密码也算一次,历史中只需保留 password_history_size - 1 条
+        sqlx::query(
+            r#"
+            DELETE FROM password_history
+            WHERE user_id = ? AND id NOT IN (
+                SELECT id FROM password_history WHERE user_id = ? ORDER BY id DESC LIMIT ?
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(user_id)
+        .bind(self.config.password_history_size.saturating_sub(1) as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// 用户修改自己的密码,需要验证当前密码
+    ///
+    /// 当前密码输错计入登录失败次数;修改成功后除当前会话外的其他会话全部失效。
+    pub async fn change_password(
+        &self,
+        user: &UserInfo,
+        current_session: Option<&str>,
+        current_password: &str,
+        new_password: &str,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        let throttle = LoginThrottleService::new(self.db.clone(), self.config.clone());
+        let subject = LoginSubject::user(user.id, user.company_id);
+        throttle.check(&subject, client).await?;
+
+        let hashed_password = sqlx::query_scalar::<_, String>("SELECT hashed_password FROM users WHERE id = ?")
+            .bind(user.id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user.id.to_string()))?;
+        let hasher = PasswordHasher::from_config(&self.config)?;
+        if !hasher.verify(current_password, &hashed_password).unwrap_or(false) {
+            throttle.record_failure(&subject, client).await?;
+            // 不使用401,避免前端把它当作登录失效
+            return Err(AppError::InvalidInput("当前密码错误".to_string()));
+        }
+        throttle.record_success(&subject).await?;
+
+        self.check(user.id, &user.username, new_password).await?;
+        self.store(user.id, new_password).await?;
+
+        let revoked = SessionService::new(self.db.clone(), self.config.clone())
+            .revoke_user_sessions(user.id, current_session, REVOKED_PASSWORD_CHANGED)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_PASSWORD_CHANGED)
+                    .actor(user.id)
+                    .company(user.company_id)
+                    .target("user", user.id)
+                    .client(client)
+                    .details(json!({ "revoked_sessions": revoked })),
+            )
+            .await
+    }
+
+    /// 管理员为其他用户设置密码,该用户所有会话失效
+    ///
+    /// 修改自己的密码必须通过 `change_password` 验证当前密码。
+    pub async fn set_password_for_user(
+        &self,
+        user_id: i64,
+        new_password: &str,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        if user_id == current_user.id {
+            return Err(AppError::OperationNotAllowed(
+                "修改自己的密码请使用修改密码功能并验证当前密码".to_string(),
+            ));
+        }
+
+        let (username, company_id) = sqlx::query_as::<_, (String, Option<i64>)>(
+            "SELECT username, company_id FROM users WHERE id = ?",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+        if !current_user.can(Permission::UserSecurity, company_id) {
+            return Err(AppError::MissingPermission(Permission::UserSecurity));
+        }
+
+        self.check(user_id, &username, new_password).await?;
+        self.store(user_id, new_password).await?;
+
+        let revoked = SessionService::new(self.db.clone(), self.config.clone())
+            .revoke_user_sessions(user_id, None, REVOKED_PASSWORD_CHANGED)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_PASSWORD_SET_BY_ADMIN)
+                    .actor(current_user.id)
+                    .company(company_id)
+                    .target("user", user_id)
+                    .client(client)
+                    .details(json!({ "revoked_sessions": revoked })),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(min_length: usize, classes: &[CharacterClass]) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length,
+            required_classes: classes.to_vec(),
+            blocklist_file: None,
+        }
+    }
+
+    fn problems(result: Result<(), AppError>) -> String {
+        match result {
+            Err(AppError::InvalidInput(message)) => message,
+            other => panic!("应返回 InvalidInput, 实际: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_length_and_character_classes() {
+        let policy = policy(8, &[CharacterClass::Lower, CharacterClass::Upper, CharacterClass::Digit]);
+
+        assert!(policy.check("Harvest2024", "alice").is_ok());
+
+        let message = problems(policy.check("abc", "alice"));
+        assert!(message.contains("至少8个字符"));
+        assert!(message.contains("大写字母"));
+        assert!(message.contains("数字"));
+        assert!(!message.contains("小写字母"));
+
+        assert!(policy.check(&"Aa1".repeat(30), "alice").is_err(), "超过72字节");
+    }
+
+    #[test]
+    fn test_symbol_class_and_username() {
+        let policy = policy(6, &[CharacterClass::Symbol]);
+
+        assert!(policy.check("farm-field", "alice").is_ok());
+        assert!(problems(policy.check("farmfield", "alice")).contains("符号"));
+        assert!(problems(policy.check("Alice!", "alice!")).contains("用户名"));
+    }
+
+    #[test]
+    fn test_blocklist_file() {
+        let path = std::env::temp_dir().join(format!("flow-farm-blocklist-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "# 常见密码\nPassword1\nqwerty123\n").unwrap();
+        let policy = PasswordPolicy {
+            blocklist_file: Some(path.to_string_lossy().to_string()),
+            ..policy(6, &[])
+        };
+
+        assert!(problems(policy.check("password1", "alice")).contains("泄露密码"));
+        assert!(policy.check("qwerty1234", "alice").is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
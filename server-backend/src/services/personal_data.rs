@@ -0,0 +1,389 @@
+use std::io::{Cursor, Write};
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    connections,
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{Permission, UserInfo},
+    repositories::UserRepository,
+    services::{
+        audit::{AuditEvent, AuditService, ACTION_PERSONAL_DATA_EXPORTED, ACTION_USER_DELETED},
+        session::REVOKED_ACCOUNT_ERASED,
+    },
+    utils::pii::{self, OptionalSealedText, SealedText},
+    Database,
+};
+
+/// 匿名化后显示的姓名
+pub const ERASED_USER_NAME: &str = "已注销用户";
+
+fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ProfileRow {
+    id: i64,
+    username: String,
+    #[sqlx(try_from = "SealedText")]
+    email: String,
+    full_name: Option<String>,
+    #[sqlx(try_from = "OptionalSealedText")]
+    phone: Option<String>,
+    role: String,
+    is_active: bool,
+    company_id: Option<i64>,
+    company_name: Option<String>,
+    created_at: Option<String>,
+    last_login: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct SessionRow {
+    created_at: String,
+    last_used_at: String,
+    expires_at: String,
+    revoked_at: Option<String>,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct IdentityRow {
+    issuer: String,
+    subject: String,
+    #[sqlx(try_from = "OptionalSealedText")]
+    email: Option<String>,
+    created_at: String,
+    last_login_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ApiTokenRow {
+    name: String,
+    kind: String,
+    token_prefix: String,
+    scopes: String,
+    created_at: String,
+    expires_at: Option<String>,
+    last_used_at: Option<String>,
+    last_used_ip: Option<String>,
+    revoked_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct TaskRow {
+    id: String,
+    title: String,
+    description: String,
+    status: String,
+    priority: String,
+    project_id: Option<String>,
+    assigned_to: Option<String>,
+    created_by: String,
+    due_date: Option<String>,
+    estimated_hours: Option<f64>,
+    actual_hours: Option<f64>,
+    created_at: String,
+    updated_at: String,
+    completed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct WorkLogRow {
+    id: String,
+    task_id: String,
+    hours: f64,
+    notes: Option<String>,
+    logged_at: String,
+    created_at: String,
+}
+
+/// 导出的个人数据压缩包
+#[derive(Debug, Clone)]
+pub struct PersonalDataArchive {
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+/// 个人数据的导出与删除(匿名化)
+///
+/// 删除账号时保留用户记录和任务、工作记录,只清除可以识别本人的信息,
+/// 任务历史和统计汇总仍然指向同一个(已匿名化的)用户。
+pub struct PersonalDataService {
+    db: Database,
+}
+
+impl PersonalDataService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// 本人或对其所在公司有 `permission` 权限的管理者可以操作,返回用户所在公司
+    ///
+    /// 看不到该用户的调用者得到"用户不存在",不暴露其他公司的用户ID。
+    async fn authorize(&self, user_id: i64, current_user: &UserInfo, permission: Permission) -> Result<Option<i64>, AppError> {
+        let company_id = sqlx::query_scalar::<_, Option<i64>>("SELECT company_id FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+        if user_id != current_user.id && !current_user.can(permission, company_id) {
+            if current_user.can(Permission::UserView, company_id) {
+                return Err(AppError::MissingPermission(permission));
+            }
+            return Err(AppError::UserNotFound(user_id.to_string()));
+        }
+        Ok(company_id)
+    }
+
+    /// 导出用户的个人数据: 账号资料、登录设备、关联身份和访问令牌,
+    /// 创建或分配给本人的任务,以及本人的工作记录,每类数据一个 JSON 文件
+    pub async fn export(
+        &self,
+        user_id: i64,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<PersonalDataArchive, AppError> {
+        let company_id = self.authorize(user_id, current_user, Permission::UserSecurity).await?;
+
+        let profile = sqlx::query_as::<_, ProfileRow>(
+            r#"
+            SELECT u.id, u.username, u.email, u.full_name, u.phone, u.role, u.is_active, u.company_id,
+                   c.name AS company_name, CAST(u.created_at AS TEXT) AS created_at, CAST(u.last_login AS TEXT) AS last_login
+            FROM users u LEFT JOIN companies c ON c.id = u.company_id
+            WHERE u.id = ?
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.db.pool)
+        .await?;
+        let sessions = sqlx::query_as::<_, SessionRow>(
+            r#"
+            SELECT CAST(created_at AS TEXT) AS created_at, CAST(last_used_at AS TEXT) AS last_used_at,
+                   CAST(expires_at AS TEXT) AS expires_at, CAST(revoked_at AS TEXT) AS revoked_at, user_agent, ip_address
+            FROM sessions WHERE user_id = ? ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+        let identities = sqlx::query_as::<_, IdentityRow>(
+            r#"
+            SELECT issuer, subject, email, CAST(created_at AS TEXT) AS created_at, CAST(last_login_at AS TEXT) AS last_login_at
+            FROM user_identities WHERE user_id = ? ORDER BY id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+        let api_tokens = sqlx::query_as::<_, ApiTokenRow>(
+            r#"
+            SELECT name, kind, token_prefix, scopes, CAST(created_at AS TEXT) AS created_at,
+                   CAST(expires_at AS TEXT) AS expires_at, CAST(last_used_at AS TEXT) AS last_used_at,
+                   last_used_ip, CAST(revoked_at AS TEXT) AS revoked_at
+            FROM api_tokens WHERE user_id = ? ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        // 任务和工作记录中的用户ID以文本保存
+        let user_key = user_id.to_string();
+        let tasks = sqlx::query_as::<_, TaskRow>(
+            r#"
+            SELECT id, title, description, status, priority, project_id, assigned_to, created_by,
+                   CAST(due_date AS TEXT) AS due_date, estimated_hours, actual_hours,
+                   CAST(created_at AS TEXT) AS created_at, CAST(updated_at AS TEXT) AS updated_at,
+                   CAST(completed_at AS TEXT) AS completed_at
+            FROM tasks WHERE created_by = ? OR assigned_to = ?
+            ORDER BY created_at
+            "#,
+        )
+        .bind(&user_key)
+        .bind(&user_key)
+        .fetch_all(&self.db.pool)
+        .await?;
+        let work_logs = sqlx::query_as::<_, WorkLogRow>(
+            r#"
+            SELECT id, task_id, hours, notes, CAST(logged_at AS TEXT) AS logged_at, CAST(created_at AS TEXT) AS created_at
+            FROM work_logs WHERE user_id = ? ORDER BY logged_at, created_at
+            "#,
+        )
+        .bind(&user_key)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        let exported_at = Utc::now();
+        let manifest = json!({
+            "user_id": user_id,
+            "exported_at": exported_at.to_rfc3339(),
+            "files": {
+                "profile.json": "账号资料、登录设备、关联的外部身份和访问令牌",
+                "tasks.json": format!("创建或分配给本人的任务({}条)", tasks.len()),
+                "work_logs.json": format!("本人的工作记录及备注({}条)", work_logs.len()),
+            },
+        });
+        let files = [
+            ("manifest.json", manifest),
+            (
+                "profile.json",
+                json!({
+                    "user": profile,
+                    "sessions": sessions,
+                    "identities": identities,
+                    "api_tokens": api_tokens,
+                }),
+            ),
+            ("tasks.json", json!(tasks)),
+            ("work_logs.json", json!(work_logs)),
+        ];
+        let content = write_archive(&files)?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_PERSONAL_DATA_EXPORTED)
+                    .actor(current_user.id)
+                    .company(company_id)
+                    .target("user", user_id)
+                    .client(client),
+            )
+            .await?;
+
+        Ok(PersonalDataArchive {
+            filename: format!("personal-data-{}-{}.zip", user_id, exported_at.format("%Y%m%d%H%M%S")),
+            content,
+        })
+    }
+
+    /// 删除账号: 匿名化用户记录并清除登录凭据,保留任务历史和统计数据
+    ///
+    /// 用户名、姓名、邮箱替换为占位值,手机号、密码、两步验证、外部身份关联、
+    /// 密码历史和待用的邮件令牌一并清除;会话和访问令牌全部撤销,并清除其中记录的IP和设备信息。
+    /// 审计日志是只追加的哈希链,保持不变。
+    pub async fn erase(&self, user_id: i64, current_user: &UserInfo, client: &ClientInfo) -> Result<(), AppError> {
+        if current_user.id == user_id {
+            return Err(AppError::OperationNotAllowed("不能删除自己的账户".to_string()));
+        }
+        let user = UserRepository::new(self.db.clone())
+            .find_by_id(user_id)
+            .await?
+            .map(UserInfo::from)
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+        if !current_user.can(Permission::UserDelete, user.company_id) {
+            return Err(AppError::MissingPermission(Permission::UserDelete));
+        }
+
+        let now = timestamp();
+        // 占位邮箱使用保留域名,不会与真实邮箱冲突
+        let tombstone_username = format!("deleted-user-{}", user_id);
+        let tombstone_email = format!("{}@erased.invalid", tombstone_username);
+
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET username = ?, email = ?, email_index = NULL, phone = NULL, phone_index = NULL,
+                full_name = ?, hashed_password = '!', is_active = 0, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&tombstone_username)
+        .bind(pii::seal(&tombstone_email)?)
+        .bind(ERASED_USER_NAME)
+        .bind(&now)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        for table in [
+            "user_two_factor",
+            "two_factor_recovery_codes",
+            "login_challenges",
+            "email_tokens",
+            "password_history",
+            "user_identities",
+        ] {
+            sqlx::query(&format!("DELETE FROM {} WHERE user_id = ?", table))
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM login_throttles WHERE key = ?")
+            .bind(format!("user:{}", user_id))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = COALESCE(revoked_at, ?), revoked_reason = COALESCE(revoked_reason, ?),
+                user_agent = NULL, ip_address = NULL
+            WHERE user_id = ?
+            "#,
+        )
+        .bind(&now)
+        .bind(REVOKED_ACCOUNT_ERASED)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            UPDATE api_tokens
+            SET revoked_at = COALESCE(revoked_at, ?), revoked_by = COALESCE(revoked_by, ?), last_used_ip = NULL
+            WHERE user_id = ?
+            "#,
+        )
+        .bind(&now)
+        .bind(current_user.id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        // 已接受的邀请保留(占用名额的统计需要),只替换其中的邮箱
+        sqlx::query("UPDATE invitations SET email = ?, email_index = ? WHERE accepted_user_id = ?")
+            .bind(pii::seal(&tombstone_email)?)
+            .bind(pii::blind_index(&tombstone_email))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        connections::disconnect_user_sessions(user_id, None);
+
+        let company_id = user.company_id;
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_USER_DELETED)
+                    .actor(current_user.id)
+                    .company(company_id)
+                    .target("user", user_id)
+                    .client(client)
+                    // 审计日志不可改写,只记录被注销的用户ID,不保留任何个人信息
+                    .details(json!({ "user_id": user_id, "tombstone": true })),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// 把 JSON 文件打包成 ZIP
+fn write_archive(files: &[(&str, serde_json::Value)]) -> Result<Vec<u8>, AppError> {
+    let zip_error = |e: zip::result::ZipError| AppError::Internal(format!("生成导出文件失败: {}", e));
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, value) in files {
+        archive.start_file(*name, options).map_err(zip_error)?;
+        let content = serde_json::to_vec_pretty(value)
+            .map_err(|e| AppError::Internal(format!("生成导出文件失败: {}", e)))?;
+        archive
+            .write_all(&content)
+            .map_err(|e| AppError::Internal(format!("生成导出文件失败: {}", e)))?;
+    }
+    Ok(archive.finish().map_err(zip_error)?.into_inner())
+}
@@ -0,0 +1,121 @@
+use serde::Serialize;
+use sqlx::Row;
+
+use crate::{errors::AppError, utils::pii, Database};
+
+/// 每批重新加密的行数,每批单独提交,不长时间占用写锁
+pub const REENCRYPT_BATCH_SIZE: i64 = 200;
+
+/// 需要加密保存的个人信息字段: (表名, 字段, 盲索引字段)
+const SEALED_COLUMNS: &[(&str, &str, Option<&str>)] = &[
+    ("users", "email", Some("email_index")),
+    ("users", "phone", Some("phone_index")),
+    ("companies", "contact_email", None),
+    ("companies", "contact_phone", None),
+    ("invitations", "email", Some("email_index")),
+    ("user_identities", "email", None),
+];
+
+/// 一次重新加密任务的结果
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ReencryptReport {
+    /// 检查过的字段值数
+    pub scanned: u64,
+    /// 重新加密或补建索引的字段值数
+    pub updated: u64,
+}
+
+/// 个人信息字段的重新加密
+///
+/// 把明文旧数据、非当前版本主密钥加密的数据以及缺少盲索引的数据改用当前密钥加密。
+/// 按主键分批处理,服务运行期间可以随时执行;轮换主密钥后执行完毕即可移除旧版本密钥。
+pub struct PiiService {
+    db: Database,
+}
+
+impl PiiService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// 重新加密全部个人信息字段,未配置加密密钥时不做任何处理
+    pub async fn reencrypt(&self, batch_size: i64) -> Result<ReencryptReport, AppError> {
+        let mut report = ReencryptReport::default();
+        let Some(keyring) = pii::keyring() else {
+            return Ok(report);
+        };
+        let active = keyring.active_version();
+
+        for &(table, column, index_column) in SEALED_COLUMNS {
+            let mut last_id = 0i64;
+            loop {
+                let index_select = index_column.unwrap_or("NULL");
+                let rows = sqlx::query(&format!(
+                    "SELECT id, {column}, {index_select} FROM {table} WHERE id > ? AND {column} IS NOT NULL ORDER BY id LIMIT ?"
+                ))
+                .bind(last_id)
+                .bind(batch_size)
+                .fetch_all(&self.db.pool)
+                .await?;
+                let Some(last) = rows.last() else {
+                    break;
+                };
+                last_id = last.get(0);
+
+                let mut tx = self.db.pool.begin().await?;
+                for row in &rows {
+                    let id: i64 = row.get(0);
+                    let stored: String = row.get(1);
+                    let index: Option<String> = row.get(2);
+                    report.scanned += 1;
+
+                    let current = pii::key_version(&stored) == Some(active);
+                    if current && (index_column.is_none() || index.is_some()) {
+                        continue;
+                    }
+
+                    let plaintext = pii::open(&stored)?;
+                    let sealed = if current { stored.clone() } else { pii::seal(&plaintext)? };
+                    // 只在字段未被并发修改时写回,被修改的值已经按当前密钥加密
+                    let result = match index_column {
+                        Some(index_column) => {
+                            sqlx::query(&format!(
+                                "UPDATE {table} SET {column} = ?, {index_column} = ? WHERE id = ? AND {column} = ?"
+                            ))
+                            .bind(&sealed)
+                            .bind(pii::blind_index(&plaintext))
+                            .bind(id)
+                            .bind(&stored)
+                            .execute(&mut *tx)
+                            .await?
+                        }
+                        None => {
+                            sqlx::query(&format!("UPDATE {table} SET {column} = ? WHERE id = ? AND {column} = ?"))
+                                .bind(&sealed)
+                                .bind(id)
+                                .bind(&stored)
+                                .execute(&mut *tx)
+                                .await?
+                        }
+                    };
+                    report.updated += result.rows_affected();
+                }
+                tx.commit().await?;
+
+                if (rows.len() as i64) < batch_size {
+                    break;
+                }
+            }
+        }
+
+        if report.updated > 0 {
+            tracing::info!(
+                "🔐 个人信息重新加密完成: 检查{}项,更新{}项(当前密钥版本 v{})",
+                report.scanned,
+                report.updated,
+                active
+            );
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,250 @@
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{CompanyJoinCode, Permission, RegistrationMode, RegistrationPolicy, UserInfo},
+    services::audit::{AuditEvent, AuditService, ACTION_JOIN_CODE_REGENERATED, ACTION_REGISTRATION_POLICY_CHANGED},
+    utils::join_code::{generate_join_code, normalize_join_code},
+    Database,
+};
+
+/// 注册策略在 system_settings 中的键
+const MODE_KEY: &str = "registration.mode";
+/// 开放注册时允许的邮箱域名(逗号分隔)
+const ALLOWED_DOMAINS_KEY: &str = "registration.allowed_domains";
+
+fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// 规范化邮箱域名: 小写、去掉开头的 `@`
+fn normalize_domain(domain: &str) -> String {
+    domain.trim().trim_start_matches('@').to_ascii_lowercase()
+}
+
+/// 平台注册策略与公司加入码
+///
+/// - `disabled`: 只能由管理员创建账号,邀请邮件也不能再接受
+/// - `invite_only`(默认): 只能通过邀请邮件创建账号
+/// - `open`: 另外可以凭公司加入码自助注册,可限制邮箱域名
+///
+/// 自助注册的用户一律是加入码对应公司的任务执行者。
+pub struct RegistrationService {
+    db: Database,
+}
+
+impl RegistrationService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    async fn setting(&self, key: &str) -> Result<Option<String>, AppError> {
+        Ok(sqlx::query_scalar::<_, String>("SELECT value FROM system_settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.db.pool)
+            .await?)
+    }
+
+    /// 当前注册策略,未设置时为仅限邀请
+    pub async fn policy(&self) -> Result<RegistrationPolicy, AppError> {
+        let mode = match self.setting(MODE_KEY).await? {
+            Some(mode) => mode.parse().unwrap_or_else(|e| {
+                tracing::warn!("⚠️  {},按仅限邀请处理", e);
+                RegistrationMode::InviteOnly
+            }),
+            None => RegistrationMode::default(),
+        };
+        let allowed_domains = self
+            .setting(ALLOWED_DOMAINS_KEY)
+            .await?
+            .map(|domains| {
+                domains
+                    .split(',')
+                    .map(normalize_domain)
+                    .filter(|domain| !domain.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(RegistrationPolicy { mode, allowed_domains })
+    }
+
+    /// 查看注册策略设置(仅平台管理员)
+    pub async fn policy_settings(&self, current_user: &UserInfo) -> Result<RegistrationPolicy, AppError> {
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
+        }
+        self.policy().await
+    }
+
+    /// 修改注册策略(仅平台管理员)
+    pub async fn set_policy(
+        &self,
+        policy: RegistrationPolicy,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<RegistrationPolicy, AppError> {
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
+        }
+
+        let mut allowed_domains: Vec<String> = Vec::new();
+        for domain in policy.allowed_domains.iter().map(|domain| normalize_domain(domain)) {
+            if domain.is_empty() {
+                continue;
+            }
+            let valid = domain.contains('.')
+                && domain
+                    .split('.')
+                    .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+            if !valid {
+                return Err(AppError::InvalidFormat(format!("邮箱域名无效: {}", domain)));
+            }
+            if !allowed_domains.contains(&domain) {
+                allowed_domains.push(domain);
+            }
+        }
+        let policy = RegistrationPolicy {
+            mode: policy.mode,
+            allowed_domains,
+        };
+
+        let now = timestamp();
+        let mut tx = self.db.pool.begin().await?;
+        for (key, value, description) in [
+            (MODE_KEY, policy.mode.as_str().to_string(), "注册策略: disabled / invite_only / open"),
+            (ALLOWED_DOMAINS_KEY, policy.allowed_domains.join(","), "开放注册时允许的邮箱域名"),
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO system_settings (key, value, description, updated_at) VALUES (?, ?, ?, ?)
+                ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(key)
+            .bind(value)
+            .bind(description)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_REGISTRATION_POLICY_CHANGED)
+                    .actor(current_user.id)
+                    .target("platform", "registration")
+                    .client(client)
+                    .details(json!({
+                        "mode": policy.mode.as_str(),
+                        "allowed_domains": policy.allowed_domains,
+                    })),
+            )
+            .await?;
+
+        Ok(policy)
+    }
+
+    /// 禁止注册时邀请邮件也不能再接受
+    pub async fn ensure_invitations_allowed(&self) -> Result<(), AppError> {
+        if self.policy().await?.mode == RegistrationMode::Disabled {
+            return Err(AppError::OperationNotAllowed("暂停注册新账号,请联系管理员".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 检查自助注册是否允许,返回加入码对应的公司
+    ///
+    /// 加入码无效和公司已停用返回同样的错误,不暴露公司状态。
+    pub async fn resolve_join_code(&self, email: &str, join_code: &str) -> Result<i64, AppError> {
+        let policy = self.policy().await?;
+        if policy.mode != RegistrationMode::Open {
+            return Err(AppError::OperationNotAllowed("未开放自助注册,请通过邀请加入".to_string()));
+        }
+
+        if !policy.allowed_domains.is_empty() {
+            let domain = email.rsplit_once('@').map(|(_, domain)| normalize_domain(domain)).unwrap_or_default();
+            if !policy.allowed_domains.contains(&domain) {
+                return Err(AppError::OperationNotAllowed("该邮箱域名不允许注册".to_string()));
+            }
+        }
+
+        let code = normalize_join_code(join_code);
+        sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM companies WHERE join_code = ? AND COALESCE(is_active, 1) = 1",
+        )
+        .bind(&code)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("加入码无效".to_string()))
+    }
+
+    fn ensure_can_manage(company_id: i64, current_user: &UserInfo) -> Result<(), AppError> {
+        if !current_user.can(Permission::CompanySettings, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::CompanySettings));
+        }
+        Ok(())
+    }
+
+    /// 查看公司加入码,没有加入码时生成一个(本公司管理者或平台管理员)
+    pub async fn join_code(&self, company_id: i64, current_user: &UserInfo) -> Result<CompanyJoinCode, AppError> {
+        Self::ensure_can_manage(company_id, current_user)?;
+
+        let code = sqlx::query_scalar::<_, Option<String>>("SELECT join_code FROM companies WHERE id = ?")
+            .bind(company_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("公司不存在".to_string()))?;
+        let join_code = match code.filter(|code| !code.is_empty()) {
+            Some(code) => code,
+            None => self.store_join_code(company_id).await?,
+        };
+
+        Ok(CompanyJoinCode { company_id, join_code })
+    }
+
+    /// 重新生成公司加入码,旧的加入码立即失效
+    pub async fn regenerate_join_code(
+        &self,
+        company_id: i64,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<CompanyJoinCode, AppError> {
+        Self::ensure_can_manage(company_id, current_user)?;
+
+        let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM companies WHERE id = ?")
+            .bind(company_id)
+            .fetch_one(&self.db.pool)
+            .await?;
+        if exists == 0 {
+            return Err(AppError::NotFound("公司不存在".to_string()));
+        }
+        let join_code = self.store_join_code(company_id).await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_JOIN_CODE_REGENERATED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("company", company_id)
+                    .client(client),
+            )
+            .await?;
+
+        Ok(CompanyJoinCode { company_id, join_code })
+    }
+
+    async fn store_join_code(&self, company_id: i64) -> Result<String, AppError> {
+        let join_code = generate_join_code();
+        sqlx::query("UPDATE companies SET join_code = ?, updated_at = ? WHERE id = ?")
+            .bind(&join_code)
+            .bind(timestamp())
+            .bind(company_id)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(join_code)
+    }
+}
@@ -0,0 +1,360 @@
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{
+        AssignRoleRequest, BuiltinRoleInfo, CompanyRoleInfo, CompanyRoleRequest, CompanyRoles, Permission,
+        PermissionInfo, PermissionSet, User, UserInfo, UserRole,
+    },
+    services::audit::{
+        AuditEvent, AuditService, ACTION_ROLE_ASSIGNED, ACTION_ROLE_CREATED, ACTION_ROLE_DELETED, ACTION_ROLE_UPDATED,
+    },
+    Database,
+};
+
+fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// 解析保存的权限 JSON 数组,忽略已不存在的权限名称
+fn parse_permissions(permissions: &str) -> PermissionSet {
+    serde_json::from_str(permissions).unwrap_or_default()
+}
+
+#[derive(sqlx::FromRow)]
+struct CompanyRoleRow {
+    id: i64,
+    company_id: i64,
+    name: String,
+    description: Option<String>,
+    permissions: String,
+    user_count: i64,
+    created_at: String,
+    updated_at: String,
+}
+
+impl From<CompanyRoleRow> for CompanyRoleInfo {
+    fn from(row: CompanyRoleRow) -> Self {
+        Self {
+            permissions: parse_permissions(&row.permissions),
+            id: row.id,
+            company_id: row.company_id,
+            name: row.name,
+            description: row.description,
+            user_count: row.user_count,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+const ROLE_COLUMNS: &str = r#"
+    SELECT r.id, r.company_id, r.name, r.description, r.permissions, r.created_at, r.updated_at,
+           (SELECT COUNT(*) FROM users u WHERE u.custom_role_id = r.id) AS user_count
+    FROM company_roles r
+"#;
+
+/// 权限策略服务: 计算用户的有效权限,管理公司自定义角色
+///
+/// 用户的权限来自内置角色(`users.role`)的权限组合,分配了公司自定义角色时以自定义角色为准。
+/// 授予权限时只能授予比自己更少的权限(平台管理员除外),自定义角色不能包含平台级权限。
+pub struct RoleService {
+    db: Database,
+}
+
+impl RoleService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// 用户的有效权限
+    pub async fn permissions_for(&self, user: &User) -> Result<PermissionSet, AppError> {
+        let Some(role_id) = user.custom_role_id else {
+            return Ok(user.role.permissions());
+        };
+
+        // 角色已删除或不属于用户所在公司时不授予任何权限
+        let permissions = sqlx::query_scalar::<_, String>("SELECT permissions FROM company_roles WHERE id = ? AND company_id = ?")
+            .bind(role_id)
+            .bind(user.company_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(permissions.map(|permissions| parse_permissions(&permissions)).unwrap_or_default())
+    }
+
+    /// 带有效权限的用户信息
+    pub async fn user_info(&self, user: User) -> Result<UserInfo, AppError> {
+        let permissions = self.permissions_for(&user).await?;
+        let mut info = UserInfo::from(user);
+        info.permissions = permissions;
+        Ok(info)
+    }
+
+    /// 全部权限及说明
+    pub fn permission_catalog() -> Vec<PermissionInfo> {
+        Permission::ALL
+            .into_iter()
+            .map(|permission| PermissionInfo {
+                name: permission,
+                description: permission.description().to_string(),
+                platform: permission.is_platform(),
+            })
+            .collect()
+    }
+
+    /// 公司可用的角色(查看用户或管理角色的权限)
+    pub async fn list_roles(&self, company_id: i64, current_user: &UserInfo) -> Result<CompanyRoles, AppError> {
+        if !current_user.can(Permission::UserView, Some(company_id)) && !current_user.can(Permission::RoleManage, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::UserView));
+        }
+
+        let builtin = [UserRole::PlatformAdmin, UserRole::ProjectManager, UserRole::TaskExecutor]
+            .into_iter()
+            .map(|role| BuiltinRoleInfo {
+                name: role.builtin().display_name().to_string(),
+                permissions: role.permissions(),
+                role,
+            })
+            .collect();
+        let custom = sqlx::query_as::<_, CompanyRoleRow>(&format!("{} WHERE r.company_id = ? ORDER BY r.name", ROLE_COLUMNS))
+            .bind(company_id)
+            .fetch_all(&self.db.pool)
+            .await?
+            .into_iter()
+            .map(CompanyRoleInfo::from)
+            .collect();
+
+        Ok(CompanyRoles { builtin, custom })
+    }
+
+    async fn find_role(&self, company_id: i64, role_id: i64) -> Result<CompanyRoleInfo, AppError> {
+        sqlx::query_as::<_, CompanyRoleRow>(&format!("{} WHERE r.id = ? AND r.company_id = ?", ROLE_COLUMNS))
+            .bind(role_id)
+            .bind(company_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .map(CompanyRoleInfo::from)
+            .ok_or_else(|| AppError::NotFound("角色不存在".to_string()))
+    }
+
+    /// 校验角色请求: 权限名称有效、不含平台级权限、不超出当前用户可授予的范围
+    fn validate_request(request: &CompanyRoleRequest, current_user: &UserInfo) -> Result<PermissionSet, AppError> {
+        let mut permissions = PermissionSet::new();
+        for name in &request.permissions {
+            let permission: Permission = name.parse().map_err(AppError::InvalidInput)?;
+            if permission.is_platform() {
+                return Err(AppError::InvalidInput(format!("自定义角色不能包含平台权限: {}", permission)));
+            }
+            permissions.insert(permission);
+        }
+        if !current_user.permissions.can_grant(&permissions) {
+            return Err(AppError::OperationNotAllowed("只能授予比自己更少的权限".to_string()));
+        }
+        Ok(permissions)
+    }
+
+    fn ensure_can_manage(company_id: i64, current_user: &UserInfo) -> Result<(), AppError> {
+        if !current_user.can(Permission::RoleManage, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::RoleManage));
+        }
+        Ok(())
+    }
+
+    async fn name_taken(&self, company_id: i64, name: &str, except_id: Option<i64>) -> Result<bool, AppError> {
+        Ok(sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM company_roles WHERE company_id = ? AND name = ? AND id IS NOT ?")
+            .bind(company_id)
+            .bind(name)
+            .bind(except_id)
+            .fetch_one(&self.db.pool)
+            .await?
+            > 0)
+    }
+
+    /// 创建公司自定义角色
+    pub async fn create_role(
+        &self,
+        company_id: i64,
+        request: &CompanyRoleRequest,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<CompanyRoleInfo, AppError> {
+        Self::ensure_can_manage(company_id, current_user)?;
+        let permissions = Self::validate_request(request, current_user)?;
+        let name = request.name.trim();
+        if self.name_taken(company_id, name, None).await? {
+            return Err(AppError::Conflict(format!("角色名称已存在: {}", name)));
+        }
+
+        let now = timestamp();
+        let role_id = sqlx::query(
+            r#"
+            INSERT INTO company_roles (company_id, name, description, permissions, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(company_id)
+        .bind(name)
+        .bind(&request.description)
+        .bind(json!(permissions).to_string())
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.db.pool)
+        .await?
+        .last_insert_rowid();
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_ROLE_CREATED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("role", role_id)
+                    .client(client)
+                    .details(json!({ "name": name, "permissions": permissions })),
+            )
+            .await?;
+
+        self.find_role(company_id, role_id).await
+    }
+
+    /// 修改自定义角色,已分配该角色的用户立即按新权限生效
+    pub async fn update_role(
+        &self,
+        company_id: i64,
+        role_id: i64,
+        request: &CompanyRoleRequest,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<CompanyRoleInfo, AppError> {
+        Self::ensure_can_manage(company_id, current_user)?;
+        let existing = self.find_role(company_id, role_id).await?;
+        if !current_user.permissions.can_grant(&existing.permissions) {
+            return Err(AppError::OperationNotAllowed("不能修改权限不低于自己的角色".to_string()));
+        }
+        let permissions = Self::validate_request(request, current_user)?;
+        let name = request.name.trim();
+        if self.name_taken(company_id, name, Some(role_id)).await? {
+            return Err(AppError::Conflict(format!("角色名称已存在: {}", name)));
+        }
+
+        sqlx::query("UPDATE company_roles SET name = ?, description = ?, permissions = ?, updated_at = ? WHERE id = ?")
+            .bind(name)
+            .bind(&request.description)
+            .bind(json!(permissions).to_string())
+            .bind(timestamp())
+            .bind(role_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_ROLE_UPDATED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("role", role_id)
+                    .client(client)
+                    .details(json!({
+                        "name": name,
+                        "permissions": permissions,
+                        "previous_permissions": existing.permissions,
+                    })),
+            )
+            .await?;
+
+        self.find_role(company_id, role_id).await
+    }
+
+    /// 删除自定义角色,仍有用户使用时拒绝
+    pub async fn delete_role(
+        &self,
+        company_id: i64,
+        role_id: i64,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        Self::ensure_can_manage(company_id, current_user)?;
+        let existing = self.find_role(company_id, role_id).await?;
+        if !current_user.permissions.can_grant(&existing.permissions) {
+            return Err(AppError::OperationNotAllowed("不能删除权限不低于自己的角色".to_string()));
+        }
+        if existing.user_count > 0 {
+            return Err(AppError::Conflict(format!("仍有 {} 个用户使用该角色", existing.user_count)));
+        }
+
+        sqlx::query("DELETE FROM company_roles WHERE id = ?")
+            .bind(role_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_ROLE_DELETED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("role", role_id)
+                    .client(client)
+                    .details(json!({ "name": existing.name })),
+            )
+            .await
+    }
+
+    /// 为本公司用户分配自定义角色,或取消自定义角色恢复内置角色的权限
+    pub async fn assign_role(
+        &self,
+        user_id: i64,
+        request: &AssignRoleRequest,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<UserInfo, AppError> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+        if !current_user.can(Permission::RoleManage, user.company_id) {
+            return Err(AppError::MissingPermission(Permission::RoleManage));
+        }
+        if user.id == current_user.id {
+            return Err(AppError::OperationNotAllowed("不能修改自己的角色".to_string()));
+        }
+        if user.role == UserRole::PlatformAdmin {
+            return Err(AppError::OperationNotAllowed("平台管理员不能分配公司角色".to_string()));
+        }
+        let company_id = user.company_id.ok_or_else(|| AppError::OperationNotAllowed("用户没有所属公司".to_string()))?;
+
+        let current_permissions = self.permissions_for(&user).await?;
+        let new_permissions = match request.custom_role_id {
+            Some(role_id) => self.find_role(company_id, role_id).await?.permissions,
+            None => user.role.permissions(),
+        };
+        if !current_user.permissions.can_grant(&current_permissions) || !current_user.permissions.can_grant(&new_permissions) {
+            return Err(AppError::OperationNotAllowed("只能授予比自己更少的权限".to_string()));
+        }
+
+        sqlx::query("UPDATE users SET custom_role_id = ?, updated_at = ? WHERE id = ?")
+            .bind(request.custom_role_id)
+            .bind(Utc::now())
+            .bind(user.id)
+            .execute(&self.db.pool)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_ROLE_ASSIGNED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("user", user.id)
+                    .client(client)
+                    .details(json!({
+                        "custom_role_id": request.custom_role_id,
+                        "previous_custom_role_id": user.custom_role_id,
+                        "permissions": new_permissions,
+                    })),
+            )
+            .await?;
+
+        let user = User { custom_role_id: request.custom_role_id, ..user };
+        self.user_info(user).await
+    }
+}
@@ -0,0 +1,472 @@
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use serde_json::json;
+
+use crate::{
+    connections,
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{Permission, SessionInfo, TokenPair, UserInfo, UserRole},
+    services::{
+        audit::{AuditEvent, AuditService, ACTION_SESSION_REVOKED},
+        company::ensure_company_active,
+    },
+    utils::jwt::JwtKeys,
+    Config, Database,
+};
+
+/// 刷新令牌被重复使用时记录的撤销原因
+pub const REVOKED_TOKEN_REUSE: &str = "refresh_token_reuse";
+/// 用户主动注销时记录的撤销原因
+pub const REVOKED_LOGOUT: &str = "logout";
+/// 通过邮件重置密码后撤销原有会话时记录的原因
+pub const REVOKED_PASSWORD_RESET: &str = "password_reset";
+/// 修改密码后撤销其他会话时记录的原因
+pub const REVOKED_PASSWORD_CHANGED: &str = "password_changed";
+/// 管理员结束模拟登录时记录的原因
+pub const REVOKED_IMPERSONATION_ENDED: &str = "impersonation_ended";
+/// 在会话列表中撤销本人会话时记录的原因
+pub const REVOKED_BY_USER: &str = "revoked_by_user";
+/// 管理者撤销本公司用户会话时记录的原因
+pub const REVOKED_BY_ADMIN: &str = "revoked_by_admin";
+
+/// 账号被删除(匿名化)时记录的原因
+pub const REVOKED_ACCOUNT_ERASED: &str = "account_erased";
+
+/// 桌面客户端 User-Agent 的前缀
+pub const DESKTOP_USER_AGENT_PREFIX: &str = "FlowFarm-Desktop/";
+
+/// 最近使用时间的更新间隔(秒),避免每个请求都写数据库
+const LAST_USED_INTERVAL_SECONDS: i64 = 60;
+
+/// 刷新令牌轮换后的宽限期(秒): 多个标签页同时用同一个令牌刷新时不视为被盗用,
+/// 后到的请求得到冲突错误,超过宽限期再使用则撤销整个会话
+const REFRESH_REUSE_GRACE_SECONDS: i64 = 20;
+
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// 刷新令牌已被并发请求轮换: 不另发令牌,客户端应改用先完成的请求拿到的新令牌
+fn refresh_conflict() -> AppError {
+    AppError::Conflict("刷新令牌已被轮换,请使用最新的刷新令牌".to_string())
+}
+
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// 刷新令牌查询行: (会话ID, 令牌过期时间, 使用时间, 用户ID, 会话撤销时间, 会话过期时间)
+type RefreshTokenRow = (String, String, Option<String>, i64, Option<String>, String);
+
+#[derive(sqlx::FromRow)]
+struct SessionRow {
+    id: String,
+    user_id: i64,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    created_at: String,
+    last_used_at: String,
+    expires_at: String,
+    impersonator_id: Option<i64>,
+}
+
+impl SessionRow {
+    fn into_info(self, current_session: Option<&str>) -> SessionInfo {
+        let client_type = match &self.user_agent {
+            Some(user_agent) if user_agent.starts_with(DESKTOP_USER_AGENT_PREFIX) => "desktop",
+            _ => "web",
+        };
+        SessionInfo {
+            current: current_session == Some(self.id.as_str()),
+            client_type: client_type.to_string(),
+            id: self.id,
+            user_id: self.user_id,
+            user_agent: self.user_agent,
+            ip_address: self.ip_address,
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+            expires_at: self.expires_at,
+            impersonator_id: self.impersonator_id,
+        }
+    }
+}
+
+/// 登录会话服务
+///
+/// 访问令牌有效期较短(`jwt_expires_in`),过期后用刷新令牌换取新的令牌对。
+/// 刷新令牌每次使用后即作废(轮换);已作废的刷新令牌再次出现说明令牌可能泄露,
+/// 此时撤销整个会话,该会话下的访问令牌和刷新令牌全部失效。
+pub struct SessionService {
+    db: Database,
+    config: Config,
+}
+
+impl SessionService {
+    pub fn new(db: Database, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    /// 为登录成功的用户创建会话并签发令牌
+    pub async fn create_session(
+        &self,
+        user_id: i64,
+        role: &UserRole,
+        client: &ClientInfo,
+    ) -> Result<TokenPair, AppError> {
+        ensure_company_active(&self.db, user_id).await?;
+
+        let session_id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+        let expires_at = timestamp(now + Duration::seconds(self.config.refresh_token_expires_in));
+        let refresh_token = generate_refresh_token();
+
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO sessions (id, user_id, created_at, last_used_at, expires_at, user_agent, ip_address)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&session_id)
+        .bind(user_id)
+        .bind(timestamp(now))
+        .bind(timestamp(now))
+        .bind(&expires_at)
+        .bind(&client.user_agent)
+        .bind(&client.ip_address)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("INSERT INTO refresh_tokens (token_hash, session_id, created_at, expires_at) VALUES (?, ?, ?, ?)")
+            .bind(hash_refresh_token(&refresh_token))
+            .bind(&session_id)
+            .bind(timestamp(now))
+            .bind(&expires_at)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.token_pair(user_id, role, &session_id, refresh_token)
+    }
+
+    /// 为管理员创建模拟登录会话,返回会话ID和访问令牌
+    ///
+    /// 会话不签发刷新令牌,到期后必须重新发起模拟。
+    pub async fn create_impersonation_session(
+        &self,
+        user_id: i64,
+        role: &UserRole,
+        impersonator_id: i64,
+        expires_in: i64,
+        client: &ClientInfo,
+    ) -> Result<(String, String), AppError> {
+        ensure_company_active(&self.db, user_id).await?;
+
+        let session_id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            INSERT INTO sessions (id, user_id, created_at, last_used_at, expires_at, user_agent, ip_address, impersonator_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&session_id)
+        .bind(user_id)
+        .bind(timestamp(now))
+        .bind(timestamp(now))
+        .bind(timestamp(now + Duration::seconds(expires_in)))
+        .bind(&client.user_agent)
+        .bind(&client.ip_address)
+        .bind(impersonator_id)
+        .execute(&self.db.pool)
+        .await?;
+
+        let token = JwtKeys::from_config(&self.config)
+            .and_then(|keys| {
+                keys.create_impersonation_token(
+                    &user_id.to_string(),
+                    role.as_str(),
+                    &session_id,
+                    &impersonator_id.to_string(),
+                    expires_in,
+                )
+            })
+            .map_err(|e| AppError::Internal(format!("生成令牌失败: {}", e)))?;
+
+        Ok((session_id, token))
+    }
+
+    /// 用刷新令牌换取新的令牌对,旧刷新令牌随即作废
+    ///
+    /// 会话有效期随每次刷新顺延 `refresh_token_expires_in`。
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, AppError> {
+        let token_hash = hash_refresh_token(refresh_token);
+        let now = timestamp(Utc::now());
+
+        let row = sqlx::query_as::<_, RefreshTokenRow>(
+            r#"
+            SELECT r.session_id, r.expires_at, r.used_at, s.user_id, s.revoked_at, s.expires_at
+            FROM refresh_tokens r
+            JOIN sessions s ON s.id = r.session_id
+            WHERE r.token_hash = ?
+            "#,
+        )
+        .bind(&token_hash)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or(AppError::TokenInvalid)?;
+        let (session_id, token_expires_at, used_at, user_id, revoked_at, session_expires_at) = row;
+
+        if revoked_at.is_some() {
+            return Err(AppError::TokenInvalid);
+        }
+        // 刚轮换过的令牌在宽限期内再次使用时不签发新令牌,客户端改用并发请求拿到的令牌;
+        // 超过宽限期视为被盗用
+        if let Some(used_at) = used_at {
+            let grace_since = timestamp(Utc::now() - Duration::seconds(REFRESH_REUSE_GRACE_SECONDS));
+            if used_at < grace_since {
+                self.revoke_reused(&session_id, user_id).await?;
+                return Err(AppError::TokenInvalid);
+            }
+            return Err(refresh_conflict());
+        }
+        if token_expires_at <= now || session_expires_at <= now {
+            return Err(AppError::TokenExpired);
+        }
+
+        let role = sqlx::query_scalar::<_, UserRole>("SELECT role FROM users WHERE id = ? AND is_active = 1")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        let Some(role) = role else {
+            self.revoke_session(&session_id, "user_inactive").await?;
+            return Err(AppError::Unauthorized);
+        };
+        // 公司停用期间不撤销会话,恢复启用后仍可继续刷新
+        ensure_company_active(&self.db, user_id).await?;
+
+        let new_token = generate_refresh_token();
+        let new_hash = hash_refresh_token(&new_token);
+        let expires_at = timestamp(Utc::now() + Duration::seconds(self.config.refresh_token_expires_in));
+
+        let mut tx = self.db.pool.begin().await?;
+        // 条件更新保证并发刷新时同一令牌只轮换一次,同一会话始终只有一个可用的刷新令牌
+        let claimed = sqlx::query(
+            "UPDATE refresh_tokens SET used_at = ?, replaced_by = ? WHERE token_hash = ? AND used_at IS NULL",
+        )
+        .bind(&now)
+        .bind(&new_hash)
+        .bind(&token_hash)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            tracing::debug!("刷新令牌已被并发请求轮换 (会话 {})", session_id);
+            return Err(refresh_conflict());
+        }
+
+        sqlx::query("INSERT INTO refresh_tokens (token_hash, session_id, created_at, expires_at) VALUES (?, ?, ?, ?)")
+            .bind(&new_hash)
+            .bind(&session_id)
+            .bind(&now)
+            .bind(&expires_at)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE sessions SET last_used_at = ?, expires_at = ? WHERE id = ?")
+            .bind(&now)
+            .bind(&expires_at)
+            .bind(&session_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.token_pair(user_id, &role, &session_id, new_token)
+    }
+
+    /// 撤销会话(已撤销的会话保持原撤销原因)
+    pub async fn revoke_session(&self, session_id: &str, reason: &str) -> Result<bool, AppError> {
+        let revoked = sqlx::query(
+            "UPDATE sessions SET revoked_at = ?, revoked_reason = ? WHERE id = ? AND revoked_at IS NULL",
+        )
+        .bind(timestamp(Utc::now()))
+        .bind(reason)
+        .bind(session_id)
+        .execute(&self.db.pool)
+        .await?
+        .rows_affected();
+        // 访问令牌由认证中间件立即拒绝,实时连接在这里断开
+        connections::disconnect_session(session_id);
+
+        Ok(revoked > 0)
+    }
+
+    /// 撤销用户的所有会话(可保留一个当前会话),返回撤销的会话数
+    pub async fn revoke_user_sessions(
+        &self,
+        user_id: i64,
+        except_session: Option<&str>,
+        reason: &str,
+    ) -> Result<u64, AppError> {
+        let revoked = sqlx::query(
+            r#"
+            UPDATE sessions SET revoked_at = ?, revoked_reason = ?
+            WHERE user_id = ? AND revoked_at IS NULL AND id IS NOT ?
+            "#,
+        )
+        .bind(timestamp(Utc::now()))
+        .bind(reason)
+        .bind(user_id)
+        .bind(except_session)
+        .execute(&self.db.pool)
+        .await?
+        .rows_affected();
+        connections::disconnect_user_sessions(user_id, except_session);
+
+        Ok(revoked)
+    }
+
+    /// 查看用户的有效会话,按最近使用时间倒序
+    ///
+    /// 查看他人的会话需要对方所在公司的账号安全权限。
+    pub async fn list_sessions(
+        &self,
+        user_id: i64,
+        current_user: &UserInfo,
+        current_session: Option<&str>,
+    ) -> Result<Vec<SessionInfo>, AppError> {
+        if user_id != current_user.id {
+            let company_id = self.user_company(user_id).await?;
+            if !current_user.can(Permission::UserSecurity, company_id) {
+                return Err(AppError::MissingPermission(Permission::UserSecurity));
+            }
+        }
+
+        let rows = sqlx::query_as::<_, SessionRow>(
+            r#"
+            SELECT id, user_id, user_agent, ip_address, created_at, last_used_at, expires_at, impersonator_id
+            FROM sessions
+            WHERE user_id = ? AND revoked_at IS NULL AND expires_at > ?
+            ORDER BY last_used_at DESC, created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(timestamp(Utc::now()))
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.into_info(current_session)).collect())
+    }
+
+    /// 撤销指定会话: 本人可撤销自己的会话,管理者可撤销本公司用户的会话
+    ///
+    /// 无权查看的会话同样返回不存在,避免泄露其他公司的会话ID。
+    pub async fn revoke_by_id(
+        &self,
+        session_id: &str,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        let owner = sqlx::query_as::<_, (i64, Option<i64>)>(
+            r#"
+            SELECT s.user_id, u.company_id FROM sessions s JOIN users u ON u.id = s.user_id
+            WHERE s.id = ? AND s.revoked_at IS NULL AND s.expires_at > ?
+            "#,
+        )
+        .bind(session_id)
+        .bind(timestamp(Utc::now()))
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let (user_id, company_id) = match owner {
+            Some((user_id, company_id))
+                if user_id == current_user.id || current_user.can(Permission::UserSecurity, company_id) =>
+            {
+                (user_id, company_id)
+            }
+            _ => return Err(AppError::NotFound("会话不存在或已失效".to_string())),
+        };
+
+        let reason = if user_id == current_user.id { REVOKED_BY_USER } else { REVOKED_BY_ADMIN };
+        self.revoke_session(session_id, reason).await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_SESSION_REVOKED)
+                    .actor(current_user.id)
+                    .company(company_id)
+                    .target("user", user_id)
+                    .client(client)
+                    .details(json!({ "session_id": session_id, "reason": reason })),
+            )
+            .await
+    }
+
+    /// 记录会话的最近使用时间(同一会话每分钟最多更新一次)
+    pub async fn touch(&self, session_id: &str) -> Result<(), AppError> {
+        let now = Utc::now();
+        sqlx::query("UPDATE sessions SET last_used_at = ? WHERE id = ? AND last_used_at <= ?")
+            .bind(timestamp(now))
+            .bind(session_id)
+            .bind(timestamp(now - Duration::seconds(LAST_USED_INTERVAL_SECONDS)))
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn user_company(&self, user_id: i64) -> Result<Option<i64>, AppError> {
+        sqlx::query_scalar::<_, Option<i64>>("SELECT company_id FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))
+    }
+
+    /// 会话是否属于该用户且未撤销、未过期
+    pub async fn is_session_active(&self, session_id: &str, user_id: i64) -> Result<bool, AppError> {
+        let active = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM sessions WHERE id = ? AND user_id = ? AND revoked_at IS NULL AND expires_at > ?",
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .bind(timestamp(Utc::now()))
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(active > 0)
+    }
+
+    async fn revoke_reused(&self, session_id: &str, user_id: i64) -> Result<(), AppError> {
+        if self.revoke_session(session_id, REVOKED_TOKEN_REUSE).await? {
+            tracing::warn!("⚠️ 刷新令牌被重复使用,已撤销会话 {} (用户 {})", session_id, user_id);
+        }
+        Ok(())
+    }
+
+    fn token_pair(
+        &self,
+        user_id: i64,
+        role: &UserRole,
+        session_id: &str,
+        refresh_token: String,
+    ) -> Result<TokenPair, AppError> {
+        let token = JwtKeys::from_config(&self.config)
+            .and_then(|keys| {
+                keys.create_session_token(&user_id.to_string(), role.as_str(), session_id, self.config.jwt_expires_in)
+            })
+            .map_err(|e| AppError::Internal(format!("生成令牌失败: {}", e)))?;
+
+        Ok(TokenPair {
+            token,
+            refresh_token,
+            expires_in: self.config.jwt_expires_in,
+        })
+    }
+}
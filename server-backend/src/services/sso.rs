@@ -0,0 +1,681 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{
+        CompanySsoConfig, CompanySsoConfigRequest, LoginResponse, Permission, SsoAuthorization, SsoCallbackRequest, User,
+        UserIdentityInfo, UserInfo, UserRole,
+    },
+    services::{
+        audit::{
+            AuditEvent, AuditService, ACTION_SSO_CONFIG_CHANGED, ACTION_SSO_IDENTITY_LINKED,
+            ACTION_SSO_IDENTITY_UNLINKED, ACTION_SSO_LOGIN, ACTION_SSO_USER_PROVISIONED,
+        },
+        invitation::ensure_seat_available,
+        role::RoleService,
+        session::SessionService,
+    },
+    utils::{
+        oidc::{self, AuthorizationRequest, IdTokenClaims},
+        pii, PasswordHasher,
+    },
+    Config, Database,
+};
+
+/// 授权请求(state)有效期(秒)
+const STATE_TTL_SECONDS: i64 = 600;
+
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn hash_state(state: &str) -> String {
+    hex::encode(Sha256::digest(state.as_bytes()))
+}
+
+/// 角色的权限高低,多个映射同时匹配时取最高的
+fn role_rank(role: &UserRole) -> u8 {
+    match role {
+        UserRole::PlatformAdmin => 2,
+        UserRole::ProjectManager => 1,
+        UserRole::TaskExecutor => 0,
+    }
+}
+
+/// 由外部身份生成合法且不重复的用户名候选(3-50位字母、数字、`._-`)
+fn username_base(claims: &IdTokenClaims, email: &str) -> String {
+    let source = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+    let mut username: String = source
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .take(40)
+        .collect();
+    if username.len() < 3 {
+        username = format!("sso_{}", username);
+    }
+    username
+}
+
+#[derive(sqlx::FromRow)]
+struct SsoConfigRow {
+    company_id: i64,
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scopes: String,
+    role_claim: Option<String>,
+    role_mapping: String,
+    default_role: UserRole,
+    jit_provisioning: bool,
+    enabled: bool,
+    updated_at: String,
+}
+
+impl SsoConfigRow {
+    fn role_mapping(&self) -> HashMap<String, UserRole> {
+        serde_json::from_str(&self.role_mapping).unwrap_or_default()
+    }
+
+    /// 按配置的声明映射角色,没有匹配时返回 `None`
+    fn mapped_role(&self, claims: &IdTokenClaims) -> Option<UserRole> {
+        let claim = self.role_claim.as_deref()?;
+        let mapping = self.role_mapping();
+        claims
+            .claim_values(claim)
+            .iter()
+            .filter_map(|value| mapping.get(value))
+            .filter(|role| **role != UserRole::PlatformAdmin)
+            .max_by_key(|role| role_rank(role))
+            .cloned()
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct LoginStateRow {
+    company_id: i64,
+    code_verifier: String,
+    nonce: String,
+    link_user_id: Option<i64>,
+}
+
+/// 单点登录服务(OpenID Connect 授权码 + PKCE)
+///
+/// 每个公司配置自己的身份提供方。首次登录时按外部身份(issuer + sub)查找关联的本地用户,
+/// 没有关联时按配置自动创建本公司用户;已有本地账号的用户需先用密码登录再发起关联,
+/// 不会仅凭相同邮箱自动关联。两步验证由身份提供方负责,单点登录不再要求本地验证码。
+pub struct SsoService {
+    db: Database,
+    config: Config,
+}
+
+impl SsoService {
+    pub fn new(db: Database, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    /// 身份提供方需登记的回调地址(前端页面,再由前端提交到 /auth/sso/callback)
+    pub fn redirect_uri(&self) -> String {
+        format!("{}/sso/callback", self.config.public_url.trim_end_matches('/'))
+    }
+
+    async fn load_config(&self, company_id: i64) -> Result<Option<SsoConfigRow>, AppError> {
+        Ok(sqlx::query_as::<_, SsoConfigRow>("SELECT * FROM company_sso_configs WHERE company_id = ?")
+            .bind(company_id)
+            .fetch_optional(&self.db.pool)
+            .await?)
+    }
+
+    fn to_info(&self, row: SsoConfigRow) -> CompanySsoConfig {
+        CompanySsoConfig {
+            role_mapping: row.role_mapping(),
+            company_id: row.company_id,
+            issuer: row.issuer,
+            client_id: row.client_id,
+            has_client_secret: row.client_secret.is_some(),
+            scopes: row.scopes,
+            role_claim: row.role_claim,
+            default_role: row.default_role,
+            jit_provisioning: row.jit_provisioning,
+            enabled: row.enabled,
+            redirect_uri: self.redirect_uri(),
+            updated_at: row.updated_at,
+        }
+    }
+
+    /// 查看公司的单点登录配置(本公司管理者或平台管理员)
+    pub async fn get_config(&self, company_id: i64, current_user: &UserInfo) -> Result<Option<CompanySsoConfig>, AppError> {
+        if !current_user.can(Permission::CompanySettings, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::CompanySettings));
+        }
+
+        Ok(self.load_config(company_id).await?.map(|row| self.to_info(row)))
+    }
+
+    /// 设置公司的单点登录配置
+    pub async fn set_config(
+        &self,
+        company_id: i64,
+        request: &CompanySsoConfigRequest,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<CompanySsoConfig, AppError> {
+        if !current_user.can(Permission::CompanySettings, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::CompanySettings));
+        }
+        let company_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM companies WHERE id = ?")
+            .bind(company_id)
+            .fetch_one(&self.db.pool)
+            .await?
+            > 0;
+        if !company_exists {
+            return Err(AppError::NotFound(format!("公司不存在: {}", company_id)));
+        }
+
+        let issuer = request.issuer.trim().trim_end_matches('/').to_string();
+        if !issuer.starts_with("https://") && !self.config.debug {
+            return Err(AppError::InvalidInput("issuer 必须使用 HTTPS".to_string()));
+        }
+        if !request.scopes.split_whitespace().any(|scope| scope == "openid") {
+            return Err(AppError::InvalidInput("scopes 必须包含 openid".to_string()));
+        }
+        if request.default_role == UserRole::PlatformAdmin
+            || request.role_mapping.values().any(|role| *role == UserRole::PlatformAdmin)
+        {
+            return Err(AppError::InvalidInput("单点登录不能映射为平台管理员".to_string()));
+        }
+        let role_claim = request.role_claim.as_deref().map(str::trim).filter(|claim| !claim.is_empty());
+        let role_mapping = serde_json::to_string(&request.role_mapping)
+            .map_err(|e| AppError::Internal(format!("序列化角色映射失败: {}", e)))?;
+
+        // 未提供客户端密钥时保留原值,空字符串表示清除
+        let client_secret = match request.client_secret.as_deref() {
+            None => self.load_config(company_id).await?.and_then(|row| row.client_secret),
+            Some("") => None,
+            Some(secret) => Some(secret.to_string()),
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO company_sso_configs
+                (company_id, issuer, client_id, client_secret, scopes, role_claim, role_mapping, default_role, jit_provisioning, enabled, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(company_id) DO UPDATE SET
+                issuer = excluded.issuer, client_id = excluded.client_id, client_secret = excluded.client_secret,
+                scopes = excluded.scopes, role_claim = excluded.role_claim, role_mapping = excluded.role_mapping,
+                default_role = excluded.default_role, jit_provisioning = excluded.jit_provisioning,
+                enabled = excluded.enabled, updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(company_id)
+        .bind(&issuer)
+        .bind(request.client_id.trim())
+        .bind(&client_secret)
+        .bind(request.scopes.trim())
+        .bind(role_claim)
+        .bind(&role_mapping)
+        .bind(&request.default_role)
+        .bind(request.jit_provisioning)
+        .bind(request.enabled)
+        .bind(timestamp(Utc::now()))
+        .execute(&self.db.pool)
+        .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_SSO_CONFIG_CHANGED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("company", company_id)
+                    .client(client)
+                    .details(json!({
+                        "issuer": issuer,
+                        "client_id": request.client_id.trim(),
+                        "enabled": request.enabled,
+                        "jit_provisioning": request.jit_provisioning,
+                    })),
+            )
+            .await?;
+
+        self.load_config(company_id)
+            .await?
+            .map(|row| self.to_info(row))
+            .ok_or_else(|| AppError::Internal("保存单点登录配置失败".to_string()))
+    }
+
+    /// 删除公司的单点登录配置,已关联的外部身份保留,重新配置后可继续使用
+    pub async fn delete_config(
+        &self,
+        company_id: i64,
+        current_user: &UserInfo,
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        if !current_user.can(Permission::CompanySettings, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::CompanySettings));
+        }
+
+        let deleted = sqlx::query("DELETE FROM company_sso_configs WHERE company_id = ?")
+            .bind(company_id)
+            .execute(&self.db.pool)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(AppError::NotFound("该公司未配置单点登录".to_string()));
+        }
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_SSO_CONFIG_CHANGED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("company", company_id)
+                    .client(client)
+                    .details(json!({ "deleted": true })),
+            )
+            .await
+    }
+
+    /// 按公司代码发起单点登录,返回身份提供方的授权地址
+    pub async fn start_login(&self, company_code: &str) -> Result<SsoAuthorization, AppError> {
+        let company_id = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM companies WHERE code = ? AND (is_active IS NULL OR is_active = 1)",
+        )
+        .bind(company_code.trim())
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let config = match company_id {
+            Some(company_id) => self.load_config(company_id).await?,
+            None => None,
+        }
+        .filter(|config| config.enabled)
+        .ok_or_else(|| AppError::NotFound("该公司未启用单点登录".to_string()))?;
+
+        self.begin(&config, None).await
+    }
+
+    /// 已登录的本地用户发起关联,回调完成后外部身份关联到该用户
+    pub async fn start_link(&self, user: &UserInfo) -> Result<SsoAuthorization, AppError> {
+        let config = match user.company_id {
+            Some(company_id) => self.load_config(company_id).await?,
+            None => None,
+        }
+        .filter(|config| config.enabled)
+        .ok_or_else(|| AppError::NotFound("所属公司未启用单点登录".to_string()))?;
+
+        self.begin(&config, Some(user.id)).await
+    }
+
+    async fn begin(&self, config: &SsoConfigRow, link_user_id: Option<i64>) -> Result<SsoAuthorization, AppError> {
+        let metadata = oidc::discover(&config.issuer).await?;
+        let state = oidc::random_token();
+        let nonce = oidc::random_token();
+        let code_verifier = oidc::random_token();
+        let now = Utc::now();
+
+        sqlx::query(
+            r#"
+            INSERT INTO sso_login_states (state_hash, company_id, code_verifier, nonce, link_user_id, created_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(hash_state(&state))
+        .bind(config.company_id)
+        .bind(&code_verifier)
+        .bind(&nonce)
+        .bind(link_user_id)
+        .bind(timestamp(now))
+        .bind(timestamp(now + Duration::seconds(STATE_TTL_SECONDS)))
+        .execute(&self.db.pool)
+        .await?;
+
+        let redirect_uri = self.redirect_uri();
+        let authorization_url = oidc::authorization_url(
+            &metadata,
+            &AuthorizationRequest {
+                client_id: &config.client_id,
+                redirect_uri: &redirect_uri,
+                scopes: &config.scopes,
+                state: &state,
+                nonce: &nonce,
+                code_verifier: &code_verifier,
+            },
+        );
+
+        Ok(SsoAuthorization { authorization_url })
+    }
+
+    /// 处理身份提供方回调: 校验 state 和 ID Token,找到(或创建、关联)本地用户并登录
+    pub async fn complete(&self, request: &SsoCallbackRequest, client: &ClientInfo) -> Result<LoginResponse, AppError> {
+        let state_hash = hash_state(&request.state);
+        let now = timestamp(Utc::now());
+
+        // state 只能使用一次
+        let consumed = sqlx::query(
+            "UPDATE sso_login_states SET used_at = ? WHERE state_hash = ? AND used_at IS NULL AND expires_at > ?",
+        )
+        .bind(&now)
+        .bind(&state_hash)
+        .bind(&now)
+        .execute(&self.db.pool)
+        .await?
+        .rows_affected();
+        if consumed == 0 {
+            return Err(AppError::TokenInvalid);
+        }
+        let state = sqlx::query_as::<_, LoginStateRow>(
+            "SELECT company_id, code_verifier, nonce, link_user_id FROM sso_login_states WHERE state_hash = ?",
+        )
+        .bind(&state_hash)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        let config = self
+            .load_config(state.company_id)
+            .await?
+            .filter(|config| config.enabled)
+            .ok_or_else(|| AppError::NotFound("该公司未启用单点登录".to_string()))?;
+        let metadata = oidc::discover(&config.issuer).await?;
+        let id_token = oidc::exchange_code(
+            &metadata,
+            &config.client_id,
+            config.client_secret.as_deref(),
+            &self.redirect_uri(),
+            &request.code,
+            &state.code_verifier,
+        )
+        .await?;
+        let claims = oidc::verify_id_token(&metadata, &config.client_id, &id_token, &state.nonce).await?;
+
+        let user = match state.link_user_id {
+            Some(user_id) => self.link_identity(&config, user_id, &claims, client).await?,
+            None => match self.find_linked_user(&config, &claims).await? {
+                Some(user) => user,
+                None => self.provision_user(&config, &claims, client).await?,
+            },
+        };
+        let user = self.sync_role(&config, user, &claims).await?;
+
+        sqlx::query("UPDATE user_identities SET last_login_at = ? WHERE issuer = ? AND subject = ?")
+            .bind(&now)
+            .bind(&config.issuer)
+            .bind(&claims.sub)
+            .execute(&self.db.pool)
+            .await?;
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_SSO_LOGIN)
+                    .actor(user.id)
+                    .company(user.company_id)
+                    .target("user", user.id)
+                    .client(client)
+                    .details(json!({ "issuer": config.issuer, "subject": claims.sub })),
+            )
+            .await?;
+
+        let tokens = SessionService::new(self.db.clone(), self.config.clone())
+            .create_session(user.id, &user.role, client)
+            .await?;
+        let user = RoleService::new(self.db.clone()).user_info(user).await?;
+        Ok(LoginResponse::authenticated(tokens, user))
+    }
+
+    async fn find_user(&self, user_id: i64) -> Result<Option<User>, AppError> {
+        Ok(sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?)
+    }
+
+    fn ensure_usable(config: &SsoConfigRow, user: &User) -> Result<(), AppError> {
+        if !user.is_active {
+            return Err(AppError::OperationNotAllowed("账号已被禁用".to_string()));
+        }
+        if user.company_id != Some(config.company_id) {
+            return Err(AppError::OperationNotAllowed("账号不属于该公司".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn find_linked_user(&self, config: &SsoConfigRow, claims: &IdTokenClaims) -> Result<Option<User>, AppError> {
+        let user_id = sqlx::query_scalar::<_, i64>("SELECT user_id FROM user_identities WHERE issuer = ? AND subject = ?")
+            .bind(&config.issuer)
+            .bind(&claims.sub)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        let user = match user_id {
+            Some(user_id) => self.find_user(user_id).await?,
+            None => return Ok(None),
+        };
+        match user {
+            Some(user) => {
+                Self::ensure_usable(config, &user)?;
+                Ok(Some(user))
+            }
+            // 用户已被删除,清理残留的关联后按首次登录处理
+            None => {
+                sqlx::query("DELETE FROM user_identities WHERE issuer = ? AND subject = ?")
+                    .bind(&config.issuer)
+                    .bind(&claims.sub)
+                    .execute(&self.db.pool)
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn insert_identity(&self, config: &SsoConfigRow, user_id: i64, claims: &IdTokenClaims) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_identities (user_id, company_id, issuer, subject, email, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(user_id)
+        .bind(config.company_id)
+        .bind(&config.issuer)
+        .bind(&claims.sub)
+        .bind(pii::seal_optional(claims.email.as_deref())?)
+        .bind(timestamp(Utc::now()))
+        .execute(&self.db.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn link_identity(
+        &self,
+        config: &SsoConfigRow,
+        user_id: i64,
+        claims: &IdTokenClaims,
+        client: &ClientInfo,
+    ) -> Result<User, AppError> {
+        let user = self
+            .find_user(user_id)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+        Self::ensure_usable(config, &user)?;
+
+        let linked_user = sqlx::query_scalar::<_, i64>("SELECT user_id FROM user_identities WHERE issuer = ? AND subject = ?")
+            .bind(&config.issuer)
+            .bind(&claims.sub)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        match linked_user {
+            Some(linked) if linked == user.id => return Ok(user),
+            Some(_) => return Err(AppError::Conflict("该外部身份已关联其他账号".to_string())),
+            None => {}
+        }
+        let has_identity = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM user_identities WHERE user_id = ? AND issuer = ?")
+            .bind(user.id)
+            .bind(&config.issuer)
+            .fetch_one(&self.db.pool)
+            .await?
+            > 0;
+        if has_identity {
+            return Err(AppError::Conflict("账号已关联该身份提供方的其他身份,请先解除关联".to_string()));
+        }
+
+        self.insert_identity(config, user.id, claims).await?;
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_SSO_IDENTITY_LINKED)
+                    .actor(user.id)
+                    .company(user.company_id)
+                    .target("user", user.id)
+                    .client(client)
+                    .details(json!({ "issuer": config.issuer, "subject": claims.sub })),
+            )
+            .await?;
+
+        Ok(user)
+    }
+
+    /// 首次单点登录时创建本公司用户(密码为随机值,只能通过单点登录或找回密码登录)
+    async fn provision_user(&self, config: &SsoConfigRow, claims: &IdTokenClaims, client: &ClientInfo) -> Result<User, AppError> {
+        let email = claims
+            .email
+            .as_deref()
+            .map(str::trim)
+            .filter(|email| !email.is_empty())
+            .ok_or_else(|| AppError::OperationNotAllowed("身份提供方未提供邮箱,无法创建账号".to_string()))?;
+
+        // 已有本地账号时需要用户先登录再关联,避免仅凭邮箱接管账号
+        let email_index = pii::blind_index(email);
+        let email_taken = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE email_index = ? OR LOWER(email) = LOWER(?)")
+            .bind(&email_index)
+            .bind(email)
+            .fetch_one(&self.db.pool)
+            .await?
+            > 0;
+        if email_taken {
+            return Err(AppError::Conflict(
+                "该邮箱已有账号,请先使用密码登录后在个人设置中关联单点登录".to_string(),
+            ));
+        }
+        if !config.jit_provisioning {
+            return Err(AppError::OperationNotAllowed("账号尚未开通,请联系公司管理员".to_string()));
+        }
+        if claims.email_verified == Some(false) {
+            return Err(AppError::OperationNotAllowed("身份提供方的邮箱未验证".to_string()));
+        }
+        ensure_seat_available(&mut *self.db.pool.acquire().await?, config.company_id, true).await?;
+
+        let base = username_base(claims, email);
+        let mut username = base.clone();
+        let mut suffix = 1;
+        while sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE username = ?")
+            .bind(&username)
+            .fetch_one(&self.db.pool)
+            .await?
+            > 0
+        {
+            suffix += 1;
+            username = format!("{}{}", base, suffix);
+        }
+
+        let role = config.mapped_role(claims).unwrap_or_else(|| config.default_role.clone());
+        let hashed_password = PasswordHasher::from_config(&self.config)?.hash(&oidc::random_token())?;
+        let full_name = claims.name.clone().filter(|name| !name.trim().is_empty()).unwrap_or_else(|| username.clone());
+        let now = Utc::now();
+
+        let user_id = sqlx::query(
+            r#"
+            INSERT INTO users (username, email, email_index, hashed_password, role, full_name, is_active, is_verified, company_id, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, 1, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&username)
+        .bind(pii::seal(email)?)
+        .bind(&email_index)
+        .bind(&hashed_password)
+        .bind(&role)
+        .bind(&full_name)
+        .bind(claims.email_verified.unwrap_or(false))
+        .bind(config.company_id)
+        .bind(now)
+        .bind(now)
+        .execute(&self.db.pool)
+        .await?
+        .last_insert_rowid();
+        self.insert_identity(config, user_id, claims).await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_SSO_USER_PROVISIONED)
+                    .actor(user_id)
+                    .company(Some(config.company_id))
+                    .target("user", user_id)
+                    .client(client)
+                    .details(json!({ "issuer": config.issuer, "subject": claims.sub, "role": role.as_str() })),
+            )
+            .await?;
+
+        self.find_user(user_id)
+            .await?
+            .ok_or_else(|| AppError::Internal("创建用户失败".to_string()))
+    }
+
+    /// 角色声明匹配到映射时同步角色,没有匹配时保留现有角色
+    async fn sync_role(&self, config: &SsoConfigRow, mut user: User, claims: &IdTokenClaims) -> Result<User, AppError> {
+        let Some(role) = config.mapped_role(claims) else {
+            return Ok(user);
+        };
+        if role == user.role || user.role == UserRole::PlatformAdmin {
+            return Ok(user);
+        }
+
+        sqlx::query("UPDATE users SET role = ?, updated_at = ? WHERE id = ?")
+            .bind(&role)
+            .bind(Utc::now())
+            .bind(user.id)
+            .execute(&self.db.pool)
+            .await?;
+        tracing::info!("用户 {} 的角色按单点登录声明更新为 {}", user.id, role);
+        user.role = role;
+        Ok(user)
+    }
+
+    /// 当前用户关联的外部身份
+    pub async fn list_identities(&self, user: &UserInfo) -> Result<Vec<UserIdentityInfo>, AppError> {
+        Ok(sqlx::query_as::<_, UserIdentityInfo>(
+            "SELECT id, issuer, subject, email, created_at, last_login_at FROM user_identities WHERE user_id = ? ORDER BY id",
+        )
+        .bind(user.id)
+        .fetch_all(&self.db.pool)
+        .await?)
+    }
+
+    /// 解除关联外部身份
+    pub async fn unlink_identity(&self, user: &UserInfo, identity_id: i64, client: &ClientInfo) -> Result<(), AppError> {
+        let identity = sqlx::query_as::<_, (String, String)>(
+            "SELECT issuer, subject FROM user_identities WHERE id = ? AND user_id = ?",
+        )
+        .bind(identity_id)
+        .bind(user.id)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("外部身份不存在".to_string()))?;
+
+        sqlx::query("DELETE FROM user_identities WHERE id = ?")
+            .bind(identity_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_SSO_IDENTITY_UNLINKED)
+                    .actor(user.id)
+                    .company(user.company_id)
+                    .target("user", user.id)
+                    .client(client)
+                    .details(json!({ "issuer": identity.0, "subject": identity.1 })),
+            )
+            .await
+    }
+}
@@ -1,5 +1,6 @@
 use crate::database::Database;
 use crate::errors::AppError;
+use crate::models::{Permission, UserInfo};
 use serde::Serialize;
 use uuid::Uuid;
 
@@ -41,7 +42,7 @@ pub struct ProjectStatistics {
 #[derive(Debug, Serialize)]
 pub struct UserWorkloadStatistics {
     /// 员工ID
-    pub user_id: Uuid,
+    pub user_id: i64,
     /// 员工姓名
     pub user_name: Option<String>,
     /// 分配任务总数
@@ -75,241 +76,302 @@ pub struct ProjectProgressStatistics {
     pub actual_hours: f64,
 }
 
+/// 统计数据的可见范围(多租户隔离)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatisticsScope {
+    /// 全平台数据(仅PlatformAdmin)
+    Platform,
+    /// 指定公司的数据
+    Company(i64),
+    /// 仅分配给指定用户的任务
+    Assignee(i64),
+}
+
+impl StatisticsScope {
+    /// 根据当前用户确定统计范围
+    ///
+    /// - 平台管理权限: 可通过 `company_id` 指定公司,不指定则查看全平台
+    /// - 统计查看权限: 只能查看本公司,指定其他公司时拒绝访问
+    /// - 其他用户: 只能查看分配给自己的任务
+    pub fn for_user(current_user: &UserInfo, company_id: Option<i64>) -> Result<Self, AppError> {
+        if current_user.has_permission(Permission::PlatformManage) {
+            return Ok(company_id.map_or(Self::Platform, Self::Company));
+        }
+        if company_id.is_some_and(|id| Some(id) != current_user.company_id) {
+            return Err(AppError::Forbidden);
+        }
+        if current_user.has_permission(Permission::StatisticsView) {
+            let own_company_id = current_user.company_id.ok_or(AppError::Forbidden)?;
+            return Ok(Self::Company(own_company_id));
+        }
+        Ok(Self::Assignee(current_user.id))
+    }
+
+    /// 任务表(别名 t)的过滤条件及绑定参数
+    pub(crate) fn task_filter(&self) -> (&'static str, Option<i64>) {
+        match *self {
+            Self::Platform => ("1 = 1", None),
+            Self::Company(company_id) => ("t.company_id = ?", Some(company_id)),
+            Self::Assignee(user_id) => ("t.assigned_to = ?", Some(user_id)),
+        }
+    }
+
+    /// 回答任务统计的汇总表: 任务执行者按员工维度汇总,其余按公司维度汇总
+    fn task_rollup_table(&self) -> &'static str {
+        match self {
+            Self::Assignee(_) => "stats_daily_user",
+            _ => "stats_daily_company",
+        }
+    }
+
+    /// 汇总表(别名 s)的过滤条件及绑定参数
+    fn rollup_filter(&self) -> (&'static str, Option<i64>) {
+        match *self {
+            Self::Platform => ("1 = 1", None),
+            Self::Company(company_id) => ("s.company_id = ?", Some(company_id)),
+            Self::Assignee(user_id) => ("s.user_id = ?", Some(user_id)),
+        }
+    }
+
+    /// 项目表(别名 p)的过滤条件及绑定参数,任务执行者只能看到自己参与的项目
+    fn project_filter(&self) -> (&'static str, Option<i64>) {
+        match *self {
+            Self::Platform => ("1 = 1", None),
+            Self::Company(company_id) => ("p.company_id = ?", Some(company_id)),
+            Self::Assignee(user_id) => (
+                "p.id IN (SELECT project_id FROM tasks WHERE assigned_to = ?)",
+                Some(user_id),
+            ),
+        }
+    }
+}
+
 /// 数据统计服务
+///
+/// 任务与工时数据读取 `stats_daily_*` 预聚合表(由触发器随任务/工时记录的变更增量维护),
+/// 每个看板接口只需一次查询
 pub struct StatisticsService {
     db: Database,
 }
 
+/// 员工工作量汇总行: (员工ID, 姓名, 分配任务数, 已完成数, 进行中数, 工时)
+type WorkloadRow = (i64, Option<String>, i64, i64, i64, f64);
+
+/// 项目进度汇总行: (项目名称, 任务总数, 已完成数, 进行中数, 预估工时, 实际工时)
+type ProgressRow = (String, i64, i64, i64, f64, f64);
+
 impl StatisticsService {
     pub fn new(db: Database) -> Self {
         Self { db }
     }
 
-    /// 获取任务统计
-    pub async fn get_task_statistics(&self) -> Result<TaskStatistics, AppError> {
-        let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tasks")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    /// 从员工维度汇总表读取工作量, `user_id` 为空时返回范围内所有有任务的员工
+    async fn query_workloads(&self, scope: StatisticsScope, user_id: Option<i64>) -> Result<Vec<UserWorkloadStatistics>, AppError> {
+        let (filter, param) = scope.rollup_filter();
+        let sql = format!(
+            "SELECT CAST(s.user_id AS INTEGER), u.full_name, \
+                    SUM(s.pending_delta + s.in_progress_delta + s.completed_delta + s.cancelled_delta) AS assigned, \
+                    SUM(s.completed_delta), SUM(s.in_progress_delta), TOTAL(s.actual_hours_delta) \
+             FROM stats_daily_user s LEFT JOIN users u ON u.id = s.user_id \
+             WHERE {} AND {} \
+             GROUP BY s.user_id HAVING assigned <> 0 \
+             ORDER BY assigned DESC",
+            filter,
+            if user_id.is_some() { "s.user_id = ?" } else { "1 = 1" }
+        );
+
+        let mut query = sqlx::query_as::<_, WorkloadRow>(&sql);
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
+        if let Some(user_id) = user_id {
+            query = query.bind(user_id);
+        }
 
-        let pending: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tasks WHERE status = 'pending'")
-            .fetch_one(&self.db.pool)
+        let rows = query
+            .fetch_all(&self.db.pool)
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-        let in_progress: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tasks WHERE status = 'in_progress'")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(rows
+            .into_iter()
+            .map(|(user_id, user_name, assigned, completed, in_progress, total_hours)| UserWorkloadStatistics {
+                user_id,
+                user_name,
+                assigned_tasks: assigned,
+                completed_tasks: completed,
+                in_progress_tasks: in_progress,
+                total_hours,
+            })
+            .collect())
+    }
 
-        let completed: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tasks WHERE status = 'completed'")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    /// 获取任务统计
+    pub async fn get_task_statistics(&self, scope: StatisticsScope) -> Result<TaskStatistics, AppError> {
+        let (filter, param) = scope.rollup_filter();
+        let sql = format!(
+            "SELECT COALESCE(SUM(s.pending_delta), 0), COALESCE(SUM(s.in_progress_delta), 0), \
+                    COALESCE(SUM(s.completed_delta), 0), COALESCE(SUM(s.cancelled_delta), 0) \
+             FROM {} s WHERE {}",
+            scope.task_rollup_table(),
+            filter
+        );
+
+        let mut query = sqlx::query_as::<_, (i64, i64, i64, i64)>(&sql);
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
 
-        let cancelled: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tasks WHERE status = 'cancelled'")
+        let (pending, in_progress, completed, cancelled) = query
             .fetch_one(&self.db.pool)
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-        let completion_rate = if total.0 > 0 {
-            (completed.0 as f64 / total.0 as f64) * 100.0
+        let total = pending + in_progress + completed + cancelled;
+        let completion_rate = if total > 0 {
+            (completed as f64 / total as f64) * 100.0
         } else {
             0.0
         };
 
         Ok(TaskStatistics {
-            total_tasks: total.0,
-            pending_tasks: pending.0,
-            in_progress_tasks: in_progress.0,
-            completed_tasks: completed.0,
-            cancelled_tasks: cancelled.0,
+            total_tasks: total,
+            pending_tasks: pending,
+            in_progress_tasks: in_progress,
+            completed_tasks: completed,
+            cancelled_tasks: cancelled,
             completion_rate,
         })
     }
 
     /// 获取项目统计
-    pub async fn get_project_statistics(&self) -> Result<ProjectStatistics, AppError> {
-        let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM projects")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let planning: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM projects WHERE status = 'planning'")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let active: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM projects WHERE status = 'active'")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let on_hold: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM projects WHERE status = 'on_hold'")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let completed: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM projects WHERE status = 'completed'")
-            .fetch_one(&self.db.pool)
-            .await
-            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    pub async fn get_project_statistics(&self, scope: StatisticsScope) -> Result<ProjectStatistics, AppError> {
+        let (filter, param) = scope.project_filter();
+        let sql = format!(
+            "SELECT COUNT(*), \
+                    COALESCE(SUM(p.status = 'planning'), 0), COALESCE(SUM(p.status = 'active'), 0), \
+                    COALESCE(SUM(p.status = 'on_hold'), 0), COALESCE(SUM(p.status = 'completed'), 0), \
+                    COALESCE(SUM(p.status = 'cancelled'), 0) \
+             FROM projects p WHERE {}",
+            filter
+        );
+
+        let mut query = sqlx::query_as::<_, (i64, i64, i64, i64, i64, i64)>(&sql);
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
 
-        let cancelled: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM projects WHERE status = 'cancelled'")
+        let (total, planning, active, on_hold, completed, cancelled) = query
             .fetch_one(&self.db.pool)
             .await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
         Ok(ProjectStatistics {
-            total_projects: total.0,
-            planning_projects: planning.0,
-            active_projects: active.0,
-            on_hold_projects: on_hold.0,
-            completed_projects: completed.0,
-            cancelled_projects: cancelled.0,
+            total_projects: total,
+            planning_projects: planning,
+            active_projects: active,
+            on_hold_projects: on_hold,
+            completed_projects: completed,
+            cancelled_projects: cancelled,
         })
     }
 
-    /// 获取员工工作量统计
-    pub async fn get_user_workload(&self, user_id: Uuid) -> Result<UserWorkloadStatistics, AppError> {
-        let assigned: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM tasks WHERE assigned_to = ?"
-        )
-        .bind(user_id)
-        .fetch_one(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let completed: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM tasks WHERE assigned_to = ? AND status = 'completed'"
-        )
-        .bind(user_id)
-        .fetch_one(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    /// 获取员工工作量统计(仅统计范围内的任务)
+    pub async fn get_user_workload(&self, user_id: i64, scope: StatisticsScope) -> Result<UserWorkloadStatistics, AppError> {
+        if let StatisticsScope::Assignee(own_id) = scope {
+            if own_id != user_id {
+                return Err(AppError::Forbidden);
+            }
+        }
 
-        let in_progress: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM tasks WHERE assigned_to = ? AND status = 'in_progress'"
-        )
-        .bind(user_id)
-        .fetch_one(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        if let Some(workload) = self.query_workloads(scope, Some(user_id)).await?.pop() {
+            return Ok(workload);
+        }
 
-        let hours_result: Option<(Option<f64>,)> = sqlx::query_as(
-            "SELECT SUM(actual_hours) FROM tasks WHERE assigned_to = ?"
+        let user_name: Option<(Option<String>,)> = sqlx::query_as(
+            "SELECT full_name FROM users WHERE id = ?"
         )
         .bind(user_id)
         .fetch_optional(&self.db.pool)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-        let total_hours = hours_result
-            .and_then(|r| r.0)
-            .unwrap_or(0.0);
-
         Ok(UserWorkloadStatistics {
             user_id,
-            user_name: None,
-            assigned_tasks: assigned.0,
-            completed_tasks: completed.0,
-            in_progress_tasks: in_progress.0,
-            total_hours,
+            user_name: user_name.and_then(|r| r.0),
+            assigned_tasks: 0,
+            completed_tasks: 0,
+            in_progress_tasks: 0,
+            total_hours: 0.0,
         })
     }
 
-    /// 获取项目进度统计
-    pub async fn get_project_progress(&self, project_id: Uuid) -> Result<ProjectProgressStatistics, AppError> {
-        // 获取项目名称
-        let project: (String,) = sqlx::query_as(
-            "SELECT name FROM projects WHERE id = ?"
-        )
-        .bind(project_id)
-        .fetch_one(&self.db.pool)
-        .await
-        .map_err(|_| AppError::NotFound("项目不存在".to_string()))?;
-
-        let total: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM tasks WHERE project_id = ?"
-        )
-        .bind(project_id)
-        .fetch_one(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let completed: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM tasks WHERE project_id = ? AND status = 'completed'"
-        )
-        .bind(project_id)
-        .fetch_one(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let in_progress: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM tasks WHERE project_id = ? AND status = 'in_progress'"
-        )
-        .bind(project_id)
-        .fetch_one(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let estimated_result: Option<(Option<f64>,)> = sqlx::query_as(
-            "SELECT SUM(estimated_hours) FROM tasks WHERE project_id = ?"
-        )
-        .bind(project_id)
-        .fetch_optional(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let actual_result: Option<(Option<f64>,)> = sqlx::query_as(
-            "SELECT SUM(actual_hours) FROM tasks WHERE project_id = ?"
-        )
-        .bind(project_id)
-        .fetch_optional(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    /// 获取项目进度统计(项目不在统计范围内时视为不存在)
+    ///
+    /// 任务执行者只统计分配给自己的任务,该维度没有预聚合,直接查询任务表
+    pub async fn get_project_progress(&self, project_id: Uuid, scope: StatisticsScope) -> Result<ProjectProgressStatistics, AppError> {
+        let (filter, param) = scope.project_filter();
+        let (sql, assignee) = match scope {
+            StatisticsScope::Assignee(user_id) => (
+                format!(
+                    "SELECT p.name, COUNT(t.id), \
+                            COALESCE(SUM(t.status = 'completed'), 0), COALESCE(SUM(t.status = 'in_progress'), 0), \
+                            TOTAL(t.estimated_hours), TOTAL(t.actual_hours) \
+                     FROM projects p LEFT JOIN tasks t ON t.project_id = p.id AND t.assigned_to = ? \
+                     WHERE p.id = ? AND {} GROUP BY p.id",
+                    filter
+                ),
+                Some(user_id),
+            ),
+            _ => (
+                format!(
+                    "SELECT p.name, \
+                            COALESCE(SUM(s.pending_delta + s.in_progress_delta + s.completed_delta + s.cancelled_delta), 0), \
+                            COALESCE(SUM(s.completed_delta), 0), COALESCE(SUM(s.in_progress_delta), 0), \
+                            TOTAL(s.estimated_hours_delta), TOTAL(s.actual_hours_delta) \
+                     FROM projects p LEFT JOIN stats_daily_project s ON s.project_id = p.id \
+                     WHERE p.id = ? AND {} GROUP BY p.id",
+                    filter
+                ),
+                None,
+            ),
+        };
 
-        let estimated_hours = estimated_result
-            .and_then(|r| r.0)
-            .unwrap_or(0.0);
+        let mut query = sqlx::query_as::<_, ProgressRow>(&sql);
+        if let Some(user_id) = assignee {
+            query = query.bind(user_id);
+        }
+        query = query.bind(project_id.to_string());
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
 
-        let actual_hours = actual_result
-            .and_then(|r| r.0)
-            .unwrap_or(0.0);
+        let (project_name, total, completed, in_progress, estimated_hours, actual_hours) = query
+            .fetch_optional(&self.db.pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .ok_or_else(|| AppError::NotFound("项目不存在".to_string()))?;
 
-        let progress = if total.0 > 0 {
-            (completed.0 as f64 / total.0 as f64) * 100.0
+        let progress = if total > 0 {
+            (completed as f64 / total as f64) * 100.0
         } else {
             0.0
         };
 
         Ok(ProjectProgressStatistics {
             project_id,
-            project_name: project.0,
-            total_tasks: total.0,
-            completed_tasks: completed.0,
-            in_progress_tasks: in_progress.0,
+            project_name,
+            total_tasks: total,
+            completed_tasks: completed,
+            in_progress_tasks: in_progress,
             progress,
             estimated_hours,
             actual_hours,
         })
     }
 
-    /// 获取所有员工工作量统计
-    pub async fn get_all_users_workload(&self) -> Result<Vec<UserWorkloadStatistics>, AppError> {
-        let user_ids: Vec<(Uuid,)> = sqlx::query_as(
-            "SELECT DISTINCT assigned_to FROM tasks WHERE assigned_to IS NOT NULL"
-        )
-        .fetch_all(&self.db.pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-        let mut workloads = Vec::new();
-        for (user_id,) in user_ids {
-            let workload = self.get_user_workload(user_id).await?;
-            workloads.push(workload);
-        }
-
-        Ok(workloads)
+    /// 获取统计范围内所有员工的工作量统计
+    pub async fn get_all_users_workload(&self, scope: StatisticsScope) -> Result<Vec<UserWorkloadStatistics>, AppError> {
+        self.query_workloads(scope, None).await
     }
 }
@@ -0,0 +1,406 @@
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    errors::AppError,
+    models::{TwoFactorChallenge, TwoFactorEnrollment, TwoFactorStatus, UserInfo, UserRole, Permission},
+    services::audit::{
+        AuditEvent, AuditService, ACTION_TWO_FACTOR_DISABLED, ACTION_TWO_FACTOR_ENABLED,
+        ACTION_TWO_FACTOR_POLICY_CHANGED,
+    },
+    utils::totp,
+    Database,
+};
+
+/// 验证器应用中显示的发行方
+const TOTP_ISSUER: &str = "Flow Farm";
+/// 每次生成的恢复码数量
+const RECOVERY_CODE_COUNT: usize = 10;
+/// 登录挑战有效期(秒)
+const CHALLENGE_EXPIRES_IN: i64 = 300;
+/// 同一登录挑战允许输错验证码的次数
+const CHALLENGE_MAX_ATTEMPTS: i64 = 5;
+/// 平台级策略在 system_settings 中的键: 要求所有用户启用两步验证
+const PLATFORM_POLICY_KEY: &str = "security.require_2fa";
+
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn sha256_hex(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+/// 恢复码不区分大小写,忽略分隔符和空格
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// 两步验证(TOTP)服务
+///
+/// 用户可自行启用;公司策略可要求项目经理启用,平台管理员可要求所有用户启用。
+/// 策略要求但尚未启用的用户在登录时会收到需要注册的挑战,完成注册后才能登录。
+pub struct TwoFactorService {
+    db: Database,
+}
+
+impl TwoFactorService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn status(&self, user: &UserInfo) -> Result<TwoFactorStatus, AppError> {
+        let recovery_codes_remaining = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM two_factor_recovery_codes WHERE user_id = ? AND used_at IS NULL",
+        )
+        .bind(user.id)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(TwoFactorStatus {
+            enabled: self.is_enabled(user.id).await?,
+            required: self.is_required(&user.role, user.company_id).await?,
+            recovery_codes_remaining,
+        })
+    }
+
+    pub async fn is_enabled(&self, user_id: i64) -> Result<bool, AppError> {
+        let enabled = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM user_two_factor WHERE user_id = ? AND enabled_at IS NOT NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&self.db.pool)
+        .await?;
+        Ok(enabled > 0)
+    }
+
+    /// 平台策略要求所有用户启用,或公司策略要求项目经理启用
+    pub async fn is_required(&self, role: &UserRole, company_id: Option<i64>) -> Result<bool, AppError> {
+        let platform = sqlx::query_scalar::<_, String>("SELECT value FROM system_settings WHERE key = ?")
+            .bind(PLATFORM_POLICY_KEY)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        if platform.as_deref() == Some("true") {
+            return Ok(true);
+        }
+
+        if *role != UserRole::ProjectManager {
+            return Ok(false);
+        }
+        let Some(company_id) = company_id else {
+            return Ok(false);
+        };
+        let company = sqlx::query_scalar::<_, bool>("SELECT require_2fa_for_managers FROM companies WHERE id = ?")
+            .bind(company_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+        Ok(company.unwrap_or(false))
+    }
+
+    /// 生成新密钥(覆盖尚未确认的密钥),已启用时需先关闭
+    pub async fn begin_enrollment(&self, user_id: i64, account: &str) -> Result<TwoFactorEnrollment, AppError> {
+        if self.is_enabled(user_id).await? {
+            return Err(AppError::Conflict("两步验证已启用".to_string()));
+        }
+
+        let secret = totp::generate_secret();
+        sqlx::query(
+            r#"
+            INSERT INTO user_two_factor (user_id, secret, enabled_at, last_used_step, created_at)
+            VALUES (?, ?, NULL, NULL, ?)
+            ON CONFLICT(user_id) DO UPDATE SET secret = excluded.secret, last_used_step = NULL, created_at = excluded.created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(&secret)
+        .bind(timestamp(Utc::now()))
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(TwoFactorEnrollment {
+            otpauth_uri: totp::provisioning_uri(&secret, account, TOTP_ISSUER),
+            secret,
+        })
+    }
+
+    /// 用验证码确认注册并启用,返回新生成的恢复码
+    pub async fn confirm_enrollment(&self, user_id: i64, code: &str) -> Result<Vec<String>, AppError> {
+        let pending = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT secret, enabled_at FROM user_two_factor WHERE user_id = ?",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let secret = match pending {
+            Some((secret, None)) => secret,
+            Some((_, Some(_))) => return Err(AppError::Conflict("两步验证已启用".to_string())),
+            None => return Err(AppError::InvalidState("请先生成两步验证密钥".to_string())),
+        };
+
+        let step = totp::verify_code(&secret, code, Utc::now().timestamp()).ok_or(AppError::InvalidTwoFactorCode)?;
+        sqlx::query("UPDATE user_two_factor SET enabled_at = ?, last_used_step = ? WHERE user_id = ?")
+            .bind(timestamp(Utc::now()))
+            .bind(step)
+            .bind(user_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        let codes = self.replace_recovery_codes(user_id).await?;
+        AuditService::new(self.db.clone())
+            .record(AuditEvent::new(ACTION_TWO_FACTOR_ENABLED).actor(user_id).target("user", user_id))
+            .await?;
+        Ok(codes)
+    }
+
+    /// 校验已启用用户的验证码或恢复码(恢复码使用后作废,同一验证码不能重复使用)
+    pub async fn verify(&self, user_id: i64, code: &str) -> Result<bool, AppError> {
+        let secret = sqlx::query_scalar::<_, String>(
+            "SELECT secret FROM user_two_factor WHERE user_id = ? AND enabled_at IS NOT NULL",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let Some(secret) = secret else {
+            return Ok(false);
+        };
+
+        if let Some(step) = totp::verify_code(&secret, code, Utc::now().timestamp()) {
+            // 条件更新保证同一时间步的验证码只能成功使用一次
+            let accepted = sqlx::query(
+                "UPDATE user_two_factor SET last_used_step = ? WHERE user_id = ? AND (last_used_step IS NULL OR last_used_step < ?)",
+            )
+            .bind(step)
+            .bind(user_id)
+            .bind(step)
+            .execute(&self.db.pool)
+            .await?
+            .rows_affected();
+            return Ok(accepted > 0);
+        }
+
+        let used = sqlx::query(
+            "UPDATE two_factor_recovery_codes SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL",
+        )
+        .bind(timestamp(Utc::now()))
+        .bind(user_id)
+        .bind(sha256_hex(&normalize_recovery_code(code)))
+        .execute(&self.db.pool)
+        .await?
+        .rows_affected();
+        Ok(used > 0)
+    }
+
+    /// 用户自行关闭两步验证(策略要求时不允许关闭)
+    pub async fn disable(&self, user: &UserInfo, code: &str) -> Result<(), AppError> {
+        if self.is_required(&user.role, user.company_id).await? {
+            return Err(AppError::OperationNotAllowed("安全策略要求启用两步验证".to_string()));
+        }
+        if !self.verify(user.id, code).await? {
+            return Err(AppError::InvalidTwoFactorCode);
+        }
+
+        self.remove(user.id).await?;
+        AuditService::new(self.db.clone())
+            .record(AuditEvent::new(ACTION_TWO_FACTOR_DISABLED).actor(user.id).target("user", user.id))
+            .await
+    }
+
+    /// 重新生成恢复码(旧恢复码全部作废)
+    pub async fn regenerate_recovery_codes(&self, user_id: i64, code: &str) -> Result<Vec<String>, AppError> {
+        if !self.verify(user_id, code).await? {
+            return Err(AppError::InvalidTwoFactorCode);
+        }
+        self.replace_recovery_codes(user_id).await
+    }
+
+    /// 管理员为丢失验证器和恢复码的用户重置两步验证,用户下次登录时按策略重新注册
+    pub async fn reset_for_user(&self, user_id: i64, current_user: &UserInfo) -> Result<(), AppError> {
+        let company_id = sqlx::query_scalar::<_, Option<i64>>("SELECT company_id FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+            .ok_or_else(|| AppError::UserNotFound(user_id.to_string()))?;
+        if !current_user.can(Permission::UserSecurity, company_id) {
+            return Err(AppError::MissingPermission(Permission::UserSecurity));
+        }
+
+        self.remove(user_id).await?;
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_TWO_FACTOR_DISABLED)
+                    .actor(current_user.id)
+                    .company(company_id)
+                    .target("user", user_id)
+                    .details(json!({ "reset_by_admin": true })),
+            )
+            .await
+    }
+
+    /// 设置公司策略: 是否要求本公司项目经理启用两步验证
+    pub async fn set_company_policy(
+        &self,
+        company_id: i64,
+        required: bool,
+        current_user: &UserInfo,
+    ) -> Result<(), AppError> {
+        if !current_user.can(Permission::CompanySettings, Some(company_id)) {
+            return Err(AppError::MissingPermission(Permission::CompanySettings));
+        }
+
+        let updated = sqlx::query("UPDATE companies SET require_2fa_for_managers = ? WHERE id = ?")
+            .bind(required)
+            .bind(company_id)
+            .execute(&self.db.pool)
+            .await?
+            .rows_affected();
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("公司不存在: {}", company_id)));
+        }
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_TWO_FACTOR_POLICY_CHANGED)
+                    .actor(current_user.id)
+                    .company(Some(company_id))
+                    .target("company", company_id)
+                    .details(json!({ "require_for_managers": required })),
+            )
+            .await
+    }
+
+    /// 设置平台策略: 是否要求所有用户启用两步验证(仅平台管理员)
+    pub async fn set_platform_policy(&self, required: bool, current_user: &UserInfo) -> Result<(), AppError> {
+        if !current_user.has_permission(Permission::PlatformManage) {
+            return Err(AppError::MissingPermission(Permission::PlatformManage));
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO system_settings (key, value, description, updated_at) VALUES (?, ?, '要求所有用户启用两步验证', ?)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(PLATFORM_POLICY_KEY)
+        .bind(required.to_string())
+        .bind(timestamp(Utc::now()))
+        .execute(&self.db.pool)
+        .await?;
+
+        AuditService::new(self.db.clone())
+            .record(
+                AuditEvent::new(ACTION_TWO_FACTOR_POLICY_CHANGED)
+                    .actor(current_user.id)
+                    .target("platform", "all_users")
+                    .details(json!({ "require_for_all": required })),
+            )
+            .await
+    }
+
+    /// 为密码校验通过的用户创建登录挑战
+    pub async fn create_challenge(&self, user_id: i64, enrollment_required: bool) -> Result<TwoFactorChallenge, AppError> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let challenge_token = hex::encode(bytes);
+        let now = Utc::now();
+
+        sqlx::query("INSERT INTO login_challenges (token_hash, user_id, created_at, expires_at) VALUES (?, ?, ?, ?)")
+            .bind(sha256_hex(&challenge_token))
+            .bind(user_id)
+            .bind(timestamp(now))
+            .bind(timestamp(now + Duration::seconds(CHALLENGE_EXPIRES_IN)))
+            .execute(&self.db.pool)
+            .await?;
+
+        Ok(TwoFactorChallenge {
+            challenge_token,
+            expires_in: CHALLENGE_EXPIRES_IN,
+            enrollment_required,
+        })
+    }
+
+    /// 登录挑战对应的用户
+    pub async fn challenge_user(&self, challenge_token: &str) -> Result<i64, AppError> {
+        let (user_id, expires_at) = sqlx::query_as::<_, (i64, String)>(
+            "SELECT user_id, expires_at FROM login_challenges WHERE token_hash = ?",
+        )
+        .bind(sha256_hex(challenge_token))
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or(AppError::TokenInvalid)?;
+
+        if expires_at <= timestamp(Utc::now()) {
+            self.consume_challenge(challenge_token).await?;
+            return Err(AppError::TokenExpired);
+        }
+        Ok(user_id)
+    }
+
+    /// 记录一次验证码错误,次数用尽后挑战作废,需要重新输入密码
+    pub async fn record_challenge_failure(&self, challenge_token: &str) -> Result<(), AppError> {
+        let token_hash = sha256_hex(challenge_token);
+        sqlx::query("UPDATE login_challenges SET attempts = attempts + 1 WHERE token_hash = ?")
+            .bind(&token_hash)
+            .execute(&self.db.pool)
+            .await?;
+        sqlx::query("DELETE FROM login_challenges WHERE token_hash = ? AND attempts >= ?")
+            .bind(&token_hash)
+            .bind(CHALLENGE_MAX_ATTEMPTS)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn consume_challenge(&self, challenge_token: &str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM login_challenges WHERE token_hash = ?")
+            .bind(sha256_hex(challenge_token))
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(&self, user_id: i64) -> Result<Vec<String>, AppError> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query("DELETE FROM two_factor_recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for code in &codes {
+            sqlx::query("INSERT INTO two_factor_recovery_codes (user_id, code_hash) VALUES (?, ?)")
+                .bind(user_id)
+                .bind(sha256_hex(&normalize_recovery_code(code)))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(codes)
+    }
+
+    async fn remove(&self, user_id: i64) -> Result<(), AppError> {
+        let mut tx = self.db.pool.begin().await?;
+        sqlx::query("DELETE FROM user_two_factor WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM two_factor_recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
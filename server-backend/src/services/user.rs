@@ -1,8 +1,13 @@
 use anyhow::{anyhow, Result};
 
 use crate::{
-    models::{CreateUserRequest, UpdateUserRequest, User, UserInfo, UserRole},
+    middleware::client::ClientInfo,
+    models::{CreateUserRequest, Permission, UpdateUserRequest, User, UserInfo},
     repositories::UserRepository,
+    services::{
+        audit::{self, AuditEvent, AuditService, ACTION_USER_CREATED, ACTION_USER_UPDATED},
+        invitation::ensure_seat_available,
+    },
     utils::hash_password,
     Database,
 };
@@ -25,22 +30,17 @@ impl UserService {
         &self,
         current_user: &UserInfo,
     ) -> Result<Vec<UserInfo>> {
-        // 根据角色返回不同范围的用户列表
-        let users = match current_user.role {
+        // 根据权限返回不同范围的用户列表
+        let users = if current_user.has_permission(Permission::PlatformManage) {
             // 平台管理员可以查看所有用户
-            UserRole::PlatformAdmin => {
-                self.user_repository.list_all_hierarchy().await?
-            }
-            // 项目经理只能查看本公司用户
-            UserRole::ProjectManager => {
-                let company_id = current_user.company_id
-                    .ok_or_else(|| anyhow!("项目经理必须关联公司"))?;
-                self.user_repository.list_by_company_id(company_id).await?
-            }
-            // 任务执行者不能查看用户列表
-            UserRole::TaskExecutor => {
-                return Err(anyhow!("权限不足：任务执行者无法查看用户列表"));
-            }
+            self.user_repository.list_all_hierarchy().await?
+        } else if current_user.has_permission(Permission::UserView) {
+            // 其他有查看权限的用户只能查看本公司用户
+            let company_id = current_user.company_id
+                .ok_or_else(|| anyhow!("查看用户列表必须关联公司"))?;
+            self.user_repository.list_by_company_id(company_id).await?
+        } else {
+            return Err(anyhow!("权限不足：无法查看用户列表"));
         };
 
         Ok(users.into_iter().map(|user| user.into()).collect())
@@ -54,22 +54,9 @@ impl UserService {
         let user = self.user_repository.find_by_id(user_id).await?
             .ok_or_else(|| anyhow!("用户不存在"))?;
 
-        // 权限检查
-        match current_user.role {
-            // 平台管理员可以查看所有用户
-            UserRole::PlatformAdmin => {}
-            // 项目经理只能查看本公司用户
-            UserRole::ProjectManager => {
-                if user.company_id != current_user.company_id {
-                    return Err(anyhow!("权限不足：只能查看本公司用户"));
-                }
-            }
-            // 任务执行者只能查看自己
-            UserRole::TaskExecutor => {
-                if user.id != current_user.id {
-                    return Err(anyhow!("权限不足：只能查看自己的信息"));
-                }
-            }
+        // 权限检查:本人之外需要本公司的查看权限
+        if user.id != current_user.id && !current_user.can(Permission::UserView, user.company_id) {
+            return Err(anyhow!("权限不足：只能查看本公司用户"));
         }
 
         Ok(user.into())
@@ -79,27 +66,25 @@ impl UserService {
         &self,
         request: CreateUserRequest,
         current_user: &UserInfo,
+        client: &ClientInfo,
     ) -> Result<UserInfo> {
-        // 只有管理员可以创建用户
-        let (company_id, parent_id) = match current_user.role {
-            UserRole::PlatformAdmin => {
-                // 平台管理员可以创建任何用户,使用请求中的company_id
-                (request.company_id, request.parent_id)
-            }
-            UserRole::ProjectManager => {
-                // 项目经理只能创建任务执行者,且必须在自己公司
-                if request.role != UserRole::TaskExecutor {
-                    return Err(anyhow!("权限不足：项目经理只能创建任务执行者账号"));
-                }
-                let company_id = current_user.company_id
-                    .ok_or_else(|| anyhow!("项目经理必须关联公司"))?;
-                (Some(company_id), Some(current_user.id))
-            }
-            UserRole::TaskExecutor => {
-                return Err(anyhow!("权限不足：任务执行者无法创建用户"));
-            }
+        let (company_id, parent_id) = if current_user.has_permission(Permission::PlatformManage) {
+            // 平台管理员可以创建任何用户,使用请求中的company_id
+            (request.company_id, request.parent_id)
+        } else if current_user.has_permission(Permission::UserCreate) {
+            // 其他管理者只能在自己公司创建用户
+            let company_id = current_user.company_id
+                .ok_or_else(|| anyhow!("创建用户必须关联公司"))?;
+            (Some(company_id), Some(current_user.id))
+        } else {
+            return Err(anyhow!("权限不足：无法创建用户"));
         };
 
+        // 只能创建权限比自己少的用户(项目经理只能创建任务执行者)
+        if !current_user.permissions.can_grant(&request.role.permissions()) {
+            return Err(anyhow!("权限不足：只能创建权限比自己少的账号"));
+        }
+
         // 检查用户名和邮箱是否已存在
         if self.user_repository.find_by_username(&request.username).await?.is_some() {
             return Err(anyhow!("用户名已存在"));
@@ -109,6 +94,11 @@ impl UserService {
             return Err(anyhow!("邮箱已存在"));
         }
 
+        // 待接受的邀请同样占用名额
+        if let Some(company_id) = company_id {
+            ensure_seat_available(&mut *self.database.pool.acquire().await?, company_id, true).await?;
+        }
+
         // 创建用户
         let hashed_password = hash_password(&request.password)?;
         let now = chrono::Utc::now();
@@ -126,22 +116,37 @@ impl UserService {
             created_at: now,
             updated_at: now,
             last_login: None,
+            custom_role_id: None,
         };
 
         let created_user = self.user_repository.create(new_user).await?;
 
-        Ok(UserInfo {
+        let info = UserInfo {
             id: created_user.id,
             username: request.username,
             email: request.email,
             full_name: request.full_name,
+            permissions: request.role.permissions(),
             role: request.role,
             is_active: true,
             company_id,
             parent_id,
             created_at: now.format("%Y-%m-%d %H:%M:%S").to_string(),
             last_login: None,
-        })
+            custom_role_id: None,
+        };
+        AuditService::new(self.database.clone())
+            .record(
+                AuditEvent::new(ACTION_USER_CREATED)
+                    .actor(current_user.id)
+                    .company(company_id)
+                    .target("user", info.id)
+                    .client(client)
+                    .details(audit::diff(None, Some(&info))),
+            )
+            .await?;
+
+        Ok(info)
     }
 
     pub async fn update_user(
@@ -149,25 +154,16 @@ impl UserService {
         user_id: i64,
         request: UpdateUserRequest,
         current_user: &UserInfo,
+        client: &ClientInfo,
     ) -> Result<UserInfo> {
         let mut user = self.user_repository.find_by_id(user_id).await?
             .ok_or_else(|| anyhow!("用户不存在"))?;
+        let before = UserInfo::from(user.clone());
 
-        // 权限检查
-        match current_user.role {
-            UserRole::PlatformAdmin => {}  // 平台管理员可以更新所有用户
-            UserRole::ProjectManager => {
-                // 项目经理只能更新本公司用户
-                if user.company_id != current_user.company_id {
-                    return Err(anyhow!("权限不足：只能更新本公司用户"));
-                }
-            }
-            UserRole::TaskExecutor => {
-                // 任务执行者只能更新自己
-                if user.id != current_user.id {
-                    return Err(anyhow!("权限不足：只能更新自己的信息"));
-                }
-            }
+        // 权限检查:本人之外需要本公司的修改权限
+        let can_update = current_user.can(Permission::UserUpdate, user.company_id);
+        if user.id != current_user.id && !can_update {
+            return Err(anyhow!("权限不足：只能更新本公司用户"));
         }
 
         // 更新字段
@@ -191,18 +187,20 @@ impl UserService {
             user.email = email;
         }
 
-        if let Some(password) = request.password {
-            user.hashed_password = hash_password(&password)?;
-        }
+        // 密码由 PasswordService 单独设置(需校验密码策略并使会话失效),这里不处理
 
         if let Some(full_name) = request.full_name {
             user.full_name = full_name;
         }
 
         if let Some(is_active) = request.is_active {
-            // 只有管理员可以更改用户状态
-            if current_user.role == UserRole::TaskExecutor {
-                return Err(anyhow!("权限不足：任务执行者无法更改用户状态"));
+            // 只有管理者可以更改用户状态
+            if !can_update {
+                return Err(anyhow!("权限不足：无法更改用户状态"));
+            }
+            // 重新启用的账号重新占用名额
+            if let (true, false, Some(company_id)) = (is_active, user.is_active, user.company_id) {
+                ensure_seat_available(&mut *self.database.pool.acquire().await?, company_id, true).await?;
             }
             user.is_active = is_active;
         }
@@ -211,40 +209,21 @@ impl UserService {
 
         self.user_repository.update(user.clone()).await?;
 
-        Ok(user.into())
-    }
-
-    pub async fn delete_user(
-        &self,
-        user_id: i64,
-        current_user: &UserInfo,
-    ) -> Result<()> {
-        // 不能删除自己
-        if current_user.id == user_id {
-            return Err(anyhow!("不能删除自己的账户"));
+        let after = UserInfo::from(user);
+        let changes = audit::diff(Some(&before), Some(&after));
+        if changes.as_object().is_some_and(|changes| !changes.is_empty()) {
+            AuditService::new(self.database.clone())
+                .record(
+                    AuditEvent::new(ACTION_USER_UPDATED)
+                        .actor(current_user.id)
+                        .company(after.company_id)
+                        .target("user", user_id)
+                        .client(client)
+                        .details(changes),
+                )
+                .await?;
         }
 
-        // 验证用户是否存在
-        let user = self.user_repository.find_by_id(user_id).await?
-            .ok_or_else(|| anyhow!("用户不存在"))?;
-
-        // 权限检查
-        match current_user.role {
-            UserRole::PlatformAdmin => {}  // 平台管理员可以删除任何用户
-            UserRole::ProjectManager => {
-                // 项目经理只能删除本公司用户
-                if user.company_id != current_user.company_id {
-                    return Err(anyhow!("权限不足：只能删除本公司用户"));
-                }
-            }
-            UserRole::TaskExecutor => {
-                return Err(anyhow!("权限不足：任务执行者无法删除用户"));
-            }
-        }
-
-        // 删除用户
-        self.user_repository.delete(user_id).await?;
-
-        Ok(())
+        Ok(after)
     }
 }
\ No newline at end of file
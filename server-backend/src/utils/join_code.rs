@@ -0,0 +1,19 @@
+use rand::Rng;
+
+/// 加入码字符集,去掉了容易混淆的 0/O、1/I/L
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+/// 加入码长度
+pub const JOIN_CODE_LEN: usize = 10;
+
+/// 生成新的公司加入码
+pub fn generate_join_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..JOIN_CODE_LEN)
+        .map(|_| JOIN_CODE_ALPHABET[rng.gen_range(0..JOIN_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// 加入码不区分大小写,忽略首尾空白
+pub fn normalize_join_code(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
@@ -1,7 +1,27 @@
-use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Instant;
+
+use anyhow::{Context, Result, anyhow, bail};
 use chrono::{Utc, Duration};
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{encode, decode, decode_header, Algorithm, Header, Validation, EncodingKey, DecodingKey};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use simple_asn1::ASN1Block;
+
+use crate::Config;
+
+/// 密钥目录的重新加载间隔,轮换密钥无需重启服务
+const KEY_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 已加载的密钥及加载时间
+type LoadedKeys = (Instant, Arc<JwtKeys>);
+
+/// 已加载的签名密钥,按算法、目录和签名密钥ID缓存
+static KEY_RINGS: LazyLock<Mutex<HashMap<String, LoadedKeys>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -9,50 +29,269 @@ pub struct Claims {
     pub role: String, // 用户角色
     pub exp: i64,    // 过期时间
     pub iat: i64,    // 签发时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>, // 登录会话ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>, // 模拟登录时实际操作的管理员
 }
 
-pub fn create_jwt_token(
-    user_id: &str,
-    role: &str,  // 改为String引用
-    secret: &str,
-    expires_in: i64,
-) -> Result<String> {
-    let now = Utc::now();
-    let exp = now + Duration::seconds(expires_in);
-
-    let claims = Claims {
-        sub: user_id.to_string(),
-        role: role.to_string(),  // 直接转换为String
-        exp: exp.timestamp(),
-        iat: now.timestamp(),
-    };
+/// 实际操作人(RFC 8693 `act` 声明)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Actor {
+    pub sub: String,
+}
+
+impl Claims {
+    /// 模拟登录的管理员ID,普通令牌为空
+    pub fn impersonator_id(&self) -> Option<i64> {
+        self.act.as_ref().and_then(|actor| actor.sub.parse().ok())
+    }
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )?;
+    fn new(user_id: &str, role: &str, session_id: Option<&str>, expires_in: i64) -> Self {
+        let now = Utc::now();
+        let exp = now + Duration::seconds(expires_in);
 
-    Ok(token)
+        Self {
+            sub: user_id.to_string(),
+            role: role.to_string(),  // 直接转换为String
+            exp: exp.timestamp(),
+            iat: now.timestamp(),
+            sid: session_id.map(str::to_string),
+            act: None,
+        }
+    }
+}
+
+/// 访问令牌的签名与验证密钥
+///
+/// `jwt_algorithm=HS256` 时使用 `jwt_secret`;RS256/EdDSA 从 `jwt_keys_dir` 加载密钥对,
+/// `<kid>.key.pem` 为私钥(PKCS#8),`<kid>.pub.pem` 为公钥(SPKI)。
+/// 目录中的公钥都可用于验证,并通过 JWKS 公开;签名使用 `jwt_signing_kid` 指定的私钥,
+/// 未指定时使用文件名排序最后的私钥。轮换时放入新密钥对并切换签名密钥,
+/// 旧令牌全部过期后再删除旧公钥。
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    signing_kid: Option<String>,
+    encoding_key: EncodingKey,
+    decoding_keys: HashMap<String, DecodingKey>,
+    jwks: Vec<Value>,
 }
 
-pub fn decode_jwt_token(token: &str, secret: &str) -> Result<Claims> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
+impl JwtKeys {
+    pub fn from_config(config: &Config) -> Result<Arc<Self>> {
+        let algorithm = match config.jwt_algorithm.to_uppercase().as_str() {
+            "HS256" => return Ok(Arc::new(Self::hmac(&config.jwt_secret))),
+            "RS256" => Algorithm::RS256,
+            "EDDSA" => Algorithm::EdDSA,
+            other => bail!("不支持的 JWT 签名算法: {}(可选 HS256 / RS256 / EdDSA)", other),
+        };
+        let dir = config
+            .jwt_keys_dir
+            .as_deref()
+            .ok_or_else(|| anyhow!("JWT_ALGORITHM={} 时必须配置 JWT_KEYS_DIR", config.jwt_algorithm))?;
+        let signing_kid = config.jwt_signing_kid.as_deref();
+
+        let cache_key = format!("{:?}|{}|{}", algorithm, dir, signing_kid.unwrap_or_default());
+        let mut cache = KEY_RINGS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((loaded_at, keys)) = cache.get(&cache_key) {
+            if loaded_at.elapsed() < KEY_RELOAD_INTERVAL {
+                return Ok(keys.clone());
+            }
+        }
+
+        match Self::load(algorithm, Path::new(dir), signing_kid) {
+            Ok(keys) => {
+                let keys = Arc::new(keys);
+                cache.insert(cache_key, (Instant::now(), keys.clone()));
+                Ok(keys)
+            }
+            // 轮换过程中目录暂时不完整时继续使用上次加载的密钥
+            Err(e) => match cache.get_mut(&cache_key) {
+                Some((loaded_at, keys)) => {
+                    tracing::warn!("重新加载 JWT 密钥失败,继续使用原有密钥: {:#}", e);
+                    *loaded_at = Instant::now();
+                    Ok(keys.clone())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn hmac(secret: &str) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            signing_kid: None,
+            encoding_key: EncodingKey::from_secret(secret.as_ref()),
+            decoding_keys: HashMap::from([(String::new(), DecodingKey::from_secret(secret.as_ref()))]),
+            jwks: Vec::new(),
+        }
+    }
+
+    fn load(algorithm: Algorithm, dir: &Path, signing_kid: Option<&str>) -> Result<Self> {
+        let mut private_keys = BTreeMap::new();
+        let mut public_keys = BTreeMap::new();
+        let entries = std::fs::read_dir(dir).with_context(|| format!("无法读取 JWT 密钥目录 {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if let Some(kid) = name.strip_suffix(".key.pem") {
+                private_keys.insert(kid.to_string(), path.clone());
+            } else if let Some(kid) = name.strip_suffix(".pub.pem") {
+                public_keys.insert(kid.to_string(), path.clone());
+            }
+        }
+
+        let signing_kid = match signing_kid {
+            Some(kid) => kid.to_string(),
+            None => private_keys
+                .keys()
+                .next_back()
+                .cloned()
+                .ok_or_else(|| anyhow!("JWT 密钥目录 {} 中没有私钥(<kid>.key.pem)", dir.display()))?,
+        };
+        let private_path = private_keys
+            .get(&signing_kid)
+            .ok_or_else(|| anyhow!("找不到签名私钥 {}.key.pem", signing_kid))?;
+        if !public_keys.contains_key(&signing_kid) {
+            bail!("签名密钥 {} 缺少公钥 {}.pub.pem", signing_kid, signing_kid);
+        }
 
-    Ok(token_data.claims)
+        let private_pem = std::fs::read(private_path)?;
+        let encoding_key = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(&private_pem),
+            _ => EncodingKey::from_ed_pem(&private_pem),
+        }
+        .with_context(|| format!("私钥 {} 格式错误", private_path.display()))?;
+
+        let mut decoding_keys = HashMap::new();
+        let mut jwks = Vec::new();
+        for (kid, path) in &public_keys {
+            let public_pem = std::fs::read(path)?;
+            let decoding_key = match algorithm {
+                Algorithm::RS256 => DecodingKey::from_rsa_pem(&public_pem),
+                _ => DecodingKey::from_ed_pem(&public_pem),
+            }
+            .with_context(|| format!("公钥 {} 格式错误", path.display()))?;
+            jwks.push(public_jwk(algorithm, kid, &public_pem).with_context(|| format!("公钥 {} 格式错误", path.display()))?);
+            decoding_keys.insert(kid.clone(), decoding_key);
+        }
+
+        let keys = Self {
+            algorithm,
+            signing_kid: Some(signing_kid),
+            encoding_key,
+            decoding_keys,
+            jwks,
+        };
+        // 私钥和同名公钥不匹配时,签发的令牌无法通过验证
+        let probe = keys.sign(&Claims::new("0", "probe", None, 60))?;
+        keys.decode(&probe).context("签名私钥与同名公钥不匹配")?;
+
+        Ok(keys)
+    }
+
+    /// 生成关联登录会话的访问令牌
+    pub fn create_session_token(&self, user_id: &str, role: &str, session_id: &str, expires_in: i64) -> Result<String> {
+        self.sign(&Claims::new(user_id, role, Some(session_id), expires_in))
+    }
+
+    /// 模拟登录令牌: `sub` 为被模拟的用户,`act.sub` 为实际操作的管理员
+    pub fn create_impersonation_token(
+        &self,
+        user_id: &str,
+        role: &str,
+        session_id: &str,
+        impersonator_id: &str,
+        expires_in: i64,
+    ) -> Result<String> {
+        let mut claims = Claims::new(user_id, role, Some(session_id), expires_in);
+        claims.act = Some(Actor {
+            sub: impersonator_id.to_string(),
+        });
+        self.sign(&claims)
+    }
+
+    fn sign(&self, claims: &Claims) -> Result<String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.signing_kid.clone();
+
+        Ok(encode(&header, claims, &self.encoding_key)?)
+    }
+
+    /// 验证令牌: 只接受配置的签名算法,非对称签名按 `kid` 选择公钥
+    pub fn decode(&self, token: &str) -> Result<Claims> {
+        let header = decode_header(token)?;
+        if header.alg != self.algorithm {
+            bail!("令牌签名算法 {:?} 与配置不符", header.alg);
+        }
+        let kid = match self.algorithm {
+            Algorithm::HS256 => Some(""),
+            _ => header.kid.as_deref(),
+        };
+        let key = kid
+            .and_then(|kid| self.decoding_keys.get(kid))
+            .ok_or_else(|| anyhow!("未知的签名密钥"))?;
+
+        Ok(decode::<Claims>(token, key, &Validation::new(self.algorithm))?.claims)
+    }
+
+    /// 公开的验证公钥(JWK Set),HS256 时为空
+    pub fn jwks(&self) -> Value {
+        json!({ "keys": self.jwks })
+    }
 }
 
-pub fn verify_jwt_token(token: &str, secret: &str) -> Result<bool> {
-    let claims = decode_jwt_token(token, secret)?;
-    let now = Utc::now().timestamp();
+fn public_jwk(algorithm: Algorithm, kid: &str, public_pem: &[u8]) -> Result<Value> {
+    let pem = pem::parse(public_pem)?;
+    // PKCS#1 格式的 RSA 公钥直接是 RSAPublicKey,SPKI 格式需要取出其中的公钥
+    let public_key = match pem.tag() {
+        "RSA PUBLIC KEY" => pem.contents().to_vec(),
+        _ => spki_public_key(pem.contents())?,
+    };
 
-    if claims.exp < now {
-        return Err(anyhow!("Token已过期"));
+    match algorithm {
+        Algorithm::RS256 => {
+            let blocks = simple_asn1::from_der(&public_key).map_err(|e| anyhow!("RSA 公钥解析失败: {}", e))?;
+            let [ASN1Block::Sequence(_, fields)] = blocks.as_slice() else {
+                bail!("RSA 公钥格式错误");
+            };
+            let [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] = fields.as_slice() else {
+                bail!("RSA 公钥格式错误");
+            };
+            Ok(json!({
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": kid,
+                "n": BASE64URL_NOPAD.encode(&n.to_bytes_be().1),
+                "e": BASE64URL_NOPAD.encode(&e.to_bytes_be().1),
+            }))
+        }
+        _ => {
+            if public_key.len() != 32 {
+                bail!("Ed25519 公钥长度错误");
+            }
+            Ok(json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "use": "sig",
+                "alg": "EdDSA",
+                "kid": kid,
+                "x": BASE64URL_NOPAD.encode(&public_key),
+            }))
+        }
     }
+}
 
-    Ok(true)
+/// 从 SubjectPublicKeyInfo 中取出公钥
+fn spki_public_key(der: &[u8]) -> Result<Vec<u8>> {
+    let blocks = simple_asn1::from_der(der).map_err(|e| anyhow!("公钥解析失败: {}", e))?;
+    if let [ASN1Block::Sequence(_, fields)] = blocks.as_slice() {
+        if let [_, ASN1Block::BitString(_, _, public_key)] = fields.as_slice() {
+            return Ok(public_key.clone());
+        }
+    }
+    bail!("公钥格式错误,需要 PEM 编码的 SPKI 公钥")
 }
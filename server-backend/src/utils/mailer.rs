@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use futures::future::BoxFuture;
+use lettre::{
+    message::{
+        header::{ContentTransferEncoding, ContentType},
+        Mailbox, SinglePart,
+    },
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use uuid::Uuid;
+
+use crate::{errors::AppError, Config};
+
+/// 待发送的纯文本邮件
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// 邮件发送方式
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, Result<(), AppError>>;
+}
+
+/// 按配置创建邮件发送方式
+pub fn mailer_from_config(config: &Config) -> Result<Box<dyn Mailer>, AppError> {
+    match config.mailer.as_str() {
+        "smtp" => Ok(Box::new(SmtpMailer::new(config)?)),
+        "file" => Ok(Box::new(FileMailer::new(config)?)),
+        "log" => Ok(Box::new(LogMailer)),
+        other => Err(AppError::Internal(format!("不支持的邮件发送方式: {}", other))),
+    }
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, AppError> {
+    address
+        .parse()
+        .map_err(|e| AppError::InvalidInput(format!("邮件地址无效 {}: {}", address, e)))
+}
+
+/// 正文统一用 base64 传输编码,避免中文和长链接被折行
+fn build_message(from: &Mailbox, message: &EmailMessage) -> Result<Message, AppError> {
+    Message::builder()
+        .from(from.clone())
+        .to(parse_mailbox(&message.to)?)
+        .subject(message.subject.clone())
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_PLAIN)
+                .header(ContentTransferEncoding::Base64)
+                .body(message.body.clone()),
+        )
+        .map_err(|e| AppError::Internal(format!("构建邮件失败: {}", e)))
+}
+
+/// 通过SMTP服务器发送
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &Config) -> Result<Self, AppError> {
+        let host = config.smtp_host.as_str();
+        let builder = match config.smtp_tls.as_str() {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            "none" => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)),
+            other => return Err(AppError::Internal(format!("不支持的SMTP加密方式: {}", other))),
+        }
+        .map_err(|e| AppError::Internal(format!("SMTP配置无效: {}", e)))?;
+
+        let mut builder = builder.port(config.smtp_port);
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: parse_mailbox(&config.mail_from)?,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let email = build_message(&self.from, message)?;
+            self.transport
+                .send(email)
+                .await
+                .map_err(|e| AppError::ServiceUnavailable(format!("邮件发送失败: {}", e)))?;
+            Ok(())
+        })
+    }
+}
+
+/// 开发用: 把邮件写成 .eml 文件
+pub struct FileMailer {
+    dir: PathBuf,
+    from: Mailbox,
+}
+
+impl FileMailer {
+    pub fn new(config: &Config) -> Result<Self, AppError> {
+        Ok(Self {
+            dir: PathBuf::from(&config.mail_dir),
+            from: parse_mailbox(&config.mail_from)?,
+        })
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let email = build_message(&self.from, message)?;
+            let path = self.dir.join(format!(
+                "{}-{}.eml",
+                Utc::now().format("%Y%m%d%H%M%S"),
+                Uuid::new_v4().simple()
+            ));
+
+            tokio::fs::create_dir_all(&self.dir)
+                .await
+                .map_err(|e| AppError::Internal(format!("创建邮件目录失败: {}", e)))?;
+            tokio::fs::write(&path, email.formatted())
+                .await
+                .map_err(|e| AppError::Internal(format!("写入邮件失败: {}", e)))?;
+
+            tracing::info!("📧 邮件已写入 {} (收件人: {})", path.display(), message.to);
+            Ok(())
+        })
+    }
+}
+
+/// 开发用: 只把邮件内容输出到日志
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            tracing::info!(
+                "📧 邮件(未发送) 收件人: {} 主题: {}\n{}",
+                message.to,
+                message.subject,
+                message.body
+            );
+            Ok(())
+        })
+    }
+}
@@ -1,4 +1,9 @@
+pub mod join_code;
 pub mod jwt;
+pub mod mailer;
+pub mod oidc;
 pub mod password;
+pub mod pii;
+pub mod totp;
 
-pub use password::{hash_password, verify_password};
+pub use password::{hash_password, verify_password, PasswordAlgorithm, PasswordHasher};
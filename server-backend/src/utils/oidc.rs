@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::errors::AppError;
+
+/// 访问身份提供方的超时时间
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 身份提供方的发现文档(只取用到的字段)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// 已校验的 ID Token 声明
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub preferred_username: Option<String>,
+    /// 其余声明,用于按配置的声明映射角色
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl IdTokenClaims {
+    /// 读取字符串或字符串数组类型的声明
+    pub fn claim_values(&self, name: &str) -> Vec<String> {
+        match self.extra.get(name) {
+            Some(Value::String(value)) => vec![value.clone()],
+            Some(Value::Array(values)) => values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// 生成随机值(state、nonce、code_verifier),32字节 base64url
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE64URL_NOPAD.encode(&bytes)
+}
+
+/// PKCE S256: code_challenge = BASE64URL(SHA256(code_verifier))
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn http_client() -> Result<reqwest::Client, AppError> {
+    reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::Internal(format!("创建HTTP客户端失败: {}", e)))
+}
+
+fn provider_error(e: impl std::fmt::Display) -> AppError {
+    AppError::ServiceUnavailable(format!("身份提供方请求失败: {}", e))
+}
+
+/// 读取发现文档,文档中的 issuer 必须与配置一致
+pub async fn discover(issuer: &str) -> Result<ProviderMetadata, AppError> {
+    let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+    let metadata: ProviderMetadata = http_client()?
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(provider_error)?
+        .json()
+        .await
+        .map_err(provider_error)?;
+
+    if metadata.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        return Err(AppError::ServiceUnavailable(format!(
+            "身份提供方的 issuer 与配置不一致: {}",
+            metadata.issuer
+        )));
+    }
+    Ok(metadata)
+}
+
+/// 授权请求参数
+pub struct AuthorizationRequest<'a> {
+    pub client_id: &'a str,
+    pub redirect_uri: &'a str,
+    pub scopes: &'a str,
+    pub state: &'a str,
+    pub nonce: &'a str,
+    pub code_verifier: &'a str,
+}
+
+/// 拼接跳转到身份提供方的授权地址
+pub fn authorization_url(metadata: &ProviderMetadata, request: &AuthorizationRequest<'_>) -> String {
+    let params = [
+        ("response_type", "code"),
+        ("client_id", request.client_id),
+        ("redirect_uri", request.redirect_uri),
+        ("scope", request.scopes),
+        ("state", request.state),
+        ("nonce", request.nonce),
+        ("code_challenge", &pkce_challenge(request.code_verifier)),
+        ("code_challenge_method", "S256"),
+    ]
+    .iter()
+    .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+    .collect::<Vec<_>>()
+    .join("&");
+    let separator = if metadata.authorization_endpoint.contains('?') { '&' } else { '?' };
+
+    format!("{}{}{}", metadata.authorization_endpoint, separator, params)
+}
+
+/// 用授权码换取 ID Token(client_secret_post;公开客户端只依赖 PKCE)
+pub async fn exchange_code(
+    metadata: &ProviderMetadata,
+    client_id: &str,
+    client_secret: Option<&str>,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> Result<String, AppError> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", client_id),
+        ("code_verifier", code_verifier),
+    ];
+    if let Some(client_secret) = client_secret {
+        form.push(("client_secret", client_secret));
+    }
+
+    let response = http_client()?
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .map_err(provider_error)?;
+    if response.status().is_client_error() {
+        // 授权码无效、已使用或 PKCE 校验失败
+        tracing::warn!("身份提供方拒绝了授权码: {}", response.status());
+        return Err(AppError::TokenInvalid);
+    }
+    let tokens: TokenResponse = response
+        .error_for_status()
+        .map_err(provider_error)?
+        .json()
+        .await
+        .map_err(provider_error)?;
+
+    tokens
+        .id_token
+        .ok_or_else(|| AppError::ServiceUnavailable("身份提供方未返回 ID Token".to_string()))
+}
+
+/// 校验 ID Token: 签名(按 kid 从 JWKS 取公钥)、iss、aud、exp 和 nonce
+pub async fn verify_id_token(
+    metadata: &ProviderMetadata,
+    client_id: &str,
+    id_token: &str,
+    nonce: &str,
+) -> Result<IdTokenClaims, AppError> {
+    let header = decode_header(id_token).map_err(|_| AppError::TokenInvalid)?;
+    // 只接受非对称签名,避免用公开信息伪造 HS 签名
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(AppError::TokenInvalid);
+    }
+
+    let jwks: JwkSet = http_client()?
+        .get(&metadata.jwks_uri)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(provider_error)?
+        .json()
+        .await
+        .map_err(provider_error)?;
+    let jwk = match header.kid.as_deref() {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or(AppError::TokenInvalid)?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| AppError::TokenInvalid)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[metadata.issuer.as_str()]);
+    validation.set_audience(&[client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|e| {
+            tracing::warn!("ID Token 校验失败: {}", e);
+            AppError::TokenInvalid
+        })?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        tracing::warn!("ID Token 的 nonce 不匹配");
+        return Err(AppError::TokenInvalid);
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pkce_challenge_rfc7636_vector() {
+        // RFC 7636 附录B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_authorization_url_encodes_parameters() {
+        let metadata = ProviderMetadata {
+            issuer: "https://idp.example.com".to_string(),
+            authorization_endpoint: "https://idp.example.com/authorize?tenant=a".to_string(),
+            token_endpoint: "https://idp.example.com/token".to_string(),
+            jwks_uri: "https://idp.example.com/jwks".to_string(),
+        };
+        let url = authorization_url(
+            &metadata,
+            &AuthorizationRequest {
+                client_id: "flow farm",
+                redirect_uri: "https://app.example.com/sso/callback",
+                scopes: "openid email",
+                state: "s",
+                nonce: "n",
+                code_verifier: "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+            },
+        );
+
+        assert!(url.starts_with("https://idp.example.com/authorize?tenant=a&response_type=code&"));
+        assert!(url.contains("client_id=flow%20farm"));
+        assert!(url.contains("redirect_uri=https%3A%2F%2Fapp.example.com%2Fsso%2Fcallback"));
+        assert!(url.contains("code_challenge=E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM&code_challenge_method=S256"));
+    }
+}
@@ -1,26 +1,187 @@
 use anyhow::{anyhow, Result};
-use bcrypt::{hash, verify, DEFAULT_COST};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier as _, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use rand::RngCore;
 
-/// 对密码进行哈希加密
+use crate::Config;
+
+/// 密码哈希算法
+///
+/// 哈希值本身带有算法标识(PHC 格式 `$argon2id$...`,bcrypt 为 `$2b$...`),
+/// 校验时按标识选择算法,因此旧的 bcrypt 哈希可以继续使用。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordAlgorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+impl PasswordAlgorithm {
+    /// 根据哈希值前缀识别算法
+    pub fn of_hash(hash: &str) -> Option<Self> {
+        if hash.starts_with("$argon2id$") {
+            Some(Self::Argon2id)
+        } else if ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix)) {
+            Some(Self::Bcrypt)
+        } else {
+            None
+        }
+    }
+}
+
+/// 密码哈希配置: 新密码使用的算法和参数
+#[derive(Debug, Clone)]
+pub struct PasswordHasher {
+    algorithm: PasswordAlgorithm,
+    argon2_params: Params,
+    bcrypt_cost: u32,
+}
+
+impl Default for PasswordHasher {
+    /// Argon2id,参数为 OWASP 推荐的最低配置(19 MiB 内存、2 次迭代、1 线程)
+    fn default() -> Self {
+        Self {
+            algorithm: PasswordAlgorithm::Argon2id,
+            argon2_params: Params::default(),
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+        }
+    }
+}
+
+impl PasswordHasher {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let algorithm = match config.password_hash_algorithm.as_str() {
+            "argon2id" => PasswordAlgorithm::Argon2id,
+            "bcrypt" => PasswordAlgorithm::Bcrypt,
+            other => return Err(anyhow!("不支持的密码哈希算法: {}", other)),
+        };
+        let argon2_params = Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None,
+        )
+        .map_err(|e| anyhow!("Argon2参数无效: {}", e))?;
+
+        Ok(Self {
+            algorithm,
+            argon2_params,
+            bcrypt_cost: config.bcrypt_rounds,
+        })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.argon2_params.clone())
+    }
+
+    /// 用配置的算法和参数计算密码哈希
+    pub fn hash(&self, password: &str) -> Result<String> {
+        match self.algorithm {
+            PasswordAlgorithm::Argon2id => {
+                let mut salt = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut salt);
+                let salt = SaltString::encode_b64(&salt).map_err(|e| anyhow!("生成盐值失败: {}", e))?;
+                self.argon2()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map(|hash| hash.to_string())
+                    .map_err(|e| anyhow!("密码加密失败: {}", e))
+            }
+            PasswordAlgorithm::Bcrypt => {
+                bcrypt::hash(password, self.bcrypt_cost).map_err(|e| anyhow!("密码加密失败: {}", e))
+            }
+        }
+    }
+
+    /// 校验密码,支持 Argon2id 和 bcrypt 哈希(参数以哈希中记录的为准)
+    pub fn verify(&self, password: &str, hash: &str) -> Result<bool> {
+        match PasswordAlgorithm::of_hash(hash) {
+            Some(PasswordAlgorithm::Argon2id) => {
+                let parsed = PasswordHash::new(hash).map_err(|e| anyhow!("密码哈希格式无效: {}", e))?;
+                Ok(Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+            }
+            Some(PasswordAlgorithm::Bcrypt) => {
+                bcrypt::verify(password, hash).map_err(|e| anyhow!("密码验证失败: {}", e))
+            }
+            None => Err(anyhow!("无法识别的密码哈希格式")),
+        }
+    }
+
+    /// 哈希的算法或参数与当前配置不同,需要在用户下次登录时重新计算
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match (self.algorithm, PasswordAlgorithm::of_hash(hash)) {
+            (PasswordAlgorithm::Argon2id, Some(PasswordAlgorithm::Argon2id)) => PasswordHash::new(hash)
+                .ok()
+                .and_then(|parsed| Params::try_from(&parsed).ok())
+                .is_none_or(|params| {
+                    params.m_cost() != self.argon2_params.m_cost()
+                        || params.t_cost() != self.argon2_params.t_cost()
+                        || params.p_cost() != self.argon2_params.p_cost()
+                }),
+            (PasswordAlgorithm::Bcrypt, Some(PasswordAlgorithm::Bcrypt)) => {
+                hash.get(4..6).and_then(|cost| cost.parse::<u32>().ok()) != Some(self.bcrypt_cost)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// 使用默认配置(Argon2id)对密码进行哈希加密
+///
+/// 没有配置可用的场景(初始数据等)使用;参数与配置不同时会在用户登录后自动更新。
 pub fn hash_password(password: &str) -> Result<String> {
-    hash(password, DEFAULT_COST).map_err(|e| anyhow!("密码加密失败: {}", e))
+    PasswordHasher::default().hash(password)
 }
 
 /// 验证密码是否匹配
 pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
-    verify(password, hash).map_err(|e| anyhow!("密码验证失败: {}", e))
+    PasswordHasher::default().verify(password, hash)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn hasher(algorithm: PasswordAlgorithm, m_cost: u32, t_cost: u32, bcrypt_cost: u32) -> PasswordHasher {
+        PasswordHasher {
+            algorithm,
+            argon2_params: Params::new(m_cost, t_cost, 1, None).unwrap(),
+            bcrypt_cost,
+        }
+    }
+
     #[test]
     fn test_hash_and_verify() {
         let password = "test_password_123";
         let hashed = hash_password(password).unwrap();
-        
+
+        assert!(hashed.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
         assert!(verify_password(password, &hashed).unwrap());
         assert!(!verify_password("wrong_password", &hashed).unwrap());
     }
+
+    #[test]
+    fn test_verify_legacy_bcrypt() {
+        let legacy = bcrypt::hash("legacy-password", 4).unwrap();
+
+        assert_eq!(PasswordAlgorithm::of_hash(&legacy), Some(PasswordAlgorithm::Bcrypt));
+        assert!(verify_password("legacy-password", &legacy).unwrap());
+        assert!(!verify_password("other-password", &legacy).unwrap());
+        assert!(verify_password("password", "plain-text").is_err());
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let argon2 = hasher(PasswordAlgorithm::Argon2id, 1024, 1, 4);
+        let current = argon2.hash("password").unwrap();
+        assert!(!argon2.needs_rehash(&current));
+        assert!(hasher(PasswordAlgorithm::Argon2id, 2048, 1, 4).needs_rehash(&current), "内存参数变化");
+        assert!(hasher(PasswordAlgorithm::Argon2id, 1024, 2, 4).needs_rehash(&current), "迭代次数变化");
+        assert!(argon2.needs_rehash(&bcrypt::hash("password", 4).unwrap()), "bcrypt 迁移到 Argon2id");
+
+        let bcrypt_hasher = hasher(PasswordAlgorithm::Bcrypt, 1024, 1, 5);
+        assert!(bcrypt_hasher.needs_rehash(&bcrypt::hash("password", 4).unwrap()), "bcrypt cost 变化");
+        assert!(!bcrypt_hasher.needs_rehash(&bcrypt::hash("password", 5).unwrap()));
+        assert!(bcrypt_hasher.needs_rehash(&current));
+    }
 }
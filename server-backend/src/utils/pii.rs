@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use anyhow::{anyhow, bail, Result};
+use data_encoding::BASE64;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use sha2::Sha256;
+use sqlx::{
+    error::BoxDynError,
+    sqlite::{SqliteTypeInfo, SqliteValueRef},
+    Decode, Sqlite, Type,
+};
+
+use crate::{errors::AppError, Config};
+
+/// 加密后的字段值前缀,完整格式为 `pii:v<密钥版本>:<加密的数据密钥>:<密文>`
+const SEALED_PREFIX: &str = "pii:v";
+const KEY_LEN: usize = 32;
+
+/// 当前使用的密钥环,未配置 PII_KEYS 时为空,字段按明文读写
+static KEYRING: LazyLock<RwLock<Option<Arc<Keyring>>>> = LazyLock::new(Default::default);
+
+/// 个人信息字段的信封加密密钥环
+///
+/// 每个字段值使用随机生成的数据密钥(AES-256-GCM)加密,数据密钥再由带版本号的主密钥加密后
+/// 与密文一起保存。轮换主密钥时只需新增一个版本并设为当前版本,旧版本保留到重新加密完成。
+pub struct Keyring {
+    keys: BTreeMap<u32, LessSafeKey>,
+    active: u32,
+    /// 盲索引密钥,轮换主密钥时保持不变,否则需要重建全部索引
+    index_key: Vec<u8>,
+}
+
+impl Keyring {
+    /// 从 `PII_KEYS`(`版本:base64密钥`,逗号分隔)、`PII_ACTIVE_KEY` 和 `PII_INDEX_KEY` 加载
+    ///
+    /// 未配置主密钥时返回 `None`。
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let mut keys = BTreeMap::new();
+        for entry in config.pii_keys.iter().filter(|entry| !entry.trim().is_empty()) {
+            let (version, key) = entry
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| anyhow!("PII_KEYS 格式应为 版本:base64密钥"))?;
+            let version: u32 = version.parse().map_err(|_| anyhow!("PII_KEYS 中的密钥版本无效: {}", version))?;
+            let key = decode_key(key).map_err(|e| anyhow!("PII_KEYS 第{}版密钥无效: {}", version, e))?;
+            let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow!("PII_KEYS 第{}版密钥无效", version))?;
+            if keys.insert(version, LessSafeKey::new(key)).is_some() {
+                bail!("PII_KEYS 中的密钥版本重复: {}", version);
+            }
+        }
+        let Some(&latest) = keys.keys().next_back() else {
+            return Ok(None);
+        };
+
+        let active = config.pii_active_key.unwrap_or(latest);
+        if !keys.contains_key(&active) {
+            bail!("PII_ACTIVE_KEY 指定的第{}版密钥不在 PII_KEYS 中", active);
+        }
+        let index_key = config
+            .pii_index_key
+            .as_deref()
+            .ok_or_else(|| anyhow!("配置了 PII_KEYS 时必须同时配置 PII_INDEX_KEY"))
+            .and_then(|key| decode_key(key).map_err(|e| anyhow!("PII_INDEX_KEY 无效: {}", e)))?;
+
+        Ok(Some(Self { keys, active, index_key }))
+    }
+
+    pub fn active_version(&self) -> u32 {
+        self.active
+    }
+
+    fn seal(&self, value: &str) -> Result<String, AppError> {
+        let mut data_key = [0u8; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut data_key);
+        let data_cipher = UnboundKey::new(&AES_256_GCM, &data_key)
+            .map(LessSafeKey::new)
+            .map_err(|_| AppError::Internal("生成数据密钥失败".to_string()))?;
+
+        let master = &self.keys[&self.active];
+        let wrapped = encrypt(master, &version_aad(self.active), &data_key)?;
+        let ciphertext = encrypt(&data_cipher, b"", value.as_bytes())?;
+        Ok(format!(
+            "{}{}:{}:{}",
+            SEALED_PREFIX,
+            self.active,
+            BASE64.encode(&wrapped),
+            BASE64.encode(&ciphertext)
+        ))
+    }
+
+    fn open(&self, version: u32, wrapped: &[u8], ciphertext: &[u8]) -> Result<String, AppError> {
+        let master = self
+            .keys
+            .get(&version)
+            .ok_or_else(|| AppError::Internal(format!("缺少第{}版个人信息加密密钥", version)))?;
+        let data_key = decrypt(master, &version_aad(version), wrapped)?;
+        let data_cipher = UnboundKey::new(&AES_256_GCM, &data_key)
+            .map(LessSafeKey::new)
+            .map_err(|_| AppError::Internal("数据密钥无效".to_string()))?;
+        let plaintext = decrypt(&data_cipher, b"", ciphertext)?;
+        String::from_utf8(plaintext).map_err(|_| AppError::Internal("解密结果不是有效的文本".to_string()))
+    }
+
+    fn blind_index(&self, value: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.index_key).expect("HMAC 接受任意长度的密钥");
+        mac.update(normalize(value).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>> {
+    let key = BASE64.decode(key.trim().as_bytes()).map_err(|_| anyhow!("不是有效的 base64"))?;
+    if key.len() != KEY_LEN {
+        bail!("密钥长度应为{}字节", KEY_LEN);
+    }
+    Ok(key)
+}
+
+/// 主密钥版本号作为附加认证数据,防止把数据密钥挪到其他版本下解密
+fn version_aad(version: u32) -> Vec<u8> {
+    format!("flow-farm-pii-v{}", version).into_bytes()
+}
+
+/// 加密并返回 `随机数 || 密文 || 认证标签`
+fn encrypt(key: &LessSafeKey, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut in_out)
+        .map_err(|_| AppError::Internal("个人信息加密失败".to_string()))?;
+    Ok([nonce.as_slice(), &in_out].concat())
+}
+
+fn decrypt(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, AppError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(AppError::Internal("个人信息密文已损坏".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| AppError::Internal("个人信息密文已损坏".to_string()))?;
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| AppError::Internal("个人信息解密失败".to_string()))?;
+    Ok(plaintext.to_vec())
+}
+
+/// 盲索引前统一大小写和首尾空白,使邮箱查找不区分大小写
+fn normalize(value: &str) -> String {
+    value.trim().to_lowercase()
+}
+
+/// 按配置加载并启用密钥环,未配置主密钥时字段按明文读写
+pub fn install(config: &Config) -> Result<()> {
+    let keyring = Keyring::from_config(config)?.map(Arc::new);
+    *KEYRING.write().unwrap_or_else(|e| e.into_inner()) = keyring;
+    Ok(())
+}
+
+/// 当前启用的密钥环
+pub fn keyring() -> Option<Arc<Keyring>> {
+    KEYRING.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// 加密字段值,未启用加密时原样返回
+pub fn seal(value: &str) -> Result<String, AppError> {
+    match keyring() {
+        Some(keyring) => keyring.seal(value),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// 加密可为空的字段值
+pub fn seal_optional(value: Option<&str>) -> Result<Option<String>, AppError> {
+    value.map(seal).transpose()
+}
+
+/// 解密字段值;未加密的旧数据(尚未被重新加密任务处理)原样返回
+pub fn open(stored: &str) -> Result<String, AppError> {
+    let Some(rest) = stored.strip_prefix(SEALED_PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let mut parts = rest.splitn(3, ':');
+    let (Some(version), Some(wrapped), Some(ciphertext)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(AppError::Internal("个人信息密文格式无效".to_string()));
+    };
+    let version: u32 = version
+        .parse()
+        .map_err(|_| AppError::Internal("个人信息密文格式无效".to_string()))?;
+    let wrapped = BASE64
+        .decode(wrapped.as_bytes())
+        .map_err(|_| AppError::Internal("个人信息密文格式无效".to_string()))?;
+    let ciphertext = BASE64
+        .decode(ciphertext.as_bytes())
+        .map_err(|_| AppError::Internal("个人信息密文格式无效".to_string()))?;
+
+    keyring()
+        .ok_or_else(|| AppError::Internal("数据已加密,但未配置 PII_KEYS".to_string()))?
+        .open(version, &wrapped, &ciphertext)
+}
+
+/// 字段值使用的主密钥版本,未加密时为空
+pub fn key_version(stored: &str) -> Option<u32> {
+    stored.strip_prefix(SEALED_PREFIX)?.split(':').next()?.parse().ok()
+}
+
+/// 用于等值查找的盲索引,未启用加密时为空
+pub fn blind_index(value: &str) -> Option<String> {
+    keyring().map(|keyring| keyring.blind_index(value))
+}
+
+/// 可为空字段的盲索引
+pub fn blind_index_optional(value: Option<&str>) -> Option<String> {
+    value.and_then(blind_index)
+}
+
+/// 读取时自动解密的文本字段,配合 `#[sqlx(try_from = "SealedText")]` 使用
+#[derive(Debug, Clone)]
+pub struct SealedText(pub String);
+
+impl From<SealedText> for String {
+    fn from(value: SealedText) -> Self {
+        value.0
+    }
+}
+
+impl Type<Sqlite> for SealedText {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for SealedText {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let stored = <String as Decode<Sqlite>>::decode(value)?;
+        Ok(Self(open(&stored)?))
+    }
+}
+
+/// 读取时自动解密的可为空文本字段
+#[derive(Debug, Clone)]
+pub struct OptionalSealedText(pub Option<String>);
+
+impl From<OptionalSealedText> for Option<String> {
+    fn from(value: OptionalSealedText) -> Self {
+        value.0
+    }
+}
+
+impl Type<Sqlite> for OptionalSealedText {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for OptionalSealedText {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let stored = <Option<String> as Decode<Sqlite>>::decode(value)?;
+        Ok(Self(stored.as_deref().map(open).transpose()?))
+    }
+}
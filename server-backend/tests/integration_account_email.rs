@@ -0,0 +1,501 @@
+// API集成测试 - 邮箱验证与找回密码
+// 验证 SMTP(本地收件服务)/文件邮件发送、令牌签名、一次性使用、过期以及重置密码后的会话失效
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::{account_email::AccountEmailService, session::SessionService},
+    utils::mailer::{EmailMessage, Mailer},
+    Config, Database,
+};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+};
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "password123";
+const NEW_PASSWORD: &str = "new-password-456";
+
+const ALICE_ID: i64 = 1;
+const BOB_ID: i64 = 2;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "https://farm.example.com/".to_string(),
+    }
+}
+
+/// 两个未验证邮箱的执行者
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database
+        .create_two_factor_tables()
+        .await
+        .expect("Failed to create two factor tables");
+    database.create_email_tokens().await.expect("Failed to create email tokens");
+    database
+        .create_password_history()
+        .await
+        .expect("Failed to create password history");
+
+    let hashed = bcrypt::hash(PASSWORD, 4).unwrap();
+    for (id, username) in [(ALICE_ID, "alice"), (BOB_ID, "bob")] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, 'task_executor', ?, 1)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(&hashed)
+            .bind(username)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn token_for(database: &Database, user_id: i64) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &UserRole::TaskExecutor, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+async fn is_verified(database: &Database, user_id: i64) -> bool {
+    sqlx::query_scalar("SELECT is_verified FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(&database.pool)
+        .await
+        .unwrap()
+}
+
+/// 从原始邮件(RFC 5322)中解出正文里链接携带的令牌
+fn token_from_raw_mail(raw: &str, path: &str) -> String {
+    let (_, body) = raw.split_once("\r\n\r\n").expect("邮件缺少正文");
+    let encoded: String = body.chars().filter(|c| !c.is_whitespace() && *c != '.').collect();
+    let decoded = data_encoding::BASE64.decode(encoded.as_bytes()).expect("正文应为base64编码");
+    token_from_body(&String::from_utf8(decoded).unwrap(), path)
+}
+
+fn token_from_body(body: &str, path: &str) -> String {
+    let prefix = format!("https://farm.example.com/{}?token=", path);
+    let start = body.find(&prefix).expect("正文中缺少链接") + prefix.len();
+    body[start..].split_whitespace().next().unwrap().to_string()
+}
+
+/// 最简单的本地SMTP收件服务,收到的每封邮件原文发送到通道
+async fn start_smtp_sink() -> (u16, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let command = line.to_ascii_uppercase();
+                    let reply: &[u8] = if command.starts_with("EHLO") {
+                        b"250-sink\r\n250 8BITMIME\r\n"
+                    } else if command.starts_with("DATA") {
+                        writer.write_all(b"354 end with .\r\n").await.unwrap();
+                        let mut data = String::new();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            data.push_str(&line);
+                            data.push_str("\r\n");
+                        }
+                        let _ = sender.send(data);
+                        b"250 queued\r\n"
+                    } else if command.starts_with("QUIT") {
+                        let _ = writer.write_all(b"221 bye\r\n").await;
+                        break;
+                    } else {
+                        b"250 ok\r\n"
+                    };
+                    writer.write_all(reply).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (port, receiver)
+}
+
+/// 把邮件保存在内存中的发送方式
+#[derive(Clone, Default)]
+struct CapturingMailer {
+    sent: Arc<Mutex<Vec<EmailMessage>>>,
+}
+
+impl Mailer for CapturingMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            self.sent.lock().unwrap().push(message.clone());
+            Ok(())
+        })
+    }
+}
+
+impl CapturingMailer {
+    fn last_body(&self) -> String {
+        self.sent.lock().unwrap().last().expect("没有发出邮件").body.clone()
+    }
+}
+
+#[cfg(test)]
+mod account_email_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_email_verification_with_file_mailer() {
+        let database = setup_database().await;
+        let mail_dir = std::env::temp_dir().join(format!("flow-farm-mail-{}", uuid::Uuid::new_v4()));
+        let config = Config {
+            mailer: "file".to_string(),
+            mail_dir: mail_dir.to_string_lossy().to_string(),
+            ..test_config()
+        };
+        let app = create_app(database.clone(), config).await;
+        let token = token_for(&database, ALICE_ID).await;
+
+        let (status, _) = send(&app, "/api/v1/auth/verify-email/send", Some(&token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let files: Vec<_> = std::fs::read_dir(&mail_dir).unwrap().collect();
+        assert_eq!(files.len(), 1, "应写入一封 .eml 邮件");
+        let raw = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(raw.contains("To: alice@example.com"));
+        let verify_token = token_from_raw_mail(&raw, "verify-email");
+
+        // 间隔内不能重复发送
+        let (status, _) = send(&app, "/api/v1/auth/verify-email/send", Some(&token), json!({})).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+        let (status, _) = send(&app, "/api/v1/auth/verify-email", None, json!({ "token": verify_token })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(is_verified(&database, ALICE_ID).await);
+        assert!(!is_verified(&database, BOB_ID).await);
+
+        let (status, body) = send(&app, "/api/v1/auth/verify-email", None, json!({ "token": verify_token })).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "令牌只能使用一次");
+        assert_eq!(body["code"], 1003);
+
+        let (status, _) = send(&app, "/api/v1/auth/verify-email/send", Some(&token), json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT, "已验证的邮箱不再发送");
+
+        std::fs::remove_dir_all(&mail_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_over_smtp() {
+        let database = setup_database().await;
+        let (port, mut inbox) = start_smtp_sink().await;
+        let config = Config {
+            mailer: "smtp".to_string(),
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: port,
+            ..test_config()
+        };
+        let app = create_app(database.clone(), config).await;
+        let session_token = token_for(&database, ALICE_ID).await;
+
+        // 不存在的邮箱同样返回成功,但不发邮件
+        let (status, _) = send(
+            &app,
+            "/api/v1/auth/password-reset/request",
+            None,
+            json!({ "email": "nobody@example.com" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(inbox.try_recv().is_err());
+
+        let (status, _) = send(
+            &app,
+            "/api/v1/auth/password-reset/request",
+            None,
+            json!({ "email": "Alice@Example.com" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let raw = inbox.try_recv().expect("收件服务应收到邮件");
+        assert!(raw.contains("alice@example.com"));
+        let reset_token = token_from_raw_mail(&raw, "reset-password");
+
+        // 间隔内再次申请不重复发送
+        let (status, _) = send(
+            &app,
+            "/api/v1/auth/password-reset/request",
+            None,
+            json!({ "email": "alice@example.com" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(inbox.try_recv().is_err());
+
+        // 篡改签名
+        let mut tampered = reset_token.clone();
+        let last = if tampered.ends_with('0') { "1" } else { "0" };
+        tampered.replace_range(tampered.len() - 1.., last);
+        let (status, _) = send(
+            &app,
+            "/api/v1/auth/password-reset/confirm",
+            None,
+            json!({ "token": tampered, "new_password": NEW_PASSWORD }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(
+            &app,
+            "/api/v1/auth/password-reset/confirm",
+            None,
+            json!({ "token": reset_token, "new_password": NEW_PASSWORD }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&app, "/api/v1/auth/logout", Some(&session_token), json!({})).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "重置密码后原有会话失效");
+
+        let (status, _) = send(
+            &app,
+            "/api/v1/auth/login",
+            None,
+            json!({ "username": "alice", "password": PASSWORD }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = send(
+            &app,
+            "/api/v1/auth/login",
+            None,
+            json!({ "username": "alice", "password": NEW_PASSWORD }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "新密码登录失败: {}", body);
+        assert!(is_verified(&database, ALICE_ID).await, "通过邮件重置密码同时完成邮箱验证");
+
+        let (status, _) = send(
+            &app,
+            "/api/v1/auth/password-reset/confirm",
+            None,
+            json!({ "token": reset_token, "new_password": "another-password" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "令牌只能使用一次");
+
+        let actions: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_logs WHERE target_id = '1'")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        assert!(actions.contains(&"auth.password_reset".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_token_purpose_expiry_and_email_change() {
+        let database = setup_database().await;
+        let mailer = CapturingMailer::default();
+        let service =
+            AccountEmailService::with_mailer(database.clone(), test_config(), Box::new(mailer.clone()));
+        let client = ClientInfo::default();
+
+        service.request_password_reset("bob@example.com").await.unwrap();
+        let reset_token = token_from_body(&mailer.last_body(), "reset-password");
+
+        // 找回密码令牌不能用于验证邮箱
+        let result = service.verify_email(&reset_token, &client).await;
+        assert!(matches!(result, Err(AppError::TokenInvalid)));
+
+        sqlx::query("UPDATE email_tokens SET expires_at = '2000-01-01 00:00:00'")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let result = service.reset_password(&reset_token, NEW_PASSWORD, &client).await;
+        assert!(matches!(result, Err(AppError::TokenExpired)));
+
+        // 签发后修改了邮箱,令牌作废
+        let bob = flow_farm_backend::models::UserInfo {
+            id: BOB_ID,
+            username: "bob".to_string(),
+            email: "bob@example.com".to_string(),
+            full_name: "bob".to_string(),
+            role: UserRole::TaskExecutor,
+            is_active: true,
+            company_id: Some(1),
+            parent_id: None,
+            created_at: String::new(),
+            last_login: None,
+            custom_role_id: None,
+            permissions: UserRole::TaskExecutor.permissions(),
+        };
+        service.send_verification(&bob).await.unwrap();
+        let verify_token = token_from_body(&mailer.last_body(), "verify-email");
+        sqlx::query("UPDATE users SET email = 'bob@other.example.com' WHERE id = ?")
+            .bind(BOB_ID)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let result = service.verify_email(&verify_token, &client).await;
+        assert!(matches!(result, Err(AppError::TokenInvalid)));
+        assert!(!is_verified(&database, BOB_ID).await);
+    }
+
+    #[tokio::test]
+    async fn test_reset_password_enforces_policy_without_consuming_token() {
+        let database = setup_database().await;
+        let mailer = CapturingMailer::default();
+        let config = Config {
+            password_min_length: 8,
+            password_required_classes: vec!["digit".to_string()],
+            password_history_size: 2,
+            ..test_config()
+        };
+        let service = AccountEmailService::with_mailer(database.clone(), config, Box::new(mailer.clone()));
+        let client = ClientInfo::default();
+
+        service.request_password_reset("alice@example.com").await.unwrap();
+        let reset_token = token_from_body(&mailer.last_body(), "reset-password");
+
+        let result = service.reset_password(&reset_token, "weak", &client).await;
+        assert!(matches!(result, Err(AppError::InvalidInput(_))));
+        let result = service.reset_password(&reset_token, PASSWORD, &client).await;
+        assert!(matches!(result, Err(AppError::InvalidInput(_))), "不能重置为当前密码");
+
+        service.reset_password(&reset_token, "Harvest2025", &client).await.unwrap();
+        let hashed: String = sqlx::query_scalar("SELECT hashed_password FROM users WHERE id = ?")
+            .bind(ALICE_ID)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert!(flow_farm_backend::utils::verify_password("Harvest2025", &hashed).unwrap());
+    }
+}
@@ -0,0 +1,310 @@
+// API集成测试 - 个人访问令牌与公司API密钥
+// 验证令牌的授权范围、过期与撤销、摘要存储、最近使用记录以及公司密钥的管理权限
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PM_A_ID: i64 = 1;
+const PM_A2_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 公司1的两个项目经理和一个执行者、公司2的项目经理
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A'), (2, '公司B')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_api_tokens().await.expect("Failed to create api tokens");
+
+    for (id, username, role, company_id) in [
+        (PM_A_ID, "pm_a", "project_manager", 1),
+        (PM_A2_ID, "pm_a2", "project_manager", 1),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", 1),
+        (PM_B_ID, "pm_b", "project_manager", 2),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", token))
+        .header("X-Real-IP", "203.0.113.7")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+async fn create_token(app: &Router, session: &str, body: Value) -> (StatusCode, Value) {
+    send(app, "POST", "/api/v1/auth/tokens", session, body).await
+}
+
+#[cfg(test)]
+mod api_token_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_personal_token_scopes_and_revocation() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let session = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let (status, body) = create_token(&app, &session, json!({ "name": "sync", "scopes": ["tasks:read", "admin:all"] })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "未知的授权范围");
+        assert!(body["message"].as_str().unwrap().contains("admin:all"));
+
+        let (status, body) = create_token(&app, &session, json!({ "name": "sync", "scopes": ["tasks:read"] })).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+        let token_id = body["data"]["id"].as_str().unwrap().to_string();
+        assert!(token.starts_with("ffp_"));
+        assert_eq!(body["data"]["kind"], "personal");
+        assert_eq!(body["data"]["token_prefix"], &token[..12]);
+
+        // 只保存摘要
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens WHERE token_hash = ? OR token_prefix = ?")
+            .bind(&token)
+            .bind(&token)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, 0);
+
+        // 授权范围内的接口可以访问,写接口和仅限会话的接口被拒绝
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", &token, Value::Null).await.0, StatusCode::OK);
+        let (status, body) = send(&app, "POST", "/api/v1/tasks", &token, json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], 1010);
+        assert!(body["message"].as_str().unwrap().contains("tasks:write"));
+        assert_eq!(send(&app, "GET", "/api/v1/statistics/tasks", &token, Value::Null).await.0, StatusCode::FORBIDDEN);
+        let (status, body) = send(&app, "GET", "/api/v1/auth/tokens", &token, Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "令牌不能管理令牌");
+        assert_eq!(body["code"], 1010);
+        assert_eq!(send(&app, "POST", "/api/v1/auth/change-password", &token, json!({})).await.0, StatusCode::FORBIDDEN);
+
+        // 登录会话不受授权范围限制(临时任务接口对写操作返回400)
+        assert_eq!(send(&app, "POST", "/api/v1/tasks", &session, json!({})).await.0, StatusCode::BAD_REQUEST);
+
+        // 记录最近使用
+        let (_, body) = send(&app, "GET", "/api/v1/auth/tokens", &session, Value::Null).await;
+        let listed = &body["data"][0];
+        assert_eq!(listed["id"], token_id.as_str());
+        assert!(listed.get("token").is_none());
+        assert!(listed["last_used_at"].is_string());
+        assert_eq!(listed["last_used_ip"], "203.0.113.7");
+
+        // 撤销后立即失效
+        let uri = format!("/api/v1/auth/tokens/{}", token_id);
+        assert_eq!(send(&app, "DELETE", &uri, &session, Value::Null).await.0, StatusCode::OK);
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", &token, Value::Null).await.0, StatusCode::UNAUTHORIZED);
+
+        let audited: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_logs WHERE target_id = ? AND action IN ('auth.api_token_created', 'auth.api_token_revoked')")
+            .bind(&token_id)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(audited, 2);
+    }
+
+    #[tokio::test]
+    async fn test_token_expiry() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let session = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let (status, _) = create_token(&app, &session, json!({ "name": "ci", "scopes": ["tasks:read"], "expires_in_days": 0 })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = create_token(&app, &session, json!({ "name": "ci", "scopes": ["tasks:read"], "expires_in_days": 30 })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["data"]["expires_at"].is_string());
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", &token, Value::Null).await.0, StatusCode::OK);
+
+        sqlx::query("UPDATE api_tokens SET expires_at = '2000-01-01 00:00:00'")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", &token, Value::Null).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", "ffp_unknown", Value::Null).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_company_api_keys() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_a2 = session_token(&database, PM_A2_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+
+        let body = json!({ "name": "desktop", "kind": "company", "scopes": ["tasks:read", "users:read"] });
+        assert_eq!(create_token(&app, &executor, body.clone()).await.0, StatusCode::FORBIDDEN);
+
+        let (status, created) = create_token(&app, &pm_a, body).await;
+        assert_eq!(status, StatusCode::OK);
+        let key = created["data"]["token"].as_str().unwrap().to_string();
+        let key_id = created["data"]["id"].as_str().unwrap().to_string();
+        assert!(key.starts_with("ffk_"));
+        assert_eq!(created["data"]["company_id"], 1);
+
+        // 以创建人的身份访问,并受其数据范围限制
+        let (status, users) = send(&app, "GET", "/api/v1/users", &key, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(users["data"].as_array().unwrap().iter().all(|user| user["company_id"] == 1));
+
+        // 本公司的其他管理者可以看到,其他公司看不到也不能撤销
+        let (_, listed) = send(&app, "GET", "/api/v1/auth/tokens", &pm_a2, Value::Null).await;
+        assert_eq!(listed["data"].as_array().unwrap().len(), 1);
+        let (_, listed) = send(&app, "GET", "/api/v1/auth/tokens", &pm_b, Value::Null).await;
+        assert!(listed["data"].as_array().unwrap().is_empty());
+        let (_, listed) = send(&app, "GET", "/api/v1/auth/tokens", &executor, Value::Null).await;
+        assert!(listed["data"].as_array().unwrap().is_empty());
+        let uri = format!("/api/v1/auth/tokens/{}", key_id);
+        assert_eq!(send(&app, "DELETE", &uri, &pm_b, Value::Null).await.0, StatusCode::NOT_FOUND);
+
+        // 创建人被禁用后密钥失效
+        sqlx::query("UPDATE users SET is_active = 0 WHERE id = ?")
+            .bind(PM_A_ID)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", &key, Value::Null).await.0, StatusCode::UNAUTHORIZED);
+
+        assert_eq!(send(&app, "DELETE", &uri, &pm_a2, Value::Null).await.0, StatusCode::OK);
+        let revoked_by: i64 = sqlx::query_scalar("SELECT revoked_by FROM api_tokens WHERE id = ?")
+            .bind(&key_id)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(revoked_by, PM_A2_ID);
+    }
+}
@@ -0,0 +1,362 @@
+// API集成测试 - 审计日志
+// 验证修改类操作记录操作人、来源和字段变化,按公司隔离的查询与导出,
+// 以及只追加的存储和哈希链对篡改的检测
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const ADMIN_ID: i64 = 1;
+const PM_A_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 平台管理员、公司1的项目经理和执行者、公司2的项目经理
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE companies (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            code TEXT UNIQUE,
+            description TEXT,
+            contact_email TEXT,
+            contact_phone TEXT,
+            max_employees INTEGER DEFAULT 10,
+            is_active BOOLEAN DEFAULT TRUE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        "INSERT INTO companies (id, name, code) VALUES (1, '公司A', 'ACME'), (2, '公司B', 'BETA')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+    // 删除用户时会清除的登录凭据和关联数据
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_two_factor_tables().await.expect("Failed to create two factor tables");
+    database.create_email_tokens().await.expect("Failed to create email tokens");
+    database.create_api_tokens().await.expect("Failed to create api tokens");
+    database.create_sso_tables().await.expect("Failed to create sso tables");
+    database.create_invitations().await.expect("Failed to create invitations");
+    database.create_password_history().await.expect("Failed to create password history");
+
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(1)),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+        (PM_B_ID, "pm_b", "project_manager", Some(2)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    request = request.header("User-Agent", "audit-test").header("X-Request-Id", format!("req-{}", method));
+
+    let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+async fn get_raw(app: &Router, uri: &str, token: &str) -> (StatusCode, String, String) {
+    let request = Request::builder()
+        .uri(uri)
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, content_type, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+/// 公司1和公司2各产生一条用户修改记录
+async fn seed_events(app: &Router, pm_a: &str, pm_b: &str) {
+    let (status, body) = send(
+        app,
+        "PUT",
+        &format!("/api/v1/users/{}", EXECUTOR_A_ID),
+        Some(pm_a),
+        json!({ "full_name": "执行者A" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = send(app, "PUT", &format!("/api/v1/users/{}", PM_B_ID), Some(pm_b), json!({ "full_name": "经理B" })).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mutations_record_actor_source_and_diff() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        seed_events(&app, &pm_a, &pm_b).await;
+
+        let (status, body) = send(
+            &app,
+            "GET",
+            &format!("/api/v1/audit-logs?target_type=user&target_id={}", EXECUTOR_A_ID),
+            Some(&pm_a),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let entry = &body["data"][0];
+        assert_eq!(entry["action"], "user.updated");
+        assert_eq!(entry["actor_id"], PM_A_ID);
+        assert_eq!(entry["company_id"], 1);
+        assert_eq!(entry["user_agent"], "audit-test");
+        assert_eq!(entry["request_id"], "req-PUT");
+        assert_eq!(entry["details"], json!({ "full_name": { "redacted": true } }));
+        assert!(!entry["details"].to_string().contains("执行者A"));
+
+        // 没有变化的修改不记录
+        send(&app, "PUT", &format!("/api/v1/users/{}", EXECUTOR_A_ID), Some(&pm_a), json!({ "full_name": "执行者A" })).await;
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs?action=user.updated", Some(&pm_a), Value::Null).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+
+        // 公司状态变化和用户删除
+        let (status, _) = send(&app, "POST", "/api/v1/companies/2/toggle-status", Some(&admin), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs?action=company.updated", Some(&admin), Value::Null).await;
+        assert_eq!(body["data"][0]["details"]["is_active"], json!({ "from": true, "to": false }));
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/users/{}", EXECUTOR_A_ID), Some(&pm_a), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs?action=user.deleted", Some(&pm_a), Value::Null).await;
+        assert_eq!(body["data"][0]["details"], json!({ "user_id": EXECUTOR_A_ID, "tombstone": true }));
+
+        // 响应带回请求ID,没有传入时自动生成
+        let request = Request::builder().uri("/api/v1/auth/me").header("Authorization", format!("Bearer {}", pm_a)).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert!(!response.headers().get("x-request-id").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_is_company_scoped() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+        seed_events(&app, &pm_a, &pm_b).await;
+
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs", Some(&pm_a), Value::Null).await;
+        let entries = body["data"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries.iter().all(|entry| entry["company_id"] == 1));
+
+        assert_eq!(send(&app, "GET", "/api/v1/audit-logs?company_id=2", Some(&pm_a), Value::Null).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, "GET", "/api/v1/audit-logs", Some(&executor), Value::Null).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, "GET", "/api/v1/audit-logs/verify", Some(&pm_a), Value::Null).await.0, StatusCode::FORBIDDEN);
+
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs", Some(&admin), Value::Null).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs?company_id=2", Some(&admin), Value::Null).await;
+        assert_eq!(body["data"][0]["actor_id"], PM_B_ID);
+
+        // 游标分页
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs?limit=1", Some(&admin), Value::Null).await;
+        let last_id = body["data"][0]["id"].as_i64().unwrap();
+        let (_, body) = send(&app, "GET", &format!("/api/v1/audit-logs?before_id={}", last_id), Some(&admin), Value::Null).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert!(body["data"][0]["id"].as_i64().unwrap() < last_id);
+    }
+
+    #[tokio::test]
+    async fn test_export_csv_and_jsonl() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        seed_events(&app, &pm_a, &pm_b).await;
+
+        let (status, content_type, csv) = get_raw(&app, "/api/v1/audit-logs/export", &pm_a).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("text/csv"));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[0].starts_with("id,occurred_at,action,actor_id"));
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("user.updated") && lines[1].contains("redacted") && !lines[1].contains("执行者A"));
+
+        // 导出本身也会记录
+        let (status, content_type, jsonl) = get_raw(&app, "/api/v1/audit-logs/export?format=jsonl", &pm_a).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/x-ndjson");
+        let actions: Vec<String> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["action"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(actions, vec!["audit.exported", "user.updated"]);
+
+        assert_eq!(get_raw(&app, "/api/v1/audit-logs/export?format=xml", &pm_a).await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_append_only_and_tamper_detection() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        seed_events(&app, &pm_a, &pm_b).await;
+
+        let (status, body) = send(&app, "GET", "/api/v1/audit-logs/verify", Some(&admin), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], json!({ "checked": 2, "valid": true, "broken_at": null }));
+
+        // 修改和删除被触发器拒绝
+        assert!(sqlx::query("UPDATE audit_logs SET actor_id = 99").execute(&database.pool).await.is_err());
+        assert!(sqlx::query("DELETE FROM audit_logs").execute(&database.pool).await.is_err());
+
+        // 绕过触发器直接改库后,哈希链校验能发现被改动的记录
+        let first_id: i64 = sqlx::query_scalar("SELECT MIN(id) FROM audit_logs").fetch_one(&database.pool).await.unwrap();
+        sqlx::query("DROP TRIGGER audit_logs_no_update").execute(&database.pool).await.unwrap();
+        sqlx::query("UPDATE audit_logs SET actor_id = 99 WHERE id = ?")
+            .bind(first_id)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs/verify", Some(&admin), Value::Null).await;
+        assert_eq!(body["data"]["valid"], false);
+        assert_eq!(body["data"]["broken_at"], first_id);
+    }
+}
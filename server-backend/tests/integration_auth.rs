@@ -164,9 +164,9 @@ mod auth_integration_tests {
         let token = generate_test_token("user-1", "testuser", "employee");
 
         // 验证Token
-        use flow_farm_backend::utils::jwt::decode_jwt_token;
+        use flow_farm_backend::utils::jwt::JwtKeys;
 
-        let result = decode_jwt_token(&token, &config.jwt_secret);
+        let result = JwtKeys::from_config(&config).and_then(|keys| keys.decode(&token));
         assert!(result.is_ok(), "Token验证应该成功");
 
         let claims = result.unwrap();
@@ -178,19 +178,16 @@ mod auth_integration_tests {
     async fn test_token_expiration() {
         // 创建一个过期时间很短的配置
         let config = create_test_config();
-        let short_expire = 1; // 1秒后过期
+        // 签发时即已过期,且超出验证时允许的时钟偏差
+        let expired = -120;
 
-        use flow_farm_backend::utils::jwt::create_jwt_token;
+        use flow_farm_backend::utils::jwt::JwtKeys;
 
-        let token = create_jwt_token("user-1", "employee", &config.jwt_secret, short_expire).unwrap();
-
-        // 等待Token过期
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        let keys = JwtKeys::from_config(&config).unwrap();
+        let token = keys.create_session_token("user-1", "employee", "test-session", expired).unwrap();
 
         // 验证过期的Token
-        use flow_farm_backend::utils::jwt::verify_jwt_token;
-
-        let result = verify_jwt_token(&token, &config.jwt_secret);
+        let result = keys.decode(&token);
         assert!(result.is_err(), "过期的Token应该验证失败");
     }
 
@@ -198,7 +195,9 @@ mod auth_integration_tests {
     async fn test_invalid_token_format() {
         let config = create_test_config();
 
-        use flow_farm_backend::utils::jwt::decode_jwt_token;
+        use flow_farm_backend::utils::jwt::JwtKeys;
+
+        let keys = JwtKeys::from_config(&config).unwrap();
 
         // 测试无效的Token格式
         let invalid_tokens = vec![
@@ -209,7 +208,7 @@ mod auth_integration_tests {
         ];
 
         for invalid_token in invalid_tokens {
-            let result = decode_jwt_token(invalid_token, &config.jwt_secret);
+            let result = keys.decode(invalid_token);
             assert!(result.is_err(), "无效的Token格式应该验证失败: {}", invalid_token);
         }
     }
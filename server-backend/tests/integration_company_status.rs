@@ -0,0 +1,303 @@
+// API集成测试 - 公司停用
+// 验证公司停用后本公司用户无法登录、刷新令牌或使用已有会话和访问令牌,
+// 其他公司和平台管理员不受影响,恢复启用后原有会话和令牌继续可用
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "Secret123!";
+const ADMIN_ID: i64 = 1;
+const EXECUTOR_A_ID: i64 = 2;
+const PM_B_ID: i64 = 3;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 平台管理员、公司A的执行者、公司B的项目经理
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE companies (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            code TEXT UNIQUE,
+            join_code TEXT UNIQUE,
+            description TEXT,
+            contact_email TEXT,
+            contact_phone TEXT,
+            max_employees INTEGER DEFAULT 10,
+            is_active BOOLEAN DEFAULT TRUE,
+            require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        "INSERT INTO companies (id, name, code) VALUES (1, '公司A', 'ACME'), (2, '公司B', 'BETA')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_two_factor_tables().await.expect("Failed to create two factor tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_api_tokens().await.expect("Failed to create api tokens");
+
+    let hashed = bcrypt::hash(PASSWORD, 4).unwrap();
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+        (PM_B_ID, "pm_b", "project_manager", Some(2)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(&hashed)
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn login(app: &Router, username: &str, password: &str) -> (StatusCode, Value) {
+    send(app, "POST", "/api/v1/auth/login", None, json!({ "username": username, "password": password })).await
+}
+
+/// 平台管理员切换公司状态,返回切换后是否启用
+async fn toggle_company(app: &Router, database: &Database, company_id: i64) -> bool {
+    let admin = SessionService::new(database.clone(), test_config())
+        .create_session(ADMIN_ID, &UserRole::PlatformAdmin, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token;
+    let uri = format!("/api/v1/companies/{}/toggle-status", company_id);
+    let (status, body) = send(app, "POST", &uri, Some(&admin), Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "切换公司状态失败: {}", body);
+    body["is_active"].as_bool().unwrap()
+}
+
+#[cfg(test)]
+mod company_status_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_inactive_company_blocks_login() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        assert_eq!(login(&app, "exec_a", PASSWORD).await.0, StatusCode::OK);
+        assert!(!toggle_company(&app, &database, 1).await);
+
+        let (status, body) = login(&app, "exec_a", PASSWORD).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], 1011);
+
+        // 密码错误时不提示公司状态
+        let (status, body) = login(&app, "exec_a", "wrong-password").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1001);
+
+        // 其他公司和平台管理员不受影响
+        assert_eq!(login(&app, "pm_b", PASSWORD).await.0, StatusCode::OK);
+        assert_eq!(login(&app, "admin", PASSWORD).await.0, StatusCode::OK);
+
+        assert!(toggle_company(&app, &database, 1).await);
+        assert_eq!(login(&app, "exec_a", PASSWORD).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_inactive_company_blocks_sessions_and_api_tokens() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let (status, body) = login(&app, "exec_a", PASSWORD).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+        let refresh_token = body["data"]["refresh_token"].as_str().unwrap().to_string();
+        let (status, body) = send(&app, "POST", "/api/v1/auth/tokens", Some(&token), json!({ "name": "sync", "scopes": ["tasks:read"] })).await;
+        assert_eq!(status, StatusCode::OK);
+        let api_token = body["data"]["token"].as_str().unwrap().to_string();
+
+        assert!(!toggle_company(&app, &database, 1).await);
+
+        for (uri, token) in [
+            ("/api/v1/auth/me", token.as_str()),
+            ("/api/v1/tasks", api_token.as_str()),
+            ("/ws/task-updates", token.as_str()),
+        ] {
+            let (status, body) = send(&app, "GET", uri, Some(token), Value::Null).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", uri);
+            assert_eq!(body["code"], 1011, "{}", uri);
+        }
+        let (status, body) = send(&app, "POST", "/api/v1/auth/refresh", None, json!({ "refresh_token": refresh_token })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], 1011);
+
+        // 停用不撤销会话,恢复启用后原有令牌继续可用
+        let revoked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions WHERE revoked_at IS NOT NULL")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(revoked, 0);
+
+        assert!(toggle_company(&app, &database, 1).await);
+        assert_eq!(send(&app, "GET", "/api/v1/auth/me", Some(&token), Value::Null).await.0, StatusCode::OK);
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", Some(&api_token), Value::Null).await.0, StatusCode::OK);
+        let (status, _) = send(&app, "POST", "/api/v1/auth/refresh", None, json!({ "refresh_token": refresh_token })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_company_code_set_through_api() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = SessionService::new(database.clone(), test_config())
+            .create_session(ADMIN_ID, &UserRole::PlatformAdmin, &ClientInfo::default())
+            .await
+            .unwrap()
+            .token;
+        let company = |name: &str, code: &str| json!({ "name": name, "code": code, "contact_email": "c@example.com", "max_employees": 5 });
+
+        // 创建时指定单点登录用的公司代码,与生成的加入码分开保存
+        let (status, body) = send(&app, "POST", "/api/v1/companies", Some(&admin), company("公司C", "gamma")).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(body["code"], "gamma");
+        let id = body["id"].as_i64().unwrap();
+        let (code, join_code): (String, String) = sqlx::query_as("SELECT code, join_code FROM companies WHERE id = ?")
+            .bind(id)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(code, "gamma");
+        assert_eq!(join_code.len(), 10);
+
+        for (name, code) in [("公司D", "ACME"), ("公司D", "bad code")] {
+            let (status, _) = send(&app, "POST", "/api/v1/companies", Some(&admin), company(name, code)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", code);
+        }
+
+        // 修改公司代码
+        let uri = format!("/api/v1/companies/{}", id);
+        assert_eq!(send(&app, "PUT", &uri, Some(&admin), json!({ "code": "BETA" })).await.0, StatusCode::BAD_REQUEST);
+        let (status, body) = send(&app, "PUT", &uri, Some(&admin), json!({ "code": "gamma-2" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["code"], "gamma-2");
+        let (_, body) = send(&app, "GET", &uri, Some(&admin), Value::Null).await;
+        assert_eq!(body["code"], "gamma-2");
+    }
+}
@@ -0,0 +1,317 @@
+// API集成测试 - 管理员模拟登录
+// 验证模拟令牌同时携带被模拟的用户和实际操作的管理员、不能模拟平台管理员、
+// 期间每个请求都记入审计日志、不能修改密码和两步验证设置,以及结束后令牌失效
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    utils::jwt::JwtKeys,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const ADMIN_ID: i64 = 1;
+const ADMIN2_ID: i64 = 2;
+const PM_A_ID: i64 = 3;
+const EXECUTOR_A_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 两个平台管理员、公司A的项目经理和执行者
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+    database.create_two_factor_tables().await.expect("Failed to create two factor tables");
+    database.create_api_tokens().await.expect("Failed to create api tokens");
+
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (ADMIN2_ID, "admin2", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(1)),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+async fn impersonate(app: &Router, admin: &str, user_id: i64, body: Value) -> (StatusCode, Value) {
+    send(app, "POST", &format!("/api/v1/users/{}/impersonate", user_id), admin, body).await
+}
+
+#[cfg(test)]
+mod impersonation_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_impersonation_token_and_request_audit() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+
+        let (status, body) = impersonate(&app, &admin, EXECUTOR_A_ID, json!({ "reason": "工单 #42", "duration_minutes": 15 })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["expires_in"], 900);
+        assert_eq!(body["data"]["user"]["username"], "exec_a");
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+        let claims = JwtKeys::from_config(&test_config()).unwrap().decode(&token).unwrap();
+        assert_eq!(claims.sub, EXECUTOR_A_ID.to_string());
+        assert_eq!(claims.impersonator_id(), Some(ADMIN_ID));
+        assert_eq!(claims.exp - claims.iat, 900);
+
+        // 普通会话没有模拟标记,模拟会话标出实际操作的管理员
+        let (_, body) = send(&app, "GET", "/api/v1/auth/me", &admin, Value::Null).await;
+        assert!(body["data"].get("impersonator").is_none());
+        let (status, body) = send(&app, "GET", "/api/v1/auth/me", &token, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["username"], "exec_a");
+        assert_eq!(body["data"]["impersonator"]["id"], ADMIN_ID);
+        assert_eq!(body["data"]["impersonator"]["username"], "admin");
+        assert_eq!(send(&app, "GET", "/api/v1/tasks", &token, Value::Null).await.0, StatusCode::OK);
+
+        let started: (i64, Option<i64>, String) = sqlx::query_as(
+            "SELECT actor_id, impersonator_id, details FROM audit_logs WHERE action = 'auth.impersonation_started'",
+        )
+        .fetch_one(&database.pool)
+        .await
+        .unwrap();
+        assert_eq!((started.0, started.1), (ADMIN_ID, None));
+        assert!(started.2.contains("工单 #42"));
+
+        let requests: Vec<(i64, i64, String)> = sqlx::query_as(
+            "SELECT actor_id, impersonator_id, details FROM audit_logs WHERE action = 'auth.impersonated_request' ORDER BY id",
+        )
+        .fetch_all(&database.pool)
+        .await
+        .unwrap();
+        assert_eq!(requests.len(), 2, "每个请求各记一条");
+        assert!(requests.iter().all(|(actor, impersonator, _)| (*actor, *impersonator) == (EXECUTOR_A_ID, ADMIN_ID)));
+        assert!(requests[0].2.contains("/api/v1/auth/me"));
+        assert!(requests[1].2.contains("/api/v1/tasks"));
+    }
+
+    #[tokio::test]
+    async fn test_impersonation_restrictions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let reason = json!({ "reason": "排查问题" });
+
+        let (status, body) = impersonate(&app, &admin, ADMIN2_ID, reason.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "不能模拟其他平台管理员");
+        assert_eq!(body["code"], 4006);
+        assert_eq!(impersonate(&app, &pm, EXECUTOR_A_ID, reason.clone()).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(impersonate(&app, &admin, EXECUTOR_A_ID, json!({ "reason": "" })).await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            impersonate(&app, &admin, EXECUTOR_A_ID, json!({ "reason": "排查问题", "duration_minutes": 240 })).await.0,
+            StatusCode::BAD_REQUEST
+        );
+
+        let (_, body) = impersonate(&app, &admin, PM_A_ID, reason.clone()).await;
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+
+        // 密码、两步验证和再次模拟一律拒绝
+        for (method, uri, body) in [
+            ("POST", "/api/v1/auth/change-password", json!({ "current_password": "x", "new_password": "Another-Secret-1" })),
+            ("POST", "/api/v1/auth/2fa/setup", Value::Null),
+            ("POST", "/api/v1/auth/2fa/disable", json!({ "code": "000000" })),
+            ("DELETE", "/api/v1/users/4/2fa", Value::Null),
+            ("PUT", "/api/v1/users/4", json!({ "password": "Another-Secret-1" })),
+            ("POST", "/api/v1/users/4/impersonate", reason.clone()),
+            ("POST", "/api/v1/auth/tokens", json!({ "name": "keep", "scopes": ["tasks:read"] })),
+        ] {
+            let (status, body) = send(&app, method, uri, &token, body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+            assert_eq!(body["code"], 1010, "{} {}", method, uri);
+        }
+        let password: String = sqlx::query_scalar("SELECT hashed_password FROM users WHERE id = 4")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(password, "x");
+    }
+
+    #[tokio::test]
+    async fn test_mutations_record_impersonator() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+
+        let (_, body) = impersonate(&app, &admin, PM_A_ID, json!({ "reason": "协助修改资料" })).await;
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+
+        let uri = format!("/api/v1/users/{}", EXECUTOR_A_ID);
+        let (status, body) = send(&app, "PUT", &uri, &token, json!({ "full_name": "代为修改" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        // 本人直接操作时没有模拟标记
+        let (status, _) = send(&app, "PUT", &uri, &pm, json!({ "full_name": "本人修改" })).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let updates: Vec<(i64, Option<i64>)> = sqlx::query_as(
+            "SELECT actor_id, impersonator_id FROM audit_logs WHERE action = 'user.updated' ORDER BY id",
+        )
+        .fetch_all(&database.pool)
+        .await
+        .unwrap();
+        assert_eq!(updates, vec![(PM_A_ID, Some(ADMIN_ID)), (PM_A_ID, None)]);
+    }
+
+    #[tokio::test]
+    async fn test_end_impersonation_revokes_token() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+
+        let (_, body) = impersonate(&app, &admin, EXECUTOR_A_ID, json!({ "reason": "排查问题" })).await;
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+        assert_eq!(body["data"]["expires_in"], 1800, "默认30分钟");
+
+        let (status, _) = send(&app, "POST", "/api/v1/auth/impersonation/end", &admin, Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "普通会话不能结束模拟");
+        let (status, _) = send(&app, "POST", "/api/v1/auth/impersonation/end", &token, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(send(&app, "GET", "/api/v1/auth/me", &token, Value::Null).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&app, "GET", "/api/v1/auth/me", &admin, Value::Null).await.0, StatusCode::OK);
+
+        let ended: (i64, String) = sqlx::query_as(
+            "SELECT actor_id, target_id FROM audit_logs WHERE action = 'auth.impersonation_ended'",
+        )
+        .fetch_one(&database.pool)
+        .await
+        .unwrap();
+        assert_eq!(ended, (ADMIN_ID, EXECUTOR_A_ID.to_string()));
+    }
+}
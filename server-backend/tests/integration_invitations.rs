@@ -0,0 +1,384 @@
+// API集成测试 - 成员邀请
+// 验证邀请链接的发出、预览、接受和一次性使用,公司名额在发出和接受邀请时的检查,
+// 以及邀请的权限范围、重新邀请和过期
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    errors::AppError,
+    middleware::client::ClientInfo,
+    models::{CreateInvitationRequest, InvitationInfo, UserInfo, UserRole},
+    server::create_app,
+    services::{invitation::InvitationService, role::RoleService, session::SessionService},
+    utils::mailer::{EmailMessage, Mailer},
+    Config, Database,
+};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const ADMIN_ID: i64 = 1;
+const PM_A_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "https://farm.example.com".to_string(),
+    }
+}
+
+/// 平台管理员、公司A(最多3人)的项目经理和执行者、公司B的项目经理
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE companies (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            code TEXT UNIQUE,
+            description TEXT,
+            contact_email TEXT,
+            contact_phone TEXT,
+            max_employees INTEGER DEFAULT 10,
+            is_active BOOLEAN DEFAULT TRUE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        "INSERT INTO companies (id, name, code, max_employees) VALUES (1, '公司A', 'ACME', 3), (2, '公司B', 'BETA', 10)",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+    database.create_invitations().await.expect("Failed to create invitations");
+
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(1)),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+        (PM_B_ID, "pm_b", "project_manager", Some(2)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+/// 把邮件保存在内存中的发送方式
+#[derive(Clone, Default)]
+struct CapturingMailer {
+    sent: Arc<Mutex<Vec<EmailMessage>>>,
+}
+
+impl Mailer for CapturingMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            self.sent.lock().unwrap().push(message.clone());
+            Ok(())
+        })
+    }
+}
+
+impl CapturingMailer {
+    /// 最后一封邮件中邀请链接携带的令牌
+    fn last_token(&self) -> String {
+        let body = self.sent.lock().unwrap().last().expect("没有发出邮件").body.clone();
+        let prefix = "https://farm.example.com/accept-invitation?token=";
+        let start = body.find(prefix).expect("正文中缺少链接") + prefix.len();
+        body[start..].split_whitespace().next().unwrap().to_string()
+    }
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+async fn user_info(database: &Database, user_id: i64) -> UserInfo {
+    let user = sqlx::query_as("SELECT * FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(&database.pool)
+        .await
+        .unwrap();
+    RoleService::new(database.clone()).user_info(user).await.unwrap()
+}
+
+/// 以公司A项目经理的身份邀请执行者,返回邀请记录和邀请令牌
+async fn invite(database: &Database, email: &str) -> Result<(InvitationInfo, String), AppError> {
+    let mailer = CapturingMailer::default();
+    let service = InvitationService::with_mailer(database.clone(), test_config(), Box::new(mailer.clone()));
+    let request = CreateInvitationRequest {
+        email: email.to_string(),
+        role: UserRole::TaskExecutor,
+        company_id: None,
+        full_name: Some("新同事".to_string()),
+    };
+    let invitation = service
+        .invite(&request, &user_info(database, PM_A_ID).await, &ClientInfo::default())
+        .await?;
+    Ok((invitation, mailer.last_token()))
+}
+
+fn accept_body(token: &str, username: &str) -> Value {
+    json!({ "token": token, "username": username, "password": "invited-pass-1", "phone": "13800000000" })
+}
+
+#[cfg(test)]
+mod invitation_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invite_preview_and_accept() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let (invitation, token) = invite(&database, "New.Member@Example.com").await.unwrap();
+        assert_eq!(invitation.status, "pending");
+        assert_eq!(invitation.email, "new.member@example.com");
+        assert_eq!(invitation.company_id, 1);
+
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation", None, json!({ "token": token })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["company_name"], "公司A");
+        assert_eq!(body["data"]["role"], "task_executor");
+        assert_eq!(body["data"]["email"], "new.member@example.com");
+
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept_body(&token, "newbie")).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(body["data"]["username"], "newbie");
+        assert_eq!(body["data"]["full_name"], "新同事");
+        assert_eq!(body["data"]["role"], "task_executor");
+        assert_eq!(body["data"]["company_id"], 1);
+        assert_eq!(body["data"]["parent_id"], PM_A_ID);
+        let user_id = body["data"]["id"].as_i64().unwrap();
+        let (verified, phone): (bool, String) = sqlx::query_as("SELECT is_verified, phone FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert!(verified, "受邀邮箱视为已验证");
+        assert_eq!(phone, "13800000000");
+
+        // 邀请只能使用一次
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept_body(&token, "newbie2")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1003);
+
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let (status, body) = send(&app, "GET", "/api/v1/invitations", Some(&pm), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"][0]["status"], "accepted");
+        assert_eq!(body["data"][0]["accepted_user_id"], user_id);
+
+        let (status, _) = send(&app, "POST", "/api/v1/invitations", Some(&pm), json!({ "email": "pm_b@example.com", "role": "task_executor" })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "已注册的邮箱不能邀请");
+    }
+
+    #[tokio::test]
+    async fn test_seat_limit_enforced_on_issue_and_accept() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+
+        // 公司A最多3人,已有2人;待接受的邀请占用第3个名额
+        let (first, _) = invite(&database, "first@example.com").await.unwrap();
+        let err = invite(&database, "second@example.com").await.unwrap_err();
+        assert!(matches!(err, AppError::SeatLimitExceeded(3)));
+
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/api/v1/users",
+            Some(&pm),
+            json!({ "username": "direct", "email": "direct@example.com", "password": "direct-pass-1", "role": "task_executor", "full_name": "" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        assert_eq!(body["code"], 4007);
+
+        // 撤销后名额释放
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/invitations/{}", first.id), Some(&pm), Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, token) = invite(&database, "second@example.com").await.unwrap();
+
+        // 接受前名额被占满(如平台管理员调低了上限),接受失败且邀请仍然有效
+        sqlx::query("UPDATE companies SET max_employees = 2 WHERE id = 1").execute(&database.pool).await.unwrap();
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept_body(&token, "second")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], 4007);
+
+        sqlx::query("UPDATE users SET is_active = 0 WHERE id = ?").bind(EXECUTOR_A_ID).execute(&database.pool).await.unwrap();
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept_body(&token, "second")).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        // 名额已满时不能重新启用被禁用的账号
+        let (status, body) = send(&app, "PUT", &format!("/api/v1/users/{}", EXECUTOR_A_ID), Some(&pm), json!({ "is_active": true })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_invitation_permissions_reinvite_and_expiry() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+
+        let invitation = json!({ "email": "someone@example.com", "role": "task_executor" });
+        let (status, _) = send(&app, "POST", "/api/v1/invitations", Some(&executor), invitation.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, "POST", "/api/v1/invitations", Some(&pm_a), json!({ "email": "pm@example.com", "role": "project_manager" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "只能邀请权限比自己少的角色");
+        let (status, _) = send(&app, "POST", "/api/v1/invitations", Some(&admin), invitation.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "平台管理员需指定公司");
+        let (status, body) = send(&app, "POST", "/api/v1/invitations", Some(&admin), json!({ "email": "pm@example.com", "role": "project_manager", "company_id": 2 })).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        let invitation_b = body["data"]["id"].as_i64().unwrap();
+
+        // 其他公司的邀请不可见也不能撤销
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/invitations/{}", invitation_b), Some(&pm_a), Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, "GET", "/api/v1/invitations?company_id=2", Some(&pm_a), Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = send(&app, "GET", "/api/v1/invitations", Some(&pm_b), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+
+        // 重新邀请同一邮箱时原链接作废
+        let (_, old_token) = invite(&database, "someone@example.com").await.unwrap();
+        let (_, new_token) = invite(&database, "someone@example.com").await.unwrap();
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation", None, json!({ "token": old_token })).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1003);
+
+        // 过期的邀请
+        sqlx::query("UPDATE invitations SET expires_at = '2000-01-01 00:00:00' WHERE email = 'someone@example.com' AND revoked_at IS NULL")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept_body(&new_token, "someone")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1002);
+
+        // 公司停用后不能接受邀请
+        let (_, token) = invite(&database, "late@example.com").await.unwrap();
+        sqlx::query("UPDATE companies SET is_active = 0 WHERE id = 1").execute(&database.pool).await.unwrap();
+        let (status, _) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept_body(&token, "late")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
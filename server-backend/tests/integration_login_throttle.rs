@@ -0,0 +1,347 @@
+// API集成测试 - 登录防爆破
+// 验证统一的登录错误、按账号/按IP的失败计数与锁定、退避等待、解锁权限以及审计日志
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo, models::UserRole, server::create_app, services::session::SessionService, Config,
+    Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "password123";
+
+const ADMIN_ID: i64 = 1;
+const PM_A_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const EXECUTOR_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 3,
+        login_ip_max_attempts: 5,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 平台管理员、公司A的项目经理和执行者、公司B的执行者
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to create users table");
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database
+        .create_two_factor_tables()
+        .await
+        .expect("Failed to create two factor tables");
+    for ddl in [
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let hashed = bcrypt::hash(PASSWORD, 4).unwrap();
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(1)),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+        (EXECUTOR_B_ID, "exec_b", "task_executor", Some(2)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(&hashed)
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, uri: &str, ip: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("X-Real-IP", ip);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn login(app: &Router, username: &str, password: &str, ip: &str) -> (StatusCode, Value) {
+    send(
+        app,
+        "/api/v1/auth/login",
+        ip,
+        None,
+        json!({ "username": username, "password": password }),
+    )
+    .await
+}
+
+async fn token_for(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+async fn audit_actions(database: &Database) -> Vec<(String, Option<String>, Option<i64>)> {
+    sqlx::query_as("SELECT action, target_id, actor_id FROM audit_logs ORDER BY id")
+        .fetch_all(&database.pool)
+        .await
+        .unwrap()
+}
+
+#[cfg(test)]
+mod login_throttle_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_login_errors_are_uniform() {
+        let database = setup_database().await;
+        let app = create_app(database, test_config()).await;
+
+        let (status, unknown) = login(&app, "nobody", PASSWORD, "198.51.100.1").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, wrong) = login(&app, "exec_a", "wrong-password", "198.51.100.1").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        assert_eq!(unknown["code"], 1001);
+        assert_eq!(unknown["code"], wrong["code"]);
+        assert_eq!(unknown["message"], wrong["message"], "不应暴露账号是否存在");
+    }
+
+    #[tokio::test]
+    async fn test_account_locked_after_repeated_failures() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        for _ in 0..3 {
+            let (status, _) = login(&app, "exec_a", "wrong-password", "198.51.100.1").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        // 锁定期间即使密码正确、通过邮箱登录也会被拒绝
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/auth/login")
+                    .header("Content-Type", "application/json")
+                    .header("X-Real-IP", "198.51.100.2")
+                    .body(Body::from(json!({ "username": "exec_a@example.com", "password": PASSWORD }).to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key("retry-after"));
+
+        let (status, _) = login(&app, "pm_a", PASSWORD, "198.51.100.1").await;
+        assert_eq!(status, StatusCode::OK, "其他账号不受影响");
+
+        assert_eq!(
+            audit_actions(&database).await,
+            vec![("auth.account_locked".to_string(), Some(EXECUTOR_A_ID.to_string()), None)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_account_locks_like_existing_one() {
+        let database = setup_database().await;
+        let app = create_app(database, test_config()).await;
+
+        for _ in 0..3 {
+            let (status, _) = login(&app, "nobody", PASSWORD, "198.51.100.1").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        let (status, body) = login(&app, "NOBODY", PASSWORD, "198.51.100.2").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["code"], 1008);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_between_failures() {
+        let database = setup_database().await;
+        let config = Config {
+            login_backoff_seconds: 60,
+            ..test_config()
+        };
+        let app = create_app(database, config).await;
+
+        let (status, _) = login(&app, "exec_a", "wrong-password", "198.51.100.1").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = login(&app, "exec_a", PASSWORD, "198.51.100.1").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "退避期内不允许再次尝试");
+        assert_eq!(body["code"], 1008);
+    }
+
+    #[tokio::test]
+    async fn test_ip_locked_across_accounts() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        for username in ["a1", "a2", "a3", "a4", "a5"] {
+            let (status, _) = login(&app, username, PASSWORD, "198.51.100.9").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        let (status, _) = login(&app, "pm_a", PASSWORD, "198.51.100.9").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "IP锁定后该IP的所有登录都被拒绝");
+        let (status, _) = login(&app, "pm_a", PASSWORD, "198.51.100.10").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let actions = audit_actions(&database).await;
+        assert!(actions.contains(&("auth.ip_locked".to_string(), Some("198.51.100.9".to_string()), None)));
+    }
+
+    #[tokio::test]
+    async fn test_unlock_permissions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        for username in ["exec_a", "exec_b"] {
+            for _ in 0..3 {
+                login(&app, username, "wrong-password", "198.51.100.1").await;
+            }
+        }
+
+        let pm_token = token_for(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let executor_token = token_for(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+        let admin_token = token_for(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+
+        let unlock = |user_id: i64| format!("/api/v1/users/{}/unlock", user_id);
+
+        let (status, _) = send(&app, &unlock(EXECUTOR_B_ID), "198.51.100.1", Some(&pm_token), json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "项目经理不能解锁其他公司的用户");
+        let (status, _) = send(&app, &unlock(EXECUTOR_A_ID), "198.51.100.1", Some(&executor_token), json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "执行者不能解锁用户");
+
+        let (status, _) = send(&app, &unlock(EXECUTOR_A_ID), "198.51.100.1", Some(&pm_token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(&app, &unlock(EXECUTOR_B_ID), "198.51.100.1", Some(&admin_token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        for username in ["exec_a", "exec_b"] {
+            let (status, _) = login(&app, username, PASSWORD, "198.51.100.2").await;
+            assert_eq!(status, StatusCode::OK, "解锁后 {} 可以登录", username);
+        }
+
+        let unlocks: Vec<_> = audit_actions(&database)
+            .await
+            .into_iter()
+            .filter(|(action, _, _)| action == "auth.account_unlocked")
+            .collect();
+        assert_eq!(
+            unlocks,
+            vec![
+                ("auth.account_unlocked".to_string(), Some(EXECUTOR_A_ID.to_string()), Some(PM_A_ID)),
+                ("auth.account_unlocked".to_string(), Some(EXECUTOR_B_ID.to_string()), Some(ADMIN_ID)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+// API集成测试 - Prometheus监控指标
+// 验证 /metrics 的访问控制、HTTP/SQL/WebSocket/业务指标的采集
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    metrics::{metrics_router, QueryMetricsLayer, WebSocketConnectionGuard, METRICS},
+    server::create_app,
+    Config, Database,
+};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+use tracing_subscriber::layer::SubscriberExt;
+
+const METRICS_TOKEN: &str = "scrape-token";
+
+fn test_config(metrics_token: Option<&str>, metrics_bind: Option<&str>) -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 3600,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 5,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: metrics_token.map(str::to_string),
+        metrics_bind: metrics_bind.map(str::to_string),
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 公司1: 2个用户(1个已禁用), 3个任务(2个未完成); 公司2: 1个用户, 1个已完成任务
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            company_id INTEGER
+        )
+        "#,
+        r#"
+        CREATE TABLE tasks (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            company_id INTEGER,
+            project_id TEXT,
+            assigned_to TEXT,
+            created_by TEXT NOT NULL,
+            estimated_hours REAL,
+            actual_hours REAL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        r#"
+        CREATE TABLE work_logs (
+            id TEXT PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            hours REAL NOT NULL,
+            logged_at DATE DEFAULT (date('now'))
+        )
+        "#,
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database
+        .create_statistics_rollups()
+        .await
+        .expect("Failed to create statistics rollups");
+
+    for (username, is_active, company_id) in [("pm_1", true, 1), ("disabled_1", false, 1), ("pm_2", true, 2)] {
+        sqlx::query("INSERT INTO users (username, hashed_password, role, is_active, company_id) VALUES (?, 'x', 'project_manager', ?, ?)")
+            .bind(username)
+            .bind(is_active)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    for (id, status, company_id) in [
+        ("t1", "pending", 1),
+        ("t2", "in_progress", 1),
+        ("t3", "completed", 1),
+        ("t4", "completed", 2),
+    ] {
+        sqlx::query("INSERT INTO tasks (id, title, status, company_id, created_by) VALUES (?, ?, ?, ?, '1')")
+            .bind(id)
+            .bind(id)
+            .bind(status)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn get(app: Router, uri: &str, token: Option<&str>) -> (StatusCode, String) {
+    let mut request = Request::builder().uri(uri);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// 从指标文本中读取某个样本的值
+fn sample_value(body: &str, sample: &str) -> Option<f64> {
+    body.lines()
+        .find_map(|line| line.strip_prefix(sample))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_not_exposed_without_token_or_bind() {
+        let database = setup_database().await;
+        let app = create_app(database, test_config(None, None)).await;
+
+        let (status, _) = get(app, "/metrics", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "未配置令牌或单独地址时主端口不提供指标");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_require_token_on_main_port() {
+        let database = setup_database().await;
+        let app = create_app(database, test_config(Some(METRICS_TOKEN), None)).await;
+
+        let (status, _) = get(app.clone(), "/metrics", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = get(app.clone(), "/metrics", Some("wrong-token")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = get(app.clone(), "/health", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = get(app, "/metrics", Some(METRICS_TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            sample_value(
+                &body,
+                r#"flow_farm_http_requests_total{method="GET",route="/health",status="200"}"#
+            )
+            .is_some_and(|count| count >= 1.0),
+            "应记录按路由和状态码的请求数"
+        );
+        assert!(body.contains(r#"flow_farm_http_request_duration_seconds_bucket{method="GET",route="/health""#));
+        assert_eq!(sample_value(&body, r#"flow_farm_open_tasks{company_id="1"}"#), Some(2.0));
+        assert_eq!(sample_value(&body, r#"flow_farm_open_tasks{company_id="2"}"#), Some(0.0));
+        assert_eq!(sample_value(&body, r#"flow_farm_active_users{company_id="1"}"#), Some(1.0));
+        assert!(sample_value(&body, r#"flow_farm_db_pool_connections{state="max"}"#).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_metrics_router_on_dedicated_bind() {
+        let database = setup_database().await;
+        let config = test_config(None, Some("127.0.0.1:9100"));
+
+        // 配置了单独监听地址时主端口不提供指标
+        let app = create_app(database.clone(), config.clone()).await;
+        let (status, _) = get(app, "/metrics", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = get(metrics_router(database, config), "/metrics", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("flow_farm_websocket_connections"));
+    }
+
+    #[tokio::test]
+    async fn test_query_latency_and_websocket_gauge() {
+        // sqlite 语句在 sqlx 的工作线程上执行,需要全局订阅(与 main 中一致)
+        let subscriber = tracing_subscriber::registry().with(QueryMetricsLayer);
+        tracing::subscriber::set_global_default(subscriber).expect("Failed to install subscriber");
+        let database = setup_database().await;
+
+        let before = METRICS.db_query_duration.with_label_values(&["SELECT"]).get_sample_count();
+        sqlx::query("SELECT COUNT(*) FROM tasks")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        let after = METRICS.db_query_duration.with_label_values(&["SELECT"]).get_sample_count();
+        assert!(after > before, "应从 sqlx 语句日志中记录SQL耗时");
+
+        let connections = METRICS.websocket_connections.get();
+        {
+            let _connection = WebSocketConnectionGuard::new();
+            assert_eq!(METRICS.websocket_connections.get(), connections + 1);
+        }
+        assert_eq!(METRICS.websocket_connections.get(), connections);
+    }
+}
@@ -0,0 +1,345 @@
+// API集成测试 - 修改密码与密码策略
+// 验证当前密码校验、长度/字符类别/泄露密码列表/历史密码策略、修改后其他会话失效以及管理员设置密码
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "Password1";
+
+const PM_A_ID: i64 = 1;
+const EXECUTOR_A_ID: i64 = 2;
+const EXECUTOR_B_ID: i64 = 3;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 8,
+        password_required_classes: vec!["lower".to_string(), "upper".to_string(), "digit".to_string()],
+        password_blocklist_file: None,
+        password_history_size: 3,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 公司1的项目经理和执行者、公司2的执行者
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A'), (2, '公司B')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database
+        .create_two_factor_tables()
+        .await
+        .expect("Failed to create two factor tables");
+    database
+        .create_password_history()
+        .await
+        .expect("Failed to create password history");
+
+    let hashed = bcrypt::hash(PASSWORD, 4).unwrap();
+    for (id, username, role, company_id) in [
+        (PM_A_ID, "pm_a", "project_manager", 1),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", 1),
+        (EXECUTOR_B_ID, "exec_b", "task_executor", 2),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(&hashed)
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn token_for(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+async fn change_password(app: &Router, token: &str, old_password: &str, new_password: &str) -> (StatusCode, Value) {
+    send(
+        app,
+        "POST",
+        "/api/v1/auth/change-password",
+        Some(token),
+        json!({ "old_password": old_password, "new_password": new_password }),
+    )
+    .await
+}
+
+async fn login_status(app: &Router, username: &str, password: &str) -> StatusCode {
+    send(
+        app,
+        "POST",
+        "/api/v1/auth/login",
+        None,
+        json!({ "username": username, "password": password }),
+    )
+    .await
+    .0
+}
+
+#[cfg(test)]
+mod password_change_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_change_password_requires_current_and_revokes_other_sessions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let current = token_for(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+        let other = token_for(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let (status, body) = change_password(&app, &current, "wrong-password", "NewHarvest2").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "当前密码错误不应返回401");
+        assert!(body["message"].as_str().unwrap().contains("当前密码错误"));
+
+        let (status, _) = change_password(&app, &current, PASSWORD, "NewHarvest2").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(&current), json!({})).await;
+        assert_eq!(status, StatusCode::OK, "当前会话保持有效");
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(&other), json!({})).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "其他会话失效");
+
+        assert_eq!(login_status(&app, "exec_a", PASSWORD).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(login_status(&app, "exec_a", "NewHarvest2").await, StatusCode::OK);
+
+        let actions: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_logs WHERE target_id = '2'")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        assert!(actions.contains(&"auth.password_changed".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_policy_and_password_history() {
+        let database = setup_database().await;
+        let blocklist = std::env::temp_dir().join(format!("flow-farm-blocklist-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&blocklist, "# 泄露密码\nSummer2024\n").unwrap();
+        let config = Config {
+            password_blocklist_file: Some(blocklist.to_string_lossy().to_string()),
+            ..test_config()
+        };
+        let app = create_app(database.clone(), config).await;
+        let token = token_for(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let (status, body) = change_password(&app, &token, PASSWORD, "short").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let message = body["message"].as_str().unwrap();
+        assert!(message.contains("至少8个字符") && message.contains("大写字母") && message.contains("数字"));
+
+        let (status, body) = change_password(&app, &token, PASSWORD, "summer2024").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().contains("大写字母"));
+        let (status, body) = change_password(&app, &token, PASSWORD, "SUMMER2024a").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (status, body) = change_password(&app, &token, "SUMMER2024a", "Summer2024").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().contains("泄露密码"));
+
+        // 最近3次(含当前)的密码都不能再用
+        let (status, body) = change_password(&app, &token, "SUMMER2024a", PASSWORD).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().contains("最近3次"));
+        let (status, _) = change_password(&app, &token, "SUMMER2024a", "Harvest2025").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = change_password(&app, &token, "Harvest2025", "SUMMER2024a").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = change_password(&app, &token, "Harvest2025", "Planting2026").await;
+        assert_eq!(status, StatusCode::OK);
+
+        // 历史只保留 history_size - 1 条,最早的密码可以重新使用
+        let history: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM password_history WHERE user_id = ?")
+            .bind(EXECUTOR_A_ID)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(history, 2);
+        let (status, _) = change_password(&app, &token, "Planting2026", PASSWORD).await;
+        assert_eq!(status, StatusCode::OK);
+
+        std::fs::remove_file(&blocklist).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_manager_sets_password_for_employee() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_token = token_for(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let executor_token = token_for(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        // 通过更新接口不能修改自己的密码
+        let (status, _) = send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}", EXECUTOR_A_ID),
+            Some(&executor_token),
+            json!({ "password": "Harvest2025" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}", EXECUTOR_B_ID),
+            Some(&pm_token),
+            json!({ "password": "Harvest2025" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "不能设置其他公司用户的密码");
+
+        // 不满足策略时其他字段也不修改
+        let (status, _) = send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}", EXECUTOR_A_ID),
+            Some(&pm_token),
+            json!({ "password": "weak", "full_name": "改名" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let full_name: String = sqlx::query_scalar("SELECT full_name FROM users WHERE id = ?")
+            .bind(EXECUTOR_A_ID)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(full_name, "exec_a");
+
+        let (status, _) = send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}", EXECUTOR_A_ID),
+            Some(&pm_token),
+            json!({ "password": "Harvest2025" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(&executor_token), json!({})).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "被设置密码的用户所有会话失效");
+        assert_eq!(login_status(&app, "exec_a", "Harvest2025").await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,245 @@
+// API集成测试 - 密码哈希算法
+// 验证新密码使用 Argon2id、旧的 bcrypt 哈希仍可登录并在登录成功后按当前配置重新哈希
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "Password1";
+const USER_ID: i64 = 1;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 3,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 一个使用给定密码哈希的执行者账号
+async fn setup_database(hashed_password: &str) -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database
+        .create_two_factor_tables()
+        .await
+        .expect("Failed to create two factor tables");
+    database
+        .create_password_history()
+        .await
+        .expect("Failed to create password history");
+
+    sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, 'exec_a', 'exec_a@example.com', ?, 'task_executor', 'exec_a', 1)")
+        .bind(USER_ID)
+        .bind(hashed_password)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    database
+}
+
+async fn send(app: &Router, uri: &str, token: Option<&str>, body: Value) -> StatusCode {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    app.clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn login_status(app: &Router, password: &str) -> StatusCode {
+    send(app, "/api/v1/auth/login", None, json!({ "username": "exec_a", "password": password })).await
+}
+
+async fn stored_hash(database: &Database) -> String {
+    sqlx::query_scalar("SELECT hashed_password FROM users WHERE id = ?")
+        .bind(USER_ID)
+        .fetch_one(&database.pool)
+        .await
+        .unwrap()
+}
+
+#[cfg(test)]
+mod password_hashing_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_legacy_bcrypt_hash_upgraded_on_login() {
+        let legacy = bcrypt::hash(PASSWORD, 4).unwrap();
+        let database = setup_database(&legacy).await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        // 密码错误时不升级
+        assert_eq!(login_status(&app, "wrong-password").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(stored_hash(&database).await, legacy);
+
+        assert_eq!(login_status(&app, PASSWORD).await, StatusCode::OK);
+        let upgraded = stored_hash(&database).await;
+        assert!(upgraded.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"), "{}", upgraded);
+
+        // 升级后的哈希可以继续登录,且不会再次重新哈希
+        assert_eq!(login_status(&app, PASSWORD).await, StatusCode::OK);
+        assert_eq!(stored_hash(&database).await, upgraded);
+        assert_eq!(login_status(&app, "wrong-password").await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_rehash_when_parameters_change() {
+        let database = setup_database(&bcrypt::hash(PASSWORD, 4).unwrap()).await;
+        let app = create_app(database.clone(), test_config()).await;
+        assert_eq!(login_status(&app, PASSWORD).await, StatusCode::OK);
+
+        // 调高 Argon2id 参数
+        let mut stronger = test_config();
+        stronger.argon2_memory_kib = 2048;
+        stronger.argon2_iterations = 2;
+        let app = create_app(database.clone(), stronger).await;
+        assert_eq!(login_status(&app, PASSWORD).await, StatusCode::OK);
+        assert!(stored_hash(&database).await.starts_with("$argon2id$v=19$m=2048,t=2,p=1$"));
+
+        // 配置为 bcrypt 时按配置的成本因子重新哈希
+        let mut bcrypt_config = test_config();
+        bcrypt_config.password_hash_algorithm = "bcrypt".to_string();
+        bcrypt_config.bcrypt_rounds = 5;
+        let app = create_app(database.clone(), bcrypt_config).await;
+        assert_eq!(login_status(&app, PASSWORD).await, StatusCode::OK);
+        assert!(stored_hash(&database).await.starts_with("$2b$05$"));
+        assert_eq!(login_status(&app, PASSWORD).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_new_password_stored_as_argon2id() {
+        let database = setup_database(&bcrypt::hash(PASSWORD, 4).unwrap()).await;
+        let app = create_app(database.clone(), test_config()).await;
+        let token = SessionService::new(database.clone(), test_config())
+            .create_session(USER_ID, &UserRole::TaskExecutor, &ClientInfo::default())
+            .await
+            .expect("Failed to create session")
+            .token;
+
+        let status = send(
+            &app,
+            "/api/v1/auth/change-password",
+            Some(&token),
+            json!({ "old_password": PASSWORD, "new_password": "harvest-2024" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(stored_hash(&database).await.starts_with("$argon2id$"));
+        assert_eq!(login_status(&app, "harvest-2024").await, StatusCode::OK);
+
+        // 历史中的 bcrypt 哈希仍参与重复密码检查
+        let status = send(
+            &app,
+            "/api/v1/auth/change-password",
+            Some(&token),
+            json!({ "old_password": "harvest-2024", "new_password": PASSWORD }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
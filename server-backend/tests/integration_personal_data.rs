@@ -0,0 +1,416 @@
+// API集成测试 - 个人数据导出与删除
+// 验证个人数据导出的内容与权限,以及删除用户时匿名化账号、清除登录凭据并保留任务历史
+
+use std::io::{Cursor, Read};
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+use zip::ZipArchive;
+
+const ADMIN_ID: i64 = 1;
+const PM_A_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 公司A的项目经理和执行者、公司B的项目经理,以及执行者A参与的任务和工作记录
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE companies (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            code TEXT UNIQUE,
+            is_active BOOLEAN DEFAULT TRUE,
+            require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0
+        )
+        "#,
+        r#"
+        CREATE TABLE tasks (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            description TEXT NOT NULL DEFAULT '',
+            status TEXT NOT NULL DEFAULT 'pending',
+            priority TEXT NOT NULL DEFAULT 'medium',
+            company_id INTEGER,
+            project_id TEXT,
+            assigned_to TEXT,
+            created_by TEXT NOT NULL,
+            due_date TEXT,
+            estimated_hours REAL,
+            actual_hours REAL,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            completed_at DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE work_logs (
+            id TEXT PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            hours REAL NOT NULL,
+            notes TEXT,
+            logged_at TEXT NOT NULL DEFAULT (date('now')),
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        "INSERT INTO companies (id, name, code) VALUES (1, '公司A', 'ACME'), (2, '公司B', 'BETA')",
+        r#"
+        INSERT INTO tasks (id, title, company_id, assigned_to, created_by, status) VALUES
+            ('task-assigned', '分配给执行者的任务', 1, '3', '2', 'completed'),
+            ('task-created', '执行者创建的任务', 1, NULL, '3', 'pending'),
+            ('task-other', '其他人的任务', 1, '2', '2', 'pending')
+        "#,
+        r#"
+        INSERT INTO work_logs (id, task_id, user_id, hours, notes) VALUES
+            ('log-1', 'task-assigned', '3', 2.5, '完成了首页'),
+            ('log-2', 'task-other', '2', 1.0, '经理的记录')
+        "#,
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_two_factor_tables().await.expect("Failed to create two factor tables");
+    database.create_email_tokens().await.expect("Failed to create email tokens");
+    database.create_api_tokens().await.expect("Failed to create api tokens");
+    database.create_sso_tables().await.expect("Failed to create sso tables");
+    database.create_invitations().await.expect("Failed to create invitations");
+    database.create_password_history().await.expect("Failed to create password history");
+
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(1)),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+        (PM_B_ID, "pm_b", "project_manager", Some(2)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, phone, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(format!("1380000000{}", id))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: &str) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    let client = ClientInfo {
+        ip_address: Some("203.0.113.7".to_string()),
+        user_agent: Some("export-test".to_string()),
+        ..ClientInfo::default()
+    };
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &client)
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+/// 下载导出文件并解析其中的 JSON 文件
+async fn export(app: &Router, user_id: i64, token: &str) -> Result<Vec<(String, Value)>, StatusCode> {
+    let request = Request::builder()
+        .uri(format!("/api/v1/users/{}/export", user_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    if response.status() != StatusCode::OK {
+        return Err(response.status());
+    }
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/zip");
+    let disposition = response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().to_string();
+    assert!(disposition.starts_with(&format!("attachment; filename=\"personal-data-{}-", user_id)));
+
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let mut archive = ZipArchive::new(Cursor::new(bytes.to_vec())).expect("导出文件应为ZIP");
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        files.push((file.name().to_string(), serde_json::from_str(&content).unwrap()));
+    }
+    Ok(files)
+}
+
+fn file<'a>(files: &'a [(String, Value)], name: &str) -> &'a Value {
+    &files.iter().find(|(file, _)| file == name).unwrap_or_else(|| panic!("缺少 {}", name)).1
+}
+
+fn ids(value: &Value) -> Vec<&str> {
+    value.as_array().unwrap().iter().map(|item| item["id"].as_str().unwrap()).collect()
+}
+
+#[cfg(test)]
+mod personal_data_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_contains_profile_tasks_and_work_logs() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let files = export(&app, EXECUTOR_A_ID, &executor).await.expect("本人可以导出");
+        let mut names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["manifest.json", "profile.json", "tasks.json", "work_logs.json"]);
+
+        let profile = file(&files, "profile.json");
+        assert_eq!(profile["user"]["username"], "exec_a");
+        assert_eq!(profile["user"]["email"], "exec_a@example.com");
+        assert_eq!(profile["user"]["phone"], "13800000003");
+        assert_eq!(profile["user"]["company_name"], "公司A");
+        assert_eq!(profile["sessions"][0]["ip_address"], "203.0.113.7");
+        assert_eq!(ids(file(&files, "tasks.json")), ["task-assigned", "task-created"]);
+        let work_logs = file(&files, "work_logs.json");
+        assert_eq!(ids(work_logs), ["log-1"]);
+        assert_eq!(work_logs[0]["notes"], "完成了首页");
+        assert_eq!(file(&files, "manifest.json")["user_id"], EXECUTOR_A_ID);
+
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs?action=user.data_exported", &session_token(&database, PM_A_ID, UserRole::ProjectManager).await).await;
+        assert_eq!(body["data"][0]["target_id"], EXECUTOR_A_ID.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_export_requires_user_security_permission_in_company() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        assert!(export(&app, EXECUTOR_A_ID, &pm_a).await.is_ok(), "本公司管理者可以导出");
+        assert!(export(&app, EXECUTOR_A_ID, &admin).await.is_ok(), "平台管理员可以导出");
+        assert_eq!(export(&app, EXECUTOR_A_ID, &pm_b).await.unwrap_err(), StatusCode::NOT_FOUND, "其他公司看不到该用户");
+        assert_eq!(export(&app, PM_A_ID, &executor).await.unwrap_err(), StatusCode::NOT_FOUND);
+        assert_eq!(export(&app, 99, &admin).await.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_anonymizes_user_and_keeps_task_history() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+        sqlx::query("INSERT INTO user_identities (user_id, company_id, issuer, subject, email, created_at) VALUES (?, 1, 'https://idp.example.com', 'sub-3', 'exec_a@example.com', '2024-01-01 00:00:00')")
+            .bind(EXECUTOR_A_ID)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO password_history (user_id, hashed_password, created_at) VALUES (?, 'old', '2024-01-01 00:00:00')")
+            .bind(EXECUTOR_A_ID)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+
+        let (status, body) = send(&app, "DELETE", &format!("/api/v1/users/{}", EXECUTOR_A_ID), &pm_a).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        // 用户记录保留但不再包含可识别本人的信息
+        let (username, email, full_name, phone, is_active, hashed_password): (String, String, String, Option<String>, bool, String) =
+            sqlx::query_as("SELECT username, email, full_name, phone, is_active, hashed_password FROM users WHERE id = ?")
+                .bind(EXECUTOR_A_ID)
+                .fetch_one(&database.pool)
+                .await
+                .unwrap();
+        assert_eq!(username, format!("deleted-user-{}", EXECUTOR_A_ID));
+        assert!(email.ends_with("@erased.invalid"));
+        assert_eq!(full_name, "已注销用户");
+        assert!(phone.is_none());
+        assert!(!is_active);
+        assert_eq!(hashed_password, "!");
+
+        // 任务和工作记录仍然指向同一个用户
+        let assigned: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tasks WHERE assigned_to = '3' OR created_by = '3'")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(assigned, 2);
+        let logs: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM work_logs WHERE user_id = '3'")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(logs, 1);
+
+        // 登录凭据和关联身份被清除,会话被撤销且不再保留IP
+        for table in ["user_identities", "password_history"] {
+            let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {} WHERE user_id = ?", table))
+                .bind(EXECUTOR_A_ID)
+                .fetch_one(&database.pool)
+                .await
+                .unwrap();
+            assert_eq!(count, 0, "{} 应被清除", table);
+        }
+        let (revoked_reason, ip_address): (Option<String>, Option<String>) =
+            sqlx::query_as("SELECT revoked_reason, ip_address FROM sessions WHERE user_id = ?")
+                .bind(EXECUTOR_A_ID)
+                .fetch_one(&database.pool)
+                .await
+                .unwrap();
+        assert_eq!(revoked_reason.as_deref(), Some("account_erased"));
+        assert!(ip_address.is_none());
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", &executor).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // 用户列表中显示为已注销用户
+        let (_, body) = send(&app, "GET", &format!("/api/v1/users/{}", EXECUTOR_A_ID), &pm_a).await;
+        assert_eq!(body["data"]["full_name"], "已注销用户");
+        assert_eq!(body["data"]["is_active"], false);
+
+        // 注销事件只记录用户ID,审计日志中不留下明文个人信息
+        let (_, body) = send(&app, "GET", "/api/v1/audit-logs?action=user.deleted", &pm_a).await;
+        assert_eq!(body["data"][0]["details"], json!({ "user_id": EXECUTOR_A_ID, "tombstone": true }));
+        let details: Vec<String> = sqlx::query_scalar("SELECT details FROM audit_logs WHERE details IS NOT NULL")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        for details in details {
+            assert!(!details.contains("exec_a"), "审计日志包含明文个人信息: {}", details);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_permissions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/users/{}", EXECUTOR_A_ID), &pm_b).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "不能删除其他公司的用户");
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/users/{}", PM_A_ID), &executor).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/users/{}", PM_A_ID), &pm_a).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "不能删除自己");
+
+        let username: String = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+            .bind(EXECUTOR_A_ID)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(username, "exec_a");
+    }
+}
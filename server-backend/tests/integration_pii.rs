@@ -0,0 +1,365 @@
+// API集成测试 - 个人信息字段加密
+// 验证邮箱、手机号和公司联系方式加密保存、盲索引登录、明文旧数据迁移和主密钥轮换
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    models::Company,
+    repositories::CompanyRepository,
+    server::create_app,
+    services::pii::PiiService,
+    utils::pii,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tokio::sync::{Mutex, MutexGuard};
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "password123";
+
+const KEY_V1: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+const KEY_V2: &str = "AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=";
+const INDEX_KEY: &str = "AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM=";
+
+/// 密钥环是进程内全局状态,切换密钥的测试需要依次执行
+static KEYRING_LOCK: Mutex<()> = Mutex::const_new(());
+
+fn test_config(active_key: Option<u32>) -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 5,
+        login_ip_max_attempts: 20,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        // 未指定当前密钥时不启用加密
+        pii_keys: if active_key.is_some() {
+            vec![format!("1:{}", KEY_V1), format!("2:{}", KEY_V2)]
+        } else {
+            vec![]
+        },
+        pii_active_key: active_key,
+        pii_index_key: active_key.map(|_| INDEX_KEY.to_string()),
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 以明文保存的旧数据: 两个用户和一个公司
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to create users table");
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database
+        .create_two_factor_tables()
+        .await
+        .expect("Failed to create two factor tables");
+    database.create_sso_tables().await.expect("Failed to create sso tables");
+    database.create_invitations().await.expect("Failed to create invitations");
+    for ddl in [
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, code TEXT UNIQUE, join_code TEXT UNIQUE, contact_email TEXT, contact_phone TEXT, max_employees INTEGER DEFAULT 10, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0, created_at DATETIME DEFAULT CURRENT_TIMESTAMP, updated_at DATETIME DEFAULT CURRENT_TIMESTAMP)",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        "INSERT INTO companies (id, name, code, contact_email, contact_phone) VALUES (1, 'Company A', 'company_a', 'contact@a.example.com', '010-12345678')",
+        "INSERT INTO invitations (id, company_id, email, role, token_hash, invited_by, created_at, expires_at) VALUES (1, 1, 'invitee@example.com', 'task_executor', 'hash', 1, '2026-01-01T00:00:00Z', '2099-01-01T00:00:00Z')",
+        "INSERT INTO user_identities (id, user_id, company_id, issuer, subject, email, created_at) VALUES (1, 2, 1, 'https://idp.example.com', 'sub-2', 'exec_a@idp.example.com', '2026-01-01T00:00:00Z')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let hashed = bcrypt::hash(PASSWORD, 4).unwrap();
+    for (id, username, role, phone) in [
+        (1, "pm_a", "project_manager", "13800000001"),
+        (2, "exec_a", "task_executor", "13800000002"),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, phone, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, ?, ?, ?, 1)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(phone)
+            .bind(&hashed)
+            .bind(role)
+            .bind(username)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+/// 按 `active_key` 启用密钥环并创建应用,返回的锁在测试结束前保持持有
+async fn setup(active_key: Option<u32>) -> (MutexGuard<'static, ()>, Database, Router) {
+    let guard = KEYRING_LOCK.lock().await;
+    let database = setup_database().await;
+    let app = create_app(database.clone(), test_config(active_key)).await;
+    (guard, database, app)
+}
+
+async fn login(app: &Router, username: &str) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/auth/login")
+                .header("Content-Type", "application/json")
+                .header("X-Real-IP", "198.51.100.1")
+                .body(Body::from(json!({ "username": username, "password": PASSWORD }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn current_user(app: &Router, token: &str) -> Value {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/auth/me")
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+/// 数据库中实际保存的 (邮箱, 手机号, 邮箱索引, 手机号索引)
+async fn stored_user(database: &Database, user_id: i64) -> (String, String, Option<String>, Option<String>) {
+    sqlx::query_as("SELECT email, phone, email_index, phone_index FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(&database.pool)
+        .await
+        .unwrap()
+}
+
+async fn stored_company(database: &Database, company_id: i64) -> (String, String) {
+    sqlx::query_as("SELECT contact_email, contact_phone FROM companies WHERE id = ?")
+        .bind(company_id)
+        .fetch_one(&database.pool)
+        .await
+        .unwrap()
+}
+
+#[cfg(test)]
+mod pii_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reencrypt_migrates_plaintext_and_keeps_login_working() {
+        let (_guard, database, app) = setup(Some(1)).await;
+
+        let report = PiiService::new(database.clone()).reencrypt(1).await.unwrap();
+        assert_eq!(report.scanned, 8);
+        assert_eq!(report.updated, 8);
+
+        let (email, phone, email_index, phone_index) = stored_user(&database, 2).await;
+        assert!(email.starts_with("pii:v1:"), "邮箱应加密保存: {}", email);
+        assert!(phone.starts_with("pii:v1:"), "手机号应加密保存: {}", phone);
+        assert!(!email.contains("exec_a"));
+        assert_eq!(email_index, pii::blind_index("exec_a@example.com"));
+        assert_eq!(phone_index, pii::blind_index("13800000002"));
+        let (contact_email, contact_phone) = stored_company(&database, 1).await;
+        assert_eq!(pii::key_version(&contact_email), Some(1));
+        assert_eq!(pii::key_version(&contact_phone), Some(1));
+        let (invitation_email, invitation_index): (String, Option<String>) =
+            sqlx::query_as("SELECT email, email_index FROM invitations WHERE id = 1")
+                .fetch_one(&database.pool)
+                .await
+                .unwrap();
+        assert_eq!(pii::key_version(&invitation_email), Some(1));
+        assert_eq!(invitation_index, pii::blind_index("invitee@example.com"));
+        let (identity_email,): (String,) = sqlx::query_as("SELECT email FROM user_identities WHERE id = 1")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(pii::open(&identity_email).unwrap(), "exec_a@idp.example.com");
+        assert_ne!(identity_email, "exec_a@idp.example.com");
+
+        // 已是当前密钥加密的数据不会重复处理
+        let report = PiiService::new(database.clone()).reencrypt(100).await.unwrap();
+        assert_eq!(report.updated, 0);
+
+        // 通过盲索引按邮箱(不区分大小写)和手机号登录
+        for identifier in ["exec_a", "Exec_A@Example.com", "13800000002"] {
+            let (status, body) = login(&app, identifier).await;
+            assert_eq!(status, StatusCode::OK, "使用 {} 登录失败: {}", identifier, body);
+        }
+        let (_, body) = login(&app, "exec_a@example.com").await;
+        let me = current_user(&app, body["data"]["token"].as_str().unwrap()).await;
+        assert_eq!(me["data"]["email"], "exec_a@example.com", "读取时应自动解密");
+
+        let company = CompanyRepository::new(database.clone()).find_by_id(1).await.unwrap().unwrap();
+        assert_eq!(company.contact_email.as_deref(), Some("contact@a.example.com"));
+        assert_eq!(company.contact_phone.as_deref(), Some("010-12345678"));
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_rows_can_log_in_before_migration() {
+        let (_guard, database, app) = setup(Some(1)).await;
+
+        let (status, _) = login(&app, "pm_a@example.com").await;
+        assert_eq!(status, StatusCode::OK, "尚未重新加密的明文数据仍可登录");
+        let (email, _, email_index, _) = stored_user(&database, 1).await;
+        assert_eq!(email, "pm_a@example.com");
+        assert!(email_index.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_new_records_are_written_encrypted() {
+        let (_guard, database, _app) = setup(Some(1)).await;
+        let repository = CompanyRepository::new(database.clone());
+
+        let now = chrono::Utc::now();
+        let company = repository
+            .create(Company {
+                id: 0,
+                name: "Company B".to_string(),
+                code: "company_b".to_string(),
+                contact_email: Some("contact@b.example.com".to_string()),
+                contact_phone: None,
+                max_employees: 10,
+                is_active: true,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+        assert_eq!(company.contact_email.as_deref(), Some("contact@b.example.com"));
+
+        let stored: (String, Option<String>) =
+            sqlx::query_as("SELECT contact_email, contact_phone FROM companies WHERE id = ?")
+                .bind(company.id)
+                .fetch_one(&database.pool)
+                .await
+                .unwrap();
+        assert_eq!(pii::key_version(&stored.0), Some(1));
+        assert!(stored.1.is_none(), "空值保持为空");
+        // 每次加密使用随机数据密钥,相同的明文得到不同的密文
+        assert_ne!(pii::seal("same@example.com").unwrap(), pii::seal("same@example.com").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_key_rotation_reencrypts_with_active_key() {
+        let (guard, database, _) = setup(Some(1)).await;
+        PiiService::new(database.clone()).reencrypt(100).await.unwrap();
+        let (old_email, _, old_index, _) = stored_user(&database, 1).await;
+        assert_eq!(pii::key_version(&old_email), Some(1));
+        drop(guard);
+
+        // 轮换到第2版主密钥后,旧数据仍可读取,重新加密后全部改用新版本
+        let _guard = KEYRING_LOCK.lock().await;
+        let app = create_app(database.clone(), test_config(Some(2))).await;
+        let (status, _) = login(&app, "pm_a@example.com").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let report = PiiService::new(database.clone()).reencrypt(100).await.unwrap();
+        assert_eq!(report.updated, 8);
+        let (email, phone, email_index, _) = stored_user(&database, 1).await;
+        assert_eq!(pii::key_version(&email), Some(2));
+        assert_eq!(pii::key_version(&phone), Some(2));
+        assert_eq!(email_index, old_index, "盲索引不随主密钥轮换而变化");
+        let (contact_email, _) = stored_company(&database, 1).await;
+        assert_eq!(pii::key_version(&contact_email), Some(2));
+
+        let (status, _) = login(&app, "13800000001").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_without_keys_fields_stay_plaintext() {
+        let (_guard, database, app) = setup(None).await;
+
+        let report = PiiService::new(database.clone()).reencrypt(100).await.unwrap();
+        assert_eq!(report.updated, 0);
+        let (email, _, email_index, _) = stored_user(&database, 2).await;
+        assert_eq!(email, "exec_a@example.com");
+        assert!(email_index.is_none());
+
+        let (status, _) = login(&app, "exec_a@example.com").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,324 @@
+// API集成测试 - 接口限流
+// 验证登录接口按IP限流、认证接口按用户和公司限流、RateLimit-* 与 Retry-After 响应头,
+// 以及被拒绝的请求计入监控指标
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    metrics::METRICS,
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "password123";
+const PM_A_ID: i64 = 1;
+const EXECUTOR_A_ID: i64 = 2;
+const EXECUTOR_B_ID: i64 = 3;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 公司A的项目经理和执行者,公司B的执行者
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A'), (2, '公司B')",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+    database.create_two_factor_tables().await.expect("Failed to create two factor tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+
+    let password = bcrypt::hash(PASSWORD, 4).unwrap();
+    for (id, username, role, company_id) in [
+        (PM_A_ID, "pm_a", "project_manager", 1),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", 1),
+        (EXECUTOR_B_ID, "exec_b", "task_executor", 2),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(&password)
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+    ip: &str,
+    body: Option<Value>,
+) -> (StatusCode, HeaderMap, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("X-Real-IP", ip);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let body = body.map(|body| Body::from(body.to_string())).unwrap_or_else(Body::empty);
+
+    let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, headers, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+fn rejected_count(group: &str, limit: &str) -> u64 {
+    METRICS.rate_limited_requests.with_label_values(&[group, limit]).get()
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_login_limited_per_ip() {
+        let database = setup_database().await;
+        let mut config = test_config();
+        config.rate_limit_auth = "2/60".to_string();
+        let app = create_app(database, config).await;
+        let login = json!({ "username": "pm_a", "password": PASSWORD });
+        let rejected_before = rejected_count("auth", "ip");
+
+        let (status, headers, body) = send(&app, "POST", "/api/v1/auth/login", None, "203.0.113.1", Some(login.clone())).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(headers["ratelimit-limit"], "2");
+        assert_eq!(headers["ratelimit-remaining"], "1");
+        assert_eq!(headers["ratelimit-policy"], "2;w=60");
+
+        // 登录失败同样计数
+        let wrong = json!({ "username": "pm_a", "password": "wrong-password" });
+        let (status, _, _) = send(&app, "POST", "/api/v1/auth/login", None, "203.0.113.1", Some(wrong)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, headers, body) = send(&app, "POST", "/api/v1/auth/login", None, "203.0.113.1", Some(login.clone())).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["code"], 4008);
+        assert_eq!(headers["ratelimit-remaining"], "0");
+        let retry_after: u64 = headers["retry-after"].to_str().unwrap().parse().unwrap();
+        assert!((1..=30).contains(&retry_after), "2/60 的额度约30秒补充一次: {}", retry_after);
+        assert_eq!(rejected_count("auth", "ip"), rejected_before + 1);
+
+        let (status, _, _) = send(&app, "POST", "/api/v1/auth/login", None, "203.0.113.2", Some(login)).await;
+        assert_eq!(status, StatusCode::OK, "其他IP不受影响");
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_request_limited_per_ip() {
+        let database = setup_database().await;
+        let mut config = test_config();
+        config.rate_limit_auth = "2/60".to_string();
+        let app = create_app(database, config).await;
+
+        // 不存在的邮箱同样计数,不能借此无限探测账号
+        for email in ["nobody@example.com", "someone@example.com"] {
+            let (status, _, body) = send(
+                &app,
+                "POST",
+                "/api/v1/auth/password-reset/request",
+                None,
+                "203.0.113.1",
+                Some(json!({ "email": email })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+        }
+        let (status, _, body) = send(
+            &app,
+            "POST",
+            "/api/v1/auth/password-reset/request",
+            None,
+            "203.0.113.1",
+            Some(json!({ "email": "third@example.com" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["code"], 4008);
+    }
+
+    #[tokio::test]
+    async fn test_api_limited_per_user() {
+        let database = setup_database().await;
+        let mut config = test_config();
+        config.rate_limit_user = "3/60".to_string();
+        let app = create_app(database.clone(), config).await;
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        for remaining in ["2", "1", "0"] {
+            let (status, headers, _) = send(&app, "GET", "/api/v1/auth/me", Some(&pm), "203.0.113.1", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(headers["ratelimit-remaining"], remaining);
+        }
+        let (status, headers, _) = send(&app, "GET", "/api/v1/auth/me", Some(&pm), "203.0.113.2", None).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "按用户计数,与来源IP无关");
+        assert!(headers.contains_key("retry-after"));
+
+        let (status, _, _) = send(&app, "GET", "/api/v1/auth/me", Some(&executor), "203.0.113.1", None).await;
+        assert_eq!(status, StatusCode::OK, "其他用户不受影响");
+    }
+
+    #[tokio::test]
+    async fn test_api_limited_per_company() {
+        let database = setup_database().await;
+        let mut config = test_config();
+        config.rate_limit_user = "100/60".to_string();
+        config.rate_limit_company = "3/60".to_string();
+        let app = create_app(database.clone(), config).await;
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+        let other = session_token(&database, EXECUTOR_B_ID, UserRole::TaskExecutor).await;
+        let rejected_before = rejected_count("api", "company");
+
+        for token in [&pm, &executor, &pm] {
+            let (status, _, _) = send(&app, "GET", "/api/v1/auth/me", Some(token), "203.0.113.1", None).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, _, _) = send(&app, "GET", "/api/v1/auth/me", Some(&executor), "203.0.113.1", None).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "同一公司的用户共用额度");
+        assert_eq!(rejected_count("api", "company"), rejected_before + 1);
+
+        let (status, _, _) = send(&app, "GET", "/api/v1/auth/me", Some(&other), "203.0.113.1", None).await;
+        assert_eq!(status, StatusCode::OK, "其他公司不受影响");
+    }
+
+    #[tokio::test]
+    async fn test_throttled_user_does_not_drain_company_quota() {
+        let database = setup_database().await;
+        let mut config = test_config();
+        config.rate_limit_user = "2/60".to_string();
+        config.rate_limit_company = "4/60".to_string();
+        let app = create_app(database.clone(), config).await;
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        for attempt in 0..5 {
+            let (status, _, _) = send(&app, "GET", "/api/v1/auth/me", Some(&pm), "203.0.113.1", None).await;
+            let expected = if attempt < 2 { StatusCode::OK } else { StatusCode::TOO_MANY_REQUESTS };
+            assert_eq!(status, expected, "第{}次请求", attempt + 1);
+        }
+
+        // 被拒绝的请求不占用公司额度,同公司其他用户还剩2次
+        for remaining in ["1", "0"] {
+            let (status, headers, _) = send(&app, "GET", "/api/v1/auth/me", Some(&executor), "203.0.113.1", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(headers["ratelimit-remaining"], remaining);
+        }
+        let (status, _, _) = send(&app, "GET", "/api/v1/auth/me", Some(&executor), "203.0.113.1", None).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
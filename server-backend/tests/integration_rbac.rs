@@ -0,0 +1,400 @@
+// API集成测试 - 权限策略与公司自定义角色
+// 验证用户信息携带有效权限、自定义角色授予和收回权限立即生效、
+// 不能越权授予权限、跨公司访问被拒绝,以及仍在使用的角色不能删除
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PM_A_ID: i64 = 1;
+const EXECUTOR_A_ID: i64 = 2;
+const LEAD_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 公司1的项目经理和两名执行者、公司2的项目经理
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, code TEXT UNIQUE, is_active BOOLEAN DEFAULT 1)",
+        "INSERT INTO companies (id, name, code) VALUES (1, '公司A', 'ACME'), (2, '公司B', 'BETA')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+
+    for (id, username, role, company_id) in [
+        (PM_A_ID, "pm_a", "project_manager", 1),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", 1),
+        (LEAD_A_ID, "lead_a", "task_executor", 1),
+        (PM_B_ID, "pm_b", "project_manager", 2),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+fn permissions(body: &Value) -> Vec<&str> {
+    body["data"]["permissions"]
+        .as_array()
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// 在公司1创建一个"组长"角色: 查看用户和分配任务
+async fn create_lead_role(app: &Router, token: &str) -> i64 {
+    let (status, body) = send(
+        app,
+        "POST",
+        "/api/v1/companies/1/roles",
+        Some(token),
+        json!({ "name": "组长", "permissions": ["company.view", "project.view", "user.view", "task.assign"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    body["data"]["id"].as_i64().unwrap()
+}
+
+#[cfg(test)]
+mod rbac_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_user_info_carries_builtin_permissions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let (status, body) = send(&app, "GET", "/api/v1/auth/me", Some(&pm_a), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        let granted = permissions(&body);
+        assert!(granted.contains(&"task.assign") && granted.contains(&"project.delete"));
+        assert!(!granted.contains(&"platform.manage"));
+
+        let (_, body) = send(&app, "GET", "/api/v1/auth/me", Some(&executor), Value::Null).await;
+        assert_eq!(permissions(&body), vec!["company.view", "project.view"]);
+
+        // 权限清单和公司可用角色
+        let (status, body) = send(&app, "GET", "/api/v1/permissions", Some(&executor), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["data"].as_array().unwrap().iter().any(|p| p["name"] == "task.assign"));
+        let (status, body) = send(&app, "GET", "/api/v1/companies/1/roles", Some(&pm_a), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["builtin"].as_array().unwrap().len(), 3);
+        assert_eq!(send(&app, "GET", "/api/v1/companies/1/roles", Some(&executor), Value::Null).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_custom_role_takes_effect_immediately() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let lead = session_token(&database, LEAD_A_ID, UserRole::TaskExecutor).await;
+        let role_id = create_lead_role(&app, &pm_a).await;
+
+        assert!(!send(&app, "GET", "/api/v1/users", Some(&lead), Value::Null).await.0.is_success());
+
+        let uri = format!("/api/v1/users/{}/custom-role", LEAD_A_ID);
+        let (status, body) = send(&app, "PUT", &uri, Some(&pm_a), json!({ "custom_role_id": role_id })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["custom_role_id"], role_id);
+        assert!(permissions(&body).contains(&"task.assign"));
+
+        // 已登录的会话立即获得新权限,只能看到本公司用户
+        let (_, body) = send(&app, "GET", "/api/v1/auth/me", Some(&lead), Value::Null).await;
+        assert!(permissions(&body).contains(&"user.view"));
+        let (status, body) = send(&app, "GET", "/api/v1/users", Some(&lead), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"].as_array().unwrap().len(), 3);
+
+        // 修改角色后同样立即生效
+        let (status, _) = send(
+            &app,
+            "PUT",
+            &format!("/api/v1/companies/1/roles/{}", role_id),
+            Some(&pm_a),
+            json!({ "name": "组长", "permissions": ["company.view", "project.view"] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!send(&app, "GET", "/api/v1/users", Some(&lead), Value::Null).await.0.is_success());
+
+        // 取消自定义角色后恢复内置角色的权限
+        let (status, body) = send(&app, "PUT", &uri, Some(&pm_a), json!({ "custom_role_id": null })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(permissions(&body), vec!["company.view", "project.view"]);
+
+        let actions: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_logs ORDER BY id")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(actions, vec!["role.created", "role.assigned", "role.updated", "role.assigned"]);
+    }
+
+    #[tokio::test]
+    async fn test_cannot_grant_more_than_own_permissions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+
+        // 平台级权限不能加入自定义角色
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/v1/companies/1/roles",
+            Some(&pm_a),
+            json!({ "name": "超级管理员", "permissions": ["platform.manage"] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/v1/companies/1/roles",
+            Some(&pm_a),
+            json!({ "name": "未知", "permissions": ["task.fly"] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // 项目经理不能创建与自己权限相同的角色
+        let (_, me) = send(&app, "GET", "/api/v1/auth/me", Some(&pm_a), Value::Null).await;
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/v1/companies/1/roles",
+            Some(&pm_a),
+            json!({ "name": "副经理", "permissions": me["data"]["permissions"] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // 拥有角色管理权限的用户也不能授予自己没有的权限
+        let role_id = create_lead_role(&app, &pm_a).await;
+        let (status, _) = send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}/custom-role", LEAD_A_ID),
+            Some(&pm_a),
+            json!({ "custom_role_id": role_id }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let lead = session_token(&database, LEAD_A_ID, UserRole::TaskExecutor).await;
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/api/v1/users",
+            Some(&lead),
+            json!({ "username": "new_exec", "email": "new@example.com", "password": "secret123", "role": "task_executor" }),
+        )
+        .await;
+        assert!(!status.is_success(), "{}", body);
+        assert_eq!(
+            send(&app, "POST", "/api/v1/companies/1/roles", Some(&lead), json!({ "name": "x", "permissions": [] })).await.0,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_company_isolation_and_role_deletion() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let role_id = create_lead_role(&app, &pm_a).await;
+
+        // 其他公司的项目经理不能查看、修改本公司角色,也不能分配给本公司用户
+        assert_eq!(send(&app, "GET", "/api/v1/companies/1/roles", Some(&pm_b), Value::Null).await.0, StatusCode::FORBIDDEN);
+        let (status, body) = send(
+            &app,
+            "DELETE",
+            &format!("/api/v1/companies/1/roles/{}", role_id),
+            Some(&pm_b),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body["message"].as_str().unwrap().contains("role.manage"), "{}", body);
+        let (status, _) = send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}/custom-role", EXECUTOR_A_ID),
+            Some(&pm_b),
+            json!({ "custom_role_id": role_id }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        // 公司2的路径下找不到公司1的角色
+        let (status, _) = send(
+            &app,
+            "DELETE",
+            &format!("/api/v1/companies/2/roles/{}", role_id),
+            Some(&pm_b),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // 角色名称在公司内唯一,仍有用户使用时不能删除
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/v1/companies/1/roles",
+            Some(&pm_a),
+            json!({ "name": "组长", "permissions": [] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}/custom-role", EXECUTOR_A_ID),
+            Some(&pm_a),
+            json!({ "custom_role_id": role_id }),
+        )
+        .await;
+        let uri = format!("/api/v1/companies/1/roles/{}", role_id);
+        assert_eq!(send(&app, "DELETE", &uri, Some(&pm_a), Value::Null).await.0, StatusCode::CONFLICT);
+        send(
+            &app,
+            "PUT",
+            &format!("/api/v1/users/{}/custom-role", EXECUTOR_A_ID),
+            Some(&pm_a),
+            json!({ "custom_role_id": null }),
+        )
+        .await;
+        assert_eq!(send(&app, "DELETE", &uri, Some(&pm_a), Value::Null).await.0, StatusCode::NO_CONTENT);
+    }
+}
+
@@ -0,0 +1,394 @@
+// API集成测试 - 注册策略与公司加入码
+// 验证默认仅限邀请、开放注册时凭加入码自助注册为任务执行者、邮箱域名限制,
+// 禁止注册时邀请也不能接受,以及加入码的查看、重新生成和权限
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const ADMIN_ID: i64 = 1;
+const PM_A_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 平台管理员、公司A(最多3人)的项目经理和执行者、公司B的项目经理,公司C已停用
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            is_verified BOOLEAN DEFAULT FALSE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE companies (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            code TEXT UNIQUE,
+            join_code TEXT UNIQUE,
+            description TEXT,
+            contact_email TEXT,
+            contact_phone TEXT,
+            max_employees INTEGER DEFAULT 10,
+            is_active BOOLEAN DEFAULT TRUE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        r#"
+        INSERT INTO companies (id, name, code, join_code, max_employees, is_active) VALUES
+            (1, '公司A', 'company_a', 'ACMEJOIN22', 3, 1),
+            (2, '公司B', 'company_b', 'BETAJOIN33', 10, 1),
+            (3, '公司C', 'company_c', 'GONEJOIN44', 10, 0)
+        "#,
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+    database.create_email_tokens().await.expect("Failed to create email tokens");
+    database.create_invitations().await.expect("Failed to create invitations");
+
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(1)),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+        (PM_B_ID, "pm_b", "project_manager", Some(2)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn session_token(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+fn register_body(username: &str, email: &str, join_code: &str) -> Value {
+    json!({
+        "username": username,
+        "email": email,
+        "password": "self-signup-1",
+        "full_name": "自助注册",
+        "join_code": join_code,
+        "role": "platform_admin",
+    })
+}
+
+#[cfg(test)]
+mod registration_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_default_policy_is_invite_only() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let (status, body) = send(&app, "GET", "/api/v1/auth/registration-policy", None, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], json!({ "mode": "invite_only" }), "公开接口不返回域名白名单");
+
+        let (status, body) = send(&app, "POST", "/api/v1/auth/register", None, register_body("walkin", "walkin@example.com", "ACMEJOIN22")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 4006);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&database.pool).await.unwrap();
+        assert_eq!(count, 4);
+    }
+
+    #[tokio::test]
+    async fn test_open_registration_with_join_code() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+
+        let (status, body) = send(
+            &app,
+            "PUT",
+            "/api/v1/settings/registration",
+            Some(&admin),
+            json!({ "mode": "open", "allowed_domains": ["@Example.COM", "example.com", "farm.example.org"] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["allowed_domains"], json!(["example.com", "farm.example.org"]));
+
+        let (status, body) = send(&app, "GET", "/api/v1/settings/registration", Some(&admin), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["mode"], "open");
+
+        // 加入码不区分大小写,请求中的角色被忽略
+        let (status, body) = send(&app, "POST", "/api/v1/auth/register", None, register_body("walkin", "walkin@example.com", " acmejoin22 ")).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["role"], "task_executor");
+        assert_eq!(body["data"]["company_id"], 1);
+        assert_eq!(body["data"]["full_name"], "自助注册");
+
+        // 不在白名单中的邮箱域名
+        let (status, body) = send(&app, "POST", "/api/v1/auth/register", None, register_body("outsider", "outsider@other.com", "ACMEJOIN22")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 4006);
+
+        // 无效加入码和已停用公司的加入码返回同样的错误
+        for code in ["NOSUCHCODE", "GONEJOIN44"] {
+            let (status, body) = send(&app, "POST", "/api/v1/auth/register", None, register_body("guesser", "guesser@example.com", code)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["code"], 2001);
+        }
+
+        // 公司A最多3人,第4人无法加入
+        let (status, body) = send(&app, "POST", "/api/v1/auth/register", None, register_body("fourth", "fourth@example.com", "ACMEJOIN22")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], 4007);
+
+        let actions: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_logs ORDER BY id")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        assert!(actions.contains(&"settings.registration_policy_changed".to_string()), "{:?}", actions);
+    }
+
+    #[tokio::test]
+    async fn test_policy_requires_platform_admin() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+
+        let (status, _) = send(&app, "PUT", "/api/v1/settings/registration", Some(&pm), json!({ "mode": "open" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, "GET", "/api/v1/settings/registration", Some(&pm), Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, body) = send(
+            &app,
+            "PUT",
+            "/api/v1/settings/registration",
+            Some(&admin),
+            json!({ "mode": "open", "allowed_domains": ["not a domain"] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 2003);
+
+        let (status, body) = send(&app, "GET", "/api/v1/auth/registration-policy", None, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["mode"], "invite_only");
+    }
+
+    #[tokio::test]
+    async fn test_disabled_blocks_invitation_accept() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let accept = json!({ "token": "unknown-token", "username": "invitee", "password": "invited-pass-1" });
+
+        // 仅限邀请时按令牌校验
+        let (status, _) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept.clone()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(&app, "PUT", "/api/v1/settings/registration", Some(&admin), json!({ "mode": "disabled" })).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(&app, "POST", "/api/v1/auth/invitation/accept", None, accept).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 4006);
+        let (status, _) = send(&app, "POST", "/api/v1/auth/register", None, register_body("walkin", "walkin@example.com", "ACMEJOIN22")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_join_code_management() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let admin = session_token(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let pm_a = session_token(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let pm_b = session_token(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor).await;
+
+        let (status, body) = send(&app, "GET", "/api/v1/companies/1/join-code", Some(&pm_a), Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["join_code"], "ACMEJOIN22");
+
+        // 其他公司的管理者和本公司执行者都不能查看
+        for token in [&pm_b, &executor] {
+            let (status, _) = send(&app, "GET", "/api/v1/companies/1/join-code", Some(token), Value::Null).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            let (status, _) = send(&app, "POST", "/api/v1/companies/1/join-code", Some(token), Value::Null).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+
+        let (status, body) = send(&app, "POST", "/api/v1/companies/1/join-code", Some(&pm_a), Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let new_code = body["data"]["join_code"].as_str().unwrap().to_string();
+        assert_ne!(new_code, "ACMEJOIN22");
+        assert_eq!(new_code.len(), 10);
+        // 单点登录使用的公司代码不受影响
+        let code: String = sqlx::query_scalar("SELECT code FROM companies WHERE id = 1").fetch_one(&database.pool).await.unwrap();
+        assert_eq!(code, "company_a");
+
+        // 旧加入码立即失效
+        let (status, _) = send(&app, "PUT", "/api/v1/settings/registration", Some(&admin), json!({ "mode": "open" })).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send(&app, "POST", "/api/v1/auth/register", None, register_body("walkin", "walkin@anywhere.net", "ACMEJOIN22")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 2001);
+        let (status, body) = send(&app, "POST", "/api/v1/auth/register", None, register_body("walkin", "walkin@anywhere.net", &new_code)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["company_id"], 1);
+
+        // 没有加入码的公司在查看时生成
+        sqlx::query("UPDATE companies SET join_code = NULL WHERE id = 2").execute(&database.pool).await.unwrap();
+        let (status, body) = send(&app, "GET", "/api/v1/companies/2/join-code", Some(&admin), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["join_code"].as_str().unwrap().len(), 10);
+
+        let (status, _) = send(&app, "GET", "/api/v1/companies/99/join-code", Some(&admin), Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_migration_generates_join_codes_for_existing_companies() {
+        let database = setup_database().await;
+        sqlx::query("UPDATE companies SET join_code = NULL WHERE id IN (2, 3)").execute(&database.pool).await.unwrap();
+
+        database.create_company_join_codes().await.unwrap();
+        let rows: Vec<(i64, String, String)> = sqlx::query_as("SELECT id, code, join_code FROM companies ORDER BY id")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(rows[0], (1, "company_a".to_string(), "ACMEJOIN22".to_string()), "已有加入码的公司保持不变");
+        for (id, code, join_code) in &rows[1..] {
+            assert_eq!(join_code.len(), 10, "公司 {} 应生成加入码", id);
+            assert_ne!(join_code, code, "加入码与公司代码分开保存");
+        }
+        assert_ne!(rows[1].2, rows[2].2);
+        assert_eq!(rows[1].1, "company_b");
+
+        // 再次执行不会改动已生成的加入码
+        database.create_company_join_codes().await.unwrap();
+        let join_code: String = sqlx::query_scalar("SELECT join_code FROM companies WHERE id = 2").fetch_one(&database.pool).await.unwrap();
+        assert_eq!(join_code, rows[1].2);
+    }
+}
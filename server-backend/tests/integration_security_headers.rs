@@ -0,0 +1,176 @@
+// API集成测试 - 跨域访问与安全响应头
+// 验证CORS只放行配置的来源(含通配子域名)、所有响应附加安全响应头、
+// /docs 使用单独的内容安全策略,以及只在启用TLS时发送HSTS
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request},
+    Router,
+};
+use flow_farm_backend::{
+    config::{DEFAULT_CONTENT_SECURITY_POLICY, DEFAULT_DOCS_CONTENT_SECURITY_POLICY},
+    server::create_app,
+    Config, Database,
+};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["https://app.example.com".to_string(), "https://*.example.org".to_string()],
+        content_security_policy: DEFAULT_CONTENT_SECURITY_POLICY.to_string(),
+        docs_content_security_policy: DEFAULT_DOCS_CONTENT_SECURITY_POLICY.to_string(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "strict-origin-when-cross-origin".to_string(),
+        hsts_max_age: 31536000,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+async fn setup_app(config: Config) -> Router {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+    create_app(Database { pool }, config).await
+}
+
+async fn request(app: &Router, method: &str, uri: &str, origin: Option<&str>) -> HeaderMap {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(origin) = origin {
+        request = request
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET");
+    }
+    let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+    response.headers().clone()
+}
+
+fn allowed_origin(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .and_then(|value| value.to_str().ok())
+}
+
+#[cfg(test)]
+mod security_headers_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cors_allows_configured_origins() {
+        let app = setup_app(test_config()).await;
+
+        for origin in ["https://app.example.com", "https://tenant.example.org", "https://a.b.example.org"] {
+            let headers = request(&app, "OPTIONS", "/health", Some(origin)).await;
+            assert_eq!(allowed_origin(&headers), Some(origin), "{} 应被放行", origin);
+        }
+
+        for origin in [
+            "https://evil.com",
+            "http://app.example.com",
+            "https://example.org",
+            "https://tenant.example.org.evil.com",
+            "https://evil.com/.example.org",
+        ] {
+            let headers = request(&app, "OPTIONS", "/health", Some(origin)).await;
+            assert_eq!(allowed_origin(&headers), None, "{} 不应被放行", origin);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cors_wildcard_allows_any_origin() {
+        let mut config = test_config();
+        config.allowed_origins = vec!["*".to_string()];
+        let app = setup_app(config).await;
+
+        let headers = request(&app, "OPTIONS", "/health", Some("https://anything.test")).await;
+        assert_eq!(allowed_origin(&headers), Some("*"));
+    }
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let app = setup_app(test_config()).await;
+
+        // 认证失败的响应同样附加安全响应头
+        for uri in ["/health", "/api/v1/auth/me"] {
+            let headers = request(&app, "GET", uri, None).await;
+            assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+            assert_eq!(headers[header::REFERRER_POLICY], "strict-origin-when-cross-origin");
+            assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+            let csp = headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+            assert!(csp.starts_with(DEFAULT_CONTENT_SECURITY_POLICY));
+            assert!(csp.ends_with("frame-ancestors 'none'"));
+            assert!(headers.get(header::STRICT_TRANSPORT_SECURITY).is_none(), "未启用TLS时不发送HSTS");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_docs_csp_override() {
+        let app = setup_app(test_config()).await;
+
+        let headers = request(&app, "GET", "/docs", None).await;
+        let csp: Vec<_> = headers.get_all(header::CONTENT_SECURITY_POLICY).iter().collect();
+        assert_eq!(csp.len(), 1, "/docs 只发送自己的内容安全策略");
+        let csp = csp[0].to_str().unwrap();
+        assert!(csp.starts_with(DEFAULT_DOCS_CONTENT_SECURITY_POLICY));
+        assert!(csp.ends_with("frame-ancestors 'none'"));
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+
+    #[tokio::test]
+    async fn test_hsts_when_tls_enabled() {
+        let mut config = test_config();
+        config.enable_tls = true;
+        config.frame_ancestors = "'self'".to_string();
+        let app = setup_app(config).await;
+
+        let headers = request(&app, "GET", "/health", None).await;
+        assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], "max-age=31536000; includeSubDomains");
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+    }
+}
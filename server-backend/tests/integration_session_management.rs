@@ -0,0 +1,255 @@
+// API集成测试 - 登录会话管理
+// 验证查看有效会话(区分浏览器与桌面客户端)、撤销会话后令牌立即失效、
+// 项目经理只能撤销本公司用户的会话,以及普通成员不能查看他人的会话
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::session::SessionService,
+    Config, Database,
+};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PM_A_ID: i64 = 1;
+const EXECUTOR_A_ID: i64 = 2;
+const EXECUTOR_B_ID: i64 = 3;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 公司A的项目经理和执行者,公司B的执行者
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT UNIQUE,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A'), (2, '公司B')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database.create_company_roles().await.expect("Failed to create company roles");
+    database.create_two_factor_tables().await.expect("Failed to create two factor tables");
+
+    for (id, username, role, company_id) in [
+        (PM_A_ID, "pm_a", "project_manager", 1),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", 1),
+        (EXECUTOR_B_ID, "exec_b", "task_executor", 2),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: &str) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+/// 以指定 User-Agent 登录,返回访问令牌
+async fn session_token(database: &Database, user_id: i64, role: UserRole, user_agent: &str) -> String {
+    let client = ClientInfo {
+        ip_address: Some("203.0.113.7".to_string()),
+        user_agent: Some(user_agent.to_string()),
+        ..ClientInfo::default()
+    };
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &client)
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+#[cfg(test)]
+mod session_management_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_list_own_sessions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let web = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor, "Mozilla/5.0").await;
+        session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor, "FlowFarm-Desktop/1.0.0").await;
+
+        let (status, body) = send(&app, "GET", "/api/v1/sessions", &web).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let sessions = body["data"].as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        let current: Vec<_> = sessions.iter().filter(|s| s["current"] == true).collect();
+        assert_eq!(current.len(), 1, "只有发出请求的会话标记为当前会话");
+        assert_eq!(current[0]["client_type"], "web");
+        assert_eq!(current[0]["ip_address"], "203.0.113.7");
+        assert!(sessions.iter().any(|s| s["client_type"] == "desktop"));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_session_takes_effect_immediately() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager, "Mozilla/5.0").await;
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor, "FlowFarm-Desktop/1.0.0").await;
+
+        let (status, body) = send(&app, "GET", &format!("/api/v1/sessions?user_id={}", EXECUTOR_A_ID), &pm).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let session_id = body["data"][0]["id"].as_str().unwrap().to_string();
+
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/sessions/{}", session_id), &pm).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", &executor).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "撤销后访问令牌立即失效");
+
+        let reason: Option<String> = sqlx::query_scalar("SELECT revoked_reason FROM sessions WHERE id = ?")
+            .bind(&session_id)
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(reason.as_deref(), Some("revoked_by_admin"));
+
+        let audited: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_logs WHERE action = 'auth.session_revoked'")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(audited, 1);
+    }
+
+    #[tokio::test]
+    async fn test_cannot_manage_other_company_sessions() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let pm = session_token(&database, PM_A_ID, UserRole::ProjectManager, "Mozilla/5.0").await;
+        let other = session_token(&database, EXECUTOR_B_ID, UserRole::TaskExecutor, "Mozilla/5.0").await;
+        let (_, body) = send(&app, "GET", "/api/v1/sessions", &other).await;
+        let session_id = body["data"][0]["id"].as_str().unwrap().to_string();
+
+        let (status, _) = send(&app, "GET", &format!("/api/v1/sessions?user_id={}", EXECUTOR_B_ID), &pm).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&app, "DELETE", &format!("/api/v1/sessions/{}", session_id), &pm).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", &other).await;
+        assert_eq!(status, StatusCode::OK, "其他公司的会话不受影响");
+    }
+
+    #[tokio::test]
+    async fn test_executor_cannot_list_others() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let executor = session_token(&database, EXECUTOR_A_ID, UserRole::TaskExecutor, "Mozilla/5.0").await;
+        session_token(&database, PM_A_ID, UserRole::ProjectManager, "Mozilla/5.0").await;
+
+        let (status, _) = send(&app, "GET", &format!("/api/v1/sessions?user_id={}", PM_A_ID), &executor).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
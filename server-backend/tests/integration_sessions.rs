@@ -0,0 +1,345 @@
+// API集成测试 - 登录会话与刷新令牌
+// 验证刷新令牌轮换、重复使用检测(撤销整个令牌族)、注销撤销会话以及中间件拒绝已撤销的会话
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use flow_farm_backend::{server::create_app, Config, Database};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "password123";
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 5,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 只包含一个项目经理账号的测试数据库
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to create users table");
+
+    let database = Database { pool: pool.clone() };
+    database
+        .create_session_tables()
+        .await
+        .expect("Failed to create session tables");
+    database
+        .create_login_throttles()
+        .await
+        .expect("Failed to create login throttles");
+    database
+        .create_two_factor_tables()
+        .await
+        .expect("Failed to create two factor tables");
+    for ddl in [
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    sqlx::query("INSERT INTO users (username, email, hashed_password, role, full_name, company_id) VALUES ('pm', 'pm@example.com', ?, 'project_manager', 'PM', 1)")
+        .bind(bcrypt::hash(PASSWORD, 4).unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "session-test")
+        .header("X-Real-IP", "203.0.113.7");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let body = body.map(|body| Body::from(body.to_string())).unwrap_or_else(Body::empty);
+
+    let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+/// 登录并返回 (访问令牌, 刷新令牌)
+async fn login(app: &Router) -> (String, String) {
+    let (status, body) = send(
+        app,
+        "POST",
+        "/api/v1/auth/login",
+        None,
+        Some(json!({ "username": "pm", "password": PASSWORD })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "登录失败: {}", body);
+    assert_eq!(body["data"]["expires_in"], 900);
+    (
+        body["data"]["token"].as_str().unwrap().to_string(),
+        body["data"]["refresh_token"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn refresh(app: &Router, refresh_token: &str) -> (StatusCode, Value) {
+    send(
+        app,
+        "POST",
+        "/api/v1/auth/refresh",
+        None,
+        Some(json!({ "refresh_token": refresh_token })),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_login_creates_session() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let (token, _) = login(&app).await;
+        let (status, body) = send(&app, "GET", "/api/v1/auth/me", Some(&token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["username"], "pm");
+
+        let (user_agent, ip_address): (Option<String>, Option<String>) =
+            sqlx::query_as("SELECT user_agent, ip_address FROM sessions")
+                .fetch_one(&database.pool)
+                .await
+                .unwrap();
+        assert_eq!(user_agent.as_deref(), Some("session-test"));
+        assert_eq!(ip_address.as_deref(), Some("203.0.113.7"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotates_token() {
+        let database = setup_database().await;
+        let app = create_app(database, test_config()).await;
+
+        let (_, refresh_token) = login(&app).await;
+        let (status, body) = refresh(&app, &refresh_token).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let new_token = body["data"]["token"].as_str().unwrap();
+        let new_refresh = body["data"]["refresh_token"].as_str().unwrap();
+        assert_ne!(new_refresh, refresh_token, "每次刷新都应签发新的刷新令牌");
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(new_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = refresh(&app, new_refresh).await;
+        assert_eq!(status, StatusCode::OK, "新刷新令牌可继续使用");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_reuse_revokes_family() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let (_, stolen) = login(&app).await;
+        let (_, body) = refresh(&app, &stolen).await;
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+        let latest = body["data"]["refresh_token"].as_str().unwrap().to_string();
+
+        // 宽限期过后,已使用过的刷新令牌再次出现
+        sqlx::query("UPDATE refresh_tokens SET used_at = datetime('now', '-1 minute') WHERE used_at IS NOT NULL")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let (status, body) = refresh(&app, &stolen).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1003);
+
+        // 整个令牌族失效: 最新的刷新令牌和访问令牌都不可用
+        let (status, _) = refresh(&app, &latest).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(&token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let reason: Option<String> = sqlx::query_scalar("SELECT revoked_reason FROM sessions")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(reason.as_deref(), Some("refresh_token_reuse"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_refresh_within_grace_window() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        // 多个标签页同时用同一个刷新令牌刷新,只有第一个请求轮换成功
+        let (_, shared) = login(&app).await;
+        let (status, first) = refresh(&app, &shared).await;
+        assert_eq!(status, StatusCode::OK);
+        for _ in 0..3 {
+            let (status, body) = refresh(&app, &shared).await;
+            assert_eq!(status, StatusCode::CONFLICT, "宽限期内重复刷新不签发新令牌: {}", body);
+        }
+
+        // 会话未被撤销,且只有一个可用的刷新令牌
+        let live: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM refresh_tokens WHERE used_at IS NULL")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(live, 1);
+        let token = first["data"]["token"].as_str().unwrap();
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = refresh(&app, first["data"]["refresh_token"].as_str().unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        let reason: Option<String> = sqlx::query_scalar("SELECT revoked_reason FROM sessions")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(reason, None);
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_session() {
+        let database = setup_database().await;
+        let app = create_app(database, test_config()).await;
+
+        let (token, refresh_token) = login(&app).await;
+        let (other_token, _) = login(&app).await;
+
+        let (status, _) = send(&app, "POST", "/api/v1/auth/logout", Some(&token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(&token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "注销后访问令牌立即失效");
+        let (status, _) = refresh(&app, &refresh_token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "注销后刷新令牌失效");
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(&other_token), None).await;
+        assert_eq!(status, StatusCode::OK, "其他会话不受影响");
+    }
+
+    #[tokio::test]
+    async fn test_expired_refresh_token() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let (_, refresh_token) = login(&app).await;
+        sqlx::query("UPDATE refresh_tokens SET expires_at = '2000-01-01 00:00:00'")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+
+        let (status, body) = refresh(&app, &refresh_token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1002);
+
+        let (status, body) = refresh(&app, "not-a-refresh-token").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1003);
+    }
+
+    #[tokio::test]
+    async fn test_token_without_session_rejected() {
+        let database = setup_database().await;
+        let config = test_config();
+        let app = create_app(database, config.clone()).await;
+
+        // 不关联会话的令牌无法注销,因此一律拒绝
+        let now = chrono::Utc::now().timestamp();
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &json!({ "sub": "1", "role": "project_manager", "iat": now, "exp": now + 3600 }),
+            &jsonwebtoken::EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+        )
+        .unwrap();
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(&token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
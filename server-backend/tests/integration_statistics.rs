@@ -0,0 +1,914 @@
+// API集成测试 - 数据统计多租户隔离
+// 验证不同角色只能看到自己数据范围内的统计数据,不会泄露其他公司的数字
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::{UserInfo, UserRole},
+    server::create_app,
+    services::estimation::{EstimationService, SimilarTaskQuery},
+    services::flow_metrics::FlowMetricsService,
+    services::session::SessionService,
+    services::statistics::{StatisticsScope, StatisticsService},
+    Config, Database,
+};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const COMPANY_A: i64 = 1;
+const COMPANY_B: i64 = 2;
+
+const ADMIN_ID: i64 = 1;
+const PM_A_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+const EXECUTOR_B_ID: i64 = 5;
+
+const PROJECT_A: &str = "00000000-0000-0000-0000-00000000000a";
+const PROJECT_B: &str = "00000000-0000-0000-0000-00000000000b";
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 3600,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 5,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 创建包含两家公司数据的测试数据库
+///
+/// - 公司A: 1个项目(进行中), 3个任务(2个分配给执行者A, 其中1个已完成)
+/// - 公司B: 2个项目(规划中), 4个任务(全部分配给执行者B, 全部已完成)
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE projects (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'planning',
+            company_id INTEGER
+        )
+        "#,
+        r#"
+        CREATE TABLE tasks (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            priority TEXT NOT NULL DEFAULT 'medium',
+            company_id INTEGER,
+            project_id TEXT,
+            assigned_to TEXT,
+            created_by TEXT NOT NULL,
+            estimated_hours REAL,
+            actual_hours REAL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            completed_at DATETIME
+        )
+        "#,
+        r#"
+        CREATE TABLE task_labels (
+            task_id TEXT NOT NULL,
+            label TEXT NOT NULL,
+            PRIMARY KEY (task_id, label)
+        )
+        "#,
+        r#"
+        CREATE TABLE work_logs (
+            id TEXT PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            hours REAL NOT NULL,
+            notes TEXT,
+            logged_at DATE DEFAULT (date('now'))
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1)",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    // 汇总表和触发器需在写入数据前创建,后续写入由触发器增量维护
+    let database = Database { pool: pool.clone() };
+    database
+        .create_statistics_rollups()
+        .await
+        .expect("Failed to create statistics rollups");
+    database
+        .create_task_status_history()
+        .await
+        .expect("Failed to create task status history");
+    database
+        .create_session_tables()
+        .await
+        .expect("Failed to create session tables");
+
+    let users = [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(COMPANY_A)),
+        (EXECUTOR_A_ID, "executor_a", "task_executor", Some(COMPANY_A)),
+        (PM_B_ID, "pm_b", "project_manager", Some(COMPANY_B)),
+        (EXECUTOR_B_ID, "executor_b", "task_executor", Some(COMPANY_B)),
+    ];
+    for (id, username, role, company_id) in users {
+        sqlx::query(
+            "INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, 'x', ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(username)
+        .bind(format!("{}@test.com", username))
+        .bind(role)
+        .bind(username)
+        .bind(company_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    let projects = [
+        (PROJECT_A, "active", COMPANY_A),
+        (PROJECT_B, "planning", COMPANY_B),
+        ("00000000-0000-0000-0000-0000000000bb", "planning", COMPANY_B),
+    ];
+    for (id, status, company_id) in projects {
+        sqlx::query("INSERT INTO projects (id, name, status, company_id) VALUES (?, ?, ?, ?)")
+            .bind(id)
+            .bind(format!("project-{}", id))
+            .bind(status)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    let tasks = [
+        ("a1", "completed", COMPANY_A, PROJECT_A, Some(EXECUTOR_A_ID), PM_A_ID, 3.0),
+        ("a2", "in_progress", COMPANY_A, PROJECT_A, Some(EXECUTOR_A_ID), PM_A_ID, 2.0),
+        ("a3", "pending", COMPANY_A, PROJECT_A, None, PM_A_ID, 0.0),
+        ("b1", "completed", COMPANY_B, PROJECT_B, Some(EXECUTOR_B_ID), PM_B_ID, 10.0),
+        ("b2", "completed", COMPANY_B, PROJECT_B, Some(EXECUTOR_B_ID), PM_B_ID, 10.0),
+        ("b3", "completed", COMPANY_B, PROJECT_B, Some(EXECUTOR_B_ID), PM_B_ID, 10.0),
+        ("b4", "completed", COMPANY_B, PROJECT_B, Some(EXECUTOR_B_ID), PM_B_ID, 10.0),
+    ];
+    for (id, status, company_id, project_id, assigned_to, created_by, hours) in tasks {
+        sqlx::query(
+            "INSERT INTO tasks (id, title, status, company_id, project_id, assigned_to, created_by, estimated_hours, actual_hours) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(format!("task-{}", id))
+        .bind(status)
+        .bind(company_id)
+        .bind(project_id)
+        .bind(assigned_to.map(|id| id.to_string()))
+        .bind(created_by.to_string())
+        .bind(hours)
+        .bind(hours)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    database
+}
+
+fn user(id: i64, role: UserRole, company_id: Option<i64>) -> UserInfo {
+    UserInfo {
+        id,
+        username: format!("user-{}", id),
+        email: format!("user-{}@test.com", id),
+        full_name: format!("user-{}", id),
+        permissions: role.permissions(),
+        role,
+        is_active: true,
+        company_id,
+        parent_id: None,
+        created_at: "2025-01-01 00:00:00".to_string(),
+        last_login: None,
+        custom_role_id: None,
+    }
+}
+
+async fn get_json(database: &Database, user_id: i64, role: UserRole, uri: &str) -> (StatusCode, serde_json::Value) {
+    let config = test_config();
+    let token = SessionService::new(database.clone(), config.clone())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token;
+    let app = create_app(database.clone(), config).await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+    (status, body)
+}
+
+#[cfg(test)]
+mod statistics_isolation_tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_resolution_by_role() {
+        let admin = user(ADMIN_ID, UserRole::PlatformAdmin, None);
+        let pm_a = user(PM_A_ID, UserRole::ProjectManager, Some(COMPANY_A));
+        let executor_a = user(EXECUTOR_A_ID, UserRole::TaskExecutor, Some(COMPANY_A));
+        let orphan_pm = user(99, UserRole::ProjectManager, None);
+
+        assert_eq!(StatisticsScope::for_user(&admin, None).unwrap(), StatisticsScope::Platform);
+        assert_eq!(
+            StatisticsScope::for_user(&admin, Some(COMPANY_B)).unwrap(),
+            StatisticsScope::Company(COMPANY_B)
+        );
+        assert_eq!(
+            StatisticsScope::for_user(&pm_a, None).unwrap(),
+            StatisticsScope::Company(COMPANY_A)
+        );
+        assert!(StatisticsScope::for_user(&pm_a, Some(COMPANY_B)).is_err(), "项目经理不能查看其他公司");
+        assert!(StatisticsScope::for_user(&orphan_pm, None).is_err(), "未关联公司的项目经理无数据范围");
+        assert_eq!(
+            StatisticsScope::for_user(&executor_a, None).unwrap(),
+            StatisticsScope::Assignee(EXECUTOR_A_ID)
+        );
+        assert!(StatisticsScope::for_user(&executor_a, Some(COMPANY_B)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_task_statistics_are_scoped() {
+        let database = setup_database().await;
+        let service = StatisticsService::new(database);
+
+        let platform = service.get_task_statistics(StatisticsScope::Platform).await.unwrap();
+        assert_eq!(platform.total_tasks, 7);
+        assert_eq!(platform.completed_tasks, 5);
+
+        let company_a = service.get_task_statistics(StatisticsScope::Company(COMPANY_A)).await.unwrap();
+        assert_eq!(company_a.total_tasks, 3, "公司A只能看到自己的3个任务");
+        assert_eq!(company_a.completed_tasks, 1);
+        assert_eq!(company_a.in_progress_tasks, 1);
+        assert_eq!(company_a.pending_tasks, 1);
+
+        let executor_a = service
+            .get_task_statistics(StatisticsScope::Assignee(EXECUTOR_A_ID))
+            .await
+            .unwrap();
+        assert_eq!(executor_a.total_tasks, 2, "执行者只能看到分配给自己的任务");
+        assert_eq!(executor_a.completed_tasks, 1);
+        assert_eq!(executor_a.completion_rate, 50.0);
+    }
+
+    #[tokio::test]
+    async fn test_project_statistics_are_scoped() {
+        let database = setup_database().await;
+        let service = StatisticsService::new(database);
+
+        let platform = service.get_project_statistics(StatisticsScope::Platform).await.unwrap();
+        assert_eq!(platform.total_projects, 3);
+
+        let company_a = service
+            .get_project_statistics(StatisticsScope::Company(COMPANY_A))
+            .await
+            .unwrap();
+        assert_eq!(company_a.total_projects, 1);
+        assert_eq!(company_a.active_projects, 1);
+        assert_eq!(company_a.planning_projects, 0, "不能包含公司B的规划中项目");
+
+        let executor_b = service
+            .get_project_statistics(StatisticsScope::Assignee(EXECUTOR_B_ID))
+            .await
+            .unwrap();
+        assert_eq!(executor_b.total_projects, 1, "执行者只能看到自己参与的项目");
+    }
+
+    #[tokio::test]
+    async fn test_workload_and_progress_are_scoped() {
+        let database = setup_database().await;
+        let service = StatisticsService::new(database);
+
+        let workloads = service
+            .get_all_users_workload(StatisticsScope::Company(COMPANY_A))
+            .await
+            .unwrap();
+        assert_eq!(workloads.len(), 1);
+        assert_eq!(workloads[0].user_id, EXECUTOR_A_ID);
+        assert_eq!(workloads[0].assigned_tasks, 2);
+        assert_eq!(workloads[0].total_hours, 5.0);
+
+        // 执行者不能查看他人的工作量
+        let result = service
+            .get_user_workload(EXECUTOR_B_ID, StatisticsScope::Assignee(EXECUTOR_A_ID))
+            .await;
+        assert!(result.is_err());
+
+        // 其他公司的项目视为不存在
+        let project_b = PROJECT_B.parse().unwrap();
+        let result = service
+            .get_project_progress(project_b, StatisticsScope::Company(COMPANY_A))
+            .await;
+        assert!(result.is_err());
+
+        let progress = service
+            .get_project_progress(project_b, StatisticsScope::Company(COMPANY_B))
+            .await
+            .unwrap();
+        assert_eq!(progress.total_tasks, 4);
+        assert_eq!(progress.progress, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_statistics_endpoints_do_not_leak_across_tenants() {
+        let database = setup_database().await;
+
+        let (status, body) = get_json(&database, PM_A_ID, UserRole::ProjectManager, "/api/v1/statistics/tasks").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_tasks"], 3);
+
+        let (status, _) = get_json(
+            &database,
+            PM_A_ID,
+            UserRole::ProjectManager,
+            "/api/v1/statistics/tasks?company_id=2",
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "项目经理不能通过参数查看其他公司");
+
+        let (status, body) = get_json(
+            &database,
+            ADMIN_ID,
+            UserRole::PlatformAdmin,
+            "/api/v1/statistics/tasks?company_id=2",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_tasks"], 4);
+
+        let (status, body) = get_json(&database, ADMIN_ID, UserRole::PlatformAdmin, "/api/v1/statistics/projects").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_projects"], 3);
+
+        let (status, body) = get_json(
+            &database,
+            EXECUTOR_B_ID,
+            UserRole::TaskExecutor,
+            "/api/v1/statistics/users/workload",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["user_id"], EXECUTOR_B_ID);
+
+        let uri = format!("/api/v1/statistics/projects/{}/progress", PROJECT_B);
+        let (status, _) = get_json(&database, PM_A_ID, UserRole::ProjectManager, &uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    /// 依次比较各范围的汇总结果与直接按任务表计算的结果
+    async fn assert_rollups_match_live_counts(database: &Database) {
+        let service = StatisticsService::new(database.clone());
+        for (scope, filter, param) in [
+            (StatisticsScope::Platform, "1 = 1", None),
+            (StatisticsScope::Company(COMPANY_A), "company_id = ?", Some(COMPANY_A)),
+            (StatisticsScope::Company(COMPANY_B), "company_id = ?", Some(COMPANY_B)),
+            (StatisticsScope::Assignee(EXECUTOR_A_ID), "assigned_to = ?", Some(EXECUTOR_A_ID)),
+        ] {
+            let sql = format!(
+                "SELECT COUNT(*), COALESCE(SUM(status = 'completed'), 0) FROM tasks WHERE {}",
+                filter
+            );
+            let mut query = sqlx::query_as::<_, (i64, i64)>(&sql);
+            if let Some(param) = param {
+                query = query.bind(param);
+            }
+            let (total, completed) = query.fetch_one(&database.pool).await.unwrap();
+
+            let stats = service.get_task_statistics(scope).await.unwrap();
+            assert_eq!(stats.total_tasks, total, "{:?} 任务总数与实时统计不一致", scope);
+            assert_eq!(stats.completed_tasks, completed, "{:?} 完成数与实时统计不一致", scope);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rollups_follow_task_and_work_log_changes() {
+        let database = setup_database().await;
+        let pool = &database.pool;
+        let service = StatisticsService::new(database.clone());
+
+        // 状态流转、改派、跨公司迁移和删除都应同步到汇总表
+        sqlx::query("UPDATE tasks SET status = 'completed', actual_hours = 4.0 WHERE id = 'a2'")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE tasks SET assigned_to = ? WHERE id = 'a3'")
+            .bind(EXECUTOR_A_ID.to_string())
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE tasks SET company_id = ?, project_id = ? WHERE id = 'b4'")
+            .bind(COMPANY_A)
+            .bind(PROJECT_A)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM tasks WHERE id = 'b3'").execute(pool).await.unwrap();
+        assert_rollups_match_live_counts(&database).await;
+
+        let executor_a = service
+            .get_user_workload(EXECUTOR_A_ID, StatisticsScope::Company(COMPANY_A))
+            .await
+            .unwrap();
+        assert_eq!(executor_a.assigned_tasks, 3);
+        assert_eq!(executor_a.completed_tasks, 2);
+        assert_eq!(executor_a.total_hours, 7.0);
+
+        let progress = service
+            .get_project_progress(PROJECT_A.parse().unwrap(), StatisticsScope::Company(COMPANY_A))
+            .await
+            .unwrap();
+        assert_eq!(progress.total_tasks, 4, "迁入的任务计入新项目");
+        assert_eq!(progress.completed_tasks, 3);
+
+        // 工作日志的新增、修改和删除
+        sqlx::query("INSERT INTO work_logs (id, task_id, user_id, hours, logged_at) VALUES ('w1', 'a1', ?, 2.5, '2025-10-01')")
+            .bind(EXECUTOR_A_ID.to_string())
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO work_logs (id, task_id, user_id, hours, logged_at) VALUES ('w2', 'a2', ?, 1.0, '2025-10-02')")
+            .bind(EXECUTOR_A_ID.to_string())
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE work_logs SET hours = 3.5 WHERE id = 'w1'").execute(pool).await.unwrap();
+        sqlx::query("DELETE FROM work_logs WHERE id = 'w2'").execute(pool).await.unwrap();
+
+        let logged: (f64, i64) = sqlx::query_as(
+            "SELECT TOTAL(logged_hours), COALESCE(SUM(work_log_count), 0) FROM stats_daily_user WHERE user_id = ?",
+        )
+        .bind(EXECUTOR_A_ID)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(logged, (3.5, 1));
+
+        // 重建后结果与增量维护的结果一致
+        let before = service.get_task_statistics(StatisticsScope::Platform).await.unwrap();
+        database.rebuild_statistics_rollups().await.unwrap();
+        let after = service.get_task_statistics(StatisticsScope::Platform).await.unwrap();
+        assert_eq!(before.total_tasks, after.total_tasks);
+        assert_eq!(before.completed_tasks, after.completed_tasks);
+        assert_rollups_match_live_counts(&database).await;
+
+        let logged_after: (f64, i64) = sqlx::query_as(
+            "SELECT TOTAL(logged_hours), COALESCE(SUM(work_log_count), 0) FROM stats_daily_user WHERE user_id = ?",
+        )
+        .bind(EXECUTOR_A_ID)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(logged_after, logged);
+    }
+
+    /// 各汇总表中记录了工时的行: (表, 公司, 维度, 日期, 工时, 日志数)
+    async fn logged_rollups(pool: &SqlitePool) -> Vec<(String, i64, String, String, f64, i64)> {
+        let mut rows = Vec::new();
+        for (table, dimension) in [
+            ("stats_daily_company", "''"),
+            ("stats_daily_project", "project_id"),
+            ("stats_daily_user", "user_id"),
+        ] {
+            let sql = format!(
+                "SELECT '{0}', company_id, {1}, stat_date, logged_hours, work_log_count FROM {0} \
+                 WHERE logged_hours <> 0 OR work_log_count <> 0 ORDER BY 2, 3, 4",
+                table, dimension
+            );
+            rows.extend(sqlx::query_as(&sql).fetch_all(pool).await.unwrap());
+        }
+        rows
+    }
+
+    #[tokio::test]
+    async fn test_moving_task_moves_logged_hours() {
+        let database = setup_database().await;
+        let pool = &database.pool;
+
+        for (id, user_id, hours, logged_at) in [
+            ("w1", EXECUTOR_B_ID, 2.0, "2025-10-01"),
+            ("w2", EXECUTOR_B_ID, 1.5, "2025-10-01"),
+            ("w3", PM_B_ID, 3.0, "2025-10-02"),
+        ] {
+            sqlx::query("INSERT INTO work_logs (id, task_id, user_id, hours, logged_at) VALUES (?, 'b1', ?, ?, ?)")
+                .bind(id)
+                .bind(user_id.to_string())
+                .bind(hours)
+                .bind(logged_at)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+
+        // 先换项目,再迁到另一个公司,已记录的工时随任务移动
+        sqlx::query("UPDATE tasks SET project_id = NULL WHERE id = 'b1'").execute(pool).await.unwrap();
+        sqlx::query("UPDATE tasks SET company_id = ?, project_id = ? WHERE id = 'b1'")
+            .bind(COMPANY_A)
+            .bind(PROJECT_A)
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let incremental = logged_rollups(pool).await;
+        assert!(incremental
+            .iter()
+            .all(|(_, company_id, dimension, ..)| *company_id == COMPANY_A && dimension != PROJECT_B));
+        assert!(incremental.contains(&("stats_daily_project".to_string(), COMPANY_A, PROJECT_A.to_string(), "2025-10-01".to_string(), 3.5, 2)));
+
+        database.rebuild_statistics_rollups().await.unwrap();
+        assert_eq!(logged_rollups(pool).await, incremental, "增量维护的工时与重建结果一致");
+    }
+
+    /// 用固定的流转时间线替换公司B任务的流转记录
+    ///
+    /// - b1(urgent): 10-13 创建, 10:00 开始, 10-14 完成 => 前置24h, 周期14h
+    /// - b2: 10-14 创建, 12:00 开始, 10-16 完成 => 前置48h, 周期36h
+    /// - b3: 10-15 创建, 未经过进行中, 10-20 完成 => 前置120h
+    /// - b4: 10-10 完成, 在统计周期之外
+    async fn replace_company_b_timeline(pool: &SqlitePool) {
+        sqlx::query("DELETE FROM task_status_transitions WHERE task_id LIKE 'b%'")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE tasks SET priority = 'urgent' WHERE id = 'b1'")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let transitions = [
+            ("b1", None, "pending", "2025-10-13 00:00:00"),
+            ("b1", Some("pending"), "in_progress", "2025-10-13 10:00:00"),
+            ("b1", Some("in_progress"), "completed", "2025-10-14 00:00:00"),
+            ("b2", None, "pending", "2025-10-14 00:00:00"),
+            ("b2", Some("pending"), "in_progress", "2025-10-14 12:00:00"),
+            ("b2", Some("in_progress"), "completed", "2025-10-16 00:00:00"),
+            ("b3", None, "pending", "2025-10-15 00:00:00"),
+            ("b3", Some("pending"), "completed", "2025-10-20 00:00:00"),
+            ("b4", None, "pending", "2025-10-01 00:00:00"),
+            ("b4", Some("pending"), "completed", "2025-10-10 00:00:00"),
+        ];
+        for (task_id, from_status, to_status, changed_at) in transitions {
+            sqlx::query(
+                "INSERT INTO task_status_transitions (task_id, from_status, to_status, changed_at) VALUES (?, ?, ?, ?)",
+            )
+            .bind(task_id)
+            .bind(from_status)
+            .bind(to_status)
+            .bind(changed_at)
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_transitions_are_recorded() {
+        let database = setup_database().await;
+        let pool = &database.pool;
+
+        // 以非待处理状态创建的任务补一条 pending -> 当前状态 的流转
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM task_status_transitions WHERE task_id = 'a1'")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        sqlx::query("UPDATE tasks SET status = 'in_progress' WHERE id = 'a3'")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE tasks SET title = 'renamed' WHERE id = 'a3'")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE tasks SET status = 'completed' WHERE id = 'a3'")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let statuses: Vec<(Option<String>, String)> = sqlx::query_as(
+            "SELECT from_status, to_status FROM task_status_transitions WHERE task_id = 'a3' ORDER BY id",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            statuses,
+            vec![
+                (None, "pending".to_string()),
+                (Some("pending".to_string()), "in_progress".to_string()),
+                (Some("in_progress".to_string()), "completed".to_string()),
+            ],
+            "只有状态变化才记录流转"
+        );
+
+        sqlx::query("DELETE FROM tasks WHERE id = 'a3'").execute(pool).await.unwrap();
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM task_status_transitions WHERE task_id = 'a3'")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_flow_metrics_percentiles_and_throughput() {
+        let database = setup_database().await;
+        replace_company_b_timeline(&database.pool).await;
+        let service = FlowMetricsService::new(database.clone());
+        let now = "2025-10-22T12:00:00Z".parse().unwrap();
+
+        let metrics = service
+            .get_flow_metrics_at(StatisticsScope::Company(COMPANY_B), 2, now)
+            .await
+            .unwrap();
+
+        assert_eq!(metrics.overall.completed_tasks, 3, "统计周期之外完成的任务不计入");
+        assert_eq!(metrics.overall.lead_time_hours.p50, Some(48.0));
+        assert_eq!(metrics.overall.lead_time_hours.p85, Some(120.0));
+        assert_eq!(metrics.overall.cycle_time_hours.samples, 2, "未经过进行中的任务没有周期时间");
+        assert_eq!(metrics.overall.cycle_time_hours.p50, Some(14.0));
+        assert_eq!(metrics.overall.cycle_time_hours.p95, Some(36.0));
+
+        let urgent = metrics
+            .by_priority
+            .iter()
+            .find(|group| group.key.as_deref() == Some("urgent"))
+            .unwrap();
+        assert_eq!(urgent.completed_tasks, 1);
+        assert_eq!(urgent.lead_time_hours.p95, Some(24.0));
+
+        assert_eq!(metrics.by_project.len(), 1);
+        assert_eq!(metrics.by_project[0].key.as_deref(), Some(PROJECT_B));
+        assert_eq!(metrics.by_assignee[0].key, Some(EXECUTOR_B_ID.to_string()));
+        assert_eq!(metrics.by_assignee[0].name.as_deref(), Some("executor_b"));
+
+        let pending = metrics.time_in_state.iter().find(|state| state.status == "pending").unwrap();
+        assert_eq!(pending.hours.samples, 3);
+        assert_eq!(pending.hours.p50, Some(12.0));
+
+        let weekly: Vec<(String, i64)> = metrics
+            .weekly_throughput
+            .iter()
+            .map(|week| (week.week_start.to_string(), week.completed_tasks))
+            .collect();
+        assert_eq!(weekly, vec![("2025-10-13".to_string(), 2), ("2025-10-20".to_string(), 1)]);
+
+        // 执行者A看不到公司B的任务
+        let executor_a = service
+            .get_flow_metrics_at(StatisticsScope::Assignee(EXECUTOR_A_ID), 2, now)
+            .await
+            .unwrap();
+        assert!(executor_a.by_project.iter().all(|group| group.key.as_deref() != Some(PROJECT_B)));
+
+        assert!(service.get_flow_metrics_at(StatisticsScope::Platform, 0, now).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_flow_metrics_endpoint() {
+        let database = setup_database().await;
+
+        let (status, body) = get_json(&database, PM_A_ID, UserRole::ProjectManager, "/api/v1/statistics/flow").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["weeks"], 12);
+        assert_eq!(body["weekly_throughput"].as_array().unwrap().len(), 12);
+
+        let (status, _) = get_json(
+            &database,
+            PM_A_ID,
+            UserRole::ProjectManager,
+            "/api/v1/statistics/flow?weeks=500",
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    /// 为公司B的已完成任务设置预估/实际工时、完成时间和标签
+    ///
+    /// 实际/预估比值: b1 = 2.0, b2 = 1.0, b3 = 0.5, b4 = 1.25
+    async fn prepare_estimation_history(pool: &SqlitePool, early: &str, late: &str) {
+        let history = [
+            ("b1", 2.0, 4.0, early),
+            ("b2", 6.0, 6.0, early),
+            ("b3", 16.0, 8.0, late),
+            ("b4", 9.6, 12.0, late),
+        ];
+        for (id, estimated, actual, completed_at) in history {
+            sqlx::query("UPDATE tasks SET estimated_hours = ?, actual_hours = ?, completed_at = ? WHERE id = ?")
+                .bind(estimated)
+                .bind(actual)
+                .bind(completed_at)
+                .bind(id)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+        for (task_id, label) in [("b1", "design"), ("b2", "design"), ("b3", "copy")] {
+            sqlx::query("INSERT INTO task_labels (task_id, label) VALUES (?, ?)")
+                .bind(task_id)
+                .bind(label)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_estimation_accuracy_report() {
+        let database = setup_database().await;
+        prepare_estimation_history(&database.pool, "2025-09-10 12:00:00", "2025-10-05 12:00:00").await;
+        let service = EstimationService::new(database.clone());
+        let now = "2025-10-20T12:00:00Z".parse().unwrap();
+
+        let report = service
+            .get_estimation_report_at(StatisticsScope::Company(COMPANY_B), 2, now)
+            .await
+            .unwrap();
+
+        let overall = &report.overall;
+        assert_eq!(overall.samples, 4);
+        assert_eq!(overall.mean_absolute_error, Some(0.44));
+        assert_eq!(overall.bias, Some(0.19), "整体偏向低估");
+        assert_eq!(overall.median_ratio, Some(1.0));
+        assert_eq!((overall.underestimated, overall.overestimated, overall.accurate), (2, 1, 1));
+        let distribution: Vec<(&str, i64)> = overall
+            .distribution
+            .iter()
+            .map(|bucket| (bucket.range.as_str(), bucket.tasks))
+            .collect();
+        assert_eq!(
+            distribution,
+            vec![("<0.5", 0), ("0.5-0.8", 1), ("0.8-1.2", 1), ("1.2-1.5", 1), ("1.5-2", 0), (">=2", 1)]
+        );
+
+        assert_eq!(report.by_user.len(), 1);
+        assert_eq!(report.by_user[0].key, Some(EXECUTOR_B_ID.to_string()));
+        assert_eq!(report.by_project[0].key.as_deref(), Some(PROJECT_B));
+
+        let design = report
+            .by_label
+            .iter()
+            .find(|group| group.key.as_deref() == Some("design"))
+            .unwrap();
+        assert_eq!(design.samples, 2);
+        assert_eq!(design.bias, Some(0.5));
+
+        let trend: Vec<(&str, i64, Option<f64>)> = report
+            .trend
+            .iter()
+            .map(|point| (point.month.as_str(), point.samples, point.bias))
+            .collect();
+        assert_eq!(trend, vec![("2025-09", 2, Some(0.5)), ("2025-10", 2, Some(-0.13))]);
+
+        // 只分析本月时,上月完成的任务不计入
+        let this_month = service
+            .get_estimation_report_at(StatisticsScope::Company(COMPANY_B), 1, now)
+            .await
+            .unwrap();
+        assert_eq!(this_month.overall.samples, 2);
+
+        assert!(service.get_estimation_report_at(StatisticsScope::Platform, 0, now).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_suggested_estimate_uses_similar_tasks() {
+        let database = setup_database().await;
+        let recent = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        prepare_estimation_history(&database.pool, &recent, &recent).await;
+        let service = EstimationService::new(database.clone());
+
+        // 相同标签的任务只有2个,退回到同项目的4个任务: 实际工时 4, 6, 8, 12
+        let query = SimilarTaskQuery {
+            project_id: Some(PROJECT_B.to_string()),
+            priority: None,
+            labels: vec!["design".to_string()],
+        };
+        let suggestion = service
+            .suggest_estimate(StatisticsScope::Company(COMPANY_B), &query)
+            .await
+            .unwrap();
+        assert_eq!(suggestion.basis.as_deref(), Some("project"));
+        assert_eq!(suggestion.similar_tasks, 4);
+        assert_eq!(suggestion.suggested_hours, Some(6.0));
+        assert_eq!((suggestion.low_hours, suggestion.high_hours), (Some(4.0), Some(8.0)));
+
+        sqlx::query("INSERT INTO task_labels (task_id, label) VALUES ('b4', 'design')")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let suggestion = service
+            .suggest_estimate(StatisticsScope::Company(COMPANY_B), &query)
+            .await
+            .unwrap();
+        assert_eq!(suggestion.basis.as_deref(), Some("project_and_labels"));
+        assert_eq!(suggestion.suggested_hours, Some(6.0));
+
+        // 历史数据不足时不给出建议,也不会参考其他公司的任务
+        let suggestion = service
+            .suggest_estimate(StatisticsScope::Company(COMPANY_A), &query)
+            .await
+            .unwrap();
+        assert_eq!(suggestion.suggested_hours, None);
+        assert_eq!(suggestion.basis, None);
+
+        let uri = format!("/api/v1/statistics/estimation/suggest?project_id={}&labels=design", PROJECT_B);
+        let (status, body) = get_json(&database, PM_B_ID, UserRole::ProjectManager, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["basis"], "project_and_labels");
+
+        let (status, body) = get_json(&database, PM_B_ID, UserRole::ProjectManager, "/api/v1/statistics/estimation").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["overall"]["samples"], 4);
+    }
+}
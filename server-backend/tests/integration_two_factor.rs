@@ -0,0 +1,401 @@
+// API集成测试 - TOTP两步验证
+// 验证注册与确认、两步登录、恢复码、公司/平台策略、验证码防重放以及管理员重置
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::Utc;
+use flow_farm_backend::{
+    middleware::client::ClientInfo,
+    models::UserRole,
+    server::create_app,
+    services::{session::SessionService, two_factor::TwoFactorService},
+    utils::totp::{code_for_step, time_step},
+    Config, Database,
+};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tower::ServiceExt; // for `oneshot`
+
+const PASSWORD: &str = "password123";
+
+const ADMIN_ID: i64 = 1;
+const PM_A_ID: i64 = 2;
+const EXECUTOR_A_ID: i64 = 3;
+const PM_B_ID: i64 = 4;
+
+fn test_config() -> Config {
+    Config {
+        app_name: "Flow Farm Test".to_string(),
+        version: "1.0.0-test".to_string(),
+        debug: true,
+        allow_insecure_defaults: false,
+        host: "127.0.0.1".to_string(),
+        port: 8000,
+        database_url: ":memory:".to_string(),
+        jwt_secret: "test-secret-key".to_string(),
+        jwt_expires_in: 900,
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 10,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
+        allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
+        bcrypt_rounds: 4,
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
+        static_dir: "./test_static".to_string(),
+        enable_tls: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
+    }
+}
+
+/// 平台管理员、公司A的项目经理和执行者、公司B的项目经理
+async fn setup_database() -> Database {
+    let pool = SqlitePool::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+
+    for ddl in [
+        r#"
+        CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            email TEXT,
+            hashed_password TEXT NOT NULL,
+            role TEXT NOT NULL,
+            is_active BOOLEAN DEFAULT TRUE,
+            full_name TEXT,
+            phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
+            company_id INTEGER,
+            parent_id INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )
+        "#,
+        "CREATE TABLE companies (id INTEGER PRIMARY KEY, name TEXT NOT NULL, is_active BOOLEAN DEFAULT 1, require_2fa_for_managers BOOLEAN NOT NULL DEFAULT 0)",
+        "CREATE TABLE system_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL, description TEXT, updated_at DATETIME)",
+        "INSERT INTO companies (id, name) VALUES (1, '公司A'), (2, '公司B')",
+    ] {
+        sqlx::query(ddl).execute(&pool).await.expect("Failed to create table");
+    }
+
+    let database = Database { pool: pool.clone() };
+    database.create_session_tables().await.expect("Failed to create session tables");
+    database.create_login_throttles().await.expect("Failed to create login throttles");
+    database.create_audit_log().await.expect("Failed to create audit log");
+    database
+        .create_two_factor_tables()
+        .await
+        .expect("Failed to create two factor tables");
+
+    let hashed = bcrypt::hash(PASSWORD, 4).unwrap();
+    for (id, username, role, company_id) in [
+        (ADMIN_ID, "admin", "platform_admin", None),
+        (PM_A_ID, "pm_a", "project_manager", Some(1)),
+        (EXECUTOR_A_ID, "exec_a", "task_executor", Some(1)),
+        (PM_B_ID, "pm_b", "project_manager", Some(2)),
+    ] {
+        sqlx::query("INSERT INTO users (id, username, email, hashed_password, role, full_name, company_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(id)
+            .bind(username)
+            .bind(format!("{}@example.com", username))
+            .bind(&hashed)
+            .bind(role)
+            .bind(username)
+            .bind(company_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    database
+}
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn login(app: &Router, username: &str) -> Value {
+    let (status, body) = send(
+        app,
+        "POST",
+        "/api/v1/auth/login",
+        None,
+        json!({ "username": username, "password": PASSWORD }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "登录失败: {}", body);
+    body["data"].clone()
+}
+
+async fn verify(app: &Router, challenge_token: &str, code: &str) -> (StatusCode, Value) {
+    send(
+        app,
+        "POST",
+        "/api/v1/auth/2fa/verify",
+        None,
+        json!({ "challenge_token": challenge_token, "code": code }),
+    )
+    .await
+}
+
+async fn token_for(database: &Database, user_id: i64, role: UserRole) -> String {
+    SessionService::new(database.clone(), test_config())
+        .create_session(user_id, &role, &ClientInfo::default())
+        .await
+        .expect("Failed to create session")
+        .token
+}
+
+/// 当前时间步偏移若干步的验证码
+fn code(secret: &str, offset: i64) -> String {
+    code_for_step(secret, time_step(Utc::now().timestamp()) + offset).unwrap()
+}
+
+/// 直接通过服务为用户启用两步验证,返回 (密钥, 恢复码)
+async fn enable_two_factor(database: &Database, user_id: i64) -> (String, Vec<String>) {
+    let service = TwoFactorService::new(database.clone());
+    let enrollment = service.begin_enrollment(user_id, "user@example.com").await.unwrap();
+    let recovery_codes = service
+        .confirm_enrollment(user_id, &code(&enrollment.secret, 0))
+        .await
+        .unwrap();
+    (enrollment.secret, recovery_codes)
+}
+
+#[cfg(test)]
+mod two_factor_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_enroll_and_two_step_login() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let token = login(&app, "pm_a").await["token"].as_str().unwrap().to_string();
+
+        let (status, body) = send(&app, "POST", "/api/v1/auth/2fa/setup", Some(&token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        let secret = body["data"]["secret"].as_str().unwrap().to_string();
+        let uri = body["data"]["otpauth_uri"].as_str().unwrap();
+        assert!(uri.starts_with("otpauth://totp/Flow%20Farm:pm_a%40example.com?secret="));
+
+        let (status, body) = send(&app, "POST", "/api/v1/auth/2fa/confirm", Some(&token), json!({ "code": "000000" })).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1009);
+
+        let confirm_code = code(&secret, 0);
+        let (status, body) = send(&app, "POST", "/api/v1/auth/2fa/confirm", Some(&token), json!({ "code": confirm_code })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"].as_array().unwrap().len(), 10);
+
+        let (_, body) = send(&app, "GET", "/api/v1/auth/2fa", Some(&token), json!({})).await;
+        assert_eq!(body["data"]["enabled"], true);
+        assert_eq!(body["data"]["required"], false);
+        assert_eq!(body["data"]["recovery_codes_remaining"], 10);
+
+        // 启用后登录只返回挑战,不签发令牌
+        let data = login(&app, "pm_a").await;
+        assert!(data.get("token").is_none());
+        assert!(data.get("user").is_none());
+        assert_eq!(data["two_factor"]["enrollment_required"], false);
+        let challenge = data["two_factor"]["challenge_token"].as_str().unwrap();
+
+        let (status, _) = verify(&app, challenge, &confirm_code).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "已使用过的验证码不能重放");
+
+        let (status, body) = verify(&app, challenge, &code(&secret, 1)).await;
+        assert_eq!(status, StatusCode::OK);
+        let new_token = body["data"]["token"].as_str().unwrap();
+        assert_eq!(body["data"]["user"]["username"], "pm_a");
+
+        let (status, _) = send(&app, "GET", "/api/v1/auth/me", Some(new_token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = verify(&app, challenge, &code(&secret, 1)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "挑战只能使用一次");
+    }
+
+    #[tokio::test]
+    async fn test_recovery_codes_are_single_use() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let (_, recovery_codes) = enable_two_factor(&database, EXECUTOR_A_ID).await;
+
+        let data = login(&app, "exec_a").await;
+        let challenge = data["two_factor"]["challenge_token"].as_str().unwrap();
+        let (status, _) = verify(&app, challenge, &recovery_codes[0].to_uppercase()).await;
+        assert_eq!(status, StatusCode::OK, "恢复码不区分大小写");
+
+        let data = login(&app, "exec_a").await;
+        let challenge = data["two_factor"]["challenge_token"].as_str().unwrap();
+        let (status, _) = verify(&app, challenge, &recovery_codes[0]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "恢复码只能使用一次");
+        let (status, _) = verify(&app, challenge, &recovery_codes[1]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_challenge_attempts_are_limited() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let (_, recovery_codes) = enable_two_factor(&database, EXECUTOR_A_ID).await;
+
+        let data = login(&app, "exec_a").await;
+        let challenge = data["two_factor"]["challenge_token"].as_str().unwrap();
+        for _ in 0..5 {
+            let (status, _) = verify(&app, challenge, "000000").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        let (status, body) = verify(&app, challenge, &recovery_codes[0]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 1003, "输错次数用尽后挑战作废,需重新输入密码");
+    }
+
+    #[tokio::test]
+    async fn test_company_policy_requires_managers() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let pm_b_token = token_for(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let (status, _) = send(&app, "PUT", "/api/v1/companies/1/2fa-policy", Some(&pm_b_token), json!({ "required": true })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "不能修改其他公司的策略");
+
+        let admin_token = token_for(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let (status, _) = send(&app, "PUT", "/api/v1/companies/1/2fa-policy", Some(&admin_token), json!({ "required": true })).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // 执行者和其他公司的项目经理不受影响
+        assert!(login(&app, "exec_a").await["token"].is_string());
+        assert!(login(&app, "pm_b").await["token"].is_string());
+
+        let data = login(&app, "pm_a").await;
+        assert_eq!(data["two_factor"]["enrollment_required"], true);
+        let challenge = data["two_factor"]["challenge_token"].as_str().unwrap().to_string();
+
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/api/v1/auth/2fa/challenge/enroll",
+            None,
+            json!({ "challenge_token": challenge }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let secret = body["data"]["secret"].as_str().unwrap().to_string();
+
+        let (status, body) = verify(&app, &challenge, &code(&secret, 0)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["recovery_codes"].as_array().unwrap().len(), 10);
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+
+        let (status, body) = send(&app, "GET", "/api/v1/auth/2fa", Some(&token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["enabled"], true);
+        assert_eq!(body["data"]["required"], true);
+
+        let (status, _) = send(&app, "POST", "/api/v1/auth/2fa/disable", Some(&token), json!({ "code": code(&secret, 1) })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "策略要求时不能关闭两步验证");
+    }
+
+    #[tokio::test]
+    async fn test_platform_mandate() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+
+        let pm_token = token_for(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let (status, _) = send(&app, "PUT", "/api/v1/settings/2fa-policy", Some(&pm_token), json!({ "required": true })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let admin_token = token_for(&database, ADMIN_ID, UserRole::PlatformAdmin).await;
+        let (status, _) = send(&app, "PUT", "/api/v1/settings/2fa-policy", Some(&admin_token), json!({ "required": true })).await;
+        assert_eq!(status, StatusCode::OK);
+
+        for username in ["exec_a", "admin"] {
+            let data = login(&app, username).await;
+            assert!(data.get("token").is_none());
+            assert_eq!(data["two_factor"]["enrollment_required"], true, "{} 需要注册两步验证", username);
+        }
+
+        let actions: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_logs")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(actions, vec!["auth.2fa_policy_changed".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_disable_and_admin_reset() {
+        let database = setup_database().await;
+        let app = create_app(database.clone(), test_config()).await;
+        let (secret, _) = enable_two_factor(&database, PM_A_ID).await;
+        enable_two_factor(&database, EXECUTOR_A_ID).await;
+
+        let pm_token = token_for(&database, PM_A_ID, UserRole::ProjectManager).await;
+        let (status, _) = send(&app, "POST", "/api/v1/auth/2fa/disable", Some(&pm_token), json!({ "code": code(&secret, 1) })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(login(&app, "pm_a").await["token"].is_string());
+
+        let pm_b_token = token_for(&database, PM_B_ID, UserRole::ProjectManager).await;
+        let (status, _) = send(&app, "DELETE", "/api/v1/users/3/2fa", Some(&pm_b_token), json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = send(&app, "DELETE", "/api/v1/users/3/2fa", Some(&pm_token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(login(&app, "exec_a").await["token"].is_string(), "重置后可直接登录");
+    }
+}
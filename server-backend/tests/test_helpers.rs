@@ -30,6 +30,8 @@ async fn initialize_test_schema(pool: &SqlitePool) {
             role TEXT NOT NULL,
             email TEXT,
             phone TEXT,
+            email_index TEXT,
+            phone_index TEXT,
             full_name TEXT,
             company TEXT,
             parent_id TEXT,
@@ -116,17 +118,56 @@ pub fn create_test_config() -> Config {
         app_name: "Flow Farm Test".to_string(),
         version: "1.0.0-test".to_string(),
         debug: true,
+        allow_insecure_defaults: false,
         host: "127.0.0.1".to_string(),
         port: 8000,
         database_url: ":memory:".to_string(),
         jwt_secret: "test-secret-key".to_string(),
         jwt_expires_in: 3600, // 1小时
+        jwt_algorithm: "HS256".to_string(),
+        jwt_keys_dir: None,
+        jwt_signing_kid: None,
+        refresh_token_expires_in: 86400,
+        login_max_attempts: 5,
+        login_ip_max_attempts: 50,
+        login_lockout_seconds: 900,
+        login_backoff_seconds: 0,
         allowed_origins: vec!["*".to_string()],
+        content_security_policy: String::new(),
+        docs_content_security_policy: String::new(),
+        frame_ancestors: "'none'".to_string(),
+        referrer_policy: "no-referrer".to_string(),
+        hsts_max_age: 0,
+        rate_limit_auth: String::new(),
+        rate_limit_user: String::new(),
+        rate_limit_company: String::new(),
+        pii_keys: vec![],
+        pii_active_key: None,
+        pii_index_key: None,
+        password_hash_algorithm: "argon2id".to_string(),
+        argon2_memory_kib: 1024,
+        argon2_iterations: 1,
+        argon2_parallelism: 1,
         bcrypt_rounds: 4, // 测试时使用更低的成本
+        password_min_length: 6,
+        password_required_classes: vec![],
+        password_blocklist_file: None,
+        password_history_size: 0,
         static_dir: "./test_static".to_string(),
         enable_tls: false,
         tls_cert_path: None,
         tls_key_path: None,
+        metrics_token: None,
+        metrics_bind: None,
+        mailer: "log".to_string(),
+        smtp_host: "localhost".to_string(),
+        smtp_port: 25,
+        smtp_username: None,
+        smtp_password: None,
+        smtp_tls: "none".to_string(),
+        mail_from: "Flow Farm <noreply@localhost>".to_string(),
+        mail_dir: "./test_mail".to_string(),
+        public_url: "http://localhost:8000".to_string(),
     }
 }
 
@@ -174,10 +215,11 @@ pub async fn insert_test_user(
 
 /// 生成测试JWT token
 pub fn generate_test_token(user_id: &str, username: &str, role: &str) -> String {
-    use flow_farm_backend::utils::jwt::create_jwt_token;
-    
+    use flow_farm_backend::utils::jwt::JwtKeys;
+
     let config = create_test_config();
-    create_jwt_token(user_id, role, &config.jwt_secret, config.jwt_expires_in)
+    JwtKeys::from_config(&config)
+        .and_then(|keys| keys.create_session_token(user_id, role, "test-session", config.jwt_expires_in))
         .expect("Failed to create test token")
 }
 
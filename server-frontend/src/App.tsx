@@ -5,6 +5,10 @@ import { Navigate, Route, Routes } from 'react-router-dom'
 import './App.css'
 import AppLayout from './components/layout/AppLayout'
 import Login from './pages/Login'
+import ResetPassword from './pages/ResetPassword'
+import AcceptInvitation from './pages/AcceptInvitation'
+import VerifyEmail from './pages/VerifyEmail'
+import SsoCallback from './pages/SsoCallback'
 import Dashboard from './pages/Dashboard'
 import TaskManagement from './pages/TaskManagement'
 import ProjectManagement from './pages/ProjectManagement'
@@ -54,6 +58,10 @@ const App: React.FC = () => {
         <Routes>
           {/* 重定向所有路径到登录页 */}
           <Route path="/login" element={<Login />} />
+          <Route path="/reset-password" element={<ResetPassword />} />
+          <Route path="/accept-invitation" element={<AcceptInvitation />} />
+          <Route path="/verify-email" element={<VerifyEmail />} />
+          <Route path="/sso/callback" element={<SsoCallback />} />
           <Route path="*" element={<Navigate to="/login" replace />} />
         </Routes>
       </AntApp>
@@ -91,6 +99,8 @@ const App: React.FC = () => {
           path="/login"
           element={<Navigate to="/dashboard" replace />}
         />
+        <Route path="/verify-email" element={<VerifyEmail />} />
+        <Route path="/sso/callback" element={<SsoCallback />} />
 
         {/* TaskFleet主应用路由 - 所有路由都需要认证 */}
         <Route path="/" element={<AppLayout />}>
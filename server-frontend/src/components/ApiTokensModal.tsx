@@ -0,0 +1,204 @@
+/**
+ * 访问令牌管理弹窗
+ * 供脚本和桌面端自动化代替密码登录,令牌只在创建时显示一次
+ */
+
+import React, { useCallback, useEffect, useState } from 'react';
+import {
+  Alert,
+  Button,
+  Checkbox,
+  Form,
+  Input,
+  InputNumber,
+  Modal,
+  Popconfirm,
+  Radio,
+  Space,
+  Table,
+  Tag,
+  Typography,
+  message,
+} from 'antd';
+import { useSelector } from 'react-redux';
+import { RootState } from '../store';
+import { authService } from '../services/authService';
+import { ApiToken, CreatedApiToken } from '../types';
+import { UserRole } from '../types/user';
+
+const SCOPE_OPTIONS = [
+  { label: '读取任务', value: 'tasks:read' },
+  { label: '修改任务', value: 'tasks:write' },
+  { label: '读取项目', value: 'projects:read' },
+  { label: '读取工作记录', value: 'worklogs:read' },
+  { label: '提交工作记录', value: 'worklogs:write' },
+  { label: '读取统计', value: 'statistics:read' },
+  { label: '读取用户', value: 'users:read' },
+];
+
+interface ApiTokensModalProps {
+  open: boolean;
+  onClose: () => void;
+}
+
+const ApiTokensModal: React.FC<ApiTokensModalProps> = ({ open, onClose }) => {
+  const [form] = Form.useForm();
+  const user = useSelector((state: RootState) => state.auth.user);
+  const [tokens, setTokens] = useState<ApiToken[]>([]);
+  const [loading, setLoading] = useState(false);
+  const [submitting, setSubmitting] = useState(false);
+  const [created, setCreated] = useState<CreatedApiToken | null>(null);
+  const canCreateCompanyKey = user?.role === UserRole.ProjectManager;
+
+  const loadTokens = useCallback(async () => {
+    setLoading(true);
+    try {
+      setTokens(await authService.listApiTokens());
+    } catch (error: any) {
+      message.error(error.response?.data?.message || error.message || '加载访问令牌失败');
+    } finally {
+      setLoading(false);
+    }
+  }, []);
+
+  useEffect(() => {
+    if (open) {
+      loadTokens();
+    }
+  }, [open, loadTokens]);
+
+  const handleClose = () => {
+    form.resetFields();
+    setCreated(null);
+    onClose();
+  };
+
+  const handleCreate = async () => {
+    const values = await form.validateFields();
+    setSubmitting(true);
+    try {
+      const token = await authService.createApiToken({
+        name: values.name.trim(),
+        kind: values.kind,
+        scopes: values.scopes,
+        expires_in_days: values.expires_in_days || undefined,
+      });
+      setCreated(token);
+      form.resetFields();
+      loadTokens();
+    } catch (error: any) {
+      message.error(error.response?.data?.message || error.message || '创建访问令牌失败');
+    } finally {
+      setSubmitting(false);
+    }
+  };
+
+  const handleRevoke = async (id: string) => {
+    try {
+      await authService.revokeApiToken(id);
+      message.success('访问令牌已撤销');
+      loadTokens();
+    } catch (error: any) {
+      message.error(error.response?.data?.message || error.message || '撤销访问令牌失败');
+    }
+  };
+
+  const columns = [
+    {
+      title: '名称',
+      dataIndex: 'name',
+      render: (name: string, token: ApiToken) => (
+        <Space direction="vertical" size={0}>
+          <span>
+            {name} {token.kind === 'company' && <Tag color="blue">公司密钥</Tag>}
+          </span>
+          <Typography.Text type="secondary" code>{token.token_prefix}…</Typography.Text>
+        </Space>
+      ),
+    },
+    {
+      title: '授权范围',
+      dataIndex: 'scopes',
+      render: (scopes: string[]) => scopes.map((scope) => <Tag key={scope}>{scope}</Tag>),
+    },
+    {
+      title: '过期时间',
+      dataIndex: 'expires_at',
+      render: (expiresAt?: string) => expiresAt || '永不过期',
+    },
+    {
+      title: '最近使用',
+      dataIndex: 'last_used_at',
+      render: (lastUsedAt: string | undefined, token: ApiToken) =>
+        lastUsedAt ? `${lastUsedAt}${token.last_used_ip ? ` (${token.last_used_ip})` : ''}` : '从未使用',
+    },
+    {
+      title: '操作',
+      key: 'action',
+      render: (_: unknown, token: ApiToken) =>
+        token.revoked_at ? (
+          <Tag>已撤销</Tag>
+        ) : (
+          <Popconfirm title="撤销后使用该令牌的脚本将无法访问,确认撤销?" onConfirm={() => handleRevoke(token.id)}>
+            <Button type="link" danger size="small">撤销</Button>
+          </Popconfirm>
+        ),
+    },
+  ];
+
+  return (
+    <Modal
+      title="访问令牌"
+      open={open}
+      onCancel={handleClose}
+      footer={null}
+      width={860}
+      destroyOnClose
+    >
+      {created && (
+        <Alert
+          type="success"
+          showIcon
+          style={{ marginBottom: 16 }}
+          message="访问令牌已创建,请立即复制保存,关闭后将无法再次查看"
+          description={<Typography.Text copyable code>{created.token}</Typography.Text>}
+        />
+      )}
+
+      <Form
+        form={form}
+        layout="inline"
+        autoComplete="off"
+        initialValues={{ kind: 'personal', scopes: ['tasks:read'] }}
+        style={{ marginBottom: 16, rowGap: 8 }}
+      >
+        <Form.Item name="name" rules={[{ required: true, whitespace: true, message: '请输入令牌名称' }]}>
+          <Input placeholder="名称,如 桌面端同步" maxLength={100} />
+        </Form.Item>
+        {canCreateCompanyKey && (
+          <Form.Item name="kind">
+            <Radio.Group>
+              <Radio value="personal">个人令牌</Radio>
+              <Radio value="company">公司密钥</Radio>
+            </Radio.Group>
+          </Form.Item>
+        )}
+        <Form.Item name="expires_in_days">
+          <InputNumber min={1} max={365} placeholder="有效天数" addonAfter="天" />
+        </Form.Item>
+        <Form.Item name="scopes" rules={[{ required: true, message: '请至少选择一个授权范围' }]}>
+          <Checkbox.Group options={SCOPE_OPTIONS} />
+        </Form.Item>
+        <Form.Item>
+          <Button type="primary" onClick={handleCreate} loading={submitting}>
+            创建令牌
+          </Button>
+        </Form.Item>
+      </Form>
+
+      <Table rowKey="id" size="small" loading={loading} columns={columns} dataSource={tokens} pagination={false} />
+    </Modal>
+  );
+};
+
+export default ApiTokensModal;
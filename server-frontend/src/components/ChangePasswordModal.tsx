@@ -0,0 +1,88 @@
+/**
+ * 修改密码弹窗
+ * 需验证当前密码,成功后其他设备上的登录会话全部失效
+ */
+
+import React, { useState } from 'react';
+import { Form, Input, Modal, message } from 'antd';
+import { authService } from '../services/authService';
+
+interface ChangePasswordModalProps {
+  open: boolean;
+  onClose: () => void;
+}
+
+const ChangePasswordModal: React.FC<ChangePasswordModalProps> = ({ open, onClose }) => {
+  const [form] = Form.useForm();
+  const [submitting, setSubmitting] = useState(false);
+
+  const handleClose = () => {
+    form.resetFields();
+    onClose();
+  };
+
+  const handleSubmit = async () => {
+    const values = await form.validateFields();
+    setSubmitting(true);
+    try {
+      await authService.changePassword(values.old_password, values.new_password);
+      message.success('密码已修改,其他设备需重新登录');
+      handleClose();
+    } catch (error: any) {
+      message.error(error.response?.data?.message || error.message || '修改密码失败');
+    } finally {
+      setSubmitting(false);
+    }
+  };
+
+  return (
+    <Modal
+      title="修改密码"
+      open={open}
+      onOk={handleSubmit}
+      onCancel={handleClose}
+      confirmLoading={submitting}
+      okText="确认修改"
+      cancelText="取消"
+      destroyOnClose
+    >
+      <Form form={form} layout="vertical" autoComplete="off">
+        <Form.Item
+          name="old_password"
+          label="当前密码"
+          rules={[{ required: true, message: '请输入当前密码' }]}
+        >
+          <Input.Password />
+        </Form.Item>
+        <Form.Item
+          name="new_password"
+          label="新密码"
+          extra="不能与最近使用过的密码相同"
+          rules={[{ required: true, message: '请输入新密码' }]}
+        >
+          <Input.Password />
+        </Form.Item>
+        <Form.Item
+          name="confirm_password"
+          label="确认新密码"
+          dependencies={['new_password']}
+          rules={[
+            { required: true, message: '请再次输入新密码' },
+            ({ getFieldValue }) => ({
+              validator(_, value) {
+                if (!value || getFieldValue('new_password') === value) {
+                  return Promise.resolve();
+                }
+                return Promise.reject(new Error('两次输入的密码不一致'));
+              },
+            }),
+          ]}
+        >
+          <Input.Password />
+        </Form.Item>
+      </Form>
+    </Modal>
+  );
+};
+
+export default ChangePasswordModal;
@@ -0,0 +1,102 @@
+/**
+ * 登录会话管理弹窗
+ * 列出浏览器和桌面客户端的有效会话,撤销后对应设备立即退出登录
+ */
+
+import React, { useCallback, useEffect, useState } from 'react';
+import { Button, Modal, Popconfirm, Space, Table, Tag, Typography, message } from 'antd';
+import { authService } from '../services/authService';
+import { Session } from '../types';
+
+interface SessionsModalProps {
+  open: boolean;
+  onClose: () => void;
+  userId?: number;   // 管理者查看本公司用户的会话,为空时查看自己的
+  title?: string;
+}
+
+const SessionsModal: React.FC<SessionsModalProps> = ({ open, onClose, userId, title }) => {
+  const [sessions, setSessions] = useState<Session[]>([]);
+  const [loading, setLoading] = useState(false);
+
+  const loadSessions = useCallback(async () => {
+    setLoading(true);
+    try {
+      setSessions(await authService.listSessions(userId));
+    } catch (error: any) {
+      message.error(error.response?.data?.message || error.message || '加载登录会话失败');
+    } finally {
+      setLoading(false);
+    }
+  }, [userId]);
+
+  useEffect(() => {
+    if (open) {
+      loadSessions();
+    }
+  }, [open, loadSessions]);
+
+  const handleRevoke = async (id: string) => {
+    try {
+      await authService.revokeSession(id);
+      message.success('会话已撤销');
+      loadSessions();
+    } catch (error: any) {
+      message.error(error.response?.data?.message || error.message || '撤销会话失败');
+    }
+  };
+
+  const columns = [
+    {
+      title: '设备',
+      dataIndex: 'user_agent',
+      render: (userAgent: string | undefined, session: Session) => (
+        <Space direction="vertical" size={0}>
+          <span>
+            <Tag color={session.client_type === 'desktop' ? 'purple' : 'blue'}>
+              {session.client_type === 'desktop' ? '桌面客户端' : '浏览器'}
+            </Tag>
+            {session.current && <Tag color="green">当前会话</Tag>}
+            {session.impersonator_id && <Tag color="orange">模拟登录</Tag>}
+          </span>
+          <Typography.Text type="secondary" ellipsis style={{ maxWidth: 280 }}>
+            {userAgent || '未知设备'}
+          </Typography.Text>
+        </Space>
+      ),
+    },
+    {
+      title: 'IP地址',
+      dataIndex: 'ip_address',
+      render: (ipAddress?: string) => ipAddress || '-',
+    },
+    {
+      title: '登录时间',
+      dataIndex: 'created_at',
+    },
+    {
+      title: '最近活动',
+      dataIndex: 'last_used_at',
+    },
+    {
+      title: '操作',
+      key: 'action',
+      render: (_: unknown, session: Session) => (
+        <Popconfirm
+          title={session.current ? '撤销当前会话将退出登录,确认撤销?' : '撤销后该设备将立即退出登录,确认撤销?'}
+          onConfirm={() => handleRevoke(session.id)}
+        >
+          <Button type="link" danger size="small">撤销</Button>
+        </Popconfirm>
+      ),
+    },
+  ];
+
+  return (
+    <Modal title={title || '登录设备'} open={open} onCancel={onClose} footer={null} width={860} destroyOnClose>
+      <Table rowKey="id" size="small" loading={loading} columns={columns} dataSource={sessions} pagination={false} />
+    </Modal>
+  );
+};
+
+export default SessionsModal;
@@ -3,14 +3,19 @@
  * 显示用户信息和角色
  */
 
-import React from 'react';
-import { Layout, Avatar, Dropdown, Space, Tag } from 'antd';
-import { UserOutlined, SettingOutlined, LogoutOutlined } from '@ant-design/icons';
+import React, { useState } from 'react';
+import { Layout, Avatar, Alert, Button, Dropdown, Space, Tag, message } from 'antd';
+import { UserOutlined, SettingOutlined, LogoutOutlined, LockOutlined, KeyOutlined, LinkOutlined, DesktopOutlined, DownloadOutlined } from '@ant-design/icons';
 import type { MenuProps } from 'antd';
 import { useSelector, useDispatch } from 'react-redux';
 import { RootState } from '../../store';
 import { logout } from '../../store/authSlice';
+import { authService } from '../../services/authService';
+import { userService } from '../../services/userService';
 import { UserRole } from '../../types/user';
+import ChangePasswordModal from '../ChangePasswordModal';
+import ApiTokensModal from '../ApiTokensModal';
+import SessionsModal from '../SessionsModal';
 
 const { Header: AntHeader } = Layout;
 
@@ -33,17 +38,77 @@ const getRoleDisplay = (role: UserRole): { label: string; color: string } => {
 const Header: React.FC = () => {
   const dispatch = useDispatch();
   const user = useSelector((state: RootState) => state.auth.user);
+  const [changePasswordOpen, setChangePasswordOpen] = useState(false);
+  const [apiTokensOpen, setApiTokensOpen] = useState(false);
+  const [sessionsOpen, setSessionsOpen] = useState(false);
 
   const handleLogout = () => {
     dispatch(logout() as any);
   };
 
+  // 结束模拟登录,恢复管理员自己的会话
+  const handleEndImpersonation = async () => {
+    try {
+      await authService.endImpersonation();
+    } finally {
+      window.location.href = '/';
+    }
+  };
+
+  // 关联企业单点登录账号,完成后跳回 /sso/callback
+  const handleSsoLink = async () => {
+    try {
+      window.location.href = await authService.startSsoLink();
+    } catch (error: any) {
+      message.error(error.response?.data?.message || error.message || '发起关联失败');
+    }
+  };
+
+  const handleExportData = async () => {
+    if (!user) return;
+    try {
+      await userService.exportPersonalData(user.id);
+    } catch (error: any) {
+      message.error(error.response?.data?.message || '导出失败');
+    }
+  };
+
   const menuItems: MenuProps['items'] = [
     {
       key: 'profile',
       icon: <UserOutlined />,
       label: '个人资料',
     },
+    {
+      key: 'change-password',
+      icon: <LockOutlined />,
+      label: '修改密码',
+      onClick: () => setChangePasswordOpen(true),
+    },
+    {
+      key: 'api-tokens',
+      icon: <KeyOutlined />,
+      label: '访问令牌',
+      onClick: () => setApiTokensOpen(true),
+    },
+    {
+      key: 'sessions',
+      icon: <DesktopOutlined />,
+      label: '登录设备',
+      onClick: () => setSessionsOpen(true),
+    },
+    {
+      key: 'export-data',
+      icon: <DownloadOutlined />,
+      label: '导出个人数据',
+      onClick: handleExportData,
+    },
+    {
+      key: 'sso-link',
+      icon: <LinkOutlined />,
+      label: '关联企业账号',
+      onClick: handleSsoLink,
+    },
     {
       key: 'settings',
       icon: <SettingOutlined />,
@@ -72,6 +137,15 @@ const Header: React.FC = () => {
       justifyContent: 'flex-end',
       borderBottom: '1px solid #f0f0f0',
     }}>
+      {user?.impersonator && (
+        <Alert
+          type="warning"
+          showIcon
+          style={{ marginRight: 'auto' }}
+          message={`正在以 ${user.full_name || user.username} 的身份操作(${user.impersonator.full_name || user.impersonator.username},至 ${new Date(`${user.impersonator.expires_at.replace(' ', 'T')}Z`).toLocaleTimeString('zh-CN')})`}
+          action={<Button size="small" onClick={handleEndImpersonation}>结束模拟</Button>}
+        />
+      )}
       <Dropdown menu={{ items: menuItems }} placement="bottomRight">
         <Space style={{ cursor: 'pointer' }}>
           <Avatar icon={<UserOutlined />} />
@@ -83,6 +157,9 @@ const Header: React.FC = () => {
           </Space>
         </Space>
       </Dropdown>
+      <ChangePasswordModal open={changePasswordOpen} onClose={() => setChangePasswordOpen(false)} />
+      <ApiTokensModal open={apiTokensOpen} onClose={() => setApiTokensOpen(false)} />
+      <SessionsModal open={sessionsOpen} onClose={() => setSessionsOpen(false)} />
     </AntHeader>
   );
 };
@@ -0,0 +1,172 @@
+import { LockOutlined, PhoneOutlined, UserOutlined } from '@ant-design/icons'
+import { Alert, Button, Card, Descriptions, Form, Input, Result, Spin, Typography } from 'antd'
+import React, { useEffect, useState } from 'react'
+import { Link, useSearchParams } from 'react-router-dom'
+import { authService } from '../services/authService'
+import { InvitationPreview } from '../types'
+
+const { Title } = Typography
+
+const roleNames: Record<string, string> = {
+  project_manager: '项目经理',
+  task_executor: '任务执行者',
+}
+
+// 把邀请接口的错误码转成提示
+const invitationError = (error: any, fallback: string) => {
+  const code = error.response?.data?.code
+  if (code === 1002) return '邀请已过期,请联系邀请人重新发送'
+  if (code === 1003) return '邀请链接无效或已使用'
+  if (code === 4007) return '公司人数已达上限,请联系公司管理员'
+  return error.response?.data?.message || error.message || fallback
+}
+
+// 接受邀请: 受邀人通过邮件链接设置用户名、密码和资料
+const AcceptInvitation: React.FC = () => {
+  const [searchParams] = useSearchParams()
+  const token = searchParams.get('token')
+  const [invitation, setInvitation] = useState<InvitationPreview | null>(null)
+  const [loading, setLoading] = useState(true)
+  const [submitting, setSubmitting] = useState(false)
+  const [done, setDone] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  useEffect(() => {
+    if (!token) {
+      setError('邀请链接缺少令牌')
+      setLoading(false)
+      return
+    }
+    authService
+      .getInvitation(token)
+      .then(setInvitation)
+      .catch(error => setError(invitationError(error, '邀请加载失败')))
+      .finally(() => setLoading(false))
+  }, [token])
+
+  const onFinish = async (values: { username: string; password: string; full_name?: string; phone?: string }) => {
+    if (!token) return
+    setSubmitting(true)
+    setError(null)
+    try {
+      await authService.acceptInvitation({
+        token,
+        username: values.username.trim(),
+        password: values.password,
+        full_name: values.full_name?.trim() || undefined,
+        phone: values.phone?.trim() || undefined,
+      })
+      setDone(true)
+    } catch (error: any) {
+      setError(invitationError(error, '创建账号失败'))
+    } finally {
+      setSubmitting(false)
+    }
+  }
+
+  if (loading) {
+    return (
+      <div className="login-container">
+        <Spin size="large" />
+      </div>
+    )
+  }
+
+  if (done) {
+    return (
+      <div className="login-container">
+        <Card className="login-form">
+          <Result
+            status="success"
+            title="账号已创建"
+            subTitle={`您已加入${invitation?.company_name ?? ''},请使用新账号登录`}
+            extra={<Link to="/login"><Button type="primary">去登录</Button></Link>}
+          />
+        </Card>
+      </div>
+    )
+  }
+
+  return (
+    <div className="login-container">
+      <Card className="login-form">
+        <div style={{ textAlign: 'center', marginBottom: '2rem' }}>
+          <Title level={2}>接受邀请</Title>
+        </div>
+
+        {error && (
+          <Alert message={error} type="error" showIcon style={{ marginBottom: '1rem' }} />
+        )}
+
+        {invitation && (
+          <>
+            <Descriptions column={1} size="small" style={{ marginBottom: '1.5rem' }}>
+              <Descriptions.Item label="公司">{invitation.company_name}</Descriptions.Item>
+              <Descriptions.Item label="角色">{roleNames[invitation.role] ?? invitation.role}</Descriptions.Item>
+              <Descriptions.Item label="邮箱">{invitation.email}</Descriptions.Item>
+            </Descriptions>
+
+            <Form
+              name="accept-invitation"
+              onFinish={onFinish}
+              autoComplete="off"
+              size="large"
+              initialValues={{ full_name: invitation.full_name }}
+            >
+              <Form.Item
+                name="username"
+                rules={[
+                  { required: true, message: '请输入用户名!' },
+                  { min: 3, max: 50, message: '用户名为3-50个字符!' },
+                ]}
+              >
+                <Input prefix={<UserOutlined />} placeholder="用户名" disabled={submitting} />
+              </Form.Item>
+              <Form.Item name="full_name">
+                <Input prefix={<UserOutlined />} placeholder="姓名(可选)" disabled={submitting} />
+              </Form.Item>
+              <Form.Item name="phone">
+                <Input prefix={<PhoneOutlined />} placeholder="手机号(可选)" disabled={submitting} />
+              </Form.Item>
+              <Form.Item
+                name="password"
+                rules={[{ required: true, message: '请输入密码!' }]}
+                extra="密码强度要求以服务器策略为准"
+              >
+                <Input.Password prefix={<LockOutlined />} placeholder="密码" disabled={submitting} />
+              </Form.Item>
+              <Form.Item
+                name="confirm"
+                dependencies={['password']}
+                rules={[
+                  { required: true, message: '请再次输入密码!' },
+                  ({ getFieldValue }) => ({
+                    validator(_, value) {
+                      if (!value || getFieldValue('password') === value) {
+                        return Promise.resolve()
+                      }
+                      return Promise.reject(new Error('两次输入的密码不一致!'))
+                    },
+                  }),
+                ]}
+              >
+                <Input.Password prefix={<LockOutlined />} placeholder="确认密码" disabled={submitting} />
+              </Form.Item>
+              <Form.Item>
+                <Button type="primary" htmlType="submit" block loading={submitting}>
+                  创建账号
+                </Button>
+              </Form.Item>
+            </Form>
+          </>
+        )}
+
+        <div style={{ textAlign: 'center' }}>
+          <Link to="/login">返回登录</Link>
+        </div>
+      </Card>
+    </div>
+  )
+}
+
+export default AcceptInvitation
@@ -226,6 +226,7 @@ const CompanyManagement: React.FC = () => {
           <Form.Item
             label="公司代码"
             name="code"
+            extra="员工单点登录时填写，修改后需改用新代码登录"
             rules={[
               { required: true, message: '请输入公司代码' },
               { min: 2, message: '公司代码至少2个字符' },
@@ -233,7 +234,7 @@ const CompanyManagement: React.FC = () => {
               { pattern: /^[a-zA-Z0-9_-]+$/, message: '公司代码只能包含字母、数字、下划线和横线' },
             ]}
           >
-            <Input placeholder="请输入公司代码（唯一标识）" disabled={!!editingCompany} />
+            <Input placeholder="请输入公司代码（唯一标识）" />
           </Form.Item>
 
           <Form.Item
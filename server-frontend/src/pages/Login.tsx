@@ -1,12 +1,14 @@
-import { LockOutlined, UserOutlined } from '@ant-design/icons'
-import { Alert, Button, Card, Form, Input, Spin, Typography } from 'antd'
-import React, { useEffect } from 'react'
+import { BankOutlined, LockOutlined, SafetyOutlined, UserOutlined } from '@ant-design/icons'
+import { Alert, Button, Card, Divider, Form, Input, Modal, Space, Spin, Typography } from 'antd'
+import React, { useEffect, useState } from 'react'
 import { useDispatch, useSelector } from 'react-redux'
+import { Link } from 'react-router-dom'
+import { authService } from '../services/authService'
 import { AppDispatch, RootState } from '../store'
-import { clearAuthState, clearError, login } from '../store/authSlice'
-import { LoginRequest } from '../types'
+import { clearAuthState, clearError, login, setCredentials } from '../store/authSlice'
+import { LoginRequest, LoginResponse, TwoFactorChallenge, TwoFactorEnrollment } from '../types'
 
-const { Title } = Typography
+const { Title, Paragraph, Text } = Typography
 
 const Login: React.FC = () => {
   const dispatch = useDispatch<AppDispatch>()
@@ -18,8 +20,92 @@ const Login: React.FC = () => {
     dispatch(clearError())
   }, [dispatch])
 
-  const onFinish = (values: LoginRequest) => {
-    dispatch(login(values))
+  // 两步验证: 密码校验通过后返回的挑战
+  const [challenge, setChallenge] = useState<TwoFactorChallenge | null>(null)
+  const [enrollment, setEnrollment] = useState<TwoFactorEnrollment | null>(null)
+  const [verifying, setVerifying] = useState(false)
+  const [twoFactorError, setTwoFactorError] = useState<string | null>(null)
+
+  // 企业单点登录: 按公司代码跳转到该公司的身份提供方
+  const [companyCode, setCompanyCode] = useState('')
+  const [ssoLoading, setSsoLoading] = useState(false)
+  const [ssoError, setSsoError] = useState<string | null>(null)
+
+  const onSso = async () => {
+    if (!companyCode.trim()) {
+      setSsoError('请输入公司代码')
+      return
+    }
+    setSsoLoading(true)
+    setSsoError(null)
+    try {
+      window.location.href = await authService.startSso(companyCode.trim())
+    } catch (err: any) {
+      setSsoError(err.response?.data?.message || err.message || '发起单点登录失败')
+      setSsoLoading(false)
+    }
+  }
+
+  const onFinish = async (values: LoginRequest) => {
+    const result = await dispatch(login(values))
+    if (login.fulfilled.match(result) && result.payload.two_factor) {
+      setChallenge(result.payload.two_factor)
+    }
+  }
+
+  const completeLogin = (response: LoginResponse) => {
+    localStorage.setItem('token', response.token!)
+    localStorage.setItem('refresh_token', response.refresh_token!)
+    dispatch(setCredentials({ user: response.user, token: response.token! }))
+  }
+
+  const onVerify = async ({ code }: { code: string }) => {
+    if (!challenge) return
+    setVerifying(true)
+    setTwoFactorError(null)
+    try {
+      const response = await authService.verifyTwoFactor(challenge.challenge_token, code.trim())
+      if (response.recovery_codes?.length) {
+        // 恢复码只显示这一次,确认保存后再进入系统
+        Modal.info({
+          title: '请妥善保存恢复码',
+          content: (
+            <div>
+              <Paragraph>丢失验证器时可使用以下恢复码登录,每个只能使用一次:</Paragraph>
+              <Paragraph copyable={{ text: response.recovery_codes.join('\n') }}>
+                {response.recovery_codes.map((recoveryCode) => (
+                  <div key={recoveryCode}><Text code>{recoveryCode}</Text></div>
+                ))}
+              </Paragraph>
+            </div>
+          ),
+          okText: '我已保存',
+          onOk: () => completeLogin(response),
+        })
+      } else {
+        completeLogin(response)
+      }
+    } catch (error: any) {
+      const code = error.response?.data?.code
+      if (code === 1003 || code === 1002) {
+        // 挑战失效(过期或输错次数过多),需要重新输入密码
+        setChallenge(null)
+        setEnrollment(null)
+      }
+      setTwoFactorError(error.response?.data?.message || error.message || '验证失败')
+    } finally {
+      setVerifying(false)
+    }
+  }
+
+  const onEnroll = async () => {
+    if (!challenge) return
+    setTwoFactorError(null)
+    try {
+      setEnrollment(await authService.enrollTwoFactorChallenge(challenge.challenge_token))
+    } catch (error: any) {
+      setTwoFactorError(error.response?.data?.message || error.message || '获取密钥失败')
+    }
   }
 
   // 验证用户名/邮箱/手机号格式
@@ -71,6 +157,60 @@ const Login: React.FC = () => {
           />
         )}
 
+        {challenge ? (
+          <>
+            {twoFactorError && (
+              <Alert
+                message={twoFactorError}
+                type="error"
+                showIcon
+                style={{ marginBottom: '1rem' }}
+                closable
+                onClose={() => setTwoFactorError(null)}
+              />
+            )}
+
+            {challenge.enrollment_required && !enrollment ? (
+              <>
+                <Paragraph>安全策略要求启用两步验证,请先在验证器应用(如 Google Authenticator)中添加账号。</Paragraph>
+                <Button type="primary" block size="large" onClick={onEnroll}>
+                  获取验证器密钥
+                </Button>
+              </>
+            ) : (
+              <>
+                {enrollment && (
+                  <div style={{ marginBottom: '1rem' }}>
+                    <Paragraph>在验证器应用中手动输入以下密钥,或使用二维码地址生成二维码扫描:</Paragraph>
+                    <Paragraph copyable><Text code>{enrollment.secret}</Text></Paragraph>
+                    <Paragraph copyable={{ text: enrollment.otpauth_uri }} type="secondary" style={{ wordBreak: 'break-all' }}>
+                      {enrollment.otpauth_uri}
+                    </Paragraph>
+                  </div>
+                )}
+                <Form name="two-factor" onFinish={onVerify} autoComplete="off" size="large">
+                  <Form.Item name="code" rules={[{ required: true, message: '请输入验证码或恢复码!' }]}>
+                    <Input
+                      prefix={<SafetyOutlined />}
+                      placeholder={enrollment ? '验证器中的6位验证码' : '6位验证码 / 恢复码'}
+                      disabled={verifying}
+                      autoFocus
+                    />
+                  </Form.Item>
+                  <Form.Item>
+                    <Button type="primary" htmlType="submit" block loading={verifying}>
+                      验证
+                    </Button>
+                  </Form.Item>
+                </Form>
+              </>
+            )}
+
+            <Button type="link" block onClick={() => { setChallenge(null); setEnrollment(null) }}>
+              返回重新登录
+            </Button>
+          </>
+        ) : (
         <Form
           name="login"
           onFinish={onFinish}
@@ -115,7 +255,26 @@ const Login: React.FC = () => {
               {loading ? <Spin size="small" /> : '登录'}
             </Button>
           </Form.Item>
+
+          <div style={{ textAlign: 'right', marginTop: '-0.5rem', marginBottom: '1rem' }}>
+            <Link to="/reset-password">忘记密码?</Link>
+          </div>
+
+          <Divider plain>企业账号登录</Divider>
+          {ssoError && <Alert type="error" message={ssoError} showIcon style={{ marginBottom: '1rem' }} />}
+          <Space.Compact block style={{ marginBottom: '1rem' }}>
+            <Input
+              prefix={<BankOutlined />}
+              placeholder="公司代码"
+              value={companyCode}
+              onChange={(e) => setCompanyCode(e.target.value)}
+              onPressEnter={(e) => { e.preventDefault(); onSso() }}
+              disabled={ssoLoading}
+            />
+            <Button onClick={onSso} loading={ssoLoading}>单点登录</Button>
+          </Space.Compact>
         </Form>
+        )}
 
         <div style={{ textAlign: 'center', color: '#666', fontSize: '12px' }}>
           <p>💡 登录方式：用户名、邮箱地址 或 手机号码</p>
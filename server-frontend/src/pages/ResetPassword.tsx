@@ -0,0 +1,152 @@
+import { LockOutlined, MailOutlined } from '@ant-design/icons'
+import { Alert, Button, Card, Form, Input, Result, Typography } from 'antd'
+import React, { useState } from 'react'
+import { Link, useSearchParams } from 'react-router-dom'
+import { authService } from '../services/authService'
+
+const { Title } = Typography
+
+// 找回密码: 没有令牌时申请重置邮件,带令牌(邮件链接)时设置新密码
+const ResetPassword: React.FC = () => {
+  const [searchParams] = useSearchParams()
+  const token = searchParams.get('token')
+  const [submitting, setSubmitting] = useState(false)
+  const [done, setDone] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  const onRequest = async ({ email }: { email: string }) => {
+    setSubmitting(true)
+    setError(null)
+    try {
+      await authService.requestPasswordReset(email.trim())
+      setDone(true)
+    } catch (error: any) {
+      setError(error.response?.data?.message || error.message || '提交失败')
+    } finally {
+      setSubmitting(false)
+    }
+  }
+
+  const onReset = async ({ password }: { password: string }) => {
+    if (!token) return
+    setSubmitting(true)
+    setError(null)
+    try {
+      await authService.resetPassword(token, password)
+      setDone(true)
+    } catch (error: any) {
+      const code = error.response?.data?.code
+      if (code === 1002) {
+        setError('链接已过期,请重新申请找回密码')
+      } else if (code === 1003) {
+        setError('链接无效或已使用,请重新申请找回密码')
+      } else {
+        setError(error.response?.data?.message || error.message || '重置失败')
+      }
+    } finally {
+      setSubmitting(false)
+    }
+  }
+
+  if (done) {
+    return (
+      <div className="login-container">
+        <Card className="login-form">
+          {token ? (
+            <Result
+              status="success"
+              title="密码已重置"
+              subTitle="原有的登录会话已全部失效,请使用新密码登录"
+              extra={<Link to="/login"><Button type="primary">去登录</Button></Link>}
+            />
+          ) : (
+            <Result
+              status="info"
+              title="请查收邮件"
+              subTitle="如果该邮箱已绑定账号,我们已发送找回密码链接,链接1小时内有效"
+              extra={<Link to="/login">返回登录</Link>}
+            />
+          )}
+        </Card>
+      </div>
+    )
+  }
+
+  return (
+    <div className="login-container">
+      <Card className="login-form">
+        <div style={{ textAlign: 'center', marginBottom: '2rem' }}>
+          <Title level={2}>{token ? '设置新密码' : '找回密码'}</Title>
+        </div>
+
+        {error && (
+          <Alert
+            message={error}
+            type="error"
+            showIcon
+            style={{ marginBottom: '1rem' }}
+            closable
+            onClose={() => setError(null)}
+          />
+        )}
+
+        {token ? (
+          <Form name="reset-password" onFinish={onReset} autoComplete="off" size="large">
+            <Form.Item
+              name="password"
+              rules={[{ required: true, message: '请输入新密码!' }]}
+              extra="密码强度要求以服务器策略为准"
+            >
+              <Input.Password prefix={<LockOutlined />} placeholder="新密码" disabled={submitting} />
+            </Form.Item>
+            <Form.Item
+              name="confirm"
+              dependencies={['password']}
+              rules={[
+                { required: true, message: '请再次输入新密码!' },
+                ({ getFieldValue }) => ({
+                  validator(_, value) {
+                    if (!value || getFieldValue('password') === value) {
+                      return Promise.resolve()
+                    }
+                    return Promise.reject(new Error('两次输入的密码不一致!'))
+                  },
+                }),
+              ]}
+            >
+              <Input.Password prefix={<LockOutlined />} placeholder="确认新密码" disabled={submitting} />
+            </Form.Item>
+            <Form.Item>
+              <Button type="primary" htmlType="submit" block loading={submitting}>
+                重置密码
+              </Button>
+            </Form.Item>
+          </Form>
+        ) : (
+          <Form name="request-reset" onFinish={onRequest} autoComplete="off" size="large">
+            <Form.Item
+              name="email"
+              rules={[
+                { required: true, message: '请输入邮箱!' },
+                { type: 'email', message: '请输入有效的邮箱地址!' },
+              ]}
+            >
+              <Input prefix={<MailOutlined />} placeholder="账号绑定的邮箱" disabled={submitting} />
+            </Form.Item>
+            <Form.Item>
+              <Button type="primary" htmlType="submit" block loading={submitting}>
+                发送找回密码邮件
+              </Button>
+            </Form.Item>
+          </Form>
+        )}
+
+        <div style={{ textAlign: 'center' }}>
+          <Link to="/login">返回登录</Link>
+        </div>
+      </Card>
+    </div>
+  )
+}
+
+export default ResetPassword
@@ -0,0 +1,71 @@
+import { Card, Result, Spin } from 'antd'
+import React, { useEffect, useRef, useState } from 'react'
+import { useDispatch } from 'react-redux'
+import { Link, useNavigate, useSearchParams } from 'react-router-dom'
+import { authService } from '../services/authService'
+import { AppDispatch } from '../store'
+import { setCredentials } from '../store/authSlice'
+
+// 身份提供方登录完成后跳回此页,提交授权码换取登录令牌
+const SsoCallback: React.FC = () => {
+  const dispatch = useDispatch<AppDispatch>()
+  const navigate = useNavigate()
+  const [searchParams] = useSearchParams()
+  const [message, setMessage] = useState<string | null>(null)
+  // state 只能使用一次,避免开发模式下 effect 重复执行导致第二次提交失败
+  const submitted = useRef(false)
+
+  useEffect(() => {
+    if (submitted.current) return
+    submitted.current = true
+
+    const code = searchParams.get('code')
+    const state = searchParams.get('state')
+    if (searchParams.get('error')) {
+      setMessage(searchParams.get('error_description') || '身份提供方拒绝了登录请求')
+      return
+    }
+    if (!code || !state) {
+      setMessage('回调地址缺少授权码')
+      return
+    }
+
+    authService
+      .completeSso(code, state)
+      .then((response) => {
+        localStorage.setItem('token', response.token!)
+        localStorage.setItem('refresh_token', response.refresh_token!)
+        dispatch(setCredentials({ user: response.user!, token: response.token! }))
+        navigate('/dashboard', { replace: true })
+      })
+      .catch((error: any) => {
+        const data = error.response?.data
+        if (data?.code === 1003) {
+          setMessage('登录请求已过期或无效,请重新发起')
+        } else {
+          setMessage(data?.message || error.message || '单点登录失败')
+        }
+      })
+  }, [dispatch, navigate, searchParams])
+
+  return (
+    <div className="login-container">
+      <Card className="login-form">
+        {message ? (
+          <Result
+            status="error"
+            title="单点登录失败"
+            subTitle={message}
+            extra={<Link to="/login">返回登录</Link>}
+          />
+        ) : (
+          <div style={{ textAlign: 'center', padding: '2rem' }}>
+            <Spin tip="正在完成单点登录..." />
+          </div>
+        )}
+      </Card>
+    </div>
+  )
+}
+
+export default SsoCallback
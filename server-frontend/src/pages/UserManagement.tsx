@@ -5,11 +5,14 @@
 
 import React, { useEffect, useState } from 'react';
 import { Table, Button, Space, Tag, message, Modal, Form, Input, Select, Card } from 'antd';
-import { PlusOutlined, EditOutlined, DeleteOutlined } from '@ant-design/icons';
+import { PlusOutlined, EditOutlined, DeleteOutlined, UserSwitchOutlined, DesktopOutlined, DownloadOutlined } from '@ant-design/icons';
 import type { ColumnsType } from 'antd/es/table';
 import { apiClient } from '../services/api';
+import { authService } from '../services/authService';
+import { userService } from '../services/userService';
 import { usePermissions } from '../hooks/usePermissions';
 import { UserRole } from '../types/user';
+import SessionsModal from '../components/SessionsModal';
 
 interface User {
   id: number;
@@ -27,6 +30,7 @@ const UserManagement: React.FC = () => {
   const [loading, setLoading] = useState(false);
   const [isModalVisible, setIsModalVisible] = useState(false);
   const [editingUser, setEditingUser] = useState<User | null>(null);
+  const [sessionsUser, setSessionsUser] = useState<User | null>(null);
   const [form] = Form.useForm();
   const { isSystemAdmin, canManageUsers } = usePermissions();
 
@@ -94,7 +98,7 @@ const UserManagement: React.FC = () => {
   const handleDelete = (user: User) => {
     Modal.confirm({
       title: '确认删除',
-      content: `确定要删除用户 "${user.username}" 吗?`,
+      content: `确定要删除用户 "${user.username}" 吗?账号将被匿名化并清除个人信息,已有任务和统计数据保留,此操作不可撤销。`,
       okText: '确定',
       cancelText: '取消',
       okType: 'danger',
@@ -110,6 +114,48 @@ const UserManagement: React.FC = () => {
     });
   };
 
+  // 导出用户的个人数据
+  const handleExport = async (user: User) => {
+    try {
+      await userService.exportPersonalData(user.id);
+    } catch (error: any) {
+      message.error(error.response?.data?.message || '导出失败');
+    }
+  };
+
+  // 平台管理员以该用户身份操作,需填写原因(记入审计日志)
+  const handleImpersonate = (user: User) => {
+    let reason = '';
+    Modal.confirm({
+      title: `模拟登录为 "${user.username}"`,
+      content: (
+        <div>
+          <p>模拟期间的所有操作都会记入审计日志,30分钟后自动失效,且不能修改密码和两步验证设置。</p>
+          <Input.TextArea
+            rows={3}
+            maxLength={500}
+            placeholder="模拟原因,如工单编号"
+            onChange={(e) => { reason = e.target.value; }}
+          />
+        </div>
+      ),
+      okText: '开始模拟',
+      cancelText: '取消',
+      onOk: async () => {
+        if (!reason.trim()) {
+          message.error('请填写模拟原因');
+          throw new Error('missing reason');
+        }
+        try {
+          await authService.startImpersonation(user.id, reason.trim());
+          window.location.href = '/';
+        } catch (error: any) {
+          message.error(error.response?.data?.message || '模拟登录失败');
+        }
+      },
+    });
+  };
+
   // 角色标签颜色
   const getRoleColor = (role: string) => {
     const roleColors: Record<string, string> = {
@@ -198,7 +244,7 @@ const UserManagement: React.FC = () => {
       title: '操作',
       key: 'action',
       fixed: 'right',
-      width: 150,
+      width: 320,
       render: (_, record) => (
         <Space size="small">
           <Button
@@ -210,6 +256,36 @@ const UserManagement: React.FC = () => {
           >
             编辑
           </Button>
+          {canManageUsers() && (
+            <Button
+              type="link"
+              size="small"
+              icon={<DesktopOutlined />}
+              onClick={() => setSessionsUser(record)}
+            >
+              登录设备
+            </Button>
+          )}
+          {canManageUsers() && (
+            <Button
+              type="link"
+              size="small"
+              icon={<DownloadOutlined />}
+              onClick={() => handleExport(record)}
+            >
+              导出数据
+            </Button>
+          )}
+          {isSystemAdmin() && record.role !== UserRole.PlatformAdmin && (
+            <Button
+              type="link"
+              size="small"
+              icon={<UserSwitchOutlined />}
+              onClick={() => handleImpersonate(record)}
+            >
+              模拟登录
+            </Button>
+          )}
           <Button
             type="link"
             size="small"
@@ -339,6 +415,13 @@ const UserManagement: React.FC = () => {
           </Form.Item>
         </Form>
       </Modal>
+
+      <SessionsModal
+        open={!!sessionsUser}
+        onClose={() => setSessionsUser(null)}
+        userId={sessionsUser?.id}
+        title={sessionsUser ? `${sessionsUser.full_name || sessionsUser.username} 的登录设备` : undefined}
+      />
     </div>
   );
 };
@@ -0,0 +1,69 @@
+import { Button, Card, Result, Spin } from 'antd'
+import React, { useEffect, useRef, useState } from 'react'
+import { Link, useSearchParams } from 'react-router-dom'
+import { authService } from '../services/authService'
+
+// 打开邮件中的验证链接后自动提交令牌
+const VerifyEmail: React.FC = () => {
+  const [searchParams] = useSearchParams()
+  const token = searchParams.get('token')
+  const [status, setStatus] = useState<'verifying' | 'success' | 'error'>('verifying')
+  const [message, setMessage] = useState('')
+  // 令牌只能使用一次,避免开发模式下 effect 重复执行导致第二次提交失败
+  const submitted = useRef(false)
+
+  useEffect(() => {
+    if (submitted.current) return
+    submitted.current = true
+
+    if (!token) {
+      setStatus('error')
+      setMessage('链接缺少验证令牌')
+      return
+    }
+
+    authService
+      .verifyEmail(token)
+      .then(() => setStatus('success'))
+      .catch((error: any) => {
+        const code = error.response?.data?.code
+        setStatus('error')
+        if (code === 1002) {
+          setMessage('链接已过期,请登录后重新发送验证邮件')
+        } else if (code === 1003) {
+          setMessage('链接无效或已使用')
+        } else {
+          setMessage(error.response?.data?.message || error.message || '验证失败')
+        }
+      })
+  }, [token])
+
+  return (
+    <div className="login-container">
+      <Card className="login-form">
+        {status === 'verifying' && (
+          <div style={{ textAlign: 'center', padding: '2rem' }}>
+            <Spin tip="正在验证邮箱..." />
+          </div>
+        )}
+        {status === 'success' && (
+          <Result
+            status="success"
+            title="邮箱验证成功"
+            extra={<Link to="/"><Button type="primary">进入系统</Button></Link>}
+          />
+        )}
+        {status === 'error' && (
+          <Result
+            status="error"
+            title="邮箱验证失败"
+            subTitle={message}
+            extra={<Link to="/">返回</Link>}
+          />
+        )}
+      </Card>
+    </div>
+  )
+}
+
+export default VerifyEmail
@@ -24,15 +24,96 @@ apiClient.interceptors.request.use(
   }
 )
 
-// 响应拦截器 - 处理错误
+// 所属公司已停用的错误码
+const COMPANY_INACTIVE = 1011
+
+// 同一时间只发起一次刷新,并发的401请求共用结果
+let refreshing: Promise<string> | null = null
+
+const refreshAccessToken = async (): Promise<string> => {
+  const staleRefreshToken = localStorage.getItem('refresh_token')
+
+  const rotate = async (): Promise<string> => {
+    const refreshToken = localStorage.getItem('refresh_token')
+    if (!refreshToken) {
+      throw new Error('没有刷新令牌')
+    }
+    // 其他标签页已经完成刷新,直接使用它保存的新令牌
+    const token = localStorage.getItem('token')
+    if (refreshToken !== staleRefreshToken && token) {
+      return token
+    }
+
+    // 刷新令牌只能使用一次,成功后同时保存新的刷新令牌
+    try {
+      const response = await axios.post(`${API_BASE_URL}/api/v1/auth/refresh`, { refresh_token: refreshToken })
+      const { token: newToken, refresh_token } = response.data.data
+      localStorage.setItem('token', newToken)
+      localStorage.setItem('refresh_token', refresh_token)
+      return newToken
+    } catch (error) {
+      // 409: 令牌刚被其他标签页轮换,改用它保存的新令牌
+      const latestToken = localStorage.getItem('token')
+      if (axios.isAxiosError(error) && error.response?.status === 409
+        && localStorage.getItem('refresh_token') !== refreshToken && latestToken) {
+        return latestToken
+      }
+      throw error
+    }
+  }
+
+  // 多个标签页共用localStorage中的刷新令牌,用浏览器锁让它们依次刷新
+  if ('locks' in navigator) {
+    return navigator.locks.request('flow-farm-token-refresh', rotate)
+  }
+  return rotate()
+}
+
+const redirectToLogin = () => {
+  localStorage.removeItem('token')
+  localStorage.removeItem('refresh_token')
+  window.location.href = '/login'
+}
+
+// 不依赖登录状态的认证接口,401表示凭据/验证码/邮件令牌无效,交给页面自行提示
+const PUBLIC_AUTH_PATHS = [
+  '/api/v1/auth/login',
+  '/api/v1/auth/2fa/verify',
+  '/api/v1/auth/2fa/challenge/enroll',
+  '/api/v1/auth/verify-email',
+  '/api/v1/auth/password-reset/request',
+  '/api/v1/auth/password-reset/confirm',
+]
+
+// 响应拦截器 - 访问令牌过期时用刷新令牌续期并重试一次
 apiClient.interceptors.response.use(
   (response) => {
     return response
   },
-  (error) => {
-    if (error.response?.status === 401) {
-      localStorage.removeItem('token')
-      window.location.href = '/login'
+  async (error) => {
+    const original = error.config
+    if (PUBLIC_AUTH_PATHS.includes(original?.url)) {
+      return Promise.reject(error)
+    }
+
+    if (error.response?.status === 401 && original && !original._retry && localStorage.getItem('refresh_token')) {
+      original._retry = true
+      try {
+        refreshing = refreshing ?? refreshAccessToken()
+        const token = await refreshing
+        original.headers.Authorization = `Bearer ${token}`
+        return apiClient(original)
+      } catch (refreshError) {
+        redirectToLogin()
+        return Promise.reject(refreshError)
+      } finally {
+        refreshing = null
+      }
+    }
+
+    // 401: 会话失效; 1011: 所属公司已停用(重新登录时会看到停用提示)
+    if (error.response?.status === 401 || error.response?.data?.code === COMPANY_INACTIVE) {
+      redirectToLogin()
     }
     return Promise.reject(error)
   }
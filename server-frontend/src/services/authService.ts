@@ -1,6 +1,23 @@
-import { LoginRequest, LoginResponse, User, ApiResponse } from '../types'
+import {
+  LoginRequest,
+  LoginResponse,
+  User,
+  ApiResponse,
+  TwoFactorEnrollment,
+  ApiToken,
+  CreateApiTokenRequest,
+  CreatedApiToken,
+  SsoAuthorization,
+  InvitationPreview,
+  AcceptInvitationRequest,
+  Session,
+  RegistrationMode,
+} from '../types'
 import { apiClient } from './api'
 
+// 模拟登录期间保存管理员自己令牌的位置
+const IMPERSONATION_RESTORE_KEY = 'impersonation_restore'
+
 export const authService = {
   async login(loginData: LoginRequest): Promise<LoginResponse> {
     const response = await apiClient.post<ApiResponse<LoginResponse>>('/api/v1/auth/login', loginData)
@@ -13,6 +30,33 @@ export const authService = {
     }
   },
 
+  // 两步验证登录第二步: 提交验证码或恢复码
+  async verifyTwoFactor(challengeToken: string, code: string): Promise<LoginResponse> {
+    const response = await apiClient.post<ApiResponse<LoginResponse>>('/api/v1/auth/2fa/verify', {
+      challenge_token: challengeToken,
+      code,
+    })
+
+    if (response.data.success && response.data.data) {
+      return response.data.data
+    } else {
+      throw new Error(response.data.message || '验证失败')
+    }
+  },
+
+  // 策略要求两步验证但尚未启用时,在登录过程中获取验证器密钥
+  async enrollTwoFactorChallenge(challengeToken: string): Promise<TwoFactorEnrollment> {
+    const response = await apiClient.post<ApiResponse<TwoFactorEnrollment>>('/api/v1/auth/2fa/challenge/enroll', {
+      challenge_token: challengeToken,
+    })
+
+    if (response.data.success && response.data.data) {
+      return response.data.data
+    } else {
+      throw new Error(response.data.message || '获取密钥失败')
+    }
+  },
+
   async logout(): Promise<void> {
     await apiClient.post('/api/v1/auth/logout')
   },
@@ -28,10 +72,136 @@ export const authService = {
     }
   },
 
+  // 平台管理员模拟登录为指定用户: 保存自己的令牌,切换为有时限的模拟令牌
+  async startImpersonation(userId: number, reason: string): Promise<void> {
+    const response = await apiClient.post(`/api/v1/users/${userId}/impersonate`, { reason })
+    sessionStorage.setItem(IMPERSONATION_RESTORE_KEY, JSON.stringify({
+      token: localStorage.getItem('token'),
+      refresh_token: localStorage.getItem('refresh_token'),
+    }))
+    // 模拟令牌不能刷新,移除刷新令牌避免过期后误用管理员的刷新令牌续期
+    localStorage.setItem('token', response.data.data.token)
+    localStorage.removeItem('refresh_token')
+  },
+
+  // 结束模拟登录并恢复管理员自己的令牌
+  async endImpersonation(): Promise<void> {
+    try {
+      await apiClient.post('/api/v1/auth/impersonation/end')
+    } finally {
+      const saved = JSON.parse(sessionStorage.getItem(IMPERSONATION_RESTORE_KEY) || '{}')
+      sessionStorage.removeItem(IMPERSONATION_RESTORE_KEY)
+      localStorage.removeItem('token')
+      if (saved.token) localStorage.setItem('token', saved.token)
+      if (saved.refresh_token) localStorage.setItem('refresh_token', saved.refresh_token)
+    }
+  },
+
+  // 重新发送邮箱验证邮件
+  async sendVerificationEmail(): Promise<void> {
+    await apiClient.post('/api/v1/auth/verify-email/send')
+  },
+
+  // 用邮件中的令牌完成邮箱验证
+  async verifyEmail(token: string): Promise<void> {
+    await apiClient.post('/api/v1/auth/verify-email', { token })
+  },
+
+  // 查看邀请链接对应的公司、角色和邮箱
+  async getInvitation(token: string): Promise<InvitationPreview> {
+    const response = await apiClient.post('/api/v1/auth/invitation', { token })
+    return response.data.data
+  },
+
+  // 接受邀请,设置自己的用户名、密码和资料
+  async acceptInvitation(request: AcceptInvitationRequest): Promise<void> {
+    await apiClient.post('/api/v1/auth/invitation/accept', request)
+  },
+
+  // 当前注册策略,决定登录页是否显示自助注册入口
+  async getRegistrationMode(): Promise<RegistrationMode> {
+    const response = await apiClient.get<ApiResponse<{ mode: RegistrationMode }>>('/api/v1/auth/registration-policy')
+    return response.data.data?.mode ?? 'invite_only'
+  },
+
+  // 申请找回密码,邮箱不存在时同样返回成功
+  async requestPasswordReset(email: string): Promise<void> {
+    await apiClient.post('/api/v1/auth/password-reset/request', { email })
+  },
+
+  // 用邮件中的令牌设置新密码
+  async resetPassword(token: string, newPassword: string): Promise<void> {
+    await apiClient.post('/api/v1/auth/password-reset/confirm', {
+      token,
+      new_password: newPassword,
+    })
+  },
+
   async changePassword(oldPassword: string, newPassword: string): Promise<void> {
     await apiClient.post('/api/v1/auth/change-password', {
       old_password: oldPassword,
       new_password: newPassword,
     })
   },
+
+  // 当前用户的个人访问令牌(管理者还包括本公司的API密钥)
+  async listApiTokens(): Promise<ApiToken[]> {
+    const response = await apiClient.get<ApiResponse<ApiToken[]>>('/api/v1/auth/tokens')
+    return response.data.data || []
+  },
+
+  // 创建访问令牌,返回的 token 只显示这一次
+  async createApiToken(request: CreateApiTokenRequest): Promise<CreatedApiToken> {
+    const response = await apiClient.post<ApiResponse<CreatedApiToken>>('/api/v1/auth/tokens', request)
+    if (!response.data.data) {
+      throw new Error(response.data.message || '创建访问令牌失败')
+    }
+    return response.data.data
+  },
+
+  async revokeApiToken(id: string): Promise<void> {
+    await apiClient.delete(`/api/v1/auth/tokens/${id}`)
+  },
+
+  // 有效的登录会话,管理者可查看本公司用户的会话
+  async listSessions(userId?: number): Promise<Session[]> {
+    const response = await apiClient.get<ApiResponse<Session[]>>('/api/v1/sessions', {
+      params: userId ? { user_id: userId } : undefined,
+    })
+    return response.data.data || []
+  },
+
+  // 撤销会话,对应设备立即退出登录
+  async revokeSession(id: string): Promise<void> {
+    await apiClient.delete(`/api/v1/sessions/${id}`)
+  },
+
+  // 按公司代码发起单点登录,返回身份提供方的授权地址
+  async startSso(companyCode: string): Promise<string> {
+    const response = await apiClient.post<ApiResponse<SsoAuthorization>>('/api/v1/auth/sso/start', {
+      company_code: companyCode,
+    })
+    if (!response.data.data) {
+      throw new Error(response.data.message || '发起单点登录失败')
+    }
+    return response.data.data.authorization_url
+  },
+
+  // 已登录用户关联企业账号
+  async startSsoLink(): Promise<string> {
+    const response = await apiClient.post<ApiResponse<SsoAuthorization>>('/api/v1/auth/sso/link/start')
+    if (!response.data.data) {
+      throw new Error(response.data.message || '发起关联失败')
+    }
+    return response.data.data.authorization_url
+  },
+
+  // 身份提供方回调后提交授权码,成功后返回登录令牌
+  async completeSso(code: string, state: string): Promise<LoginResponse> {
+    const response = await apiClient.post<ApiResponse<LoginResponse>>('/api/v1/auth/sso/callback', { code, state })
+    if (!response.data.data) {
+      throw new Error(response.data.message || '单点登录失败')
+    }
+    return response.data.data
+  },
 }
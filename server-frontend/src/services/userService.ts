@@ -101,11 +101,28 @@ export const userService = {
     return response.data;
   },
 
-  // 删除用户
+  // 删除用户(匿名化账号,任务和统计数据保留)
   async deleteUser(userId: number): Promise<void> {
     await apiClient.delete(`/api/v1/users/${userId}`);
   },
 
+  // 导出用户的个人数据(ZIP)并保存到本地
+  async exportPersonalData(userId: number | string): Promise<void> {
+    const response = await apiClient.get(`/api/v1/users/${userId}/export`, {
+      responseType: "blob",
+    });
+    const disposition: string = response.headers["content-disposition"] || "";
+    const filename =
+      /filename="([^"]+)"/.exec(disposition)?.[1] ||
+      `personal-data-${userId}.zip`;
+    const url = URL.createObjectURL(response.data);
+    const link = document.createElement("a");
+    link.href = url;
+    link.download = filename;
+    link.click();
+    URL.revokeObjectURL(url);
+  },
+
   // 获取公司统计信息（系统管理员使用）
   async getCompanyStatistics(): Promise<CompanyStatistics[]> {
     return callApiWithFallback<CompanyStatistics[]>(() =>
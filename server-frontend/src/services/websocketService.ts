@@ -2,6 +2,12 @@
  * TaskFleet - WebSocket实时通信服务
  */
 
+/**
+ * 服务端主动关闭连接使用的关闭码: 登录会话被撤销 / 所属公司停用
+ */
+const CLOSE_SESSION_REVOKED = 4001;
+const CLOSE_COMPANY_INACTIVE = 4011;
+
 /**
  * WebSocket事件类型
  */
@@ -148,7 +154,7 @@ class WebSocketService {
   /**
    * 处理连接关闭
    */
-  private handleClose(): void {
+  private handleClose(event: CloseEvent): void {
     console.log('WebSocket disconnected');
     
     if (this.heartbeatInterval) {
@@ -156,6 +162,12 @@ class WebSocketService {
       this.heartbeatInterval = null;
     }
 
+    // 服务端因会话被撤销或所属公司停用而断开,重连也会被拒绝
+    if (event.code === CLOSE_SESSION_REVOKED || event.code === CLOSE_COMPANY_INACTIVE) {
+      console.warn(`WebSocket closed by server: ${event.reason}`);
+      return;
+    }
+
     this.handleReconnect();
   }
 
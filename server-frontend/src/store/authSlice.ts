@@ -25,14 +25,20 @@ export const login = createAsyncThunk(
     try {
       // 登录前先完全清理之前的状态
       localStorage.removeItem('token')
+      localStorage.removeItem('refresh_token')
       dispatch(clearAuthState())
 
       const response = await authService.login(loginData)
-      localStorage.setItem('token', response.token)
+      // 需要两步验证时不签发令牌,由登录页继续完成验证
+      if (response.token && response.refresh_token) {
+        localStorage.setItem('token', response.token)
+        localStorage.setItem('refresh_token', response.refresh_token)
+      }
       return response
     } catch (error: any) {
       // 登录失败时也要清理状态
       localStorage.removeItem('token')
+      localStorage.removeItem('refresh_token')
 
       // 处理不同类型的错误
       let errorMessage = '登录失败'
@@ -75,6 +81,7 @@ export const logout = createAsyncThunk(
 
     // 无论后端登出是否成功，都要清理前端状态
     localStorage.removeItem('token')
+    localStorage.removeItem('refresh_token')
     return null
   }
 )
@@ -87,6 +94,7 @@ export const getCurrentUser = createAsyncThunk(
       return response
     } catch (error: any) {
       localStorage.removeItem('token')
+      localStorage.removeItem('refresh_token')
 
       let errorMessage = '获取用户信息失败'
       if (error.response?.data?.detail) {
@@ -131,9 +139,9 @@ const authSlice = createSlice({
         // 确保完全清理之前的状态后再设置新状态
         state.loading = false
         state.error = null
-        state.isAuthenticated = true
-        state.user = action.payload.user
-        state.token = action.payload.token
+        state.isAuthenticated = !!action.payload.token
+        state.user = action.payload.user ?? null
+        state.token = action.payload.token ?? null
       })
       .addCase(login.rejected, (state, action) => {
         state.loading = false
@@ -40,8 +40,126 @@ export interface LoginRequest {
  * 登录响应
  */
 export interface LoginResponse {
+  // 需要两步验证时以下字段为空,只返回 two_factor
+  token?: string;
+  refresh_token?: string; // 访问令牌过期后用于换取新令牌(只能使用一次)
+  expires_in?: number; // 访问令牌有效期(秒)
+  user?: any; // 使用User类型
+  two_factor?: TwoFactorChallenge;
+  recovery_codes?: string[]; // 登录时完成两步验证注册后生成的恢复码(只显示这一次)
+}
+
+/**
+ * 两步验证登录挑战
+ */
+export interface TwoFactorChallenge {
+  challenge_token: string;
+  expires_in: number;
+  enrollment_required: boolean; // 策略要求但尚未启用,需要先注册验证器
+}
+
+/**
+ * 两步验证注册信息
+ */
+export interface TwoFactorEnrollment {
+  secret: string;
+  otpauth_uri: string;
+}
+
+/**
+ * 个人访问令牌 / 公司API密钥(不含令牌本身)
+ */
+export interface ApiToken {
+  id: string;
+  kind: 'personal' | 'company';
+  name: string;
+  token_prefix: string; // 令牌开头几位,便于辨认
+  scopes: string[];
+  user_id: number; // 个人令牌的所有者或公司密钥的创建人
+  company_id?: number;
+  created_at: string;
+  expires_at?: string; // 为空表示不过期
+  last_used_at?: string;
+  last_used_ip?: string;
+  revoked_at?: string;
+}
+
+/**
+ * 创建访问令牌请求
+ */
+export interface CreateApiTokenRequest {
+  name: string;
+  kind: 'personal' | 'company';
+  scopes: string[];
+  expires_in_days?: number;
+}
+
+/**
+ * 新创建的访问令牌,token 只返回这一次
+ */
+export interface CreatedApiToken extends ApiToken {
+  token: string;
+}
+
+/**
+ * 有效的登录会话
+ */
+export interface Session {
+  id: string;
+  user_id: number;
+  user_agent?: string;
+  client_type: 'web' | 'desktop';
+  ip_address?: string;
+  created_at: string;
+  last_used_at: string;
+  expires_at: string;
+  current: boolean; // 是否为当前浏览器的会话
+  impersonator_id?: number; // 模拟登录会话对应的管理员
+}
+
+/**
+ * 单点登录授权地址,前端跳转到身份提供方
+ */
+export interface SsoAuthorization {
+  authorization_url: string;
+}
+
+/**
+ * 邀请链接对应的邀请内容
+ */
+export interface InvitationPreview {
+  email: string;
+  company_name: string;
+  role: string;
+  full_name?: string;
+  expires_at: string;
+}
+
+/**
+ * 接受邀请请求
+ */
+export interface AcceptInvitationRequest {
   token: string;
-  user: any; // 使用User类型
+  username: string;
+  password: string;
+  full_name?: string;
+  phone?: string;
+}
+
+/**
+ * 成员邀请记录
+ */
+export interface Invitation {
+  id: number;
+  company_id: number;
+  email: string;
+  role: string;
+  full_name?: string;
+  invited_by: number;
+  status: 'pending' | 'accepted' | 'revoked' | 'expired';
+  created_at: string;
+  expires_at: string;
+  accepted_user_id?: number;
 }
 
 /**
@@ -52,4 +170,10 @@ export interface RegisterRequest {
   email: string;
   password: string;
   full_name: string;
+  join_code: string;
 }
+
+/**
+ * 平台注册策略: 禁止注册、仅限邀请或凭公司加入码开放注册
+ */
+export type RegistrationMode = 'disabled' | 'invite_only' | 'open';
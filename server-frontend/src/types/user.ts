@@ -25,6 +25,19 @@ export interface User {
   created_at: string;
   updated_at: string;
   last_login?: string;
+  custom_role_id?: number;           // 公司自定义角色ID
+  permissions?: string[];            // 有效权限,如 task.assign、project.delete
+  impersonator?: Impersonator;       // 模拟登录时实际操作的平台管理员
+}
+
+/**
+ * 正在模拟登录的平台管理员
+ */
+export interface Impersonator {
+  id: number;
+  username: string;
+  full_name?: string;
+  expires_at: string;                // 模拟会话到期时间
 }
 
 /**
@@ -0,0 +1,319 @@
+//! Flow Farm 权限定义
+//!
+//! 服务器和桌面端共用同一份权限清单和内置角色的权限组合,两端的权限判断不会出现分歧。
+//! 公司自定义角色由服务器保存,用户的有效权限随用户信息一起下发给客户端。
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// 权限
+///
+/// 除 `platform.manage` 外都只在用户所属公司内生效。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Permission {
+    /// 跨公司管理: 创建和停用公司、平台设置,访问所有公司的数据
+    PlatformManage,
+    /// 查看本公司信息
+    CompanyView,
+    /// 本公司安全设置: 两步验证策略、单点登录、公司API密钥
+    CompanySettings,
+    /// 管理本公司自定义角色并分配给用户
+    RoleManage,
+    UserView,
+    UserCreate,
+    UserUpdate,
+    UserDelete,
+    /// 解锁账号、重置两步验证、重置密码、导出个人数据
+    UserSecurity,
+    ProjectView,
+    ProjectCreate,
+    ProjectUpdate,
+    ProjectDelete,
+    /// 查看本公司所有任务(没有该权限只能看到分配给自己的任务)
+    TaskViewAll,
+    TaskCreate,
+    TaskUpdate,
+    TaskAssign,
+    TaskDelete,
+    /// 查看本公司所有工作记录
+    WorklogViewAll,
+    /// 查看本公司统计数据
+    StatisticsView,
+    /// 查询和导出本公司审计日志
+    AuditView,
+}
+
+impl Permission {
+    /// 全部权限
+    pub const ALL: [Permission; 21] = [
+        Permission::PlatformManage,
+        Permission::CompanyView,
+        Permission::CompanySettings,
+        Permission::RoleManage,
+        Permission::UserView,
+        Permission::UserCreate,
+        Permission::UserUpdate,
+        Permission::UserDelete,
+        Permission::UserSecurity,
+        Permission::ProjectView,
+        Permission::ProjectCreate,
+        Permission::ProjectUpdate,
+        Permission::ProjectDelete,
+        Permission::TaskViewAll,
+        Permission::TaskCreate,
+        Permission::TaskUpdate,
+        Permission::TaskAssign,
+        Permission::TaskDelete,
+        Permission::WorklogViewAll,
+        Permission::StatisticsView,
+        Permission::AuditView,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::PlatformManage => "platform.manage",
+            Permission::CompanyView => "company.view",
+            Permission::CompanySettings => "company.settings",
+            Permission::RoleManage => "role.manage",
+            Permission::UserView => "user.view",
+            Permission::UserCreate => "user.create",
+            Permission::UserUpdate => "user.update",
+            Permission::UserDelete => "user.delete",
+            Permission::UserSecurity => "user.security",
+            Permission::ProjectView => "project.view",
+            Permission::ProjectCreate => "project.create",
+            Permission::ProjectUpdate => "project.update",
+            Permission::ProjectDelete => "project.delete",
+            Permission::TaskViewAll => "task.view_all",
+            Permission::TaskCreate => "task.create",
+            Permission::TaskUpdate => "task.update",
+            Permission::TaskAssign => "task.assign",
+            Permission::TaskDelete => "task.delete",
+            Permission::WorklogViewAll => "worklog.view_all",
+            Permission::StatisticsView => "statistics.view",
+            Permission::AuditView => "audit.view",
+        }
+    }
+
+    /// 权限的中文说明
+    pub fn description(&self) -> &'static str {
+        match self {
+            Permission::PlatformManage => "平台管理",
+            Permission::CompanyView => "查看公司信息",
+            Permission::CompanySettings => "公司安全设置",
+            Permission::RoleManage => "管理角色",
+            Permission::UserView => "查看用户",
+            Permission::UserCreate => "创建用户",
+            Permission::UserUpdate => "修改用户",
+            Permission::UserDelete => "删除用户",
+            Permission::UserSecurity => "账号安全管理",
+            Permission::ProjectView => "查看项目",
+            Permission::ProjectCreate => "创建项目",
+            Permission::ProjectUpdate => "修改项目",
+            Permission::ProjectDelete => "删除项目",
+            Permission::TaskViewAll => "查看所有任务",
+            Permission::TaskCreate => "创建任务",
+            Permission::TaskUpdate => "修改任务",
+            Permission::TaskAssign => "分配任务",
+            Permission::TaskDelete => "删除任务",
+            Permission::WorklogViewAll => "查看所有工作记录",
+            Permission::StatisticsView => "查看统计",
+            Permission::AuditView => "查看审计日志",
+        }
+    }
+
+    /// 平台级权限只属于内置的平台管理员,不能加入公司自定义角色
+    pub fn is_platform(&self) -> bool {
+        matches!(self, Permission::PlatformManage)
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Permission::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == s)
+            .ok_or_else(|| format!("未知的权限: {}", s))
+    }
+}
+
+impl Serialize for Permission {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Permission {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// 内置角色,与用户表的 `role` 列一一对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinRole {
+    PlatformAdmin,
+    ProjectManager,
+    TaskExecutor,
+}
+
+impl BuiltinRole {
+    pub const ALL: [BuiltinRole; 3] = [BuiltinRole::PlatformAdmin, BuiltinRole::ProjectManager, BuiltinRole::TaskExecutor];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuiltinRole::PlatformAdmin => "platform_admin",
+            BuiltinRole::ProjectManager => "project_manager",
+            BuiltinRole::TaskExecutor => "task_executor",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            BuiltinRole::PlatformAdmin => "平台管理员",
+            BuiltinRole::ProjectManager => "项目经理",
+            BuiltinRole::TaskExecutor => "任务执行者",
+        }
+    }
+
+    /// 内置角色的权限组合
+    ///
+    /// 任务执行者只能访问自己的任务和工作记录,这类"本人数据"的访问不需要权限。
+    pub fn permissions(&self) -> PermissionSet {
+        match self {
+            BuiltinRole::PlatformAdmin => Permission::ALL.into_iter().collect(),
+            BuiltinRole::ProjectManager => Permission::ALL.into_iter().filter(|p| !p.is_platform()).collect(),
+            BuiltinRole::TaskExecutor => [Permission::CompanyView, Permission::ProjectView].into_iter().collect(),
+        }
+    }
+}
+
+impl FromStr for BuiltinRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BuiltinRole::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| format!("未知的角色: {}", s))
+    }
+}
+
+/// 权限集合,序列化为权限名称数组
+///
+/// 反序列化时忽略不认识的权限名称,旧版本客户端收到新增的权限不会出错。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionSet(BTreeSet<Permission>);
+
+impl PermissionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, permission: Permission) -> bool {
+        self.0.contains(&permission)
+    }
+
+    pub fn insert(&mut self, permission: Permission) -> bool {
+        self.0.insert(permission)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Permission> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn is_subset(&self, other: &PermissionSet) -> bool {
+        self.0.is_subset(&other.0)
+    }
+
+    /// 能否把 `granted` 授予他人: 平台管理员不受限制,
+    /// 其他人只能授予比自己更少的权限(避免项目经理任命同级或更高权限的用户)
+    pub fn can_grant(&self, granted: &PermissionSet) -> bool {
+        self.contains(Permission::PlatformManage) || (granted.is_subset(self) && granted.len() < self.len())
+    }
+}
+
+impl FromIterator<Permission> for PermissionSet {
+    fn from_iter<I: IntoIterator<Item = Permission>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Serialize for PermissionSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for PermissionSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        Ok(names.iter().filter_map(|name| name.parse().ok()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_names_round_trip() {
+        for permission in Permission::ALL {
+            assert_eq!(permission.as_str().parse::<Permission>(), Ok(permission));
+        }
+        assert!("task.fly".parse::<Permission>().is_err());
+    }
+
+    #[test]
+    fn test_builtin_bundles() {
+        let admin = BuiltinRole::PlatformAdmin.permissions();
+        let manager = BuiltinRole::ProjectManager.permissions();
+        let executor = BuiltinRole::TaskExecutor.permissions();
+
+        assert_eq!(admin.len(), Permission::ALL.len());
+        assert!(!manager.contains(Permission::PlatformManage));
+        assert!(manager.contains(Permission::TaskAssign));
+        assert!(manager.contains(Permission::ProjectDelete));
+        assert!(!executor.contains(Permission::TaskAssign));
+        assert!(executor.is_subset(&manager) && manager.is_subset(&admin));
+    }
+
+    #[test]
+    fn test_can_grant() {
+        let admin = BuiltinRole::PlatformAdmin.permissions();
+        let manager = BuiltinRole::ProjectManager.permissions();
+        let executor = BuiltinRole::TaskExecutor.permissions();
+
+        assert!(admin.can_grant(&admin));
+        assert!(manager.can_grant(&executor));
+        assert!(!manager.can_grant(&manager), "不能授予与自己相同的权限");
+        assert!(!executor.can_grant(&[Permission::UserView].into_iter().collect()));
+    }
+
+    #[test]
+    fn test_permission_set_serde_ignores_unknown() {
+        let set: PermissionSet = serde_json::from_str(r#"["task.assign", "task.fly", "user.view"]"#).unwrap();
+        assert_eq!(set, [Permission::TaskAssign, Permission::UserView].into_iter().collect());
+        assert_eq!(serde_json::to_string(&set).unwrap(), r#"["user.view","task.assign"]"#);
+    }
+}